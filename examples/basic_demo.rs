@@ -1,40 +1,34 @@
 use yasm::*;
 
-// Define a simple door state machine
-mod door {
-    use yasm::*;
-
-    define_state_machine! {
-        name: DoorStateMachine,
-        states: { Closed, Open, Locked },
-        inputs: { OpenDoor, CloseDoor, Lock, Unlock },
-        initial: Closed,
-        transitions: {
-            Closed + OpenDoor => Open,
-            Open + CloseDoor => Closed,
-            Closed + Lock => Locked,
-            Locked + Unlock => Closed
-        }
+// Define a simple door state machine, generated into module `door`
+define_state_machine! {
+    name: DoorStateMachine,
+    module: door,
+    states: { Closed, Open, Locked },
+    inputs: { OpenDoor, CloseDoor, Lock, Unlock },
+    initial: Closed,
+    transitions: {
+        Closed + OpenDoor => Open,
+        Open + CloseDoor => Closed,
+        Closed + Lock => Locked,
+        Locked + Unlock => Closed
     }
 }
 
-// Define a more complex order processing state machine
-mod order {
-    use yasm::*;
-
-    define_state_machine! {
-        name: OrderStateMachine,
-        states: { Created, Paid, Shipped, Delivered, Cancelled },
-        inputs: { Pay, Ship, Deliver, Cancel, Refund },
-        initial: Created,
-        transitions: {
-            Created + Pay => Paid,
-            Created + Cancel => Cancelled,
-            Paid + Ship => Shipped,
-            Paid + Refund => Cancelled,
-            Shipped + Deliver => Delivered,
-            Shipped + Cancel => Cancelled
-        }
+// Define a more complex order processing state machine, generated into module `order`
+define_state_machine! {
+    name: OrderStateMachine,
+    module: order,
+    states: { Created, Paid, Shipped, Delivered, Cancelled },
+    inputs: { Pay, Ship, Deliver, Cancel, Refund },
+    initial: Created,
+    transitions: {
+        Created + Pay => Paid,
+        Created + Cancel => Cancelled,
+        Paid + Ship => Shipped,
+        Paid + Refund => Cancelled,
+        Shipped + Deliver => Delivered,
+        Shipped + Cancel => Cancelled
     }
 }
 
@@ -0,0 +1,89 @@
+use crate::core::StateMachine;
+use crate::error::TransitionError;
+use crate::instance::StateMachineInstance;
+use alloc::string::String;
+
+/// An input bound to the instance it was offered by, ready to be applied
+///
+/// Returned by [`StateMachineInstance::available_actions`] so that UI layers can render
+/// one button or menu entry per action and wire it straight to the transition, without
+/// matching on inputs themselves.
+///
+/// # Example
+/// ```ignore
+/// for action in order.available_actions() {
+///     menu.add_item(action.label(), action.is_enabled(), action);
+/// }
+/// // Later, when an item is clicked
+/// let new_state = clicked.apply(&mut order)?;
+/// ```
+pub struct BoundAction<SM: StateMachine> {
+    /// The input applied by the action
+    input: SM::Input,
+    /// Display name of the input
+    label: String,
+    /// Whether the transition guard allowed the input when the action was offered
+    enabled: bool,
+}
+
+impl<SM: StateMachine> BoundAction<SM> {
+    /// Create an action for an input
+    pub(crate) fn new(input: SM::Input, enabled: bool) -> Self {
+        Self {
+            label: SM::input_name(&input),
+            input,
+            enabled,
+        }
+    }
+
+    /// Get the input applied by the action
+    pub fn input(&self) -> &SM::Input {
+        &self.input
+    }
+
+    /// Get the display name of the input, for use as a button label
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    /// Check whether the transition guard allowed the input when the action was offered
+    ///
+    /// Disabled actions can be rendered greyed out; applying them fails with
+    /// [`TransitionError::GuardRejected`] unless the context has changed since.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Apply the action to an instance
+    ///
+    /// # Returns
+    /// - `Ok(new_state)`: Transition succeeded, returns the new state
+    /// - `Err(error)`: Transition failed, e.g. because the instance has moved on since
+    ///   the action was offered
+    pub fn apply(
+        self,
+        instance: &mut StateMachineInstance<SM>,
+    ) -> Result<SM::State, TransitionError<SM>> {
+        instance.transition(self.input)
+    }
+}
+
+impl<SM: StateMachine> Clone for BoundAction<SM> {
+    fn clone(&self) -> Self {
+        Self {
+            input: self.input.clone(),
+            label: self.label.clone(),
+            enabled: self.enabled,
+        }
+    }
+}
+
+impl<SM: StateMachine> core::fmt::Debug for BoundAction<SM> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("BoundAction")
+            .field("input", &self.input)
+            .field("label", &self.label)
+            .field("enabled", &self.enabled)
+            .finish()
+    }
+}
@@ -0,0 +1,170 @@
+use crate::core::StateMachine;
+use crate::instance::StateMachineInstance;
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::any::Any;
+
+/// Object-safe view of a state machine instance
+///
+/// [`StateMachine`] is made of static methods and associated types, so instances of
+/// different machines cannot be stored together. This trait exposes the instance-level
+/// operations by display name instead, which allows heterogeneous collections such as
+/// `Vec<Box<dyn AnyInstance>>`. Use [`as_any`](Self::as_any) to downcast back to the
+/// concrete [`StateMachineInstance`].
+///
+/// # Example
+/// ```ignore
+/// let mut fleet: Vec<Box<dyn AnyInstance>> = vec![
+///     Box::new(StateMachineInstance::<door::Door>::new()),
+///     Box::new(StateMachineInstance::<light::Light>::new()),
+/// ];
+/// for machine in &mut fleet {
+///     machine.transition_by_name("Push").unwrap();
+/// }
+/// ```
+pub trait AnyInstance {
+    /// Get the type name of the state machine
+    fn machine_name(&self) -> &'static str;
+
+    /// Get the display name of the current state
+    fn current_state_name(&self) -> String;
+
+    /// Get the display names of all valid inputs for the current state
+    fn valid_input_names(&self) -> Vec<String>;
+
+    /// Check if the input with the given display name is accepted in the current state
+    fn can_accept_name(&self, input: &str) -> bool;
+
+    /// Execute a state transition for the input with the given display name
+    ///
+    /// Inputs that carry a payload are applied with their default payload.
+    ///
+    /// # Returns
+    /// - `Ok(state_name)`: Transition succeeded, returns the name of the new state
+    /// - `Err(error_message)`: The input is unknown or the transition failed
+    fn transition_by_name(&mut self, input: &str) -> Result<String, String>;
+
+    /// Get the length of the history
+    fn history_len(&self) -> usize;
+
+    /// Reset the state machine to its initial state and clear history
+    fn reset(&mut self);
+
+    /// Get a reference to the concrete instance for downcasting
+    fn as_any(&self) -> &dyn Any;
+
+    /// Get a mutable reference to the concrete instance for downcasting
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+/// Look up an input by its display name
+fn find_input<SM: StateMachine>(name: &str) -> Option<SM::Input> {
+    SM::inputs()
+        .into_iter()
+        .find(|input| SM::input_name(input) == name)
+}
+
+impl<SM> AnyInstance for StateMachineInstance<SM>
+where
+    SM: StateMachine + 'static,
+{
+    fn machine_name(&self) -> &'static str {
+        core::any::type_name::<SM>()
+    }
+
+    fn current_state_name(&self) -> String {
+        SM::state_name(self.current_state())
+    }
+
+    fn valid_input_names(&self) -> Vec<String> {
+        SM::valid_inputs_iter(self.current_state())
+            .map(|input| SM::input_name(&input))
+            .collect()
+    }
+
+    fn can_accept_name(&self, input: &str) -> bool {
+        find_input::<SM>(input).is_some_and(|input| self.can_accept(&input))
+    }
+
+    fn transition_by_name(&mut self, input: &str) -> Result<String, String> {
+        self.try_transition_by_name(input)
+            .map(|state| SM::state_name(&state))
+            .map_err(|error| error.to_string())
+    }
+
+    fn history_len(&self) -> usize {
+        StateMachineInstance::history_len(self)
+    }
+
+    fn reset(&mut self) {
+        StateMachineInstance::reset(self);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    define_state_machine! {
+        name: Door,
+        module: door,
+        states: { Closed, Open },
+        inputs: { Push, Pull },
+        initial: Closed,
+        transitions: {
+            Closed + Push => Open,
+            Open + Pull => Closed
+        }
+    }
+
+    define_state_machine! {
+        name: Light,
+        module: light,
+        states: { Off, On },
+        inputs: { Push },
+        initial: Off,
+        transitions: {
+            Off + Push => On,
+            On + Push => Off
+        }
+    }
+
+    #[test]
+    fn test_heterogeneous_instances() {
+        let mut fleet: Vec<Box<dyn AnyInstance>> = vec![
+            Box::new(StateMachineInstance::<door::Door>::new()),
+            Box::new(StateMachineInstance::<light::Light>::new()),
+        ];
+
+        for machine in &mut fleet {
+            assert!(machine.can_accept_name("Push"));
+            machine.transition_by_name("Push").unwrap();
+            assert_eq!(machine.history_len(), 1);
+        }
+        assert_eq!(fleet[0].current_state_name(), "Open");
+        assert_eq!(fleet[1].current_state_name(), "On");
+        assert_eq!(fleet[0].valid_input_names(), vec!["Pull"]);
+        assert!(fleet[0].machine_name().ends_with("Door"));
+
+        assert!(fleet[1].transition_by_name("Pull").is_err());
+        assert!(fleet[1].transition_by_name("Unknown").is_err());
+
+        fleet[0].reset();
+        let door = fleet[0]
+            .as_any()
+            .downcast_ref::<StateMachineInstance<door::Door>>()
+            .unwrap();
+        assert_eq!(*door.current_state(), door::State::Closed);
+    }
+}
@@ -0,0 +1,294 @@
+use crate::core::StateMachine;
+use crate::error::TransitionError;
+use crate::instance::StateMachineInstance;
+use crate::rng::Rng;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Exponential backoff parameters
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackoffPolicy {
+    /// Delay before the first retry
+    pub initial_delay: Duration,
+    /// Factor applied to the delay after every failed attempt
+    pub multiplier: f64,
+    /// Upper bound for the delay
+    pub max_delay: Duration,
+    /// Fraction of the delay that is randomized, between 0.0 (none) and 1.0
+    ///
+    /// The actual delay is drawn uniformly from `[delay * (1 - jitter), delay]`.
+    pub jitter: f64,
+    /// Give up after this many consecutive failures, or never if `None`
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for BackoffPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_millis(100),
+            multiplier: 2.0,
+            max_delay: Duration::from_secs(30),
+            jitter: 0.2,
+            max_attempts: None,
+        }
+    }
+}
+
+impl BackoffPolicy {
+    /// Get the delay before retrying after the given failure, without jitter
+    ///
+    /// # Arguments
+    /// - `attempt`: Number of consecutive failures so far, starting at 1
+    pub fn base_delay(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let delay = self.initial_delay.as_secs_f64() * self.multiplier.powi(exponent);
+        Duration::from_secs_f64(delay.min(self.max_delay.as_secs_f64()))
+    }
+}
+
+/// Mutable backoff bookkeeping, shared with the registered callbacks
+#[derive(Debug)]
+struct BackoffState {
+    /// Consecutive failures so far
+    attempts: u32,
+    /// When the next retry is due, if one is scheduled
+    next_retry_at: Option<Instant>,
+    /// Source of the jitter
+    rng: Rng,
+}
+
+/// Handle to a backoff loop wired up with [`attach_backoff`]
+///
+/// Keeps the failure counter outside the state enum and tells when the next retry
+/// is due. Drive it by calling [`poll`](Self::poll) periodically.
+pub struct BackoffHandle<SM: StateMachine> {
+    /// The state that represents a failure
+    failure_state: SM::State,
+    /// The input that leaves the failure state to retry
+    retry_input: SM::Input,
+    /// The backoff parameters
+    policy: BackoffPolicy,
+    /// Shared bookkeeping
+    state: Arc<Mutex<BackoffState>>,
+}
+
+/// Wire an exponential backoff loop into an instance
+///
+/// Every entry into `failure_state` counts as a failed attempt and schedules the next
+/// retry after a jittered, exponentially growing delay. Leaving the failure state with
+/// `retry_input` keeps the count; any other transition that doesn't lead back into the
+/// failure state counts as progress and resets it. Once `max_attempts` consecutive
+/// failures happened, no further retry is scheduled.
+///
+/// # Arguments
+/// - `instance`: The instance to observe
+/// - `failure_state`: The state that represents a failure
+/// - `retry_input`: The input that leaves the failure state to retry
+/// - `policy`: The backoff parameters
+///
+/// # Example
+/// ```ignore
+/// let backoff = attach_backoff(&mut conn, State::Failed, Input::Retry, BackoffPolicy::default());
+/// loop {
+///     backoff.poll(&mut conn, Instant::now());
+///     std::thread::sleep(Duration::from_millis(10));
+/// }
+/// ```
+pub fn attach_backoff<SM>(
+    instance: &mut StateMachineInstance<SM>,
+    failure_state: SM::State,
+    retry_input: SM::Input,
+    policy: BackoffPolicy,
+) -> BackoffHandle<SM>
+where
+    SM: StateMachine + 'static,
+    SM::State: Send + Sync + 'static,
+    SM::Input: Send + Sync + 'static,
+{
+    let state = Arc::new(Mutex::new(BackoffState {
+        attempts: 0,
+        next_retry_at: None,
+        rng: Rng::from_time(),
+    }));
+
+    // Each entry into the failure state schedules the next retry
+    let entry_state = Arc::clone(&state);
+    let entry_policy = policy.clone();
+    instance.on_state_entry(failure_state.clone(), move |_| {
+        let mut backoff = entry_state.lock().unwrap();
+        backoff.attempts += 1;
+
+        if entry_policy
+            .max_attempts
+            .is_some_and(|max| backoff.attempts >= max)
+        {
+            backoff.next_retry_at = None;
+            return;
+        }
+
+        let delay = entry_policy.base_delay(backoff.attempts);
+        let jitter = entry_policy.jitter.clamp(0.0, 1.0) * backoff.rng.next_unit();
+        backoff.next_retry_at = Some(Instant::now() + delay.mul_f64(1.0 - jitter));
+    });
+
+    // Any transition outside the failure loop counts as progress
+    let progress_state = Arc::clone(&state);
+    let loop_state = failure_state.clone();
+    let loop_input = SM::canonical_input(&retry_input);
+    instance.on_any_transition(move |from, input, to| {
+        let retrying = *from == loop_state && SM::canonical_input(input) == loop_input;
+        if *to != loop_state && !retrying {
+            let mut backoff = progress_state.lock().unwrap();
+            backoff.attempts = 0;
+            backoff.next_retry_at = None;
+        }
+    });
+
+    BackoffHandle {
+        failure_state,
+        retry_input,
+        policy,
+        state,
+    }
+}
+
+impl<SM: StateMachine> BackoffHandle<SM> {
+    /// Get the number of consecutive failures
+    pub fn attempts(&self) -> u32 {
+        self.state.lock().unwrap().attempts
+    }
+
+    /// Get when the next retry is due, if one is scheduled
+    pub fn next_retry_at(&self) -> Option<Instant> {
+        self.state.lock().unwrap().next_retry_at
+    }
+
+    /// Check if the maximum number of attempts was reached
+    pub fn is_exhausted(&self) -> bool {
+        let attempts = self.attempts();
+        self.policy.max_attempts.is_some_and(|max| attempts >= max)
+    }
+
+    /// Get the backoff parameters
+    pub fn policy(&self) -> &BackoffPolicy {
+        &self.policy
+    }
+
+    /// Reset the failure counter and cancel the scheduled retry
+    pub fn reset(&self) {
+        let mut backoff = self.state.lock().unwrap();
+        backoff.attempts = 0;
+        backoff.next_retry_at = None;
+    }
+
+    /// Fire the retry input if the instance is in the failure state and the retry is due
+    ///
+    /// # Arguments
+    /// - `instance`: The instance the backoff is attached to
+    /// - `now`: The current time
+    ///
+    /// # Returns
+    /// Returns the result of the retry transition, or None if no retry was due
+    pub fn poll(
+        &self,
+        instance: &mut StateMachineInstance<SM>,
+        now: Instant,
+    ) -> Option<Result<SM::State, TransitionError<SM>>> {
+        if *instance.current_state() != self.failure_state {
+            return None;
+        }
+
+        {
+            let mut backoff = self.state.lock().unwrap();
+            match backoff.next_retry_at {
+                Some(due) if due <= now => backoff.next_retry_at = None,
+                _ => return None,
+            }
+        }
+
+        Some(instance.transition(self.retry_input.clone()))
+    }
+}
+
+impl<SM: StateMachine> std::fmt::Debug for BackoffHandle<SM> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BackoffHandle")
+            .field("failure_state", &self.failure_state)
+            .field("retry_input", &self.retry_input)
+            .field("policy", &self.policy)
+            .field("state", &self.state)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    define_state_machine! {
+        name: Connection,
+        states: { Connecting, Connected, Failed },
+        inputs: { Success, Fail, Retry },
+        initial: Connecting,
+        transitions: {
+            Connecting + Success => Connected,
+            Connecting + Fail => Failed,
+            Failed + Retry => Connecting,
+            Connected + Fail => Failed
+        }
+    }
+
+    #[test]
+    fn test_base_delay() {
+        let policy = BackoffPolicy {
+            initial_delay: Duration::from_secs(1),
+            multiplier: 2.0,
+            max_delay: Duration::from_secs(5),
+            ..BackoffPolicy::default()
+        };
+        assert_eq!(policy.base_delay(1), Duration::from_secs(1));
+        assert_eq!(policy.base_delay(3), Duration::from_secs(4));
+        assert_eq!(policy.base_delay(10), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_backoff_loop() {
+        let mut sm = StateMachineInstance::<Connection>::new();
+        let policy = BackoffPolicy {
+            initial_delay: Duration::from_secs(60),
+            max_delay: Duration::from_secs(600),
+            max_attempts: Some(3),
+            ..BackoffPolicy::default()
+        };
+        let backoff = attach_backoff(&mut sm, State::Failed, Input::Retry, policy);
+
+        sm.transition(Input::Fail).unwrap();
+        assert_eq!(backoff.attempts(), 1);
+        let due = backoff.next_retry_at().unwrap();
+        assert!(due > Instant::now() + Duration::from_secs(40));
+
+        // Not yet due
+        assert!(backoff.poll(&mut sm, Instant::now()).is_none());
+
+        // Due: fires the retry, the counter survives the retry loop
+        assert_eq!(backoff.poll(&mut sm, due), Some(Ok(State::Connecting)));
+        sm.transition(Input::Fail).unwrap();
+        assert_eq!(backoff.attempts(), 2);
+
+        // Progress resets the counter
+        backoff.poll(&mut sm, due + Duration::from_secs(3600));
+        sm.transition(Input::Success).unwrap();
+        assert_eq!(backoff.attempts(), 0);
+        assert!(backoff.next_retry_at().is_none());
+
+        // Exhausted after max_attempts consecutive failures
+        sm.transition(Input::Fail).unwrap();
+        for _ in 0..2 {
+            sm.transition(Input::Retry).unwrap();
+            sm.transition(Input::Fail).unwrap();
+        }
+        assert!(backoff.is_exhausted());
+        assert!(backoff.next_retry_at().is_none());
+    }
+}
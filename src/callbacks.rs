@@ -1,5 +1,8 @@
 use crate::core::StateMachine;
-use std::collections::HashMap;
+use crate::hashing::HashMap;
+use crate::trace::TraceContext;
+use alloc::{boxed::Box, string::String, vec::Vec};
+use core::any::Any;
 
 /// Callback function type for state entry
 pub type StateEntryCallback<SM> = Box<dyn Fn(&<SM as StateMachine>::State) + Send + Sync>;
@@ -14,9 +17,237 @@ pub type TransitionCallback<SM> = Box<
         + Sync,
 >;
 
+/// Callback function type for transition actions that may mutate the instance context
+pub type TransitionAction<SM> = Box<
+    dyn Fn(
+            &<SM as StateMachine>::State,
+            &<SM as StateMachine>::Input,
+            &<SM as StateMachine>::State,
+            &mut <SM as StateMachine>::Context,
+        ) + Send
+        + Sync,
+>;
+
+/// Callback function type for transition effects whose result is handed to the caller
+///
+/// Like [`TransitionAction`], but the returned value is collected into the
+/// [`TransitionOutcome`] of the transition.
+pub type TransitionEffect<SM> = Box<
+    dyn Fn(
+            &<SM as StateMachine>::State,
+            &<SM as StateMachine>::Input,
+            &<SM as StateMachine>::State,
+            &mut <SM as StateMachine>::Context,
+        ) -> Box<dyn Any + Send>
+        + Send
+        + Sync,
+>;
+
+/// Callback function type for validating entry into a state
+///
+/// Receives the instance context and returns an error message to veto the transition.
+pub type EntryValidator<SM> =
+    Box<dyn Fn(&<SM as StateMachine>::Context) -> Result<(), String> + Send + Sync>;
+
+/// Callback function type for vetoing a transition before it takes effect
+///
+/// Receives the source state, the input and the target state and returns an error
+/// message to cancel the transition.
+pub type BeforeTransitionCallback<SM> = Box<
+    dyn Fn(
+            &<SM as StateMachine>::State,
+            &<SM as StateMachine>::Input,
+            &<SM as StateMachine>::State,
+        ) -> Result<(), String>
+        + Send
+        + Sync,
+>;
+
+/// Callback function type for resets, receiving the state left and the state placed in
+pub type ResetCallback<SM> =
+    Box<dyn Fn(&<SM as StateMachine>::State, &<SM as StateMachine>::State) + Send + Sync>;
+
+/// Callback function type for observing transitions as owned events
+///
+/// Unlike [`TransitionCallback`], the event carries the trace context of the
+/// transition, if any.
+pub type TransitionEventCallback<SM> = Box<dyn Fn(&TransitionEvent<SM>) + Send + Sync>;
+
+/// Future returned by an async callback
+#[cfg(feature = "async")]
+pub type CallbackFuture = core::pin::Pin<Box<dyn core::future::Future<Output = ()> + Send>>;
+
+/// Async callback function type for state entry, receiving the entered state
+#[cfg(feature = "async")]
+pub type AsyncStateEntryCallback<SM> =
+    Box<dyn Fn(<SM as StateMachine>::State) -> CallbackFuture + Send + Sync>;
+
+/// Async callback function type for transition, receiving the source state, input and
+/// destination state
+#[cfg(feature = "async")]
+pub type AsyncTransitionCallback<SM> = Box<
+    dyn Fn(
+            <SM as StateMachine>::State,
+            <SM as StateMachine>::Input,
+            <SM as StateMachine>::State,
+        ) -> CallbackFuture
+        + Send
+        + Sync,
+>;
+
 /// Type alias for transition key to reduce complexity
+///
+/// The input is stored in its canonical form, see [`StateMachine::canonical_input`].
 pub type TransitionKey<SM> = (<SM as StateMachine>::State, <SM as StateMachine>::Input);
 
+/// Handle to a registered callback, used to remove it again
+///
+/// Returned by every `on_*` registration method. Ids are unique per registry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CallbackId(u64);
+
+/// Registered callbacks of one kind, tagged with their ids
+type Registered<T> = Vec<(CallbackId, T)>;
+
+/// An owned record of a single state transition
+///
+/// Used by observers that need to keep transitions around after the
+/// callback returns, e.g. for batching or forwarding to other threads.
+pub struct TransitionEvent<SM: StateMachine> {
+    /// The state before the transition
+    pub from: SM::State,
+    /// The input that triggered the transition
+    pub input: SM::Input,
+    /// The state after the transition
+    pub to: SM::State,
+    /// When the transition happened
+    #[cfg(feature = "std")]
+    pub timestamp: std::time::SystemTime,
+    /// The trace context the transition was made under, if any
+    pub trace: Option<TraceContext>,
+}
+
+impl<SM: StateMachine> TransitionEvent<SM> {
+    /// Create a new event stamped with the current time
+    pub fn new(from: SM::State, input: SM::Input, to: SM::State) -> Self {
+        Self {
+            from,
+            input,
+            to,
+            #[cfg(feature = "std")]
+            timestamp: std::time::SystemTime::now(),
+            trace: None,
+        }
+    }
+
+    /// Attach a trace context to the event
+    pub fn with_trace(mut self, trace: Option<TraceContext>) -> Self {
+        self.trace = trace;
+        self
+    }
+
+    /// Get the correlation ID of the trace context the transition was made under
+    pub fn correlation_id(&self) -> Option<&str> {
+        self.trace.as_ref()?.correlation_id()
+    }
+}
+
+impl<SM: StateMachine> Clone for TransitionEvent<SM> {
+    fn clone(&self) -> Self {
+        Self {
+            from: self.from.clone(),
+            input: self.input.clone(),
+            to: self.to.clone(),
+            #[cfg(feature = "std")]
+            timestamp: self.timestamp,
+            trace: self.trace.clone(),
+        }
+    }
+}
+
+impl<SM: StateMachine> core::fmt::Debug for TransitionEvent<SM> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let mut debug = f.debug_struct("TransitionEvent");
+        debug
+            .field("from", &self.from)
+            .field("input", &self.input)
+            .field("to", &self.to);
+        #[cfg(feature = "std")]
+        debug.field("timestamp", &self.timestamp);
+        debug.field("trace", &self.trace).finish()
+    }
+}
+
+/// Result of a transition together with the values returned by its effects
+///
+/// Returned by [`StateMachineInstance::transition_with_outcome`](crate::StateMachineInstance::transition_with_outcome).
+/// Effects registered with [`CallbackRegistry::on_transition_effect`] can hand back
+/// generated IDs, receipts and the like without going through shared state.
+pub struct TransitionOutcome<SM: StateMachine> {
+    /// The state before the transition
+    pub from: SM::State,
+    /// The input that triggered the transition
+    pub input: SM::Input,
+    /// The state after the transition
+    pub to: SM::State,
+    /// The output emitted by the transition, if the machine declares one for it
+    pub output: Option<SM::Output>,
+    /// Values returned by the effects, in the order the effects ran
+    results: Vec<Box<dyn Any + Send>>,
+}
+
+impl<SM: StateMachine> TransitionOutcome<SM> {
+    /// Create an outcome from the collected effect results
+    pub(crate) fn new(
+        from: SM::State,
+        input: SM::Input,
+        to: SM::State,
+        results: Vec<Box<dyn Any + Send>>,
+    ) -> Self {
+        Self {
+            from,
+            input,
+            to,
+            output: None,
+            results,
+        }
+    }
+
+    /// Get the first effect result of type `T`
+    pub fn get<T: Any>(&self) -> Option<&T> {
+        self.results.iter().find_map(|result| result.downcast_ref())
+    }
+
+    /// Iterate over all effect results of type `T`
+    pub fn results<T: Any>(&self) -> impl Iterator<Item = &T> {
+        self.results
+            .iter()
+            .filter_map(|result| result.downcast_ref())
+    }
+
+    /// Take ownership of all effect results
+    pub fn into_results(self) -> Vec<Box<dyn Any + Send>> {
+        self.results
+    }
+
+    /// Get the number of effect results
+    pub fn result_count(&self) -> usize {
+        self.results.len()
+    }
+}
+
+impl<SM: StateMachine> core::fmt::Debug for TransitionOutcome<SM> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("TransitionOutcome")
+            .field("from", &self.from)
+            .field("input", &self.input)
+            .field("to", &self.to)
+            .field("output", &self.output)
+            .field("result_count", &self.results.len())
+            .finish()
+    }
+}
+
 /// Callback registry for state machine events
 ///
 /// This structure manages callbacks for state machine events including:
@@ -25,22 +256,56 @@ pub type TransitionKey<SM> = (<SM as StateMachine>::State, <SM as StateMachine>:
 /// - Transition callbacks: triggered during state transitions
 pub struct CallbackRegistry<SM: StateMachine> {
     /// State entry callbacks mapped by state
-    state_entry_callbacks: HashMap<<SM as StateMachine>::State, Vec<StateEntryCallback<SM>>>,
+    state_entry_callbacks: HashMap<<SM as StateMachine>::State, Registered<StateEntryCallback<SM>>>,
 
     /// State exit callbacks mapped by state
-    state_exit_callbacks: HashMap<<SM as StateMachine>::State, Vec<StateExitCallback<SM>>>,
+    state_exit_callbacks: HashMap<<SM as StateMachine>::State, Registered<StateExitCallback<SM>>>,
 
     /// Transition callbacks mapped by (from_state, input) pairs
-    transition_callbacks: HashMap<TransitionKey<SM>, Vec<TransitionCallback<SM>>>,
+    transition_callbacks: HashMap<TransitionKey<SM>, Registered<TransitionCallback<SM>>>,
+
+    /// Transition actions mapped by (from_state, input) pairs
+    transition_actions: HashMap<TransitionKey<SM>, Registered<TransitionAction<SM>>>,
+
+    /// Transition effects mapped by (from_state, input) pairs
+    transition_effects: HashMap<TransitionKey<SM>, Registered<TransitionEffect<SM>>>,
+
+    /// Global transition actions that run on any transition
+    global_transition_actions: Registered<TransitionAction<SM>>,
+
+    /// Before-transition callbacks mapped by (from_state, input) pairs
+    before_transition_callbacks:
+        HashMap<TransitionKey<SM>, Registered<BeforeTransitionCallback<SM>>>,
+
+    /// Entry validators mapped by state
+    entry_validators: HashMap<<SM as StateMachine>::State, Registered<EntryValidator<SM>>>,
 
     /// Global callbacks that trigger on any state entry
-    global_entry_callbacks: Vec<StateEntryCallback<SM>>,
+    global_entry_callbacks: Registered<StateEntryCallback<SM>>,
 
     /// Global callbacks that trigger on any state exit
-    global_exit_callbacks: Vec<StateExitCallback<SM>>,
+    global_exit_callbacks: Registered<StateExitCallback<SM>>,
 
     /// Global callbacks that trigger on any transition
-    global_transition_callbacks: Vec<TransitionCallback<SM>>,
+    global_transition_callbacks: Registered<TransitionCallback<SM>>,
+
+    /// Global callbacks that receive every transition as an event
+    transition_event_callbacks: Registered<TransitionEventCallback<SM>>,
+
+    /// Callbacks that trigger when the instance is reset
+    reset_callbacks: Registered<ResetCallback<SM>>,
+
+    /// Async state entry callbacks mapped by state
+    #[cfg(feature = "async")]
+    async_entry_callbacks:
+        HashMap<<SM as StateMachine>::State, Registered<AsyncStateEntryCallback<SM>>>,
+
+    /// Async transition callbacks mapped by (from_state, input) pairs
+    #[cfg(feature = "async")]
+    async_transition_callbacks: HashMap<TransitionKey<SM>, Registered<AsyncTransitionCallback<SM>>>,
+
+    /// Id of the next registered callback
+    next_id: u64,
 }
 
 impl<SM: StateMachine> Default for CallbackRegistry<SM> {
@@ -53,12 +318,24 @@ impl<SM: StateMachine> CallbackRegistry<SM> {
     /// Create a new callback registry
     pub fn new() -> Self {
         Self {
-            state_entry_callbacks: HashMap::new(),
-            state_exit_callbacks: HashMap::new(),
-            transition_callbacks: HashMap::new(),
+            state_entry_callbacks: HashMap::default(),
+            state_exit_callbacks: HashMap::default(),
+            transition_callbacks: HashMap::default(),
+            transition_actions: HashMap::default(),
+            transition_effects: HashMap::default(),
+            global_transition_actions: Vec::new(),
+            before_transition_callbacks: HashMap::default(),
+            entry_validators: HashMap::default(),
             global_entry_callbacks: Vec::new(),
             global_exit_callbacks: Vec::new(),
             global_transition_callbacks: Vec::new(),
+            transition_event_callbacks: Vec::new(),
+            reset_callbacks: Vec::new(),
+            #[cfg(feature = "async")]
+            async_entry_callbacks: HashMap::default(),
+            #[cfg(feature = "async")]
+            async_transition_callbacks: HashMap::default(),
+            next_id: 0,
         }
     }
 
@@ -67,14 +344,19 @@ impl<SM: StateMachine> CallbackRegistry<SM> {
     /// # Arguments
     /// * `state` - The state to monitor for entry
     /// * `callback` - The callback function to execute
-    pub fn on_state_entry<F>(&mut self, state: SM::State, callback: F)
+    ///
+    /// # Returns
+    /// A handle for [`remove_callback`](Self::remove_callback)
+    pub fn on_state_entry<F>(&mut self, state: SM::State, callback: F) -> CallbackId
     where
         F: Fn(&SM::State) + Send + Sync + 'static,
     {
+        let id = self.next_id();
         self.state_entry_callbacks
             .entry(state)
             .or_default()
-            .push(Box::new(callback));
+            .push((id, Box::new(callback)));
+        id
     }
 
     /// Register a callback for when exiting a specific state
@@ -82,14 +364,19 @@ impl<SM: StateMachine> CallbackRegistry<SM> {
     /// # Arguments
     /// * `state` - The state to monitor for exit
     /// * `callback` - The callback function to execute
-    pub fn on_state_exit<F>(&mut self, state: SM::State, callback: F)
+    ///
+    /// # Returns
+    /// A handle for [`remove_callback`](Self::remove_callback)
+    pub fn on_state_exit<F>(&mut self, state: SM::State, callback: F) -> CallbackId
     where
         F: Fn(&SM::State) + Send + Sync + 'static,
     {
+        let id = self.next_id();
         self.state_exit_callbacks
             .entry(state)
             .or_default()
-            .push(Box::new(callback));
+            .push((id, Box::new(callback)));
+        id
     }
 
     /// Register a callback for a specific transition
@@ -98,47 +385,410 @@ impl<SM: StateMachine> CallbackRegistry<SM> {
     /// * `from_state` - The source state
     /// * `input` - The input that triggers the transition
     /// * `callback` - The callback function to execute
-    pub fn on_transition<F>(&mut self, from_state: SM::State, input: SM::Input, callback: F)
+    ///
+    /// # Returns
+    /// A handle for [`remove_callback`](Self::remove_callback)
+    pub fn on_transition<F>(
+        &mut self,
+        from_state: SM::State,
+        input: SM::Input,
+        callback: F,
+    ) -> CallbackId
     where
         F: Fn(&SM::State, &SM::Input, &SM::State) + Send + Sync + 'static,
     {
+        let id = self.next_id();
         self.transition_callbacks
-            .entry((from_state, input))
+            .entry((from_state, SM::canonical_input(&input)))
             .or_default()
-            .push(Box::new(callback));
+            .push((id, Box::new(callback)));
+        id
+    }
+
+    /// Register an action for a specific transition that may mutate the context
+    ///
+    /// Actions run after the transition callbacks, with mutable access to the
+    /// instance context.
+    ///
+    /// # Arguments
+    /// * `from_state` - The source state
+    /// * `input` - The input that triggers the transition
+    /// * `action` - The action to execute
+    ///
+    /// # Returns
+    /// A handle for [`remove_callback`](Self::remove_callback)
+    pub fn on_transition_action<F>(
+        &mut self,
+        from_state: SM::State,
+        input: SM::Input,
+        action: F,
+    ) -> CallbackId
+    where
+        F: Fn(&SM::State, &SM::Input, &SM::State, &mut SM::Context) + Send + Sync + 'static,
+    {
+        let id = self.next_id();
+        self.transition_actions
+            .entry((from_state, SM::canonical_input(&input)))
+            .or_default()
+            .push((id, Box::new(action)));
+        id
+    }
+
+    /// Register an effect for a specific transition whose result is returned to the caller
+    ///
+    /// Effects run after the transition actions, with mutable access to the instance
+    /// context. Their return values are collected into the [`TransitionOutcome`]
+    /// returned by `transition_with_outcome`; a plain `transition` discards them.
+    ///
+    /// # Arguments
+    /// * `from_state` - The source state
+    /// * `input` - The input that triggers the transition
+    /// * `effect` - The effect to execute
+    ///
+    /// # Returns
+    /// A handle for [`remove_callback`](Self::remove_callback)
+    pub fn on_transition_effect<F, R>(
+        &mut self,
+        from_state: SM::State,
+        input: SM::Input,
+        effect: F,
+    ) -> CallbackId
+    where
+        F: Fn(&SM::State, &SM::Input, &SM::State, &mut SM::Context) -> R + Send + Sync + 'static,
+        R: Any + Send,
+    {
+        let id = self.next_id();
+        self.transition_effects
+            .entry((from_state, SM::canonical_input(&input)))
+            .or_default()
+            .push((
+                id,
+                Box::new(move |from, input, to, context| {
+                    Box::new(effect(from, input, to, context)) as Box<dyn Any + Send>
+                }),
+            ));
+        id
+    }
+
+    /// Register an action that runs on any transition and may mutate the context
+    ///
+    /// # Arguments
+    /// * `action` - The action to execute
+    ///
+    /// # Returns
+    /// A handle for [`remove_callback`](Self::remove_callback)
+    pub fn on_any_transition_action<F>(&mut self, action: F) -> CallbackId
+    where
+        F: Fn(&SM::State, &SM::Input, &SM::State, &mut SM::Context) + Send + Sync + 'static,
+    {
+        let id = self.next_id();
+        self.global_transition_actions.push((id, Box::new(action)));
+        id
+    }
+
+    /// Register a callback that can veto a specific transition
+    ///
+    /// Runs after the guard and before entry validators, while the instance is still
+    /// in `from_state`. If any callback returns an error, the transition is cancelled
+    /// and no other callbacks fire. Unlike entry validators, self-loops are checked too.
+    ///
+    /// # Arguments
+    /// * `from_state` - The source state
+    /// * `input` - The input that triggers the transition
+    /// * `callback` - The check to run, receiving the source state, input and target state
+    ///
+    /// # Returns
+    /// A handle for [`remove_callback`](Self::remove_callback)
+    pub fn on_before_transition<F>(
+        &mut self,
+        from_state: SM::State,
+        input: SM::Input,
+        callback: F,
+    ) -> CallbackId
+    where
+        F: Fn(&SM::State, &SM::Input, &SM::State) -> Result<(), String> + Send + Sync + 'static,
+    {
+        let id = self.next_id();
+        self.before_transition_callbacks
+            .entry((from_state, SM::canonical_input(&input)))
+            .or_default()
+            .push((id, Box::new(callback)));
+        id
+    }
+
+    /// Register a validator that must pass before a state can be entered
+    ///
+    /// Validators run before a transition into `state` takes effect, regardless of
+    /// which transition leads there. If any validator returns an error, the transition
+    /// is rejected and no callbacks fire. Self-loops do not re-validate.
+    ///
+    /// # Arguments
+    /// * `state` - The state whose entry is validated
+    /// * `validator` - The validation function, receiving the instance context
+    ///
+    /// # Returns
+    /// A handle for [`remove_callback`](Self::remove_callback)
+    pub fn on_validate_entry<F>(&mut self, state: SM::State, validator: F) -> CallbackId
+    where
+        F: Fn(&SM::Context) -> Result<(), String> + Send + Sync + 'static,
+    {
+        let id = self.next_id();
+        self.entry_validators
+            .entry(state)
+            .or_default()
+            .push((id, Box::new(validator)));
+        id
     }
 
     /// Register a global callback that triggers on any state entry
     ///
     /// # Arguments
     /// * `callback` - The callback function to execute
-    pub fn on_any_state_entry<F>(&mut self, callback: F)
+    ///
+    /// # Returns
+    /// A handle for [`remove_callback`](Self::remove_callback)
+    pub fn on_any_state_entry<F>(&mut self, callback: F) -> CallbackId
     where
         F: Fn(&SM::State) + Send + Sync + 'static,
     {
-        self.global_entry_callbacks.push(Box::new(callback));
+        let id = self.next_id();
+        self.global_entry_callbacks.push((id, Box::new(callback)));
+        id
     }
 
     /// Register a global callback that triggers on any state exit
     ///
     /// # Arguments
     /// * `callback` - The callback function to execute
-    pub fn on_any_state_exit<F>(&mut self, callback: F)
+    ///
+    /// # Returns
+    /// A handle for [`remove_callback`](Self::remove_callback)
+    pub fn on_any_state_exit<F>(&mut self, callback: F) -> CallbackId
     where
         F: Fn(&SM::State) + Send + Sync + 'static,
     {
-        self.global_exit_callbacks.push(Box::new(callback));
+        let id = self.next_id();
+        self.global_exit_callbacks.push((id, Box::new(callback)));
+        id
     }
 
     /// Register a global callback that triggers on any transition
     ///
     /// # Arguments
     /// * `callback` - The callback function to execute
-    pub fn on_any_transition<F>(&mut self, callback: F)
+    ///
+    /// # Returns
+    /// A handle for [`remove_callback`](Self::remove_callback)
+    pub fn on_any_transition<F>(&mut self, callback: F) -> CallbackId
     where
         F: Fn(&SM::State, &SM::Input, &SM::State) + Send + Sync + 'static,
     {
-        self.global_transition_callbacks.push(Box::new(callback));
+        let id = self.next_id();
+        self.global_transition_callbacks
+            .push((id, Box::new(callback)));
+        id
+    }
+
+    /// Register a global callback that receives every transition as an event
+    ///
+    /// Runs right after the transition callbacks. The event carries the trace context
+    /// passed to `transition_with_trace`, so observers can continue the trace.
+    ///
+    /// # Arguments
+    /// * `callback` - The callback function to execute
+    ///
+    /// # Returns
+    /// A handle for [`remove_callback`](Self::remove_callback)
+    pub fn on_transition_event<F>(&mut self, callback: F) -> CallbackId
+    where
+        F: Fn(&TransitionEvent<SM>) + Send + Sync + 'static,
+    {
+        let id = self.next_id();
+        self.transition_event_callbacks
+            .push((id, Box::new(callback)));
+        id
+    }
+
+    /// Register a callback that triggers when the instance is reset
+    ///
+    /// The callback receives the state the instance was in and the state it was
+    /// placed in.
+    ///
+    /// # Arguments
+    /// * `callback` - The callback function to execute
+    ///
+    /// # Returns
+    /// A handle for [`remove_callback`](Self::remove_callback)
+    pub fn on_reset<F>(&mut self, callback: F) -> CallbackId
+    where
+        F: Fn(&SM::State, &SM::State) + Send + Sync + 'static,
+    {
+        let id = self.next_id();
+        self.reset_callbacks.push((id, Box::new(callback)));
+        id
+    }
+
+    /// Register an async callback for when entering a specific state
+    ///
+    /// Async callbacks only run for transitions made with
+    /// `transition_async`, which awaits them after all synchronous callbacks have fired.
+    ///
+    /// # Arguments
+    /// * `state` - The state to monitor for entry
+    /// * `callback` - The callback function, returning the future to await
+    ///
+    /// # Returns
+    /// A handle for [`remove_callback`](Self::remove_callback)
+    #[cfg(feature = "async")]
+    pub fn on_state_entry_async<F, Fut>(&mut self, state: SM::State, callback: F) -> CallbackId
+    where
+        F: Fn(SM::State) -> Fut + Send + Sync + 'static,
+        Fut: core::future::Future<Output = ()> + Send + 'static,
+    {
+        let id = self.next_id();
+        self.async_entry_callbacks
+            .entry(state)
+            .or_default()
+            .push((id, Box::new(move |state| Box::pin(callback(state)))));
+        id
+    }
+
+    /// Register an async callback for a specific transition
+    ///
+    /// Async callbacks only run for transitions made with `transition_async`.
+    ///
+    /// # Arguments
+    /// * `from_state` - The source state
+    /// * `input` - The input that triggers the transition
+    /// * `callback` - The callback function, returning the future to await
+    ///
+    /// # Returns
+    /// A handle for [`remove_callback`](Self::remove_callback)
+    #[cfg(feature = "async")]
+    pub fn on_transition_async<F, Fut>(
+        &mut self,
+        from_state: SM::State,
+        input: SM::Input,
+        callback: F,
+    ) -> CallbackId
+    where
+        F: Fn(SM::State, SM::Input, SM::State) -> Fut + Send + Sync + 'static,
+        Fut: core::future::Future<Output = ()> + Send + 'static,
+    {
+        let id = self.next_id();
+        self.async_transition_callbacks
+            .entry((from_state, SM::canonical_input(&input)))
+            .or_default()
+            .push((
+                id,
+                Box::new(move |from, input, to| Box::pin(callback(from, input, to))),
+            ));
+        id
+    }
+
+    /// Allocate the id for a new callback
+    fn next_id(&mut self) -> CallbackId {
+        let id = CallbackId(self.next_id);
+        self.next_id += 1;
+        id
+    }
+
+    /// Remove a single callback, leaving all others registered
+    ///
+    /// # Arguments
+    /// * `id` - The handle returned when the callback was registered
+    ///
+    /// # Returns
+    /// Returns true if the callback was found and removed
+    pub fn remove_callback(&mut self, id: CallbackId) -> bool {
+        fn remove<T>(callbacks: &mut Registered<T>, id: CallbackId) -> bool {
+            let before = callbacks.len();
+            callbacks.retain(|(callback_id, _)| *callback_id != id);
+            callbacks.len() != before
+        }
+
+        fn remove_keyed<K, T>(callbacks: &mut HashMap<K, Registered<T>>, id: CallbackId) -> bool {
+            let removed = callbacks.values_mut().any(|list| remove(list, id));
+            callbacks.retain(|_, list| !list.is_empty());
+            removed
+        }
+
+        remove_keyed(&mut self.state_entry_callbacks, id)
+            || remove_keyed(&mut self.state_exit_callbacks, id)
+            || remove_keyed(&mut self.transition_callbacks, id)
+            || remove_keyed(&mut self.transition_actions, id)
+            || remove_keyed(&mut self.transition_effects, id)
+            || remove_keyed(&mut self.before_transition_callbacks, id)
+            || remove_keyed(&mut self.entry_validators, id)
+            || remove(&mut self.global_entry_callbacks, id)
+            || remove(&mut self.global_exit_callbacks, id)
+            || remove(&mut self.global_transition_callbacks, id)
+            || remove(&mut self.global_transition_actions, id)
+            || remove(&mut self.transition_event_callbacks, id)
+            || remove(&mut self.reset_callbacks, id)
+            || self.remove_async_callback(id)
+    }
+
+    /// Remove a single async callback
+    #[cfg(feature = "async")]
+    fn remove_async_callback(&mut self, id: CallbackId) -> bool {
+        fn remove_keyed<K, T>(callbacks: &mut HashMap<K, Registered<T>>, id: CallbackId) -> bool {
+            let removed = callbacks.values_mut().any(|list| {
+                let before = list.len();
+                list.retain(|(callback_id, _)| *callback_id != id);
+                list.len() != before
+            });
+            callbacks.retain(|_, list| !list.is_empty());
+            removed
+        }
+
+        remove_keyed(&mut self.async_entry_callbacks, id)
+            || remove_keyed(&mut self.async_transition_callbacks, id)
+    }
+
+    /// Remove a single async callback
+    #[cfg(not(feature = "async"))]
+    fn remove_async_callback(&mut self, _id: CallbackId) -> bool {
+        false
+    }
+
+    /// Start the async callbacks of a transition
+    ///
+    /// # Arguments
+    /// * `from_state` - The source state
+    /// * `input` - The input that triggered the transition
+    /// * `to_state` - The destination state
+    /// * `entered` - Whether the destination state was entered, i.e. the transition
+    ///   is not internal
+    ///
+    /// # Returns
+    /// The futures of the transition callbacks followed by those of the entry callbacks
+    #[cfg(feature = "async")]
+    pub(crate) fn async_hooks(
+        &self,
+        from_state: &SM::State,
+        input: &SM::Input,
+        to_state: &SM::State,
+        entered: bool,
+    ) -> Vec<CallbackFuture> {
+        let mut futures = Vec::new();
+
+        let key = (from_state.clone(), SM::canonical_input(input));
+        if let Some(callbacks) = self.async_transition_callbacks.get(&key) {
+            futures.extend(callbacks.iter().map(|(_, callback)| {
+                callback(from_state.clone(), input.clone(), to_state.clone())
+            }));
+        }
+
+        if entered && let Some(callbacks) = self.async_entry_callbacks.get(to_state) {
+            futures.extend(
+                callbacks
+                    .iter()
+                    .map(|(_, callback)| callback(to_state.clone())),
+            );
+        }
+
+        futures
     }
 
     /// Trigger state entry callbacks
@@ -147,31 +797,87 @@ impl<SM: StateMachine> CallbackRegistry<SM> {
     /// * `state` - The state being entered
     pub(crate) fn trigger_state_entry(&self, state: &SM::State) {
         // Trigger global entry callbacks
-        for callback in &self.global_entry_callbacks {
+        for (_, callback) in &self.global_entry_callbacks {
             callback(state);
         }
 
         // Trigger state-specific entry callbacks
         if let Some(callbacks) = self.state_entry_callbacks.get(state) {
-            for callback in callbacks {
+            for (_, callback) in callbacks {
                 callback(state);
             }
         }
     }
 
+    /// Trigger reset callbacks
+    ///
+    /// # Arguments
+    /// * `from_state` - The state the instance was in
+    /// * `to_state` - The state the instance was placed in
+    pub(crate) fn trigger_reset(&self, from_state: &SM::State, to_state: &SM::State) {
+        for (_, callback) in &self.reset_callbacks {
+            callback(from_state, to_state);
+        }
+    }
+
+    /// Run the before-transition callbacks of a transition
+    ///
+    /// # Arguments
+    /// * `from_state` - The source state
+    /// * `input` - The input that triggers the transition
+    /// * `to_state` - The destination state
+    ///
+    /// # Returns
+    /// The first veto, if any
+    pub(crate) fn check_before_transition(
+        &self,
+        from_state: &SM::State,
+        input: &SM::Input,
+        to_state: &SM::State,
+    ) -> Result<(), String> {
+        let key = (from_state.clone(), SM::canonical_input(input));
+        if let Some(callbacks) = self.before_transition_callbacks.get(&key) {
+            for (_, callback) in callbacks {
+                callback(from_state, input, to_state)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Run the entry validators of a state
+    ///
+    /// # Arguments
+    /// * `state` - The state about to be entered
+    /// * `context` - The instance context
+    ///
+    /// # Returns
+    /// The first validation error, if any
+    pub(crate) fn validate_entry(
+        &self,
+        state: &SM::State,
+        context: &SM::Context,
+    ) -> Result<(), String> {
+        if let Some(validators) = self.entry_validators.get(state) {
+            for (_, validator) in validators {
+                validator(context)?;
+            }
+        }
+        Ok(())
+    }
+
     /// Trigger state exit callbacks
     ///
     /// # Arguments
     /// * `state` - The state being exited
     pub(crate) fn trigger_state_exit(&self, state: &SM::State) {
         // Trigger global exit callbacks
-        for callback in &self.global_exit_callbacks {
+        for (_, callback) in &self.global_exit_callbacks {
             callback(state);
         }
 
         // Trigger state-specific exit callbacks
         if let Some(callbacks) = self.state_exit_callbacks.get(state) {
-            for callback in callbacks {
+            for (_, callback) in callbacks {
                 callback(state);
             }
         }
@@ -190,27 +896,120 @@ impl<SM: StateMachine> CallbackRegistry<SM> {
         to_state: &SM::State,
     ) {
         // Trigger global transition callbacks
-        for callback in &self.global_transition_callbacks {
+        for (_, callback) in &self.global_transition_callbacks {
             callback(from_state, input, to_state);
         }
 
         // Trigger transition-specific callbacks
-        let key = (from_state.clone(), input.clone());
+        let key = (from_state.clone(), SM::canonical_input(input));
         if let Some(callbacks) = self.transition_callbacks.get(&key) {
-            for callback in callbacks {
+            for (_, callback) in callbacks {
                 callback(from_state, input, to_state);
             }
         }
     }
 
+    /// Trigger transition event callbacks
+    ///
+    /// The event is only built if an event callback is registered.
+    ///
+    /// # Arguments
+    /// * `from_state` - The source state
+    /// * `input` - The input that triggered the transition
+    /// * `to_state` - The destination state
+    /// * `trace` - The trace context of the transition
+    pub(crate) fn trigger_event(
+        &self,
+        from_state: &SM::State,
+        input: &SM::Input,
+        to_state: &SM::State,
+        trace: Option<&TraceContext>,
+    ) {
+        if self.transition_event_callbacks.is_empty() {
+            return;
+        }
+
+        let event = TransitionEvent::new(from_state.clone(), input.clone(), to_state.clone())
+            .with_trace(trace.cloned());
+        for (_, callback) in &self.transition_event_callbacks {
+            callback(&event);
+        }
+    }
+
+    /// Run transition actions
+    ///
+    /// # Arguments
+    /// * `from_state` - The source state
+    /// * `input` - The input that triggered the transition
+    /// * `to_state` - The destination state
+    /// * `context` - The instance context
+    pub(crate) fn trigger_actions(
+        &self,
+        from_state: &SM::State,
+        input: &SM::Input,
+        to_state: &SM::State,
+        context: &mut SM::Context,
+    ) {
+        // Run global actions
+        for (_, action) in &self.global_transition_actions {
+            action(from_state, input, to_state, context);
+        }
+
+        // Run transition-specific actions
+        let key = (from_state.clone(), SM::canonical_input(input));
+        if let Some(actions) = self.transition_actions.get(&key) {
+            for (_, action) in actions {
+                action(from_state, input, to_state, context);
+            }
+        }
+    }
+
+    /// Run transition effects and collect their results
+    ///
+    /// # Arguments
+    /// * `from_state` - The source state
+    /// * `input` - The input that triggered the transition
+    /// * `to_state` - The destination state
+    /// * `context` - The instance context
+    pub(crate) fn trigger_effects(
+        &self,
+        from_state: &SM::State,
+        input: &SM::Input,
+        to_state: &SM::State,
+        context: &mut SM::Context,
+    ) -> Vec<Box<dyn Any + Send>> {
+        let key = (from_state.clone(), SM::canonical_input(input));
+        self.transition_effects
+            .get(&key)
+            .map(|effects| {
+                effects
+                    .iter()
+                    .map(|(_, effect)| effect(from_state, input, to_state, context))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
     /// Clear all callbacks
     pub fn clear(&mut self) {
         self.state_entry_callbacks.clear();
         self.state_exit_callbacks.clear();
         self.transition_callbacks.clear();
+        self.transition_actions.clear();
+        self.transition_effects.clear();
+        self.global_transition_actions.clear();
+        self.before_transition_callbacks.clear();
+        self.entry_validators.clear();
         self.global_entry_callbacks.clear();
         self.global_exit_callbacks.clear();
         self.global_transition_callbacks.clear();
+        self.transition_event_callbacks.clear();
+        self.reset_callbacks.clear();
+        #[cfg(feature = "async")]
+        {
+            self.async_entry_callbacks.clear();
+            self.async_transition_callbacks.clear();
+        }
     }
 
     /// Get the number of registered callbacks
@@ -229,14 +1028,58 @@ impl<SM: StateMachine> CallbackRegistry<SM> {
                 .values()
                 .map(|v| v.len())
                 .sum::<usize>()
+            + self
+                .transition_actions
+                .values()
+                .map(|v| v.len())
+                .sum::<usize>()
+            + self
+                .transition_effects
+                .values()
+                .map(|v| v.len())
+                .sum::<usize>()
+            + self
+                .before_transition_callbacks
+                .values()
+                .map(|v| v.len())
+                .sum::<usize>()
+            + self
+                .entry_validators
+                .values()
+                .map(|v| v.len())
+                .sum::<usize>()
             + self.global_entry_callbacks.len()
             + self.global_exit_callbacks.len()
             + self.global_transition_callbacks.len()
+            + self.global_transition_actions.len()
+            + self.transition_event_callbacks.len()
+            + self.reset_callbacks.len()
+            + self.async_callback_count()
+    }
+
+    /// Get the number of registered async callbacks
+    #[cfg(feature = "async")]
+    fn async_callback_count(&self) -> usize {
+        self.async_entry_callbacks
+            .values()
+            .map(|v| v.len())
+            .sum::<usize>()
+            + self
+                .async_transition_callbacks
+                .values()
+                .map(|v| v.len())
+                .sum::<usize>()
+    }
+
+    /// Get the number of registered async callbacks
+    #[cfg(not(feature = "async"))]
+    fn async_callback_count(&self) -> usize {
+        0
     }
 }
 
-impl<SM: StateMachine> std::fmt::Debug for CallbackRegistry<SM> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl<SM: StateMachine> core::fmt::Debug for CallbackRegistry<SM> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.debug_struct("CallbackRegistry")
             .field("callback_count", &self.callback_count())
             .finish()
@@ -290,4 +1133,31 @@ mod tests {
         assert!(registry.callback_count() > 0);
         assert_eq!(registry.callback_count(), 2); // 1 state-specific + 1 global
     }
+
+    #[test]
+    fn test_remove_callback() {
+        let mut registry = CallbackRegistry::<TestStateMachine>::new();
+        let counter = Arc::new(Mutex::new(0));
+
+        let counter_clone = Arc::clone(&counter);
+        let specific = registry.on_state_entry(State::StateB, move |_state| {
+            *counter_clone.lock().unwrap() += 1;
+        });
+        let counter_clone = Arc::clone(&counter);
+        let global = registry.on_any_state_entry(move |_state| {
+            *counter_clone.lock().unwrap() += 10;
+        });
+        assert_ne!(specific, global);
+
+        // Only the removed observer stops firing
+        assert!(registry.remove_callback(specific));
+        registry.trigger_state_entry(&State::StateB);
+        assert_eq!(*counter.lock().unwrap(), 10);
+        assert_eq!(registry.callback_count(), 1);
+
+        // Removing twice is a no-op
+        assert!(!registry.remove_callback(specific));
+        assert!(registry.remove_callback(global));
+        assert_eq!(registry.callback_count(), 0);
+    }
 }
@@ -0,0 +1,289 @@
+use crate::core::StateMachine;
+use crate::error::TransitionError;
+use crate::instance::StateMachineInstance;
+use crate::rng::Rng;
+use alloc::{format, string::String, vec::Vec};
+
+/// Randomized robustness test that mixes valid and invalid inputs
+///
+/// Each step applies either a randomly chosen input the current state accepts, or one
+/// it doesn't, and checks that the instance stays consistent:
+/// - successful transitions land in the state the definition prescribes
+/// - failed transitions leave the state and the history untouched, and report the
+///   state and input they were given
+/// - inputs the current state doesn't accept fail with [`TransitionError::InvalidInput`]
+///
+/// Runs are reproducible: the same seed on the same instance yields the same inputs.
+///
+/// # Example
+/// ```ignore
+/// #[test]
+/// fn order_survives_chaos() {
+///     let report = ChaosTest::<Order>::new(42).steps(10_000).run_new().unwrap();
+///     assert!(report.invalid_injected > 0);
+/// }
+/// ```
+pub struct ChaosTest<SM: StateMachine> {
+    /// Seed of the input selection
+    seed: u64,
+    /// Number of inputs to apply
+    steps: usize,
+    /// Probability of injecting an invalid input at each step
+    invalid_ratio: f64,
+    /// The machine under test
+    _machine: core::marker::PhantomData<SM>,
+}
+
+/// Summary of a [`ChaosTest`] run that found no violation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ChaosReport {
+    /// Number of inputs applied
+    pub steps: usize,
+    /// Number of accepted inputs applied
+    pub valid_applied: usize,
+    /// Number of inputs applied that the current state doesn't accept
+    pub invalid_injected: usize,
+    /// Number of transitions that failed, including all invalid inputs
+    pub rejected: usize,
+}
+
+/// A consistency violation found by a [`ChaosTest`] run
+pub struct ChaosViolation<SM: StateMachine> {
+    /// Index of the step that broke an invariant
+    pub step: usize,
+    /// The state the instance was in before the step
+    pub state: SM::State,
+    /// The input applied in the step
+    pub input: SM::Input,
+    /// Which invariant was broken
+    pub reason: String,
+}
+
+impl<SM: StateMachine> ChaosTest<SM> {
+    /// Create a test of 1000 steps injecting an invalid input at every fourth step
+    /// on average
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            steps: 1000,
+            invalid_ratio: 0.25,
+            _machine: core::marker::PhantomData,
+        }
+    }
+
+    /// Set the number of inputs to apply
+    pub fn steps(mut self, steps: usize) -> Self {
+        self.steps = steps;
+        self
+    }
+
+    /// Set the probability of injecting an invalid input, between 0.0 and 1.0
+    pub fn invalid_ratio(mut self, ratio: f64) -> Self {
+        self.invalid_ratio = ratio.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Run the test on a new instance with default context and configuration
+    pub fn run_new(&self) -> Result<ChaosReport, ChaosViolation<SM>>
+    where
+        SM::Context: Default,
+        SM::Config: Default,
+    {
+        self.run(&mut StateMachineInstance::new())
+    }
+
+    /// Run the test on an instance
+    ///
+    /// Callbacks registered on the instance run as usual, so vetoes and entry
+    /// validators take part in the test. The run stops early if the current state
+    /// has no input to choose from.
+    ///
+    /// # Returns
+    /// - `Ok(report)`: All steps kept the instance consistent
+    /// - `Err(violation)`: The first step that broke an invariant
+    pub fn run(
+        &self,
+        instance: &mut StateMachineInstance<SM>,
+    ) -> Result<ChaosReport, ChaosViolation<SM>> {
+        let mut rng = Rng::new(self.seed);
+        let mut report = ChaosReport::default();
+        let all_inputs = SM::inputs();
+
+        for step in 0..self.steps {
+            let state = instance.current_state().clone();
+            let (accepted, rejected): (Vec<_>, Vec<_>) = all_inputs
+                .iter()
+                .cloned()
+                .partition(|input| instance.can_accept(input));
+
+            let inject = !rejected.is_empty()
+                && (accepted.is_empty() || rng.next_unit() < self.invalid_ratio);
+            let candidates = if inject { &rejected } else { &accepted };
+            if candidates.is_empty() {
+                break;
+            }
+            let input = candidates[rng.index(candidates.len())].clone();
+
+            let history_len = instance.history_len();
+            let last_seq = instance.last_history_seq();
+            let result = instance.transition(input.clone());
+            let violation = |reason: String| ChaosViolation {
+                step,
+                state: state.clone(),
+                input: input.clone(),
+                reason,
+            };
+
+            report.steps += 1;
+            if inject {
+                report.invalid_injected += 1;
+            } else {
+                report.valid_applied += 1;
+            }
+
+            match result {
+                Ok(to) => {
+                    if inject {
+                        return Err(violation(format!(
+                            "unaccepted input succeeded with state {to:?}"
+                        )));
+                    }
+                    if *instance.current_state() != to {
+                        return Err(violation(format!(
+                            "returned state {to:?} but the instance is in {:?}",
+                            instance.current_state()
+                        )));
+                    }
+                    let expected =
+                        if SM::is_ignored(&state, &input) || SM::is_deferred(&state, &input) {
+                            Some(state.clone())
+                        } else {
+                            SM::next_state(&state, &input)
+                        };
+                    if expected.as_ref() != Some(&to) {
+                        return Err(violation(format!(
+                            "moved to {to:?} instead of {expected:?}"
+                        )));
+                    }
+                }
+                Err(error) => {
+                    report.rejected += 1;
+                    if inject && !matches!(error, TransitionError::InvalidInput { .. }) {
+                        return Err(violation(format!(
+                            "unaccepted input failed with {error:?} instead of InvalidInput"
+                        )));
+                    }
+                    if *error.state() != state || *error.input() != input {
+                        return Err(violation(format!(
+                            "error {error:?} doesn't report the state and input it was given"
+                        )));
+                    }
+                    if *instance.current_state() != state {
+                        return Err(violation(format!(
+                            "failed transition moved the instance to {:?}",
+                            instance.current_state()
+                        )));
+                    }
+                    if instance.history_len() != history_len
+                        || instance.last_history_seq() != last_seq
+                    {
+                        return Err(violation("failed transition changed the history".into()));
+                    }
+                }
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+impl<SM: StateMachine> core::fmt::Debug for ChaosTest<SM> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ChaosTest")
+            .field("seed", &self.seed)
+            .field("steps", &self.steps)
+            .field("invalid_ratio", &self.invalid_ratio)
+            .finish()
+    }
+}
+
+impl<SM: StateMachine> core::fmt::Display for ChaosViolation<SM> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "Step {}: input {:?} in state {:?}: {}",
+            self.step, self.input, self.state, self.reason
+        )
+    }
+}
+
+impl<SM: StateMachine> core::fmt::Debug for ChaosViolation<SM> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ChaosViolation")
+            .field("step", &self.step)
+            .field("state", &self.state)
+            .field("input", &self.input)
+            .field("reason", &self.reason)
+            .finish()
+    }
+}
+
+impl<SM: StateMachine> core::error::Error for ChaosViolation<SM> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    define_state_machine! {
+        name: Order,
+        states: { Created, Paid, Shipped, Cancelled },
+        inputs: { Pay, Ship, Cancel, Refund },
+        initial: Created,
+        context: u32,
+        transitions: {
+            Created + Pay => Paid,
+            Created + Cancel => Cancelled,
+            Paid + Ship => Shipped if |stock| *stock > 0,
+            Paid ignores Pay,
+            Created defers Ship,
+            Shipped + Refund => Paid,
+            Cancelled + Refund => Created,
+        }
+    }
+
+    #[test]
+    fn test_chaos_run() {
+        let report = ChaosTest::<Order>::new(7).steps(500).run_new().unwrap();
+        assert_eq!(report.steps, 500);
+        assert_eq!(report.valid_applied + report.invalid_injected, 500);
+        assert!(report.invalid_injected > 0);
+        // Invalid inputs and closed guards are rejected
+        assert!(report.rejected > report.invalid_injected);
+
+        // Runs are reproducible
+        let again = ChaosTest::<Order>::new(7).steps(500).run_new().unwrap();
+        assert_eq!(report, again);
+
+        // Vetoes are defined failures too
+        let mut sm = StateMachineInstance::<Order>::with_context(1);
+        sm.on_before_transition(State::Created, Input::Pay, |_, _, _| Err("closed".into()));
+        let report = ChaosTest::new(3).invalid_ratio(0.0).run(&mut sm).unwrap();
+        assert_eq!(report.invalid_injected, 0);
+        assert!(report.rejected > 0);
+    }
+
+    #[test]
+    fn test_chaos_violation_display() {
+        let violation = ChaosViolation::<Order> {
+            step: 3,
+            state: State::Paid,
+            input: Input::Pay,
+            reason: "boom".into(),
+        };
+        assert_eq!(
+            violation.to_string(),
+            "Step 3: input Pay in state Paid: boom"
+        );
+    }
+}
@@ -1,5 +1,12 @@
-use std::fmt::Debug;
-use std::hash::Hash;
+use crate::hashing::FnvHasher;
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt::Debug;
+use core::hash::{Hash, Hasher};
+use core::time::Duration;
 
 /// Deterministic state machine definition trait
 ///
@@ -13,20 +20,201 @@ pub trait StateMachine {
     /// Input type that must support cloning, debug output, hashing, and equality comparison
     type Input: Clone + Debug + Hash + Eq;
 
+    /// Context type that transition guards are evaluated against
+    ///
+    /// Machines without guards use `()`.
+    type Context;
+
+    /// Configuration type that transition guards can read
+    ///
+    /// Unlike the context, the configuration is fixed for the lifetime of an instance,
+    /// e.g. retry limits or feature toggles. Machines without configuration use `()`.
+    type Config;
+
+    /// Output type emitted by transitions, turning the machine into a Mealy machine
+    ///
+    /// Machines without outputs use `()`.
+    type Output: Clone + Debug;
+
     /// Get all possible states
     fn states() -> Vec<Self::State>;
 
     /// Get all possible inputs
     fn inputs() -> Vec<Self::Input>;
 
+    /// Get the description of a state, shown in generated documentation
+    ///
+    /// `define_state_machine!` takes it from the doc comment on the state. By default
+    /// states have no description.
+    fn state_description(_state: &Self::State) -> Option<String> {
+        None
+    }
+
+    /// Get the description of an input, shown in generated documentation
+    ///
+    /// `define_state_machine!` takes it from the doc comment on the input. By default
+    /// inputs have no description.
+    fn input_description(_input: &Self::Input) -> Option<String> {
+        None
+    }
+
+    /// Get the position of a state in `states()`
+    ///
+    /// Used by [`StateSet`](crate::state_set::StateSet) to store declared states as
+    /// bits. Returns None for states that aren't declared, such as states carrying
+    /// data in hand-written machines. The default searches `states()`; generated
+    /// machines override it with a constant-time lookup.
+    fn state_index(state: &Self::State) -> Option<usize> {
+        Self::states().iter().position(|declared| declared == state)
+    }
+
+    /// Get the position of an input's variant in `inputs()`
+    ///
+    /// Inputs are matched by their canonical representative, so payloads are
+    /// ignored. Returns None for inputs that aren't declared. The default searches
+    /// `inputs()`; generated machines override it with a constant-time lookup.
+    fn input_index(input: &Self::Input) -> Option<usize> {
+        let canonical = Self::canonical_input(input);
+        Self::inputs()
+            .iter()
+            .position(|declared| *declared == canonical)
+    }
+
+    /// Get the canonical representative of an input
+    ///
+    /// Inputs that carry a payload are matched by variant, not by value. The canonical
+    /// input is the value that `inputs()` and `valid_inputs()` report for the input's
+    /// variant, and is used wherever inputs are looked up by kind. By default an input
+    /// is its own canonical representative.
+    fn canonical_input(input: &Self::Input) -> Self::Input {
+        input.clone()
+    }
+
     /// Get valid inputs for a given state
     fn valid_inputs(state: &Self::State) -> Vec<Self::Input>;
 
+    /// Iterate over the valid inputs for a given state, in the order of
+    /// [`valid_inputs`](Self::valid_inputs)
+    ///
+    /// Queries, documentation generators and the instance use this instead of
+    /// `valid_inputs` in their loops. Machines defined with `define_state_machine!`
+    /// iterate over a static table without allocating; by default the inputs are
+    /// collected by `valid_inputs`.
+    fn valid_inputs_iter(state: &Self::State) -> impl Iterator<Item = Self::Input> {
+        Self::valid_inputs(state).into_iter()
+    }
+
     /// Deterministic state transition: determine the next state from current state and given input
     ///
     /// Returns Some(next_state) if the transition is valid, otherwise None
     fn next_state(state: &Self::State, input: &Self::Input) -> Option<Self::State>;
 
+    /// Evaluate the guard of the transition for a state and input
+    ///
+    /// Returns false if the transition exists but its guard rejects the given context
+    /// and configuration. Transitions without a guard, and by default all transitions,
+    /// are allowed.
+    fn check_guard(
+        _state: &Self::State,
+        _input: &Self::Input,
+        _context: &Self::Context,
+        _config: &Self::Config,
+    ) -> bool {
+        true
+    }
+
+    /// Get the output emitted by the transition for a state and input
+    ///
+    /// By default no transition emits an output.
+    fn output(_state: &Self::State, _input: &Self::Input) -> Option<Self::Output> {
+        None
+    }
+
+    /// Check whether the transition for a state and input is internal
+    ///
+    /// Internal transitions stay in the state without exiting and re-entering it, so
+    /// no exit or entry callbacks fire. By default all transitions are external, so even
+    /// a self-loop exits and re-enters its state.
+    fn is_internal(_state: &Self::State, _input: &Self::Input) -> bool {
+        false
+    }
+
+    /// Check whether the transition for a state and input is a saga compensation
+    ///
+    /// Compensating transitions undo the effects of earlier steps. They behave like
+    /// other transitions and are only told apart in generated documentation. By
+    /// default no transition is a compensation.
+    fn is_compensation(_state: &Self::State, _input: &Self::Input) -> bool {
+        false
+    }
+
+    /// Check whether an input is deliberately ignored in a state
+    ///
+    /// Ignored inputs are accepted without changing state, as opposed to invalid inputs
+    /// which are rejected. By default no input is ignored.
+    fn is_ignored(_state: &Self::State, _input: &Self::Input) -> bool {
+        false
+    }
+
+    /// Check whether an input is deferred in a state
+    ///
+    /// Deferred inputs are accepted without changing state and set aside until the
+    /// next state change, when they are put back on the input queue of the instance.
+    /// They are retried once the queue is processed. By default no input is deferred.
+    fn is_deferred(_state: &Self::State, _input: &Self::Input) -> bool {
+        false
+    }
+
+    /// Check whether a state is final
+    ///
+    /// An instance in a final state has completed: it rejects every further input with
+    /// [`TransitionError::MachineCompleted`](crate::TransitionError::MachineCompleted).
+    /// By default no state is final.
+    fn is_final(_state: &Self::State) -> bool {
+        false
+    }
+
+    /// Check whether a state is accepting
+    ///
+    /// An input word is accepted if it leads from the initial state to an accepting
+    /// state, see [`StateMachineQuery::accepts`](crate::StateMachineQuery::accepts).
+    /// By default no state is accepting.
+    fn is_accepting(_state: &Self::State) -> bool {
+        false
+    }
+
+    /// Check whether consecutive queued copies of an input are merged into one
+    ///
+    /// Used by [`InputQueue`](crate::queue::InputQueue). By default no input coalesces.
+    fn coalesces(_input: &Self::Input) -> bool {
+        false
+    }
+
+    /// Check whether queuing `newer` drops already queued `older` inputs
+    ///
+    /// Used by [`InputQueue`](crate::queue::InputQueue). By default no input supersedes another.
+    fn supersedes(_newer: &Self::Input, _older: &Self::Input) -> bool {
+        false
+    }
+
+    /// Get the weight of a transition, its relative likelihood in simulations
+    ///
+    /// Used by [`StochasticInstance`](crate::StochasticInstance) to pick inputs. A
+    /// weight of 0 keeps the transition from being picked. By default every
+    /// transition weighs 1.
+    fn weight(_state: &Self::State, _input: &Self::Input) -> u32 {
+        1
+    }
+
+    /// Get the timeout of a state: how long an instance may stay in it, and the input
+    /// it receives once that time has passed
+    ///
+    /// Fired by `timers::TimedInstance`, behind the `timers` feature. By default no
+    /// state has a timeout.
+    fn timeout(_state: &Self::State) -> Option<(Duration, Self::Input)> {
+        None
+    }
+
     /// Get the initial state
     fn initial_state() -> Self::State;
 
@@ -35,4 +223,104 @@ pub trait StateMachine {
 
     /// Get the display name of an input
     fn input_name(input: &Self::Input) -> String;
+
+    /// Get a stable hash of the machine definition
+    ///
+    /// Covers the states, inputs, initial state, transitions (including whether they are
+    /// internal or compensations, their outputs and weights), ignored and deferred inputs,
+    /// timeouts, final and accepting states and queue rules by name, in declaration
+    /// order. Guard bodies are not covered. The hash only depends on the definition, not
+    /// on the compiler or platform, so it can be committed and compared in tests (see
+    /// the `sealed` option of `define_state_machine!`).
+    fn definition_hash() -> u64 {
+        // FNV-1a, chosen for being stable across Rust versions
+        fn feed(hash: &mut FnvHasher, part: &str) {
+            hash.write(part.as_bytes());
+            hash.write_u8(0);
+        }
+
+        let mut hash = FnvHasher::default();
+        let states = Self::states();
+        let inputs = Self::inputs();
+
+        feed(&mut hash, "states");
+        for state in &states {
+            feed(&mut hash, &Self::state_name(state));
+        }
+        feed(&mut hash, "inputs");
+        for input in &inputs {
+            feed(&mut hash, &Self::input_name(input));
+        }
+        feed(&mut hash, "initial");
+        feed(&mut hash, &Self::state_name(&Self::initial_state()));
+
+        feed(&mut hash, "transitions");
+        for state in &states {
+            for input in Self::valid_inputs_iter(state) {
+                if let Some(next_state) = Self::next_state(state, &input) {
+                    feed(&mut hash, &Self::state_name(state));
+                    feed(&mut hash, &Self::input_name(&input));
+                    feed(&mut hash, &Self::state_name(&next_state));
+                    if Self::is_internal(state, &input) {
+                        feed(&mut hash, "internal");
+                    }
+                    if Self::is_compensation(state, &input) {
+                        feed(&mut hash, "compensation");
+                    }
+                    if let Some(output) = Self::output(state, &input) {
+                        feed(&mut hash, "emits");
+                        feed(&mut hash, &format!("{output:?}"));
+                    }
+                    // The default weight is left out, so unweighted hashes don't change
+                    let weight = Self::weight(state, &input);
+                    if weight != 1 {
+                        feed(&mut hash, "weight");
+                        feed(&mut hash, &weight.to_string());
+                    }
+                }
+            }
+            for input in inputs.iter().filter(|input| Self::is_ignored(state, input)) {
+                feed(&mut hash, "ignores");
+                feed(&mut hash, &Self::state_name(state));
+                feed(&mut hash, &Self::input_name(input));
+            }
+            for input in inputs
+                .iter()
+                .filter(|input| Self::is_deferred(state, input))
+            {
+                feed(&mut hash, "defers");
+                feed(&mut hash, &Self::state_name(state));
+                feed(&mut hash, &Self::input_name(input));
+            }
+            if let Some((after, input)) = Self::timeout(state) {
+                feed(&mut hash, "timeout");
+                feed(&mut hash, &Self::state_name(state));
+                feed(&mut hash, &after.as_millis().to_string());
+                feed(&mut hash, &Self::input_name(&input));
+            }
+            if Self::is_final(state) {
+                feed(&mut hash, "final");
+                feed(&mut hash, &Self::state_name(state));
+            }
+            if Self::is_accepting(state) {
+                feed(&mut hash, "accepting");
+                feed(&mut hash, &Self::state_name(state));
+            }
+        }
+
+        feed(&mut hash, "queue");
+        for newer in &inputs {
+            if Self::coalesces(newer) {
+                feed(&mut hash, "coalesce");
+                feed(&mut hash, &Self::input_name(newer));
+            }
+            for older in inputs.iter().filter(|older| Self::supersedes(newer, older)) {
+                feed(&mut hash, &Self::input_name(newer));
+                feed(&mut hash, "supersedes");
+                feed(&mut hash, &Self::input_name(older));
+            }
+        }
+
+        hash.finish()
+    }
 }
@@ -0,0 +1,185 @@
+use crate::callbacks::TransitionEvent;
+use crate::core::StateMachine;
+use crate::instance::StateMachineInstance;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Callback function type for delivering a batch of transition events
+pub type DigestCallback<SM> = Box<dyn Fn(Vec<TransitionEvent<SM>>) + Send + Sync>;
+
+/// Mutable part of the digest, shared with the registered transition callback
+struct DigestBuffer<SM: StateMachine> {
+    /// Events collected since the last flush
+    pending: Vec<TransitionEvent<SM>>,
+    /// When the last batch was delivered (or the digest was created)
+    last_flush: Instant,
+}
+
+/// Batching observer for transition events
+///
+/// Collects transition events and hands them to a callback in batches, so that
+/// machines which transition rapidly don't spam downstream systems. A batch is
+/// delivered when either `max_events` events have accumulated or `interval` has
+/// elapsed since the previous delivery, whichever comes first.
+///
+/// The interval is checked when a new event arrives; there is no background timer.
+/// Call [`flush`](Self::flush) to deliver a partial batch explicitly, e.g. on shutdown.
+///
+/// # Example
+/// ```ignore
+/// let digest = TransitionDigest::new(100, Duration::from_secs(5), |batch| {
+///     send_to_audit_log(batch);
+/// });
+/// digest.attach(&mut workflow);
+/// ```
+pub struct TransitionDigest<SM: StateMachine> {
+    /// Maximum number of events per batch
+    max_events: usize,
+    /// Maximum time between deliveries
+    interval: Duration,
+    /// Buffered events
+    buffer: Mutex<DigestBuffer<SM>>,
+    /// Batch delivery callback
+    callback: DigestCallback<SM>,
+}
+
+impl<SM: StateMachine> TransitionDigest<SM>
+where
+    SM::State: Send + 'static,
+    SM::Input: Send + 'static,
+    SM: 'static,
+{
+    /// Create a new digest
+    ///
+    /// # Arguments
+    /// * `max_events` - Deliver a batch once this many events are pending (at least 1)
+    /// * `interval` - Deliver a batch once this much time has passed since the last delivery
+    /// * `callback` - The callback receiving each batch
+    pub fn new<F>(max_events: usize, interval: Duration, callback: F) -> Arc<Self>
+    where
+        F: Fn(Vec<TransitionEvent<SM>>) + Send + Sync + 'static,
+    {
+        Arc::new(Self {
+            max_events: max_events.max(1),
+            interval,
+            buffer: Mutex::new(DigestBuffer {
+                pending: Vec::new(),
+                last_flush: Instant::now(),
+            }),
+            callback: Box::new(callback),
+        })
+    }
+
+    /// Register this digest as a global transition observer on an instance
+    ///
+    /// The same digest may be attached to several instances to batch their
+    /// events together.
+    pub fn attach(self: &Arc<Self>, instance: &mut StateMachineInstance<SM>) {
+        let digest = Arc::clone(self);
+        instance.on_transition_event(move |event| digest.record(event.clone()));
+    }
+
+    /// Add an event to the pending batch, delivering it if a limit was reached
+    pub fn record(&self, event: TransitionEvent<SM>) {
+        let batch = {
+            let mut buffer = self.buffer.lock().unwrap();
+            buffer.pending.push(event);
+
+            if buffer.pending.len() >= self.max_events
+                || buffer.last_flush.elapsed() >= self.interval
+            {
+                buffer.last_flush = Instant::now();
+                std::mem::take(&mut buffer.pending)
+            } else {
+                return;
+            }
+        };
+
+        // Deliver outside the lock so the callback may record further events
+        (self.callback)(batch);
+    }
+
+    /// Deliver all pending events immediately
+    ///
+    /// Does nothing if no events are pending.
+    pub fn flush(&self) {
+        let batch = {
+            let mut buffer = self.buffer.lock().unwrap();
+            buffer.last_flush = Instant::now();
+            std::mem::take(&mut buffer.pending)
+        };
+
+        if !batch.is_empty() {
+            (self.callback)(batch);
+        }
+    }
+
+    /// Get the number of events waiting for delivery
+    pub fn pending_len(&self) -> usize {
+        self.buffer.lock().unwrap().pending.len()
+    }
+}
+
+impl<SM: StateMachine> std::fmt::Debug for TransitionDigest<SM> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TransitionDigest")
+            .field("max_events", &self.max_events)
+            .field("interval", &self.interval)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    define_state_machine! {
+        name: Toggle,
+        states: { Off, On },
+        inputs: { Flip },
+        initial: Off,
+        transitions: {
+            Off + Flip => On,
+            On + Flip => Off
+        }
+    }
+
+    #[test]
+    fn test_digest_batches_by_count() {
+        let batches = Arc::new(Mutex::new(Vec::new()));
+        let batches_clone = Arc::clone(&batches);
+        let digest = TransitionDigest::<Toggle>::new(3, Duration::from_secs(3600), move |batch| {
+            batches_clone.lock().unwrap().push(batch.len());
+        });
+
+        let mut sm = StateMachineInstance::<Toggle>::new();
+        digest.attach(&mut sm);
+
+        for _ in 0..7 {
+            sm.transition(Input::Flip).unwrap();
+        }
+        assert_eq!(*batches.lock().unwrap(), vec![3, 3]);
+        assert_eq!(digest.pending_len(), 1);
+
+        digest.flush();
+        assert_eq!(*batches.lock().unwrap(), vec![3, 3, 1]);
+        assert_eq!(digest.pending_len(), 0);
+    }
+
+    #[test]
+    fn test_digest_batches_by_interval() {
+        let batches = Arc::new(Mutex::new(Vec::new()));
+        let batches_clone = Arc::clone(&batches);
+        let digest = TransitionDigest::<Toggle>::new(100, Duration::ZERO, move |batch| {
+            batches_clone.lock().unwrap().push(batch.len());
+        });
+
+        let mut sm = StateMachineInstance::<Toggle>::new();
+        digest.attach(&mut sm);
+
+        sm.transition(Input::Flip).unwrap();
+        sm.transition(Input::Flip).unwrap();
+        assert_eq!(*batches.lock().unwrap(), vec![1, 1]);
+    }
+}
@@ -1,11 +1,19 @@
 use crate::core::StateMachine;
-use std::collections::HashMap;
+use crate::hashing::HashMap;
+use crate::instance::{HistoryEntry, StateMachineInstance, unix_millis};
+use crate::query::StateMachineQuery;
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
 
 /// State machine documentation generator
 ///
 /// Provides functionality to generate Mermaid diagrams and transition tables.
 pub struct StateMachineDoc<SM: StateMachine> {
-    _phantom: std::marker::PhantomData<SM>,
+    _phantom: core::marker::PhantomData<SM>,
 }
 
 impl<SM: StateMachine> StateMachineDoc<SM> {
@@ -22,6 +30,9 @@ impl<SM: StateMachine> StateMachineDoc<SM> {
     /// Generates a state diagram definition compliant with Mermaid syntax,
     /// which can be used to visualize the state machine structure.
     /// Self-loops and normal transitions are handled separately for better readability.
+    /// Compensating transitions are labelled with a leading "↩", as Mermaid state
+    /// diagrams cannot style individual arrows. States with a description show it
+    /// inside the state box. Final states lead to the `[*]` terminator.
     ///
     /// # Returns
     /// Returns a Mermaid-formatted state diagram string
@@ -32,36 +43,46 @@ impl<SM: StateMachine> StateMachineDoc<SM> {
         let initial = SM::initial_state();
         mermaid.push_str(&format!("    [*] --> {}\n", SM::state_name(&initial)));
 
-        // Collect normal transitions and self-loops separately
-        let mut normal_transitions = HashMap::new();
-        let mut self_loops = HashMap::new();
-
+        // Add state descriptions
         for state in SM::states() {
-            for input in SM::valid_inputs(&state) {
-                // Skip inputs starting with underscore
-                if !Self::should_include_input(&input) {
-                    continue;
-                }
-
-                if let Some(next_state) = SM::next_state(&state, &input) {
-                    if state == next_state {
-                        // Self-loop
-                        self_loops
-                            .entry(state.clone())
-                            .or_insert_with(Vec::new)
-                            .push(input.clone());
-                    } else {
-                        // Normal transition
-                        let key = (state.clone(), next_state.clone());
-                        normal_transitions
-                            .entry(key)
-                            .or_insert_with(Vec::new)
-                            .push(input.clone());
-                    }
-                }
+            if let Some(description) = SM::state_description(&state) {
+                mermaid.push_str(&format!(
+                    "    {} : {}\n",
+                    SM::state_name(&state),
+                    description.replace(':', "#colon;")
+                ));
             }
         }
 
+        // Collect normal transitions, self-loops and compensations separately
+        let mut normal_transitions = HashMap::default();
+        let mut self_loops = HashMap::default();
+        let mut compensations = Vec::new();
+
+        StateMachineQuery::<SM>::for_each_transition(|state, input, next_state| {
+            // Skip inputs starting with underscore
+            if !Self::should_include_input(input) {
+                return;
+            }
+
+            if SM::is_compensation(state, input) {
+                compensations.push((state.clone(), input.clone(), next_state.clone()));
+            } else if state == next_state {
+                // Self-loop
+                self_loops
+                    .entry(state.clone())
+                    .or_insert_with(Vec::new)
+                    .push(input.clone());
+            } else {
+                // Normal transition
+                let key = (state.clone(), next_state.clone());
+                normal_transitions
+                    .entry(key)
+                    .or_insert_with(Vec::new)
+                    .push(input.clone());
+            }
+        });
+
         // Add normal transitions
         for ((from, to), inputs) in normal_transitions {
             let input_labels: Vec<String> = inputs.iter().map(|i| SM::input_name(i)).collect();
@@ -100,12 +121,309 @@ impl<SM: StateMachine> StateMachineDoc<SM> {
             }
         }
 
+        // Add compensations, one arrow each
+        for (from, input, to) in compensations {
+            mermaid.push_str(&format!(
+                "    {} --> {} : ↩ {}\n",
+                SM::state_name(&from),
+                SM::state_name(&to),
+                SM::input_name(&input)
+            ));
+        }
+
+        // Add final state markers
+        for state in SM::states() {
+            if SM::is_final(&state) {
+                mermaid.push_str(&format!("    {} --> [*]\n", SM::state_name(&state)));
+            }
+        }
+
+        mermaid
+    }
+
+    /// Generate Mermaid state diagram with the current state of an instance highlighted
+    ///
+    /// Extends [`generate_mermaid`](Self::generate_mermaid) with a `current` class
+    /// applied to the state the instance is in. With `show_last_transition`, the state
+    /// the last recorded transition came from gets a `previous` class and the current
+    /// state a note naming that transition, as Mermaid state diagrams cannot style
+    /// individual arrows.
+    ///
+    /// # Arguments
+    /// - `instance`: The instance whose state to highlight
+    /// - `show_last_transition`: Whether to mark the last recorded transition
+    ///
+    /// # Returns
+    /// Returns a Mermaid-formatted state diagram string
+    pub fn generate_mermaid_with_current(
+        instance: &StateMachineInstance<SM>,
+        show_last_transition: bool,
+    ) -> String {
+        let mut mermaid = Self::generate_mermaid();
+        let current = SM::state_name(instance.current_state());
+
+        mermaid.push_str("    classDef current fill:#ffd54f,stroke:#f57f17,stroke-width:3px\n");
+        mermaid.push_str(&format!("    class {current} current\n"));
+
+        if show_last_transition && let Some((from, input)) = instance.history().back() {
+            let from = SM::state_name(from);
+            if from != current {
+                mermaid.push_str("    classDef previous stroke:#f57f17,stroke-dasharray:4\n");
+                mermaid.push_str(&format!("    class {from} previous\n"));
+            }
+            mermaid.push_str(&format!(
+                "    note right of {current} : via {} from {from}\n",
+                SM::input_name(input)
+            ));
+        }
+
+        mermaid
+    }
+
+    /// Generate Mermaid sequence diagram of the path recorded in an instance's history
+    ///
+    /// States become participants, in declaration order, and every history entry an
+    /// arrow numbered by its position in the path, from the oldest entry still covered by
+    /// the history to the current state. Notes attached with
+    /// [`annotate_history`](StateMachineInstance::annotate_history) are shown over the
+    /// state the transition led to.
+    ///
+    /// # Arguments
+    /// - `instance`: The instance whose history to render
+    ///
+    /// # Returns
+    /// Returns a Mermaid-formatted sequence diagram string
+    pub fn generate_history_sequence(instance: &StateMachineInstance<SM>) -> String {
+        let steps = Self::history_steps(instance);
+
+        let mut mermaid = String::from("sequenceDiagram\n");
+        for state in SM::states() {
+            let visited =
+                *instance.current_state() == state || steps.iter().any(|(from, ..)| *from == state);
+            if visited {
+                mermaid.push_str(&format!("    participant {}\n", SM::state_name(&state)));
+            }
+        }
+
+        for (number, (from, to, entry)) in steps.iter().enumerate() {
+            let to = SM::state_name(to);
+            mermaid.push_str(&format!(
+                "    {}->>{to}: {}. {}\n",
+                SM::state_name(from),
+                number + 1,
+                SM::input_name(&entry.input)
+            ));
+            for annotation in &entry.annotations {
+                mermaid.push_str(&format!(
+                    "    Note over {to}: {}\n",
+                    annotation.replace('\n', "<br/>")
+                ));
+            }
+        }
+
+        mermaid
+    }
+
+    /// Generate Mermaid state diagram of the path recorded in an instance's history
+    ///
+    /// Only the transitions taken are drawn, one arrow per history entry labelled with
+    /// its position in the path and its input, so a transition taken twice shows up
+    /// twice. The path starts at the oldest state still covered by the history, and the
+    /// current state is highlighted as in
+    /// [`generate_mermaid_with_current`](Self::generate_mermaid_with_current).
+    ///
+    /// # Arguments
+    /// - `instance`: The instance whose history to render
+    ///
+    /// # Returns
+    /// Returns a Mermaid-formatted state diagram string
+    pub fn generate_history_path_mermaid(instance: &StateMachineInstance<SM>) -> String {
+        let steps = Self::history_steps(instance);
+        let current = SM::state_name(instance.current_state());
+        let start = steps
+            .first()
+            .map(|(from, ..)| SM::state_name(from))
+            .unwrap_or_else(|| current.clone());
+
+        let mut mermaid = String::from("stateDiagram-v2\n");
+        mermaid.push_str(&format!("    [*] --> {start}\n"));
+        for (number, (from, to, entry)) in steps.iter().enumerate() {
+            mermaid.push_str(&format!(
+                "    {} --> {} : {}. {}\n",
+                SM::state_name(from),
+                SM::state_name(to),
+                number + 1,
+                SM::input_name(&entry.input)
+            ));
+        }
+        mermaid.push_str("    classDef current fill:#ffd54f,stroke:#f57f17,stroke-width:3px\n");
+        mermaid.push_str(&format!("    class {current} current\n"));
+
+        mermaid
+    }
+
+    /// Pair every history entry with the state it led to
+    fn history_steps(
+        instance: &StateMachineInstance<SM>,
+    ) -> Vec<(SM::State, SM::State, HistoryEntry<SM>)> {
+        let entries = instance.history_entries();
+        let targets: Vec<_> = entries
+            .iter()
+            .skip(1)
+            .map(|entry| entry.from.clone())
+            .chain(core::iter::once(instance.current_state().clone()))
+            .collect();
+        entries
+            .into_iter()
+            .zip(targets)
+            .map(|(entry, to)| (entry.from.clone(), to, entry))
+            .collect()
+    }
+
+    /// Generate Graphviz DOT digraph
+    ///
+    /// Generates a directed graph in DOT syntax. The initial state is marked with an
+    /// arrow from a point-shaped start node, and inputs leading from one state to the
+    /// same target are merged into a single labelled edge. Compensating transitions are
+    /// drawn as separate dotted red edges. Underscore-prefixed inputs are left out, as
+    /// in the Mermaid diagram. State descriptions become node tooltips, and accepting
+    /// states are drawn as double circles.
+    ///
+    /// # Returns
+    /// Returns a DOT-formatted digraph string
+    pub fn generate_dot() -> String {
+        let mut dot = String::from("digraph StateMachine {\n    rankdir=LR;\n");
+
+        // Add initial state marker
+        dot.push_str("    __start [shape=point];\n");
+        dot.push_str(&format!(
+            "    __start -> \"{}\";\n",
+            SM::state_name(&SM::initial_state())
+        ));
+
+        // Declare every state, so unconnected states show up too
+        for state in SM::states() {
+            let mut attributes = Vec::new();
+            if SM::is_accepting(&state) {
+                attributes.push("shape=doublecircle".to_string());
+            }
+            if let Some(description) = SM::state_description(&state) {
+                attributes.push(format!("tooltip=\"{}\"", description.replace('"', "\\\"")));
+            }
+            if attributes.is_empty() {
+                dot.push_str(&format!("    \"{}\";\n", SM::state_name(&state)));
+            } else {
+                dot.push_str(&format!(
+                    "    \"{}\" [{}];\n",
+                    SM::state_name(&state),
+                    attributes.join(", ")
+                ));
+            }
+        }
+
+        // Merge inputs per (from, to, is compensation), keeping declaration order
+        let mut edges: Vec<(_, _, _, Vec<String>)> = Vec::new();
+        StateMachineQuery::<SM>::for_each_transition(|state, input, next_state| {
+            if !Self::should_include_input(input) {
+                return;
+            }
+
+            let compensation = SM::is_compensation(state, input);
+            match edges.iter_mut().find(|(from, to, is_compensation, _)| {
+                from == state && to == next_state && *is_compensation == compensation
+            }) {
+                Some((_, _, _, labels)) => labels.push(SM::input_name(input)),
+                None => edges.push((
+                    state.clone(),
+                    next_state.clone(),
+                    compensation,
+                    vec![SM::input_name(input)],
+                )),
+            }
+        });
+
+        for (from, to, compensation, labels) in edges {
+            let style = if compensation {
+                ", style=dotted, color=red, fontcolor=red"
+            } else {
+                ""
+            };
+            dot.push_str(&format!(
+                "    \"{}\" -> \"{}\" [label=\"{}\"{style}];\n",
+                SM::state_name(&from),
+                SM::state_name(&to),
+                labels.join(" / ").replace('"', "\\\"")
+            ));
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Generate Mermaid gantt chart of state occupancy over wall-clock time
+    ///
+    /// Draws one section per state, with a bar for every stay in that state that is
+    /// still covered by the instance's history, labelled with the input that ended it.
+    /// The stay in the current state runs until now and is marked active. Ignored
+    /// inputs and self-loops don't split a stay.
+    ///
+    /// # Arguments
+    /// - `instance`: The instance whose history to render
+    ///
+    /// # Returns
+    /// Returns a Mermaid-formatted gantt chart string
+    pub fn generate_timeline_mermaid(instance: &StateMachineInstance<SM>) -> String {
+        // Each stay: (state, label, entered_at, exited_at)
+        let mut stays: Vec<(SM::State, String, u64, u64)> = Vec::new();
+        for entry in instance.history_entries() {
+            let label = SM::input_name(&entry.input);
+            match stays.last_mut() {
+                Some((state, last_label, entered_at, exited_at))
+                    if *state == entry.from && *entered_at == entry.entered_at =>
+                {
+                    *last_label = label;
+                    *exited_at = entry.exited_at;
+                }
+                _ => stays.push((entry.from, label, entry.entered_at, entry.exited_at)),
+            }
+        }
+        let entered_at = instance.state_entered_at();
+        stays.push((
+            instance.current_state().clone(),
+            "current".to_string(),
+            entered_at,
+            unix_millis().max(entered_at),
+        ));
+
+        let mut mermaid = String::from(
+            "gantt\n    title State Timeline\n    dateFormat x\n    axisFormat %H:%M:%S\n",
+        );
+        let current = stays.len() - 1;
+        for state in SM::states() {
+            let mut in_state = stays
+                .iter()
+                .enumerate()
+                .filter(|(_, (s, ..))| *s == state)
+                .peekable();
+            if in_state.peek().is_none() {
+                continue;
+            }
+
+            mermaid.push_str(&format!("    section {}\n", SM::state_name(&state)));
+            for (index, (_, label, entered_at, exited_at)) in in_state {
+                let tag = if index == current { "active, " } else { "" };
+                mermaid.push_str(&format!("    {label} :{tag}{entered_at}, {exited_at}\n"));
+            }
+        }
+
         mermaid
     }
 
     /// Generate state transition table
     ///
     /// Generates a Markdown-formatted state transition table listing all valid state transitions.
+    /// If any state or input has a description, tables of the described states and inputs
+    /// follow.
     ///
     /// # Returns
     /// Returns a Markdown-formatted transition table string
@@ -115,21 +433,183 @@ impl<SM: StateMachine> StateMachineDoc<SM> {
         table.push_str("|---------------|-------|------------|\n");
 
         for state in SM::states() {
-            for input in SM::valid_inputs(&state) {
+            for input in SM::valid_inputs_iter(&state) {
                 // Skip inputs starting with underscore
                 if !Self::should_include_input(&input) {
                     continue;
                 }
 
                 if let Some(next_state) = SM::next_state(&state, &input) {
+                    let internal = if SM::is_internal(&state, &input) {
+                        " *(internal)*"
+                    } else {
+                        ""
+                    };
+                    let output = SM::output(&state, &input)
+                        .map(|output| format!(" *(emits {output:?})*"))
+                        .unwrap_or_default();
                     table.push_str(&format!(
-                        "| {} | {} | {} |\n",
+                        "| {} | {} | {}{internal}{output} |\n",
                         SM::state_name(&state),
                         SM::input_name(&input),
                         SM::state_name(&next_state)
                     ));
                 }
             }
+
+            // List inputs that are ignored by design or deferred in this state
+            for input in SM::inputs() {
+                if !Self::should_include_input(&input) {
+                    continue;
+                }
+                let note = if SM::is_ignored(&state, &input) {
+                    "*(ignored)*"
+                } else if SM::is_deferred(&state, &input) {
+                    "*(deferred)*"
+                } else {
+                    continue;
+                };
+                table.push_str(&format!(
+                    "| {} | {} | {note} |\n",
+                    SM::state_name(&state),
+                    SM::input_name(&input)
+                ));
+            }
+        }
+
+        // Add descriptions of states and inputs
+        let states: Vec<_> = SM::states()
+            .iter()
+            .filter_map(|state| {
+                SM::state_description(state).map(|description| (SM::state_name(state), description))
+            })
+            .collect();
+        let inputs: Vec<_> = SM::inputs()
+            .iter()
+            .filter(|input| Self::should_include_input(input))
+            .filter_map(|input| {
+                SM::input_description(input).map(|description| (SM::input_name(input), description))
+            })
+            .collect();
+        for (title, column, rows) in [("States", "State", states), ("Inputs", "Input", inputs)] {
+            if rows.is_empty() {
+                continue;
+            }
+            table.push_str(&format!(
+                "\n## {title}\n\n| {column} | Description |\n|{}|-------------|\n",
+                "-".repeat(column.len() + 2)
+            ));
+            for (name, description) in rows {
+                table.push_str(&format!(
+                    "| {name} | {} |\n",
+                    description.replace('|', "\\|")
+                ));
+            }
+        }
+
+        table
+    }
+
+    /// Generate rollback paths table
+    ///
+    /// Generates a Markdown table with one row per compensating transition. Each row
+    /// follows the compensation chain from its source state, taking the first declared
+    /// compensation of each state reached, until a state without compensations.
+    ///
+    /// # Returns
+    /// Returns a Markdown-formatted rollback paths table string
+    pub fn generate_rollback_table() -> String {
+        let compensation_from = |state: &SM::State| {
+            SM::valid_inputs_iter(state)
+                .filter(|input| SM::is_compensation(state, input))
+                .filter_map(|input| {
+                    SM::next_state(state, &input).map(|next_state| (input, next_state))
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let mut table = String::from("# Rollback Paths\n\n");
+        let mut rows = Vec::new();
+        for state in SM::states() {
+            for (input, next_state) in compensation_from(&state) {
+                let mut path = format!(
+                    "{} --{}--> {}",
+                    SM::state_name(&state),
+                    SM::input_name(&input),
+                    SM::state_name(&next_state)
+                );
+                let mut visited = vec![state.clone(), next_state.clone()];
+                let mut current = next_state;
+                while let Some((input, next_state)) = compensation_from(&current).into_iter().next()
+                {
+                    path.push_str(&format!(
+                        " --{}--> {}",
+                        SM::input_name(&input),
+                        SM::state_name(&next_state)
+                    ));
+                    if visited.contains(&next_state) {
+                        break;
+                    }
+                    visited.push(next_state.clone());
+                    current = next_state;
+                }
+
+                rows.push(format!(
+                    "| {} | {} | {path} |\n",
+                    SM::state_name(&state),
+                    SM::input_name(&input)
+                ));
+            }
+        }
+
+        if rows.is_empty() {
+            table.push_str("No compensations declared.\n");
+        } else {
+            table.push_str("| State | Input | Rollback Path |\n");
+            table.push_str("|-------|-------|---------------|\n");
+            table.extend(rows);
+        }
+
+        table
+    }
+
+    /// Generate state × input matrix
+    ///
+    /// Generates a Markdown table with one row per state and one column per input.
+    /// Each cell shows the target state, *(ignored)* for inputs ignored by design,
+    /// *(deferred)* for deferred inputs, or "—" if the input is invalid in that state,
+    /// so gaps in a dense machine stand out.
+    ///
+    /// # Returns
+    /// Returns a Markdown-formatted matrix string
+    pub fn generate_matrix_table() -> String {
+        let inputs: Vec<_> = SM::inputs()
+            .into_iter()
+            .filter(Self::should_include_input)
+            .collect();
+
+        let mut table = String::from("# State × Input Matrix\n\n| State |");
+        for input in &inputs {
+            table.push_str(&format!(" {} |", SM::input_name(input)));
+        }
+        table.push_str("\n|-------|");
+        for _ in &inputs {
+            table.push_str("---|");
+        }
+        table.push('\n');
+
+        for state in SM::states() {
+            table.push_str(&format!("| {} |", SM::state_name(&state)));
+            for input in &inputs {
+                let cell = match SM::next_state(&state, input) {
+                    Some(next_state) => SM::state_name(&next_state),
+                    None if SM::is_ignored(&state, input) => "*(ignored)*".to_string(),
+                    None if SM::is_deferred(&state, input) => "*(deferred)*".to_string(),
+                    None => "—".to_string(),
+                };
+                table.push_str(&format!(" {cell} |"));
+            }
+            table.push('\n');
         }
 
         table
@@ -148,17 +628,13 @@ impl<SM: StateMachine> StateMachineDoc<SM> {
         let mut transition_count = 0;
         let mut self_loop_count = 0;
 
-        for state in &states {
-            for input in SM::valid_inputs(state) {
-                if let Some(next_state) = SM::next_state(state, &input) {
-                    if *state == next_state {
-                        self_loop_count += 1;
-                    } else {
-                        transition_count += 1;
-                    }
-                }
+        StateMachineQuery::<SM>::for_each_transition(|state, _, next_state| {
+            if state == next_state {
+                self_loop_count += 1;
+            } else {
+                transition_count += 1;
             }
-        }
+        });
 
         format!(
             "# State Machine Statistics\n\n\
@@ -177,6 +653,106 @@ impl<SM: StateMachine> StateMachineDoc<SM> {
         )
     }
 
+    /// Generate a runnable example program for the state machine
+    ///
+    /// The example creates an instance, walks a happy path and demonstrates how an
+    /// invalid input is rejected. The happy path is the shortest path from the initial
+    /// state to the nearest terminal state, or to the farthest reachable state if no
+    /// terminal state is reachable. Transitions with guards may need a different context
+    /// than the default one used by the example.
+    ///
+    /// # Arguments
+    /// - `module_path`: Path of the module that defines the machine, e.g. `my_app::door`
+    ///
+    /// # Returns
+    /// Returns the source code of a `main.rs`-style example
+    pub fn generate_example_code(module_path: &str) -> String {
+        let machine = core::any::type_name::<SM>()
+            .rsplit("::")
+            .next()
+            .unwrap_or("StateMachine");
+        let initial = SM::initial_state();
+
+        // Pick the target of the happy path
+        let paths: Vec<Vec<SM::State>> = SM::states()
+            .iter()
+            .filter_map(|state| StateMachineQuery::<SM>::shortest_path(&initial, state))
+            .collect();
+        let terminal = paths
+            .iter()
+            .filter(|path| {
+                path.len() > 1 && SM::valid_inputs_iter(path.last().unwrap()).next().is_none()
+            })
+            .min_by_key(|path| path.len());
+        let farthest = paths.iter().rev().max_by_key(|path| path.len());
+        let path = terminal.or(farthest).cloned().unwrap_or_default();
+
+        // Find an input for each step of the path
+        let steps: Vec<(SM::Input, SM::State)> = path
+            .windows(2)
+            .filter_map(|step| {
+                SM::valid_inputs_iter(&step[0])
+                    .find(|input| SM::next_state(&step[0], input).as_ref() == Some(&step[1]))
+                    .map(|input| (input, step[1].clone()))
+            })
+            .collect();
+        let final_state = steps
+            .last()
+            .map_or(initial.clone(), |(_, state)| state.clone());
+
+        let mut code = format!(
+            "//! Example usage of the `{machine}` state machine\n\
+            //!\n\
+            //! Generated by `StateMachineDoc::generate_example_code`.\n\n\
+            use yasm::prelude::*;\n\
+            use {module_path}::{{{machine}, Input, State}};\n\n\
+            fn main() {{\n    \
+                let mut machine = StateMachineInstance::<{machine}>::new();\n    \
+                println!(\"Initial state: {{:?}}\", machine.current_state());\n"
+        );
+
+        // Happy path
+        if !steps.is_empty() {
+            let route: Vec<String> = path.iter().map(SM::state_name).collect();
+            code.push_str(&format!("\n    // Happy path: {}\n", route.join(" -> ")));
+            code.push_str("    let happy_path = [\n");
+            for (input, _) in &steps {
+                code.push_str(&format!("        Input::{input:?},\n"));
+            }
+            code.push_str(
+                "    ];\n    \
+                for input in happy_path {\n        \
+                    let state = machine\n            \
+                        .transition(input.clone())\n            \
+                        .expect(\"happy path transition\");\n        \
+                    println!(\"{input:?} -> {state:?}\");\n    \
+                }\n",
+            );
+            code.push_str(&format!(
+                "    assert_eq!(*machine.current_state(), State::{final_state:?});\n"
+            ));
+        }
+
+        // Error demonstration with an input the final state neither accepts, ignores nor defers
+        let invalid = SM::inputs().into_iter().find(|input| {
+            SM::next_state(&final_state, input).is_none()
+                && !SM::is_ignored(&final_state, input)
+                && !SM::is_deferred(&final_state, input)
+        });
+        if let Some(input) = invalid {
+            code.push_str(&format!(
+                "\n    // Inputs that are not valid in the current state are rejected\n    \
+                match machine.transition(Input::{input:?}) {{\n        \
+                    Ok(state) => println!(\"Unexpectedly moved to {{state:?}}\"),\n        \
+                    Err(error) => println!(\"Rejected as expected: {{error}}\"),\n    \
+                }}\n"
+            ));
+        }
+
+        code.push_str("\n    println!(\"History: {} transitions\", machine.history_len());\n}\n");
+        code
+    }
+
     /// Generate complete documentation
     ///
     /// Complete documentation containing statistics, transition tables, and Mermaid diagrams.
@@ -0,0 +1,858 @@
+use crate::DEFAULT_MAX_HISTORY_SIZE;
+use crate::hashing::{HashMap, HashSet};
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt::Debug;
+use core::hash::Hash;
+
+/// Error returned when building a [`DynStateMachine`] from an inconsistent definition
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BuildError<S, I> {
+    /// No initial state was set
+    MissingInitialState,
+    /// A state was used but never declared
+    UnknownState(S),
+    /// An input was used but never declared
+    UnknownInput(I),
+    /// The same state and input map to two different next states
+    ConflictingTransition {
+        /// The source state
+        from: S,
+        /// The input
+        input: I,
+        /// The next state declared first
+        first: S,
+        /// The next state declared later
+        second: S,
+    },
+}
+
+impl<S: Debug, I: Debug> core::fmt::Display for BuildError<S, I> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::MissingInitialState => write!(f, "No initial state set"),
+            Self::UnknownState(state) => write!(f, "Unknown state: {state:?}"),
+            Self::UnknownInput(input) => write!(f, "Unknown input: {input:?}"),
+            Self::ConflictingTransition {
+                from,
+                input,
+                first,
+                second,
+            } => write!(
+                f,
+                "Conflicting transitions from {from:?} with input {input:?}: {first:?} and {second:?}"
+            ),
+        }
+    }
+}
+
+impl<S: Debug, I: Debug> core::error::Error for BuildError<S, I> {}
+
+/// Error returned when a transition of a [`DynInstance`] fails
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DynTransitionError<S, I> {
+    /// The input is not valid in the current state
+    InvalidInput {
+        /// The current state
+        state: S,
+        /// The rejected input
+        input: I,
+    },
+}
+
+impl<S: Debug, I: Debug> core::fmt::Display for DynTransitionError<S, I> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::InvalidInput { state, input } => {
+                write!(f, "Invalid input {input:?} for state {state:?}")
+            }
+        }
+    }
+}
+
+impl<S: Debug, I: Debug> core::error::Error for DynTransitionError<S, I> {}
+
+/// Builder for state machines defined at runtime
+///
+/// States and inputs can be any hashable type, `String` by default, so transition
+/// tables can come from a database or configuration instead of `define_state_machine!`.
+///
+/// # Example
+/// ```rust
+/// use yasm::dynamic::StateMachineBuilder;
+///
+/// let machine = StateMachineBuilder::<String, String>::new()
+///     .states(["Closed", "Open"].map(String::from))
+///     .inputs(["Push", "Pull"].map(String::from))
+///     .initial("Closed".to_string())
+///     .transition("Closed".to_string(), "Push".to_string(), "Open".to_string())
+///     .transition("Open".to_string(), "Pull".to_string(), "Closed".to_string())
+///     .build()
+///     .unwrap();
+///
+/// let mut door = machine.instance();
+/// door.transition("Push".to_string()).unwrap();
+/// assert_eq!(door.current_state(), "Open");
+/// ```
+#[derive(Debug, Clone)]
+pub struct StateMachineBuilder<S = String, I = String> {
+    /// Declared states, in declaration order
+    states: Vec<S>,
+    /// Declared inputs, in declaration order
+    inputs: Vec<I>,
+    /// Initial state
+    initial: Option<S>,
+    /// Transitions, in declaration order
+    transitions: Vec<(S, I, S)>,
+}
+
+impl<S, I> Default for StateMachineBuilder<S, I>
+where
+    S: Clone + Debug + Hash + Eq,
+    I: Clone + Debug + Hash + Eq,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S, I> StateMachineBuilder<S, I>
+where
+    S: Clone + Debug + Hash + Eq,
+    I: Clone + Debug + Hash + Eq,
+{
+    /// Create an empty builder
+    pub fn new() -> Self {
+        Self {
+            states: Vec::new(),
+            inputs: Vec::new(),
+            initial: None,
+            transitions: Vec::new(),
+        }
+    }
+
+    /// Declare a state
+    pub fn state(mut self, state: S) -> Self {
+        if !self.states.contains(&state) {
+            self.states.push(state);
+        }
+        self
+    }
+
+    /// Declare several states
+    pub fn states(self, states: impl IntoIterator<Item = S>) -> Self {
+        states.into_iter().fold(self, Self::state)
+    }
+
+    /// Declare an input
+    pub fn input(mut self, input: I) -> Self {
+        if !self.inputs.contains(&input) {
+            self.inputs.push(input);
+        }
+        self
+    }
+
+    /// Declare several inputs
+    pub fn inputs(self, inputs: impl IntoIterator<Item = I>) -> Self {
+        inputs.into_iter().fold(self, Self::input)
+    }
+
+    /// Set the initial state
+    pub fn initial(mut self, state: S) -> Self {
+        self.initial = Some(state);
+        self
+    }
+
+    /// Add a transition
+    pub fn transition(mut self, from: S, input: I, to: S) -> Self {
+        self.transitions.push((from, input, to));
+        self
+    }
+
+    /// Validate the definition and build the state machine
+    ///
+    /// # Returns
+    /// - `Ok(machine)`: The definition is consistent
+    /// - `Err(error)`: The initial state is missing, a transition refers to an
+    ///   undeclared state or input, or two transitions conflict
+    pub fn build(self) -> Result<DynStateMachine<S, I>, BuildError<S, I>> {
+        let initial = self.initial.ok_or(BuildError::MissingInitialState)?;
+        let known_states: HashSet<&S> = self.states.iter().collect();
+        let known_inputs: HashSet<&I> = self.inputs.iter().collect();
+
+        if !known_states.contains(&initial) {
+            return Err(BuildError::UnknownState(initial));
+        }
+
+        let mut table: HashMap<(S, I), S> = HashMap::default();
+        let mut valid_inputs: HashMap<S, Vec<I>> = HashMap::default();
+
+        for (from, input, to) in self.transitions {
+            for state in [&from, &to] {
+                if !known_states.contains(state) {
+                    return Err(BuildError::UnknownState(state.clone()));
+                }
+            }
+            if !known_inputs.contains(&input) {
+                return Err(BuildError::UnknownInput(input));
+            }
+
+            match table.get(&(from.clone(), input.clone())) {
+                Some(first) if *first != to => {
+                    return Err(BuildError::ConflictingTransition {
+                        first: first.clone(),
+                        from,
+                        input,
+                        second: to,
+                    });
+                }
+                Some(_) => {}
+                None => {
+                    valid_inputs
+                        .entry(from.clone())
+                        .or_default()
+                        .push(input.clone());
+                    table.insert((from, input), to);
+                }
+            }
+        }
+        // The sets borrow the declarations, which move into the machine
+        drop((known_states, known_inputs));
+
+        Ok(DynStateMachine {
+            states: self.states,
+            inputs: self.inputs,
+            initial,
+            table,
+            valid_inputs,
+        })
+    }
+}
+
+/// A validated state machine definition built at runtime
+///
+/// Offers the same queries as the [`StateMachine`](crate::StateMachine) trait, as
+/// methods on a value. Create instances with [`instance`](Self::instance).
+#[derive(Debug, Clone)]
+pub struct DynStateMachine<S = String, I = String> {
+    /// Declared states, in declaration order
+    states: Vec<S>,
+    /// Declared inputs, in declaration order
+    inputs: Vec<I>,
+    /// Initial state
+    initial: S,
+    /// Transition table
+    table: HashMap<(S, I), S>,
+    /// Valid inputs per state, in declaration order
+    valid_inputs: HashMap<S, Vec<I>>,
+}
+
+impl<S, I> DynStateMachine<S, I>
+where
+    S: Clone + Debug + Hash + Eq,
+    I: Clone + Debug + Hash + Eq,
+{
+    /// Get all possible states
+    pub fn states(&self) -> &[S] {
+        &self.states
+    }
+
+    /// Get all possible inputs
+    pub fn inputs(&self) -> &[I] {
+        &self.inputs
+    }
+
+    /// Get the initial state
+    pub fn initial_state(&self) -> &S {
+        &self.initial
+    }
+
+    /// Get valid inputs for a given state
+    pub fn valid_inputs(&self, state: &S) -> &[I] {
+        self.valid_inputs.get(state).map_or(&[], Vec::as_slice)
+    }
+
+    /// Determine the next state from a state and input
+    ///
+    /// Returns Some(next_state) if the transition is valid, otherwise None
+    pub fn next_state(&self, state: &S, input: &I) -> Option<&S> {
+        self.table.get(&(state.clone(), input.clone()))
+    }
+
+    /// Get all transitions as (from, input, to) triples, grouped by source state
+    pub fn transitions(&self) -> Vec<(S, I, S)> {
+        self.states
+            .iter()
+            .flat_map(|from| {
+                self.valid_inputs(from).iter().map(move |input| {
+                    let to = self.table[&(from.clone(), input.clone())].clone();
+                    (from.clone(), input.clone(), to)
+                })
+            })
+            .collect()
+    }
+
+    /// Generate the `define_state_machine!` invocation for this machine
+    ///
+    /// Freezes a machine prototyped at runtime into a compile-time definition. States
+    /// and inputs are written using their `Display` form, which must be a valid Rust
+    /// identifier and not a keyword.
+    ///
+    /// # Arguments
+    /// - `name`: The name of the generated machine type
+    ///
+    /// # Returns
+    /// - `Ok(source)`: The macro invocation
+    /// - `Err(error_message)`: A name is not a valid identifier or is a keyword
+    pub fn to_macro_source(&self, name: &str) -> Result<String, String>
+    where
+        S: core::fmt::Display,
+        I: core::fmt::Display,
+    {
+        // Strict and reserved keywords of the 2024 edition
+        const KEYWORDS: &[&str] = &[
+            "abstract", "as", "async", "await", "become", "box", "break", "const", "continue",
+            "crate", "do", "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "gen",
+            "if", "impl", "in", "let", "loop", "macro", "match", "mod", "move", "mut", "override",
+            "priv", "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait",
+            "true", "try", "type", "typeof", "unsafe", "unsized", "use", "virtual", "where",
+            "while", "yield",
+        ];
+
+        fn identifier(kind: &str, name: String) -> Result<String, String> {
+            let mut chars = name.chars();
+            let valid = chars
+                .next()
+                .is_some_and(|first| first.is_alphabetic() || first == '_')
+                && chars.all(|c| c.is_alphanumeric() || c == '_')
+                && name != "_";
+            if !valid {
+                Err(format!("{kind} {name:?} is not a valid Rust identifier"))
+            } else if KEYWORDS.contains(&name.as_str()) {
+                Err(format!("{kind} {name:?} is a Rust keyword"))
+            } else {
+                Ok(name)
+            }
+        }
+
+        let name = identifier("Machine name", name.to_string())?;
+        let states = self
+            .states
+            .iter()
+            .map(|state| identifier("State", state.to_string()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let inputs = self
+            .inputs
+            .iter()
+            .map(|input| identifier("Input", input.to_string()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let transitions: Vec<_> = self
+            .transitions()
+            .iter()
+            .map(|(from, input, to)| format!("        {from} + {input} => {to}"))
+            .collect();
+
+        Ok(format!(
+            "define_state_machine! {{\n    \
+                name: {name},\n    \
+                states: {{ {} }},\n    \
+                inputs: {{ {} }},\n    \
+                initial: {},\n    \
+                transitions: {{\n{}\n    }}\n\
+            }}\n",
+            states.join(", "),
+            inputs.join(", "),
+            self.initial,
+            transitions.join(",\n")
+        ))
+    }
+
+    /// Create a new instance in the initial state
+    pub fn instance(&self) -> DynInstance<S, I> {
+        DynInstance::new(Arc::new(self.clone()))
+    }
+}
+
+/// Serializable definition of a [`DynStateMachine`] with string states and inputs
+///
+/// This is the documented schema for machine definitions in configuration files:
+///
+/// ```json
+/// {
+///   "states": ["Closed", "Open"],
+///   "inputs": ["Push", "Pull"],
+///   "initial": "Closed",
+///   "transitions": [
+///     { "from": "Closed", "input": "Push", "to": "Open" },
+///     { "from": "Open", "input": "Pull", "to": "Closed" }
+///   ]
+/// }
+/// ```
+///
+/// With the `serde` feature, [`DynStateMachine::from_str`] parses it from JSON. The
+/// `toml` and `serde_yaml` features add TOML and YAML, following the same schema:
+///
+/// ```toml
+/// states = ["Closed", "Open"]
+/// inputs = ["Push", "Pull"]
+/// initial = "Closed"
+///
+/// [[transitions]]
+/// from = "Closed"
+/// input = "Push"
+/// to = "Open"
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DynDefinition {
+    /// Declared states, in declaration order
+    pub states: Vec<String>,
+    /// Declared inputs, in declaration order
+    pub inputs: Vec<String>,
+    /// Initial state
+    pub initial: String,
+    /// Transitions, in declaration order
+    pub transitions: Vec<DynTransitionDefinition>,
+}
+
+/// A single transition of a [`DynDefinition`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DynTransitionDefinition {
+    /// The source state
+    pub from: String,
+    /// The input that triggers the transition
+    pub input: String,
+    /// The destination state
+    pub to: String,
+}
+
+impl DynDefinition {
+    /// Validate the definition and build the state machine
+    ///
+    /// # Returns
+    /// - `Ok(machine)`: The definition is consistent
+    /// - `Err(error)`: See [`StateMachineBuilder::build`]
+    pub fn build(self) -> Result<DynStateMachine, BuildError<String, String>> {
+        self.transitions
+            .into_iter()
+            .fold(
+                StateMachineBuilder::new()
+                    .states(self.states)
+                    .inputs(self.inputs)
+                    .initial(self.initial),
+                |builder, transition| {
+                    builder.transition(transition.from, transition.input, transition.to)
+                },
+            )
+            .build()
+    }
+}
+
+/// Text format of a machine definition, see [`DynStateMachine::from_str`]
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DefinitionFormat {
+    /// JSON following the [`DynDefinition`] schema
+    Json,
+    /// JSON following the [`EditorDocument`](crate::EditorDocument) schema
+    EditorJson,
+    /// TOML following the [`DynDefinition`] schema
+    #[cfg(feature = "toml")]
+    Toml,
+    /// YAML following the [`DynDefinition`] schema
+    #[cfg(feature = "serde_yaml")]
+    Yaml,
+}
+
+/// Error returned when loading a [`DynStateMachine`] from text
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoadError {
+    /// The text doesn't follow the schema of its format
+    Parse(String),
+    /// The definition is inconsistent
+    Build(BuildError<String, String>),
+    /// The document follows a newer version of the editor format than this crate supports
+    UnsupportedVersion(u32),
+}
+
+#[cfg(feature = "serde")]
+impl core::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Parse(reason) => write!(f, "Malformed definition: {reason}"),
+            Self::Build(error) => write!(f, "Invalid definition: {error}"),
+            Self::UnsupportedVersion(version) => write!(
+                f,
+                "Unsupported editor format version {version}, expected at most {}",
+                crate::editor::EDITOR_FORMAT_VERSION
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl core::error::Error for LoadError {}
+
+#[cfg(feature = "serde")]
+impl From<BuildError<String, String>> for LoadError {
+    fn from(error: BuildError<String, String>) -> Self {
+        Self::Build(error)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl DynStateMachine {
+    /// Load a machine definition from text
+    ///
+    /// TOML and YAML are available with the `toml` and `serde_yaml` features.
+    ///
+    /// # Arguments
+    /// - `format`: The format of the text
+    /// - `text`: The definition, following the schema of the format
+    ///
+    /// # Returns
+    /// - `Ok(machine)`: The definition was parsed and is consistent
+    /// - `Err(error)`: The text is malformed or the definition is inconsistent
+    pub fn from_str(format: DefinitionFormat, text: &str) -> Result<Self, LoadError> {
+        let definition: DynDefinition =
+            match format {
+                DefinitionFormat::Json => serde_json::from_str(text)
+                    .map_err(|error| LoadError::Parse(error.to_string()))?,
+                DefinitionFormat::EditorJson => {
+                    crate::editor::EditorDocument::from_editor_json(text)?.to_definition()
+                }
+                #[cfg(feature = "toml")]
+                DefinitionFormat::Toml => {
+                    toml::from_str(text).map_err(|error| LoadError::Parse(error.to_string()))?
+                }
+                #[cfg(feature = "serde_yaml")]
+                DefinitionFormat::Yaml => serde_yaml::from_str(text)
+                    .map_err(|error| LoadError::Parse(error.to_string()))?,
+            };
+        Ok(definition.build()?)
+    }
+}
+
+/// Instance of a [`DynStateMachine`]
+///
+/// Mirrors [`StateMachineInstance`](crate::StateMachineInstance): it keeps the current
+/// state and a bounded history of (from_state, input) pairs. The definition is shared,
+/// so many instances can be created cheaply from one `Arc<DynStateMachine>`.
+#[derive(Debug, Clone)]
+pub struct DynInstance<S = String, I = String> {
+    /// The machine definition
+    machine: Arc<DynStateMachine<S, I>>,
+    /// Current state
+    current_state: S,
+    /// Transition history: sequence of (from_state, input) pairs
+    history: VecDeque<(S, I)>,
+    /// Maximum history size
+    max_history_size: usize,
+}
+
+impl<S, I> DynInstance<S, I>
+where
+    S: Clone + Debug + Hash + Eq,
+    I: Clone + Debug + Hash + Eq,
+{
+    /// Create a new instance with default history size
+    pub fn new(machine: Arc<DynStateMachine<S, I>>) -> Self {
+        Self::with_max_history(machine, DEFAULT_MAX_HISTORY_SIZE)
+    }
+
+    /// Create a new instance with custom history size
+    pub fn with_max_history(machine: Arc<DynStateMachine<S, I>>, max_size: usize) -> Self {
+        Self {
+            current_state: machine.initial_state().clone(),
+            machine,
+            history: VecDeque::new(),
+            max_history_size: max_size,
+        }
+    }
+
+    /// Get the machine definition
+    pub fn machine(&self) -> &DynStateMachine<S, I> {
+        &self.machine
+    }
+
+    /// Get a read-only reference to the current state
+    pub fn current_state(&self) -> &S {
+        &self.current_state
+    }
+
+    /// Get a read-only reference to the transition history
+    pub fn history(&self) -> &VecDeque<(S, I)> {
+        &self.history
+    }
+
+    /// Get the maximum history size
+    pub fn max_history_size(&self) -> usize {
+        self.max_history_size
+    }
+
+    /// Check if the given input is valid for the current state
+    pub fn can_accept(&self, input: &I) -> bool {
+        self.machine
+            .next_state(&self.current_state, input)
+            .is_some()
+    }
+
+    /// Get all valid inputs for the current state
+    pub fn valid_inputs(&self) -> &[I] {
+        self.machine.valid_inputs(&self.current_state)
+    }
+
+    /// Execute a state transition
+    ///
+    /// # Returns
+    /// - `Ok(new_state)`: Transition succeeded, returns the new state
+    /// - `Err(error)`: The input is not valid in the current state
+    pub fn transition(&mut self, input: I) -> Result<S, DynTransitionError<S, I>> {
+        let Some(new_state) = self
+            .machine
+            .next_state(&self.current_state, &input)
+            .cloned()
+        else {
+            return Err(DynTransitionError::InvalidInput {
+                state: self.current_state.clone(),
+                input,
+            });
+        };
+
+        let old_state = core::mem::replace(&mut self.current_state, new_state.clone());
+        self.history.push_back((old_state, input));
+        if self.history.len() > self.max_history_size {
+            self.history.pop_front();
+        }
+
+        Ok(new_state)
+    }
+
+    /// Reset the instance to the initial state and clear history
+    pub fn reset(&mut self) {
+        self.current_state = self.machine.initial_state().clone();
+        self.history.clear();
+    }
+
+    /// Get the length of the history
+    pub fn history_len(&self) -> usize {
+        self.history.len()
+    }
+
+    /// Check if the history is empty
+    pub fn history_is_empty(&self) -> bool {
+        self.history.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, Hash, PartialEq, Eq)]
+    enum Phase {
+        Idle,
+        Running,
+    }
+
+    #[test]
+    fn test_build_and_run() {
+        let machine = StateMachineBuilder::new()
+            .states([Phase::Idle, Phase::Running])
+            .inputs(["start", "stop"])
+            .initial(Phase::Idle)
+            .transition(Phase::Idle, "start", Phase::Running)
+            .transition(Phase::Running, "stop", Phase::Idle)
+            .build()
+            .unwrap();
+
+        assert_eq!(machine.valid_inputs(&Phase::Idle), &["start"]);
+        assert_eq!(machine.transitions().len(), 2);
+
+        let mut instance = machine.instance();
+        assert_eq!(instance.transition("start"), Ok(Phase::Running));
+        assert_eq!(
+            instance.transition("start"),
+            Err(DynTransitionError::InvalidInput {
+                state: Phase::Running,
+                input: "start"
+            })
+        );
+        assert_eq!(instance.history_len(), 1);
+
+        instance.reset();
+        assert_eq!(*instance.current_state(), Phase::Idle);
+        assert!(instance.history_is_empty());
+    }
+
+    #[test]
+    fn test_build_errors() {
+        let builder = StateMachineBuilder::<String, String>::new()
+            .state("A".into())
+            .input("go".into());
+        assert_eq!(
+            builder.clone().build().unwrap_err(),
+            BuildError::MissingInitialState
+        );
+
+        let error = builder
+            .clone()
+            .initial("A".into())
+            .transition("A".into(), "go".into(), "B".into())
+            .build()
+            .unwrap_err();
+        assert_eq!(error, BuildError::UnknownState("B".into()));
+
+        let error = builder
+            .state("B".into())
+            .initial("A".into())
+            .transition("A".into(), "go".into(), "A".into())
+            .transition("A".into(), "go".into(), "B".into())
+            .build()
+            .unwrap_err();
+        assert!(matches!(error, BuildError::ConflictingTransition { .. }));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_from_json() {
+        let text = r#"{
+            "states": ["Closed", "Open"],
+            "inputs": ["Push", "Pull"],
+            "initial": "Closed",
+            "transitions": [
+                { "from": "Closed", "input": "Push", "to": "Open" },
+                { "from": "Open", "input": "Pull", "to": "Closed" }
+            ]
+        }"#;
+        let machine = DynStateMachine::from_str(DefinitionFormat::Json, text).unwrap();
+        assert_eq!(machine.states(), &["Closed", "Open"]);
+
+        let mut door = machine.instance();
+        assert_eq!(door.transition("Push".into()), Ok("Open".to_string()));
+
+        let error = DynStateMachine::from_str(DefinitionFormat::Json, "{\"states\": []}");
+        assert!(matches!(error, Err(LoadError::Parse(_))));
+
+        let error = DynStateMachine::from_str(
+            DefinitionFormat::Json,
+            &text.replace("\"initial\": \"Closed\"", "\"initial\": \"Ajar\""),
+        );
+        assert_eq!(
+            error.unwrap_err(),
+            LoadError::Build(BuildError::UnknownState("Ajar".into()))
+        );
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn test_from_toml() {
+        let text = r#"
+            states = ["Closed", "Open"]
+            inputs = ["Push", "Pull"]
+            initial = "Closed"
+
+            [[transitions]]
+            from = "Closed"
+            input = "Push"
+            to = "Open"
+        "#;
+        let machine = DynStateMachine::from_str(DefinitionFormat::Toml, text).unwrap();
+        assert_eq!(machine.inputs(), &["Push", "Pull"]);
+        assert_eq!(
+            machine.instance().transition("Push".into()),
+            Ok("Open".to_string())
+        );
+
+        let error = DynStateMachine::from_str(DefinitionFormat::Toml, "states = [");
+        assert!(matches!(error, Err(LoadError::Parse(_))));
+    }
+
+    #[cfg(feature = "serde_yaml")]
+    #[test]
+    fn test_from_yaml() {
+        let text = "
+states: [Closed, Open]
+inputs: [Push, Pull]
+initial: Closed
+transitions:
+  - { from: Closed, input: Push, to: Open }
+  - { from: Open, input: Pull, to: Closed }
+";
+        let machine = DynStateMachine::from_str(DefinitionFormat::Yaml, text).unwrap();
+        assert_eq!(machine.states(), &["Closed", "Open"]);
+        assert_eq!(
+            machine.instance().transition("Push".into()),
+            Ok("Open".to_string())
+        );
+
+        let error = DynStateMachine::from_str(
+            DefinitionFormat::Yaml,
+            &text.replace("Closed\ntr", "Ajar\ntr"),
+        );
+        assert_eq!(
+            error.unwrap_err(),
+            LoadError::Build(BuildError::UnknownState("Ajar".into()))
+        );
+    }
+
+    #[test]
+    fn test_to_macro_source() {
+        let machine = StateMachineBuilder::<String, String>::new()
+            .states(["Closed", "Open"].map(String::from))
+            .inputs(["Push", "Pull"].map(String::from))
+            .initial("Closed".into())
+            .transition("Closed".into(), "Push".into(), "Open".into())
+            .transition("Open".into(), "Pull".into(), "Closed".into())
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            machine.to_macro_source("Door").unwrap(),
+            "define_state_machine! {\n    \
+                name: Door,\n    \
+                states: { Closed, Open },\n    \
+                inputs: { Push, Pull },\n    \
+                initial: Closed,\n    \
+                transitions: {\n        \
+                    Closed + Push => Open,\n        \
+                    Open + Pull => Closed\n    \
+                }\n\
+            }\n"
+        );
+
+        let machine = StateMachineBuilder::<String, String>::new()
+            .state("in progress".into())
+            .initial("in progress".into())
+            .build()
+            .unwrap();
+        assert_eq!(
+            machine.to_macro_source("Task").unwrap_err(),
+            "State \"in progress\" is not a valid Rust identifier"
+        );
+
+        let machine = StateMachineBuilder::<String, String>::new()
+            .states(["Idle", "Running"].map(String::from))
+            .inputs(["move"].map(String::from))
+            .initial("Idle".into())
+            .transition("Idle".into(), "move".into(), "Running".into())
+            .build()
+            .unwrap();
+        assert_eq!(
+            machine.to_macro_source("Task").unwrap_err(),
+            "Input \"move\" is a Rust keyword"
+        );
+        assert_eq!(
+            machine.to_macro_source("Self").unwrap_err(),
+            "Machine name \"Self\" is a Rust keyword"
+        );
+    }
+}
@@ -0,0 +1,365 @@
+//! Editable JSON format for graphical editors and external tooling
+//!
+//! An [`EditorDocument`] describes a machine as plain data: its states and inputs with
+//! their descriptions, its transitions, and whatever an editor needs to keep around,
+//! such as node positions. Editors and tools can load a document, change it and hand it
+//! back without losing anything they don't understand, so the format is a stable
+//! round-trip target:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "name": "Door",
+//!   "initial": "Closed",
+//!   "states": [
+//!     { "name": "Closed", "description": "Nobody can pass", "layout": { "x": 0.0, "y": 0.0 } },
+//!     { "name": "Open" }
+//!   ],
+//!   "inputs": [{ "name": "Push" }, { "name": "Pull" }],
+//!   "transitions": [
+//!     { "from": "Closed", "input": "Push", "to": "Open" },
+//!     { "from": "Open", "input": "Pull", "to": "Closed" }
+//!   ],
+//!   "ignored": [{ "state": "Open", "input": "Push" }],
+//!   "metadata": { "author": "design team" }
+//! }
+//! ```
+//!
+//! Only `version`, `initial`, `states` and `inputs` are required. Documents with a newer
+//! `version` than [`EDITOR_FORMAT_VERSION`] are rejected rather than misread.
+
+use crate::core::StateMachine;
+use crate::dynamic::{
+    BuildError, DefinitionFormat, DynDefinition, DynStateMachine, DynTransitionDefinition,
+    LoadError,
+};
+use alloc::collections::BTreeMap;
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// Version of the editor format written by this crate
+pub const EDITOR_FORMAT_VERSION: u32 = 1;
+
+/// A machine definition in the editor format
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct EditorDocument {
+    /// Version of the format the document follows
+    pub version: u32,
+    /// Name of the machine
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Initial state
+    pub initial: String,
+    /// Declared states, in declaration order
+    pub states: Vec<EditorState>,
+    /// Declared inputs, in declaration order
+    pub inputs: Vec<EditorInput>,
+    /// Transitions, in declaration order
+    #[serde(default)]
+    pub transitions: Vec<EditorTransition>,
+    /// Inputs ignored by design in a state
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ignored: Vec<EditorRule>,
+    /// Inputs deferred in a state until the next state change
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deferred: Vec<EditorRule>,
+    /// Free-form data of editors and tools, kept as is
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, String>,
+}
+
+/// A state of an [`EditorDocument`]
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct EditorState {
+    /// Name of the state
+    pub name: String,
+    /// What the state means
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Where an editor draws the state
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub layout: Option<EditorLayout>,
+    /// Free-form data of editors and tools, kept as is
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, String>,
+}
+
+/// An input of an [`EditorDocument`]
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct EditorInput {
+    /// Name of the input
+    pub name: String,
+    /// What the input means
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Free-form data of editors and tools, kept as is
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, String>,
+}
+
+/// Position of a state in an editor, in editor units
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct EditorLayout {
+    /// Horizontal position
+    pub x: f64,
+    /// Vertical position
+    pub y: f64,
+}
+
+/// A transition of an [`EditorDocument`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct EditorTransition {
+    /// The source state
+    pub from: String,
+    /// The input that triggers the transition
+    pub input: String,
+    /// The destination state
+    pub to: String,
+    /// Whether the transition stays in the state without exiting and re-entering it
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub internal: bool,
+    /// Whether the transition is a saga compensation
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub compensation: bool,
+}
+
+/// A state and input pair of an [`EditorDocument`], for ignored and deferred inputs
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct EditorRule {
+    /// The state
+    pub state: String,
+    /// The input
+    pub input: String,
+}
+
+/// Check whether a flag is unset, to leave it out of the JSON
+fn is_false(flag: &bool) -> bool {
+    !flag
+}
+
+impl EditorDocument {
+    /// Describe a machine in the editor format
+    ///
+    /// Guards can't be represented and are left out, so guarded transitions appear as
+    /// plain transitions.
+    ///
+    /// # Arguments
+    /// - `name`: The name of the machine
+    pub fn from_machine<SM: StateMachine>(name: impl Into<String>) -> Self {
+        let states = SM::states();
+        let inputs = SM::inputs();
+
+        let mut transitions = Vec::new();
+        let mut ignored = Vec::new();
+        let mut deferred = Vec::new();
+        for state in &states {
+            for input in SM::valid_inputs_iter(state) {
+                if let Some(to) = SM::next_state(state, &input) {
+                    transitions.push(EditorTransition {
+                        from: SM::state_name(state),
+                        input: SM::input_name(&input),
+                        to: SM::state_name(&to),
+                        internal: SM::is_internal(state, &input),
+                        compensation: SM::is_compensation(state, &input),
+                    });
+                }
+            }
+            for input in &inputs {
+                let rule = || EditorRule {
+                    state: SM::state_name(state),
+                    input: SM::input_name(input),
+                };
+                if SM::is_ignored(state, input) {
+                    ignored.push(rule());
+                } else if SM::is_deferred(state, input) {
+                    deferred.push(rule());
+                }
+            }
+        }
+
+        Self {
+            version: EDITOR_FORMAT_VERSION,
+            name: Some(name.into()),
+            initial: SM::state_name(&SM::initial_state()),
+            states: states
+                .iter()
+                .map(|state| EditorState {
+                    name: SM::state_name(state),
+                    description: SM::state_description(state),
+                    ..EditorState::default()
+                })
+                .collect(),
+            inputs: inputs
+                .iter()
+                .map(|input| EditorInput {
+                    name: SM::input_name(input),
+                    description: SM::input_description(input),
+                    ..EditorInput::default()
+                })
+                .collect(),
+            transitions,
+            ignored,
+            deferred,
+            metadata: BTreeMap::new(),
+        }
+    }
+
+    /// Write the document as pretty-printed JSON
+    pub fn to_editor_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("editor documents always serialize")
+    }
+
+    /// Read a document from JSON
+    ///
+    /// # Returns
+    /// - `Ok(document)`: The text follows the format
+    /// - `Err(error)`: [`LoadError::Parse`] if the text is malformed,
+    ///   [`LoadError::UnsupportedVersion`] if it follows a newer version of the format
+    pub fn from_editor_json(text: &str) -> Result<Self, LoadError> {
+        let document: Self =
+            serde_json::from_str(text).map_err(|error| LoadError::Parse(error.to_string()))?;
+        if document.version > EDITOR_FORMAT_VERSION {
+            return Err(LoadError::UnsupportedVersion(document.version));
+        }
+        Ok(document)
+    }
+
+    /// Get the states, inputs and transitions of the document as a [`DynDefinition`]
+    ///
+    /// Runtime-built machines have no notion of ignored or deferred inputs, internal
+    /// transitions or compensations, so these are left out or become plain transitions.
+    pub fn to_definition(&self) -> DynDefinition {
+        DynDefinition {
+            states: self.states.iter().map(|state| state.name.clone()).collect(),
+            inputs: self.inputs.iter().map(|input| input.name.clone()).collect(),
+            initial: self.initial.clone(),
+            transitions: self
+                .transitions
+                .iter()
+                .map(|transition| DynTransitionDefinition {
+                    from: transition.from.clone(),
+                    input: transition.input.clone(),
+                    to: transition.to.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Validate the document and build a runtime state machine from it
+    ///
+    /// # Returns
+    /// - `Ok(machine)`: The document is consistent
+    /// - `Err(error)`: See [`StateMachineBuilder::build`](crate::StateMachineBuilder::build)
+    pub fn build(&self) -> Result<DynStateMachine, BuildError<String, String>> {
+        self.to_definition().build()
+    }
+}
+
+impl DynStateMachine {
+    /// Load a machine from a document in the editor format
+    ///
+    /// Shorthand for [`DynStateMachine::from_str`] with [`DefinitionFormat::EditorJson`].
+    pub fn from_editor_json(text: &str) -> Result<Self, LoadError> {
+        Self::from_str(DefinitionFormat::EditorJson, text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    define_state_machine! {
+        name: Door,
+        states: {
+            /// Nobody can pass
+            Closed,
+            Open,
+            Locked
+        },
+        inputs: { Push, Pull, Lock, Knock },
+        initial: Closed,
+        transitions: {
+            Closed + Push => Open,
+            Open + Pull => Closed,
+            Closed + Lock => Locked,
+            Closed + Knock => internal,
+            Locked + Push => compensate Closed,
+            Open ignores Push,
+            Locked defers Pull,
+        }
+    }
+
+    #[test]
+    fn test_editor_round_trip() {
+        let mut document = EditorDocument::from_machine::<Door>("Door");
+        assert_eq!(document.version, EDITOR_FORMAT_VERSION);
+        assert_eq!(
+            document.states[0].description.as_deref(),
+            Some("Nobody can pass")
+        );
+        assert_eq!(document.transitions.len(), 5);
+        let knock = document.transitions.iter().find(|t| t.input == "Knock");
+        assert!(knock.unwrap().internal);
+        let unlock = document.transitions.iter().find(|t| t.from == "Locked");
+        assert!(unlock.unwrap().compensation);
+        assert_eq!(
+            document.ignored,
+            vec![EditorRule {
+                state: "Open".into(),
+                input: "Push".into()
+            }]
+        );
+        assert_eq!(document.deferred.len(), 1);
+
+        // Editor data survives the round trip
+        document.states[1].layout = Some(EditorLayout { x: 120.0, y: 40.5 });
+        document.states[1]
+            .metadata
+            .insert("color".into(), "green".into());
+        document.metadata.insert("author".into(), "design".into());
+        let json = document.to_editor_json();
+        assert!(json.contains("\"layout\""));
+        assert!(!json.contains("\"internal\": false"));
+        assert_eq!(EditorDocument::from_editor_json(&json).unwrap(), document);
+
+        let machine = DynStateMachine::from_editor_json(&json).unwrap();
+        assert_eq!(machine.states().len(), 3);
+        assert_eq!(
+            machine.next_state(&"Closed".to_string(), &"Push".to_string()),
+            Some(&"Open".to_string())
+        );
+    }
+
+    #[test]
+    fn test_editor_minimal_and_errors() {
+        let json = r#"{
+            "version": 1,
+            "initial": "Idle",
+            "states": [{ "name": "Idle" }, { "name": "Busy" }],
+            "inputs": [{ "name": "Start" }],
+            "transitions": [{ "from": "Idle", "input": "Start", "to": "Busy" }]
+        }"#;
+        let document = EditorDocument::from_editor_json(json).unwrap();
+        assert_eq!(document.name, None);
+        assert!(document.build().is_ok());
+
+        let newer = json.replace("\"version\": 1", "\"version\": 2");
+        assert_eq!(
+            EditorDocument::from_editor_json(&newer),
+            Err(LoadError::UnsupportedVersion(2))
+        );
+        assert!(matches!(
+            EditorDocument::from_editor_json("{}"),
+            Err(LoadError::Parse(_))
+        ));
+
+        let unknown = json.replace("\"to\": \"Busy\"", "\"to\": \"Done\"");
+        assert_eq!(
+            DynStateMachine::from_editor_json(&unknown).unwrap_err(),
+            LoadError::Build(BuildError::UnknownState("Done".into()))
+        );
+    }
+}
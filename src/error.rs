@@ -0,0 +1,581 @@
+use crate::core::StateMachine;
+use alloc::string::String;
+
+/// Error returned when a state transition fails
+///
+/// Every variant carries the state the instance was in and the input that was
+/// rejected, so callers can match on the failure kind instead of parsing messages.
+pub enum TransitionError<SM: StateMachine> {
+    /// The input is not valid in the current state
+    InvalidInput {
+        /// The current state
+        state: SM::State,
+        /// The rejected input
+        input: SM::Input,
+    },
+    /// The input is valid but the machine defines no next state for it
+    NoTransition {
+        /// The current state
+        state: SM::State,
+        /// The rejected input
+        input: SM::Input,
+    },
+    /// The transition's guard rejected the instance context
+    GuardRejected {
+        /// The current state
+        state: SM::State,
+        /// The rejected input
+        input: SM::Input,
+    },
+    /// The instance is in a final state and takes no further input
+    MachineCompleted {
+        /// The final state
+        state: SM::State,
+        /// The rejected input
+        input: SM::Input,
+    },
+    /// A before-transition callback vetoed the transition
+    Vetoed {
+        /// The current state
+        state: SM::State,
+        /// The rejected input
+        input: SM::Input,
+        /// The state the transition would have led to
+        target: SM::State,
+        /// The reason given by the callback
+        reason: String,
+    },
+    /// An entry validator of the target state rejected the transition
+    EntryRejected {
+        /// The current state
+        state: SM::State,
+        /// The rejected input
+        input: SM::Input,
+        /// The state that could not be entered
+        target: SM::State,
+        /// The reason given by the validator
+        reason: String,
+    },
+    /// The transition log failed to append the transition
+    LogFailed {
+        /// The current state
+        state: SM::State,
+        /// The rejected input
+        input: SM::Input,
+        /// The state the transition would have led to
+        target: SM::State,
+        /// The reason given by the log
+        reason: String,
+    },
+    /// An invariant of the instance failed after the transition
+    ///
+    /// The transition has taken effect: the instance is in `target`.
+    InvariantViolated {
+        /// The state the transition left
+        state: SM::State,
+        /// The applied input
+        input: SM::Input,
+        /// The state the instance is now in
+        target: SM::State,
+        /// The name of the failed invariant
+        reason: String,
+    },
+}
+
+impl<SM: StateMachine> TransitionError<SM> {
+    /// Get the state the instance was in when the transition failed
+    pub fn state(&self) -> &SM::State {
+        match self {
+            Self::InvalidInput { state, .. }
+            | Self::NoTransition { state, .. }
+            | Self::GuardRejected { state, .. }
+            | Self::MachineCompleted { state, .. }
+            | Self::Vetoed { state, .. }
+            | Self::EntryRejected { state, .. }
+            | Self::LogFailed { state, .. }
+            | Self::InvariantViolated { state, .. } => state,
+        }
+    }
+
+    /// Check whether the transition took effect despite the error
+    ///
+    /// Only [`InvariantViolated`](Self::InvariantViolated) is reported after the
+    /// transition was committed; every other error leaves the instance unchanged.
+    pub fn took_effect(&self) -> bool {
+        matches!(self, Self::InvariantViolated { .. })
+    }
+
+    /// Get the input that was rejected
+    pub fn input(&self) -> &SM::Input {
+        match self {
+            Self::InvalidInput { input, .. }
+            | Self::NoTransition { input, .. }
+            | Self::GuardRejected { input, .. }
+            | Self::MachineCompleted { input, .. }
+            | Self::Vetoed { input, .. }
+            | Self::EntryRejected { input, .. }
+            | Self::LogFailed { input, .. }
+            | Self::InvariantViolated { input, .. } => input,
+        }
+    }
+}
+
+impl<SM: StateMachine> core::fmt::Display for TransitionError<SM> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::InvalidInput { state, input } => {
+                write!(f, "Invalid input {input:?} for state {state:?}")
+            }
+            Self::NoTransition { state, input } => {
+                write!(
+                    f,
+                    "No valid transition from state {state:?} with input {input:?}"
+                )
+            }
+            Self::GuardRejected { state, input } => {
+                write!(f, "Guard rejected input {input:?} for state {state:?}")
+            }
+            Self::MachineCompleted { state, input } => {
+                write!(
+                    f,
+                    "Machine completed in final state {state:?}, input {input:?} not accepted"
+                )
+            }
+            Self::Vetoed {
+                state,
+                target,
+                reason,
+                ..
+            } => {
+                write!(
+                    f,
+                    "Transition from state {state:?} to {target:?} vetoed: {reason}"
+                )
+            }
+            Self::EntryRejected { target, reason, .. } => {
+                write!(f, "Entry into state {target:?} rejected: {reason}")
+            }
+            Self::LogFailed {
+                state,
+                target,
+                reason,
+                ..
+            } => {
+                write!(
+                    f,
+                    "Transition from state {state:?} to {target:?} could not be logged: {reason}"
+                )
+            }
+            Self::InvariantViolated {
+                state,
+                target,
+                reason,
+                ..
+            } => {
+                write!(
+                    f,
+                    "Invariant {reason} violated by transition from state {state:?} to {target:?}"
+                )
+            }
+        }
+    }
+}
+
+impl<SM: StateMachine> core::fmt::Debug for TransitionError<SM> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::InvalidInput { state, input } => f
+                .debug_struct("InvalidInput")
+                .field("state", state)
+                .field("input", input)
+                .finish(),
+            Self::NoTransition { state, input } => f
+                .debug_struct("NoTransition")
+                .field("state", state)
+                .field("input", input)
+                .finish(),
+            Self::GuardRejected { state, input } => f
+                .debug_struct("GuardRejected")
+                .field("state", state)
+                .field("input", input)
+                .finish(),
+            Self::MachineCompleted { state, input } => f
+                .debug_struct("MachineCompleted")
+                .field("state", state)
+                .field("input", input)
+                .finish(),
+            Self::Vetoed {
+                state,
+                input,
+                target,
+                reason,
+            } => f
+                .debug_struct("Vetoed")
+                .field("state", state)
+                .field("input", input)
+                .field("target", target)
+                .field("reason", reason)
+                .finish(),
+            Self::EntryRejected {
+                state,
+                input,
+                target,
+                reason,
+            } => f
+                .debug_struct("EntryRejected")
+                .field("state", state)
+                .field("input", input)
+                .field("target", target)
+                .field("reason", reason)
+                .finish(),
+            Self::LogFailed {
+                state,
+                input,
+                target,
+                reason,
+            } => f
+                .debug_struct("LogFailed")
+                .field("state", state)
+                .field("input", input)
+                .field("target", target)
+                .field("reason", reason)
+                .finish(),
+            Self::InvariantViolated {
+                state,
+                input,
+                target,
+                reason,
+            } => f
+                .debug_struct("InvariantViolated")
+                .field("state", state)
+                .field("input", input)
+                .field("target", target)
+                .field("reason", reason)
+                .finish(),
+        }
+    }
+}
+
+impl<SM: StateMachine> Clone for TransitionError<SM> {
+    fn clone(&self) -> Self {
+        match self {
+            Self::InvalidInput { state, input } => Self::InvalidInput {
+                state: state.clone(),
+                input: input.clone(),
+            },
+            Self::NoTransition { state, input } => Self::NoTransition {
+                state: state.clone(),
+                input: input.clone(),
+            },
+            Self::GuardRejected { state, input } => Self::GuardRejected {
+                state: state.clone(),
+                input: input.clone(),
+            },
+            Self::MachineCompleted { state, input } => Self::MachineCompleted {
+                state: state.clone(),
+                input: input.clone(),
+            },
+            Self::Vetoed {
+                state,
+                input,
+                target,
+                reason,
+            } => Self::Vetoed {
+                state: state.clone(),
+                input: input.clone(),
+                target: target.clone(),
+                reason: reason.clone(),
+            },
+            Self::EntryRejected {
+                state,
+                input,
+                target,
+                reason,
+            } => Self::EntryRejected {
+                state: state.clone(),
+                input: input.clone(),
+                target: target.clone(),
+                reason: reason.clone(),
+            },
+            Self::LogFailed {
+                state,
+                input,
+                target,
+                reason,
+            } => Self::LogFailed {
+                state: state.clone(),
+                input: input.clone(),
+                target: target.clone(),
+                reason: reason.clone(),
+            },
+            Self::InvariantViolated {
+                state,
+                input,
+                target,
+                reason,
+            } => Self::InvariantViolated {
+                state: state.clone(),
+                input: input.clone(),
+                target: target.clone(),
+                reason: reason.clone(),
+            },
+        }
+    }
+}
+
+impl<SM: StateMachine> PartialEq for TransitionError<SM> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (
+                Self::InvalidInput { state, input },
+                Self::InvalidInput {
+                    state: other_state,
+                    input: other_input,
+                },
+            )
+            | (
+                Self::NoTransition { state, input },
+                Self::NoTransition {
+                    state: other_state,
+                    input: other_input,
+                },
+            )
+            | (
+                Self::GuardRejected { state, input },
+                Self::GuardRejected {
+                    state: other_state,
+                    input: other_input,
+                },
+            )
+            | (
+                Self::MachineCompleted { state, input },
+                Self::MachineCompleted {
+                    state: other_state,
+                    input: other_input,
+                },
+            ) => state == other_state && input == other_input,
+            (
+                Self::Vetoed {
+                    state,
+                    input,
+                    target,
+                    reason,
+                },
+                Self::Vetoed {
+                    state: other_state,
+                    input: other_input,
+                    target: other_target,
+                    reason: other_reason,
+                },
+            )
+            | (
+                Self::EntryRejected {
+                    state,
+                    input,
+                    target,
+                    reason,
+                },
+                Self::EntryRejected {
+                    state: other_state,
+                    input: other_input,
+                    target: other_target,
+                    reason: other_reason,
+                },
+            )
+            | (
+                Self::LogFailed {
+                    state,
+                    input,
+                    target,
+                    reason,
+                },
+                Self::LogFailed {
+                    state: other_state,
+                    input: other_input,
+                    target: other_target,
+                    reason: other_reason,
+                },
+            )
+            | (
+                Self::InvariantViolated {
+                    state,
+                    input,
+                    target,
+                    reason,
+                },
+                Self::InvariantViolated {
+                    state: other_state,
+                    input: other_input,
+                    target: other_target,
+                    reason: other_reason,
+                },
+            ) => {
+                state == other_state
+                    && input == other_input
+                    && target == other_target
+                    && reason == other_reason
+            }
+            _ => false,
+        }
+    }
+}
+
+impl<SM: StateMachine> Eq for TransitionError<SM> {}
+
+impl<SM: StateMachine> core::error::Error for TransitionError<SM> {}
+
+/// Error returned when a transition is requested by name or from serialized data
+///
+/// Distinguishes input that could not be turned into an `Input` value from a
+/// transition that was attempted and failed.
+pub enum TryTransitionError<SM: StateMachine> {
+    /// No input has the given name
+    UnknownInput {
+        /// The name that was looked up
+        name: String,
+    },
+    /// The serialized input could not be parsed
+    MalformedInput {
+        /// The parser's error message
+        reason: String,
+    },
+    /// The input was parsed but the transition failed
+    Transition(TransitionError<SM>),
+}
+
+impl<SM: StateMachine> From<TransitionError<SM>> for TryTransitionError<SM> {
+    fn from(error: TransitionError<SM>) -> Self {
+        Self::Transition(error)
+    }
+}
+
+impl<SM: StateMachine> core::fmt::Display for TryTransitionError<SM> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::UnknownInput { name } => write!(f, "Unknown input: {name}"),
+            Self::MalformedInput { reason } => write!(f, "Malformed input: {reason}"),
+            Self::Transition(error) => error.fmt(f),
+        }
+    }
+}
+
+impl<SM: StateMachine> core::fmt::Debug for TryTransitionError<SM> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::UnknownInput { name } => {
+                f.debug_struct("UnknownInput").field("name", name).finish()
+            }
+            Self::MalformedInput { reason } => f
+                .debug_struct("MalformedInput")
+                .field("reason", reason)
+                .finish(),
+            Self::Transition(error) => f.debug_tuple("Transition").field(error).finish(),
+        }
+    }
+}
+
+impl<SM: StateMachine> Clone for TryTransitionError<SM> {
+    fn clone(&self) -> Self {
+        match self {
+            Self::UnknownInput { name } => Self::UnknownInput { name: name.clone() },
+            Self::MalformedInput { reason } => Self::MalformedInput {
+                reason: reason.clone(),
+            },
+            Self::Transition(error) => Self::Transition(error.clone()),
+        }
+    }
+}
+
+impl<SM: StateMachine> PartialEq for TryTransitionError<SM> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::UnknownInput { name }, Self::UnknownInput { name: other_name }) => {
+                name == other_name
+            }
+            (
+                Self::MalformedInput { reason },
+                Self::MalformedInput {
+                    reason: other_reason,
+                },
+            ) => reason == other_reason,
+            (Self::Transition(error), Self::Transition(other_error)) => error == other_error,
+            _ => false,
+        }
+    }
+}
+
+impl<SM: StateMachine> Eq for TryTransitionError<SM> {}
+
+impl<SM: StateMachine> core::error::Error for TryTransitionError<SM> {}
+
+/// Error returned when replaying an input log fails, see
+/// [`StateMachineInstance::replay`](crate::StateMachineInstance::replay)
+pub struct ReplayError<SM: StateMachine> {
+    /// Position of the failing input in the log
+    pub index: usize,
+    /// The error of the failing transition
+    pub error: TransitionError<SM>,
+}
+
+impl<SM: StateMachine> core::fmt::Display for ReplayError<SM> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "Replay failed at input {}: {}", self.index, self.error)
+    }
+}
+
+impl<SM: StateMachine> core::fmt::Debug for ReplayError<SM> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ReplayError")
+            .field("index", &self.index)
+            .field("error", &self.error)
+            .finish()
+    }
+}
+
+impl<SM: StateMachine> Clone for ReplayError<SM> {
+    fn clone(&self) -> Self {
+        Self {
+            index: self.index,
+            error: self.error.clone(),
+        }
+    }
+}
+
+impl<SM: StateMachine> PartialEq for ReplayError<SM> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index && self.error == other.error
+    }
+}
+
+impl<SM: StateMachine> Eq for ReplayError<SM> {}
+
+impl<SM: StateMachine> core::error::Error for ReplayError<SM> {}
+
+/// Error returned when parsing the name of a state or input that is not declared
+///
+/// Returned by the `FromStr` and `TryFrom<&str>` implementations generated for the
+/// `State` and `Input` enums.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseNameError {
+    /// No state has the given name
+    UnknownState {
+        /// The name that was parsed
+        name: String,
+    },
+    /// No input has the given name
+    UnknownInput {
+        /// The name that was parsed
+        name: String,
+    },
+}
+
+impl core::fmt::Display for ParseNameError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::UnknownState { name } => write!(f, "Invalid state: {name}"),
+            Self::UnknownInput { name } => write!(f, "Invalid input: {name}"),
+        }
+    }
+}
+
+impl core::error::Error for ParseNameError {}
@@ -0,0 +1,383 @@
+//! Failure injection for resilience tests of code driving a machine
+//!
+//! A [`FaultyInstance`] wraps an instance and makes selected transitions fail or stall
+//! according to a schedule of [`FaultRule`]s, so retry loops and error paths of the
+//! consuming code can be exercised without contriving real failures:
+//!
+//! ```ignore
+//! let mut order = FaultyInstance::new(StateMachineInstance::<Order>::new());
+//! // The first two payment attempts are vetoed, the third one goes through
+//! order.inject(FaultRule::new(Fault::Veto("gateway down".into())).on_input(Input::Pay).times(2));
+//! assert_eq!(pay_with_retries(&mut order, 3), Ok(State::Paid));
+//! ```
+
+use crate::core::StateMachine;
+use crate::error::TransitionError;
+use crate::instance::StateMachineInstance;
+use std::time::Duration;
+
+/// What happens to a transition hit by a [`FaultRule`]
+///
+/// Injected failures only take forms the instance can produce on its own: a transition
+/// that would take effect fails as if a before-transition callback had vetoed it or an
+/// entry validator had rejected the target state. Inputs that would fail or have no
+/// effect anyway are passed through untouched.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fault {
+    /// Fail with [`TransitionError::Vetoed`] and the given reason
+    Veto(String),
+    /// Fail with [`TransitionError::EntryRejected`] and the given reason
+    RejectEntry(String),
+    /// Block the calling thread for the given duration, then transition as usual
+    Delay(Duration),
+}
+
+/// Rule of a [`FaultyInstance`] schedule, selecting transitions to inject a fault into
+///
+/// A rule matches transitions by source state and input, both optional. Matches are
+/// counted from the moment the rule is injected: the first [`after`](Self::after)
+/// matches are spared, and the fault is injected into the following
+/// [`times`](Self::times) matches, or all of them if no limit is set.
+pub struct FaultRule<SM: StateMachine> {
+    /// The fault to inject
+    fault: Fault,
+    /// Source state the rule is restricted to
+    state: Option<SM::State>,
+    /// Input the rule is restricted to
+    input: Option<SM::Input>,
+    /// Number of matches spared before injecting
+    skip: usize,
+    /// Number of matches to inject into, unlimited if None
+    times: Option<usize>,
+    /// Number of matches so far
+    matched: usize,
+}
+
+impl<SM: StateMachine> FaultRule<SM> {
+    /// Create a rule injecting a fault into every transition
+    pub fn new(fault: Fault) -> Self {
+        Self {
+            fault,
+            state: None,
+            input: None,
+            skip: 0,
+            times: None,
+            matched: 0,
+        }
+    }
+
+    /// Restrict the rule to transitions from a state
+    pub fn in_state(mut self, state: SM::State) -> Self {
+        self.state = Some(state);
+        self
+    }
+
+    /// Restrict the rule to transitions triggered by an input
+    pub fn on_input(mut self, input: SM::Input) -> Self {
+        self.input = Some(input);
+        self
+    }
+
+    /// Spare the first `matches` matching transitions
+    pub fn after(mut self, matches: usize) -> Self {
+        self.skip = matches;
+        self
+    }
+
+    /// Inject the fault into `matches` matching transitions only
+    pub fn times(mut self, matches: usize) -> Self {
+        self.times = Some(matches);
+        self
+    }
+
+    /// Check whether the rule selects a transition
+    fn matches(&self, state: &SM::State, input: &SM::Input) -> bool {
+        self.state.as_ref().is_none_or(|rule| rule == state)
+            && self.input.as_ref().is_none_or(|rule| rule == input)
+    }
+
+    /// Check whether the rule will inject into no further transition
+    fn is_exhausted(&self) -> bool {
+        self.times
+            .is_some_and(|times| self.matched >= self.skip + times)
+    }
+
+    /// Count a matching transition
+    ///
+    /// # Returns
+    /// Returns true if the fault is to be injected into it
+    fn record_match(&mut self) -> bool {
+        let index = self.matched;
+        self.matched += 1;
+        index >= self.skip && self.times.is_none_or(|times| index < self.skip + times)
+    }
+}
+
+impl<SM: StateMachine> Clone for FaultRule<SM> {
+    fn clone(&self) -> Self {
+        Self {
+            fault: self.fault.clone(),
+            state: self.state.clone(),
+            input: self.input.clone(),
+            skip: self.skip,
+            times: self.times,
+            matched: self.matched,
+        }
+    }
+}
+
+impl<SM: StateMachine> std::fmt::Debug for FaultRule<SM> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FaultRule")
+            .field("fault", &self.fault)
+            .field("state", &self.state)
+            .field("input", &self.input)
+            .field("skip", &self.skip)
+            .field("times", &self.times)
+            .field("matched", &self.matched)
+            .finish()
+    }
+}
+
+/// Instance wrapper injecting faults into transitions, for tests
+///
+/// Rules are consulted in the order they were injected. Every rule matching a
+/// transition counts it, and the first rule that selects it decides the fault.
+/// Transitions must go through the wrapper to be affected.
+pub struct FaultyInstance<SM: StateMachine> {
+    /// The wrapped instance
+    instance: StateMachineInstance<SM>,
+    /// The schedule of faults
+    rules: Vec<FaultRule<SM>>,
+    /// Number of faults injected so far
+    injected: usize,
+}
+
+impl<SM: StateMachine> FaultyInstance<SM> {
+    /// Wrap an instance, with no fault scheduled
+    pub fn new(instance: StateMachineInstance<SM>) -> Self {
+        Self {
+            instance,
+            rules: Vec::new(),
+            injected: 0,
+        }
+    }
+
+    /// Add a rule to the schedule
+    pub fn inject(&mut self, rule: FaultRule<SM>) -> &mut Self {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Remove all rules, so later transitions behave normally
+    pub fn clear_faults(&mut self) {
+        self.rules.clear();
+    }
+
+    /// Check whether any rule can still inject a fault
+    pub fn has_pending_faults(&self) -> bool {
+        self.rules.iter().any(|rule| !rule.is_exhausted())
+    }
+
+    /// Get the number of faults injected so far, delays included
+    pub fn injected(&self) -> usize {
+        self.injected
+    }
+
+    /// Get a read-only reference to the wrapped instance
+    pub fn instance(&self) -> &StateMachineInstance<SM> {
+        &self.instance
+    }
+
+    /// Get a mutable reference to the wrapped instance, e.g. to register callbacks
+    ///
+    /// Transitions made through it bypass the schedule.
+    pub fn instance_mut(&mut self) -> &mut StateMachineInstance<SM> {
+        &mut self.instance
+    }
+
+    /// Unwrap the instance, dropping the schedule
+    pub fn into_inner(self) -> StateMachineInstance<SM> {
+        self.instance
+    }
+
+    /// Get the current state of the wrapped instance
+    pub fn current_state(&self) -> &SM::State {
+        self.instance.current_state()
+    }
+
+    /// Execute a state transition, injecting the scheduled fault if any
+    ///
+    /// # Returns
+    /// See [`StateMachineInstance::transition`]; injected failures leave the instance
+    /// unchanged
+    pub fn transition(&mut self, input: SM::Input) -> Result<SM::State, TransitionError<SM>> {
+        let Some(target) = self.effective_target(&input) else {
+            return self.instance.transition(input);
+        };
+
+        let state = self.instance.current_state().clone();
+        let mut fault = None;
+        for rule in &mut self.rules {
+            if rule.matches(&state, &input) && rule.record_match() && fault.is_none() {
+                fault = Some(rule.fault.clone());
+            }
+        }
+
+        let Some(fault) = fault else {
+            return self.instance.transition(input);
+        };
+        self.injected += 1;
+        match fault {
+            Fault::Veto(reason) => Err(TransitionError::Vetoed {
+                state,
+                input,
+                target,
+                reason,
+            }),
+            Fault::RejectEntry(reason) => Err(TransitionError::EntryRejected {
+                state,
+                input,
+                target,
+                reason,
+            }),
+            Fault::Delay(delay) => {
+                std::thread::sleep(delay);
+                self.instance.transition(input)
+            }
+        }
+    }
+
+    /// Get the state an input leads to if it would take effect, before callbacks run
+    fn effective_target(&self, input: &SM::Input) -> Option<SM::State> {
+        let state = self.instance.current_state();
+        if SM::is_ignored(state, input)
+            || SM::is_deferred(state, input)
+            || !self.instance.can_accept(input)
+            || !SM::check_guard(
+                state,
+                input,
+                self.instance.context(),
+                self.instance.config(),
+            )
+        {
+            return None;
+        }
+        SM::next_state(state, input)
+    }
+}
+
+impl<SM: StateMachine> std::fmt::Debug for FaultyInstance<SM> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FaultyInstance")
+            .field("instance", &self.instance)
+            .field("rules", &self.rules)
+            .field("injected", &self.injected)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    define_state_machine! {
+        name: Order,
+        states: { Created, Paid, Shipped },
+        inputs: { Pay, Ship, Cancel },
+        initial: Created,
+        transitions: {
+            Created + Pay => Paid,
+            Paid + Ship => Shipped,
+            Shipped ignores Cancel,
+        }
+    }
+
+    /// Consumer code under test: retry payment a few times
+    fn pay_with_retries(
+        order: &mut FaultyInstance<Order>,
+        attempts: usize,
+    ) -> Result<State, TransitionError<Order>> {
+        let mut result = order.transition(Input::Pay);
+        for _ in 1..attempts {
+            if result.is_ok() {
+                break;
+            }
+            result = order.transition(Input::Pay);
+        }
+        result
+    }
+
+    #[test]
+    fn test_fault_schedule() {
+        let mut order = FaultyInstance::new(StateMachineInstance::<Order>::new());
+        order.inject(
+            FaultRule::new(Fault::Veto("gateway down".into()))
+                .on_input(Input::Pay)
+                .times(2),
+        );
+        assert!(order.has_pending_faults());
+
+        let error = pay_with_retries(&mut order, 2).unwrap_err();
+        assert_eq!(
+            error,
+            TransitionError::Vetoed {
+                state: State::Created,
+                input: Input::Pay,
+                target: State::Paid,
+                reason: "gateway down".into(),
+            }
+        );
+        assert_eq!(*order.current_state(), State::Created);
+        assert!(order.instance().history_is_empty());
+
+        assert_eq!(pay_with_retries(&mut order, 2), Ok(State::Paid));
+        assert_eq!(order.injected(), 2);
+        assert!(!order.has_pending_faults());
+    }
+
+    #[test]
+    fn test_fault_kinds_and_pass_through() {
+        let mut order = FaultyInstance::new(StateMachineInstance::<Order>::new());
+        order
+            .inject(
+                FaultRule::new(Fault::RejectEntry("warehouse closed".into()))
+                    .in_state(State::Paid)
+                    .after(1),
+            )
+            .inject(FaultRule::new(Fault::Delay(Duration::from_millis(1))));
+
+        // Invalid inputs fail as usual and aren't counted
+        assert!(matches!(
+            order.transition(Input::Ship),
+            Err(TransitionError::InvalidInput { .. })
+        ));
+        assert_eq!(order.injected(), 0);
+
+        // Delayed, then applied
+        assert_eq!(order.transition(Input::Pay), Ok(State::Paid));
+        assert_eq!(order.injected(), 1);
+
+        // The first match in Paid is spared by the entry rule and delayed instead
+        assert_eq!(order.transition(Input::Ship), Ok(State::Shipped));
+        assert_eq!(order.injected(), 2);
+        order.instance_mut().undo();
+
+        assert_eq!(
+            order.transition(Input::Ship),
+            Err(TransitionError::EntryRejected {
+                state: State::Paid,
+                input: Input::Ship,
+                target: State::Shipped,
+                reason: "warehouse closed".into(),
+            })
+        );
+
+        order.clear_faults();
+        assert_eq!(order.transition(Input::Ship), Ok(State::Shipped));
+        // Ignored inputs have no effect to fail
+        assert_eq!(order.transition(Input::Cancel), Ok(State::Shipped));
+        assert_eq!(order.injected(), 3);
+    }
+}
@@ -0,0 +1,108 @@
+//! Hash maps and sets used internally
+//!
+//! By default they use [`RandomState`], whose per-process random keys protect servers
+//! fed with untrusted states or inputs against hash flooding. With the `deterministic`
+//! feature they use [`FnvHasher`] instead, so that iteration order, and with it the
+//! output of queries such as [`reachable_states`](crate::StateMachineQuery::reachable_states)
+//! and of the documentation generators, is reproducible across runs. Unlike the standard
+//! library's `DefaultHasher`, whose algorithm may change between Rust releases, FNV-1a
+//! is fixed.
+//!
+//! Without the `std` feature there is no source of random keys, so the maps and sets
+//! come from `hashbrown` and always use [`FnvHasher`].
+//!
+//! [`RandomState`]: std::collections::hash_map::RandomState
+
+/// Hasher builder of the internal maps and sets
+#[cfg(all(feature = "std", not(feature = "deterministic")))]
+pub type BuildStateHasher = std::collections::hash_map::RandomState;
+
+/// Hasher builder of the internal maps and sets
+#[cfg(any(not(feature = "std"), feature = "deterministic"))]
+pub type BuildStateHasher = core::hash::BuildHasherDefault<FnvHasher>;
+
+/// 64-bit FNV-1a hasher, stable across Rust versions
+///
+/// Also behind [`definition_hash`](crate::StateMachine::definition_hash). Integers are
+/// hashed in native byte order, so map order may still differ between platforms of
+/// different endianness.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FnvHasher(u64);
+
+impl Default for FnvHasher {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl core::hash::Hasher for FnvHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Hash map using [`BuildStateHasher`]
+#[cfg(feature = "std")]
+pub type HashMap<K, V> = std::collections::HashMap<K, V, BuildStateHasher>;
+
+/// Hash map using [`BuildStateHasher`]
+#[cfg(not(feature = "std"))]
+pub type HashMap<K, V> = hashbrown::HashMap<K, V, BuildStateHasher>;
+
+/// Hash set using [`BuildStateHasher`]
+#[cfg(feature = "std")]
+pub type HashSet<T> = std::collections::HashSet<T, BuildStateHasher>;
+
+/// Hash set using [`BuildStateHasher`]
+#[cfg(not(feature = "std"))]
+pub type HashSet<T> = hashbrown::HashSet<T, BuildStateHasher>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "deterministic")]
+    #[test]
+    fn test_fixed_keys() {
+        use std::hash::BuildHasher;
+
+        assert_eq!(
+            BuildStateHasher::default().hash_one("Closed"),
+            BuildStateHasher::default().hash_one("Closed")
+        );
+
+        // Separately built sets iterate alike, unlike with per-instance random keys
+        let a: HashSet<_> = (0..100).collect();
+        let b: HashSet<_> = (0..100).collect();
+        assert!(a.iter().eq(b.iter()));
+    }
+
+    #[test]
+    fn test_fnv_hasher() {
+        use std::hash::Hasher;
+
+        // Reference values of 64-bit FNV-1a
+        let hash = |bytes: &[u8]| {
+            let mut hasher = FnvHasher::default();
+            hasher.write(bytes);
+            hasher.finish()
+        };
+        assert_eq!(hash(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(hash(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn test_aliases() {
+        let mut map: HashMap<&str, u32> = HashMap::default();
+        map.insert("Open", 1);
+        assert_eq!(map["Open"], 1);
+    }
+}
@@ -0,0 +1,64 @@
+use crate::core::StateMachine;
+
+/// Why an instance looks stuck, as reported by [`HealthReport::stuck`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StuckReason {
+    /// The instance has stayed in a non-terminal state for longer than the limit set
+    /// with [`set_stuck_after`](crate::StateMachineInstance::set_stuck_after)
+    Idle,
+    /// The guards of all valid inputs reject the current context and configuration,
+    /// so no transition can fire until the context changes
+    GuardsClosed,
+}
+
+/// Snapshot of an instance's health, as returned by
+/// [`StateMachineInstance::health`](crate::StateMachineInstance::health)
+///
+/// Collects what a service would expose from a health endpoint in one serializable
+/// value. Times are in milliseconds.
+///
+/// # Example
+/// ```ignore
+/// fn healthz(order: &StateMachineInstance<Order>) -> (u16, String) {
+///     let report = order.health();
+///     let status = if report.stuck.is_some() { 503 } else { 200 };
+///     (status, serde_json::to_string(&report).unwrap())
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(
+        serialize = "SM::State: serde::Serialize",
+        deserialize = "SM::State: serde::Deserialize<'de>"
+    ))
+)]
+pub struct HealthReport<SM: StateMachine> {
+    /// The current state
+    pub state: SM::State,
+    /// How long the instance has been in the current state
+    pub time_in_state_ms: u64,
+    /// When the last transition took effect, in milliseconds since the Unix epoch
+    ///
+    /// None if no transition took effect since the instance was created or reset.
+    pub last_transition_at: Option<u64>,
+    /// Number of transitions rejected since the current state was entered
+    pub recent_rejections: usize,
+    /// Number of inputs waiting in the input queue
+    pub queue_depth: usize,
+    /// Number of inputs set aside until the next state change
+    pub deferred_count: usize,
+    /// Whether the current state is terminal, i.e. accepts no inputs
+    pub terminal: bool,
+    /// Why the instance looks stuck, if it does
+    pub stuck: Option<StuckReason>,
+}
+
+impl<SM: StateMachine> HealthReport<SM> {
+    /// Check whether the instance looks healthy, i.e. not stuck
+    pub fn is_healthy(&self) -> bool {
+        self.stuck.is_none()
+    }
+}
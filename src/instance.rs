@@ -1,44 +1,351 @@
 use crate::DEFAULT_MAX_HISTORY_SIZE;
-use crate::callbacks::CallbackRegistry;
+use crate::action::BoundAction;
+use crate::callbacks::{CallbackId, CallbackRegistry, TransitionEvent, TransitionOutcome};
 use crate::core::StateMachine;
-use std::collections::VecDeque;
+use crate::error::{ReplayError, TransitionError, TryTransitionError};
+use crate::health::{HealthReport, StuckReason};
+use crate::invariant::{InvariantPolicy, Invariants};
+use crate::metrics::TransitionMetrics;
+use crate::observer::{ErasedObserver, MachineEvent, Observer};
+use crate::persistence::{LogRecord, Snapshot, TransitionLog};
+use crate::queue::{InputQueue, InvalidInputPolicy};
+use crate::trace::TraceContext;
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::sync::mpsc;
+
+/// Get the current wall-clock time in milliseconds since the Unix epoch
+///
+/// Without the `std` feature there is no clock, and it returns 0.
+#[cfg(feature = "std")]
+pub(crate) fn unix_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+/// Get the current wall-clock time in milliseconds since the Unix epoch
+///
+/// Without the `std` feature there is no clock, and it returns 0.
+#[cfg(not(feature = "std"))]
+pub(crate) fn unix_millis() -> u64 {
+    0
+}
+
+/// Metadata kept alongside each history entry
+#[derive(Debug, Clone)]
+struct HistoryMeta {
+    /// Sequence number of the entry, unique for the lifetime of the instance
+    seq: u64,
+    /// Notes attached after the fact
+    annotations: Vec<String>,
+    /// When the state the entry started from was entered, in Unix milliseconds
+    entered_at: u64,
+    /// When the entry was recorded, in Unix milliseconds
+    exited_at: u64,
+    /// The trace context the input was applied under
+    trace: Option<TraceContext>,
+    /// Whether transitions left out of history took effect since the previous entry
+    follows_unrecorded: bool,
+}
+
+/// A history entry together with its metadata, as exported by
+/// [`StateMachineInstance::history_entries`]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(
+        serialize = "SM::State: serde::Serialize, SM::Input: serde::Serialize",
+        deserialize = "SM::State: serde::Deserialize<'de>, SM::Input: serde::Deserialize<'de>"
+    ))
+)]
+pub struct HistoryEntry<SM: StateMachine> {
+    /// Sequence number of the entry
+    pub seq: u64,
+    /// The state the transition started from
+    pub from: SM::State,
+    /// The input that triggered the transition
+    pub input: SM::Input,
+    /// Notes attached with [`StateMachineInstance::annotate_history`]
+    pub annotations: Vec<String>,
+    /// When `from` was entered, in milliseconds since the Unix epoch
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub entered_at: u64,
+    /// When the input was applied, in milliseconds since the Unix epoch
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub exited_at: u64,
+    /// The trace context passed to [`StateMachineInstance::transition_with_trace`]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub trace: Option<TraceContext>,
+    /// Whether transitions left out of history took effect between the previous
+    /// entry and this one, which stops [`StateMachineInstance::undo`] here
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub follows_unrecorded: bool,
+}
+
+impl<SM: StateMachine> HistoryEntry<SM> {
+    /// Get the correlation ID of the trace context the transition was made under
+    pub fn correlation_id(&self) -> Option<&str> {
+        self.trace.as_ref()?.correlation_id()
+    }
+}
+
+impl<SM: StateMachine> Clone for HistoryEntry<SM> {
+    fn clone(&self) -> Self {
+        Self {
+            seq: self.seq,
+            from: self.from.clone(),
+            input: self.input.clone(),
+            annotations: self.annotations.clone(),
+            entered_at: self.entered_at,
+            exited_at: self.exited_at,
+            trace: self.trace.clone(),
+            follows_unrecorded: self.follows_unrecorded,
+        }
+    }
+}
+
+impl<SM: StateMachine> PartialEq for HistoryEntry<SM> {
+    fn eq(&self, other: &Self) -> bool {
+        self.seq == other.seq
+            && self.from == other.from
+            && self.input == other.input
+            && self.annotations == other.annotations
+            && self.entered_at == other.entered_at
+            && self.exited_at == other.exited_at
+            && self.trace == other.trace
+            && self.follows_unrecorded == other.follows_unrecorded
+    }
+}
+
+impl<SM: StateMachine> core::fmt::Debug for HistoryEntry<SM> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("HistoryEntry")
+            .field("seq", &self.seq)
+            .field("from", &self.from)
+            .field("input", &self.input)
+            .field("annotations", &self.annotations)
+            .field("entered_at", &self.entered_at)
+            .field("exited_at", &self.exited_at)
+            .field("trace", &self.trace)
+            .field("follows_unrecorded", &self.follows_unrecorded)
+            .finish()
+    }
+}
+
+/// How [`StateMachineInstance::replay_with`] treats inputs of the log that fail
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ReplayMode {
+    /// Stop at the first failing input and report it
+    #[default]
+    Strict,
+    /// Skip failing inputs and carry on with the next one
+    Lenient,
+}
 
 /// State machine instance that can execute state transitions
 ///
 /// The state machine instance maintains the current state, transition history,
 /// and provides state transition operations. History is implemented using a ring buffer
 /// for automatic memory management. It also supports callbacks for state transitions.
-#[derive(Debug)]
 pub struct StateMachineInstance<SM: StateMachine> {
     /// Current state
     current_state: SM::State,
+    /// Extended state: guards read it, transition actions may mutate it
+    context: SM::Context,
+    /// Configuration: guards read it, it doesn't change after creation
+    config: Arc<SM::Config>,
     /// Transition history: sequence of (from_state, input) pairs
     history: VecDeque<(SM::State, SM::Input)>,
+    /// Metadata for each history entry, kept in lockstep with `history`
+    history_meta: VecDeque<HistoryMeta>,
+    /// Sequence number of the next history entry
+    next_seq: u64,
+    /// Whether transitions left out of history took effect since the last entry
+    unrecorded_since_last_entry: bool,
+    /// When the current state was entered, in Unix milliseconds
+    state_entered_at: u64,
+    /// When the last transition took effect, in Unix milliseconds
+    last_transition_at: Option<u64>,
+    /// Number of transitions rejected since the current state was entered
+    rejections_in_state: usize,
+    /// How long the instance may stay in a non-terminal state before it looks stuck
+    stuck_after: Option<Duration>,
     /// Maximum history size
     max_history_size: usize,
+    /// Whether ignored inputs are recorded in history
+    record_ignored: bool,
+    /// Whether self-loop transitions are left out of history
+    skip_self_loops_in_history: bool,
+    /// Whether internal transitions are left out of history
+    skip_internal_in_history: bool,
+    /// Inputs left out of history, in canonical form
+    inputs_ignored_in_history: Vec<SM::Input>,
+    /// Inputs waiting for `process_one`/`process_queue`
+    queue: InputQueue<SM>,
+    /// Failed inputs set aside until the next state change
+    deferred: VecDeque<SM::Input>,
+    /// What happens to queued inputs whose transition fails
+    invalid_input_policy: InvalidInputPolicy,
     /// Callback registry for state machine events
     callback_registry: CallbackRegistry<SM>,
+    /// Observer owned by the instance, receiving its events
+    observer: Option<Box<dyn ErasedObserver<SM>>>,
+    /// Channels of the subscribers to transition events
+    #[cfg(feature = "std")]
+    subscribers: Vec<mpsc::Sender<TransitionEvent<SM>>>,
+    /// Counters of transitions, stays and rejections, if collected
+    metrics: Option<TransitionMetrics<SM>>,
+    /// Write-ahead log of the transitions, if set
+    transition_log: Option<Box<dyn TransitionLog<SM>>>,
+    /// Sequence number of the next transition
+    transition_seq: u64,
+    /// Invariants checked after every transition
+    invariants: Invariants<SM>,
 }
 
 impl<SM: StateMachine> StateMachineInstance<SM> {
     /// Create a new state machine instance with default history size
-    pub fn new() -> Self {
+    pub fn new() -> Self
+    where
+        SM::Context: Default,
+        SM::Config: Default,
+    {
+        Self::with_context(SM::Context::default())
+    }
+
+    /// Create a new state machine instance with custom history size
+    pub fn with_max_history(max_size: usize) -> Self
+    where
+        SM::Context: Default,
+        SM::Config: Default,
+    {
+        let mut instance = Self::with_context(SM::Context::default());
+        instance.max_history_size = max_size;
+        instance.history.reserve(max_size);
+        instance
+    }
+
+    /// Create a new state machine instance with the given context
+    ///
+    /// Transition guards are evaluated against this context.
+    pub fn with_context(context: SM::Context) -> Self
+    where
+        SM::Config: Default,
+    {
+        Self::with_context_and_config(context, SM::Config::default())
+    }
+
+    /// Create a new state machine instance with the given configuration
+    ///
+    /// Transition guards can read the configuration, e.g. for retry limits or feature
+    /// toggles, without it being part of the mutable context.
+    ///
+    /// # Example
+    /// ```rust
+    /// use yasm::*;
+    /// pub struct Limits {
+    ///     pub max_retries: u32,
+    /// }
+    ///
+    /// define_state_machine! {
+    ///     name: Job,
+    ///     states: { Failed, Running },
+    ///     inputs: { Retry },
+    ///     initial: Failed,
+    ///     context: u32,
+    ///     config: Limits,
+    ///     transitions: {
+    ///         Failed + Retry => Running if |attempts, limits| *attempts < limits.max_retries
+    ///     }
+    /// }
+    ///
+    /// let mut job = StateMachineInstance::<Job>::with_config(Limits { max_retries: 3 });
+    /// assert_eq!(job.config().max_retries, 3);
+    /// assert_eq!(job.transition(Input::Retry), Ok(State::Running));
+    /// ```
+    pub fn with_config(config: SM::Config) -> Self
+    where
+        SM::Context: Default,
+    {
+        Self::with_context_and_config(SM::Context::default(), config)
+    }
+
+    /// Create a new state machine instance with the given context and configuration
+    pub fn with_context_and_config(context: SM::Context, config: SM::Config) -> Self {
         Self {
             current_state: SM::initial_state(),
+            context,
+            config: Arc::new(config),
             history: VecDeque::new(),
+            history_meta: VecDeque::new(),
+            next_seq: 0,
+            unrecorded_since_last_entry: false,
+            state_entered_at: unix_millis(),
+            last_transition_at: None,
+            rejections_in_state: 0,
+            stuck_after: None,
             max_history_size: DEFAULT_MAX_HISTORY_SIZE,
+            record_ignored: false,
+            skip_self_loops_in_history: false,
+            skip_internal_in_history: false,
+            inputs_ignored_in_history: Vec::new(),
+            queue: InputQueue::new(),
+            deferred: VecDeque::new(),
+            invalid_input_policy: InvalidInputPolicy::default(),
             callback_registry: CallbackRegistry::new(),
+            observer: None,
+            #[cfg(feature = "std")]
+            subscribers: Vec::new(),
+            metrics: None,
+            transition_log: None,
+            transition_seq: 0,
+            invariants: Invariants::new(),
         }
     }
 
-    /// Create a new state machine instance with custom history size
-    pub fn with_max_history(max_size: usize) -> Self {
-        Self {
-            current_state: SM::initial_state(),
-            history: VecDeque::with_capacity(max_size),
-            max_history_size: max_size,
-            callback_registry: CallbackRegistry::new(),
-        }
+    /// Get a read-only reference to the context
+    pub fn context(&self) -> &SM::Context {
+        &self.context
+    }
+
+    /// Get a mutable reference to the context
+    pub fn context_mut(&mut self) -> &mut SM::Context {
+        &mut self.context
+    }
+
+    /// Replace the context, returning the previous one
+    pub fn set_context(&mut self, context: SM::Context) -> SM::Context {
+        core::mem::replace(&mut self.context, context)
+    }
+
+    /// Get a read-only reference to the configuration
+    pub fn config(&self) -> &SM::Config {
+        &self.config
+    }
+
+    /// Get a shared handle to the configuration
+    ///
+    /// Callbacks and actions don't receive the configuration, so those that need it
+    /// can hold this handle instead of a copy of their own.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let config = job.shared_config();
+    /// job.on_state_entry(State::Failed, move |_| {
+    ///     if config.alerts_enabled {
+    ///         pager.notify("job failed");
+    ///     }
+    /// });
+    /// ```
+    pub fn shared_config(&self) -> Arc<SM::Config> {
+        Arc::clone(&self.config)
     }
 
     /// Get the maximum history size
@@ -51,39 +358,481 @@ impl<SM: StateMachine> StateMachineInstance<SM> {
         &self.current_state
     }
 
+    /// Check whether the instance has reached a final state
+    ///
+    /// A finished instance rejects every input with
+    /// [`TransitionError::MachineCompleted`].
+    pub fn is_finished(&self) -> bool {
+        SM::is_final(&self.current_state)
+    }
+
+    /// Check whether the current state is accepting
+    ///
+    /// The inputs applied so far form a word the machine accepts, see
+    /// [`StateMachineQuery::accepts`](crate::StateMachineQuery::accepts).
+    pub fn is_accepting(&self) -> bool {
+        SM::is_accepting(&self.current_state)
+    }
+
+    /// Get when the current state was entered, in milliseconds since the Unix epoch
+    ///
+    /// Self-loops and ignored inputs don't count as entering the state again.
+    pub fn state_entered_at(&self) -> u64 {
+        self.state_entered_at
+    }
+
     /// Get a read-only reference to the transition history
     pub fn history(&self) -> &VecDeque<(SM::State, SM::Input)> {
         &self.history
     }
 
-    /// Check if the given input is valid for the current state
+    /// Set whether ignored inputs are recorded in history
+    ///
+    /// Inputs declared with `ignores` are not recorded by default.
+    pub fn set_record_ignored(&mut self, record: bool) {
+        self.record_ignored = record;
+    }
+
+    /// Check if ignored inputs are recorded in history
+    pub fn records_ignored(&self) -> bool {
+        self.record_ignored
+    }
+
+    /// Set whether self-loop transitions are recorded in history
+    ///
+    /// Self-loops are recorded by default. Skipping them keeps high-frequency inputs
+    /// like heartbeats from pushing meaningful transitions out of the history buffer.
+    /// Callbacks still fire for skipped transitions.
+    pub fn set_skip_self_loops_in_history(&mut self, skip: bool) {
+        self.skip_self_loops_in_history = skip;
+    }
+
+    /// Check if self-loop transitions are left out of history
+    pub fn skips_self_loops_in_history(&self) -> bool {
+        self.skip_self_loops_in_history
+    }
+
+    /// Set whether internal transitions are recorded in history
+    ///
+    /// Internal transitions are recorded by default. Unlike
+    /// [`set_skip_self_loops_in_history`](Self::set_skip_self_loops_in_history), external
+    /// self-transitions are still recorded.
+    pub fn set_skip_internal_in_history(&mut self, skip: bool) {
+        self.skip_internal_in_history = skip;
+    }
+
+    /// Check if internal transitions are left out of history
+    pub fn skips_internal_in_history(&self) -> bool {
+        self.skip_internal_in_history
+    }
+
+    /// Leave the given inputs out of history
+    ///
+    /// Inputs carrying a payload are matched by kind, regardless of the payload.
+    /// Transitions triggered by these inputs still take effect and fire callbacks,
+    /// but [`undo`](Self::undo) cannot roll them back.
+    ///
+    /// # Arguments
+    /// - `inputs`: The inputs to leave out
+    pub fn ignore_in_history(&mut self, inputs: impl IntoIterator<Item = SM::Input>) {
+        for input in inputs {
+            let input = SM::canonical_input(&input);
+            if !self.inputs_ignored_in_history.contains(&input) {
+                self.inputs_ignored_in_history.push(input);
+            }
+        }
+    }
+
+    /// Get the inputs left out of history
+    pub fn inputs_ignored_in_history(&self) -> &[SM::Input] {
+        &self.inputs_ignored_in_history
+    }
+
+    /// Check if a transition should be recorded in history
+    fn records_in_history(&self, from: &SM::State, input: &SM::Input, to: &SM::State) -> bool {
+        if (self.skip_self_loops_in_history && from == to)
+            || (self.skip_internal_in_history && SM::is_internal(from, input))
+        {
+            return false;
+        }
+        self.inputs_ignored_in_history.is_empty()
+            || !self
+                .inputs_ignored_in_history
+                .contains(&SM::canonical_input(input))
+    }
+
+    /// Check if the given input is accepted in the current state
+    ///
+    /// Inputs that trigger a transition, inputs that are ignored by design and
+    /// inputs that are deferred are accepted. A finished instance accepts no input.
     pub fn can_accept(&self, input: &SM::Input) -> bool {
-        SM::valid_inputs(&self.current_state).contains(input)
+        let canonical = SM::canonical_input(input);
+        !self.is_finished()
+            && (SM::valid_inputs_iter(&self.current_state).any(|valid| valid == canonical)
+                || SM::is_ignored(&self.current_state, input)
+                || SM::is_deferred(&self.current_state, input))
+    }
+
+    /// Check if the given input is ignored by design in the current state
+    pub fn is_ignored(&self, input: &SM::Input) -> bool {
+        SM::is_ignored(&self.current_state, input)
+    }
+
+    /// Check if the given input is deferred in the current state
+    pub fn is_deferred(&self, input: &SM::Input) -> bool {
+        SM::is_deferred(&self.current_state, input)
     }
 
     /// Get all valid inputs for the current state
     pub fn valid_inputs(&self) -> Vec<SM::Input> {
-        SM::valid_inputs(&self.current_state)
+        SM::valid_inputs_iter(&self.current_state).collect()
+    }
+
+    /// Get the actions a user can take in the current state
+    ///
+    /// Returns one [`BoundAction`] per valid input, in declaration order, with guards
+    /// evaluated against the current context. Inputs whose name starts with an
+    /// underscore are meant for internal use and are left out, as in generated
+    /// documentation.
+    pub fn available_actions(&self) -> Vec<BoundAction<SM>> {
+        SM::valid_inputs_iter(&self.current_state)
+            .filter(|input| !SM::input_name(input).starts_with('_'))
+            .map(|input| {
+                let enabled =
+                    SM::check_guard(&self.current_state, &input, &self.context, &self.config);
+                BoundAction::new(input, enabled)
+            })
+            .collect()
     }
 
     /// Execute a state transition
     ///
     /// If the transition succeeds, returns the new state; if the input is invalid
-    /// or the transition fails, returns an error message.
+    /// or the transition fails, returns a [`TransitionError`] describing why. Inputs
+    /// ignored in the current state succeed without changing state or firing callbacks.
+    /// Inputs deferred in the current state succeed the same way, and are put back on
+    /// the [input queue](Self::input_queue) at the next state change. They are not
+    /// retried until the queue is processed with [`process_queue`](Self::process_queue)
+    /// or [`process_one`](Self::process_one).
+    ///
+    /// Callbacks run in the order documented by [`Hook`](crate::ordering::Hook).
     ///
     /// # Arguments
     /// - `input`: The input that triggers the transition
     ///
     /// # Returns
     /// - `Ok(new_state)`: Transition succeeded, returns the new state
-    /// - `Err(error_message)`: Transition failed, returns an error message
-    pub fn transition(&mut self, input: SM::Input) -> Result<SM::State, String> {
+    /// - `Err(error)`: Transition failed, e.g. [`TransitionError::InvalidInput`],
+    ///   [`TransitionError::GuardRejected`] when the guard rejects the current context, or
+    ///   [`TransitionError::MachineCompleted`] when the instance is in a final state
+    pub fn transition(&mut self, input: SM::Input) -> Result<SM::State, TransitionError<SM>> {
+        self.transition_with_outcome(input)
+            .map(|outcome| outcome.to)
+    }
+
+    /// Execute a state transition and return the output it emits
+    ///
+    /// Behaves like [`transition`](Self::transition), but also returns the output
+    /// declared for the transition with `emits`, as in a Mealy machine. Ignored and
+    /// deferred inputs emit no output.
+    ///
+    /// # Arguments
+    /// - `input`: The input that triggers the transition
+    ///
+    /// # Returns
+    /// - `Ok((new_state, output))`: Transition succeeded, returns the new state and output
+    /// - `Err(error)`: Transition failed, see [`transition`](Self::transition)
+    #[allow(clippy::type_complexity)]
+    pub fn transition_with_output(
+        &mut self,
+        input: SM::Input,
+    ) -> Result<(SM::State, Option<SM::Output>), TransitionError<SM>> {
+        self.run_transition(input, None)
+            .map(|outcome| (outcome.to, outcome.output))
+    }
+
+    /// Execute a state transition and collect the results of its effects
+    ///
+    /// Behaves like [`transition`](Self::transition), but also returns the values
+    /// produced by effects registered with [`on_transition_effect`](Self::on_transition_effect).
+    ///
+    /// # Arguments
+    /// - `input`: The input that triggers the transition
+    ///
+    /// # Returns
+    /// - `Ok(outcome)`: Transition succeeded, returns the new state and effect results
+    /// - `Err(error)`: Transition failed, see [`transition`](Self::transition)
+    pub fn transition_with_outcome(
+        &mut self,
+        input: SM::Input,
+    ) -> Result<TransitionOutcome<SM>, TransitionError<SM>> {
+        self.run_transition(input, None)
+    }
+
+    /// Execute a state transition as part of a distributed trace
+    ///
+    /// Behaves like [`transition`](Self::transition), but the trace context is
+    /// passed to [`on_transition_event`](Self::on_transition_event) callbacks and
+    /// stored with the history entry, so the transition can be linked to the
+    /// span that caused it.
+    ///
+    /// # Arguments
+    /// - `input`: The input that triggers the transition
+    /// - `trace`: The trace context of the caller
+    ///
+    /// # Returns
+    /// - `Ok(new_state)`: Transition succeeded, returns the new state
+    /// - `Err(error)`: Transition failed, see [`transition`](Self::transition)
+    pub fn transition_with_trace(
+        &mut self,
+        input: SM::Input,
+        trace: TraceContext,
+    ) -> Result<SM::State, TransitionError<SM>> {
+        self.run_transition(input, Some(trace))
+            .map(|outcome| outcome.to)
+    }
+
+    /// Execute a state transition in reaction to a transition of another machine
+    ///
+    /// The transition is made under the trace context of the causing event, so its
+    /// correlation ID and propagation fields carry over into this instance's events and
+    /// history. Use it wherever a transition of one machine feeds inputs to another.
+    ///
+    /// # Arguments
+    /// - `input`: The input that triggers the transition
+    /// - `cause`: The transition of the other machine that led to this input
+    ///
+    /// # Returns
+    /// - `Ok(new_state)`: Transition succeeded, returns the new state
+    /// - `Err(error)`: Transition failed, see [`transition`](Self::transition)
+    ///
+    /// # Example
+    /// ```rust
+    /// use yasm::*;
+    /// define_state_machine! {
+    ///     name: Order,
+    ///     module: order,
+    ///     states: { Placed, Paid },
+    ///     inputs: { Pay },
+    ///     initial: Placed,
+    ///     transitions: { Placed + Pay => Paid }
+    /// }
+    ///
+    /// define_state_machine! {
+    ///     name: Shipment,
+    ///     module: shipment,
+    ///     states: { Waiting, Preparing },
+    ///     inputs: { Prepare },
+    ///     initial: Waiting,
+    ///     transitions: { Waiting + Prepare => Preparing }
+    /// }
+    ///
+    /// let mut order = StateMachineInstance::<order::Order>::new();
+    /// let mut shipment = StateMachineInstance::<shipment::Shipment>::new();
+    /// let (sender, events) = std::sync::mpsc::channel();
+    /// order.on_transition_event(move |event| {
+    ///     let _ = sender.send(event.clone());
+    /// });
+    /// let trace = TraceContext::correlated();
+    /// let id = trace.correlation_id().unwrap().to_string();
+    /// order.transition_with_trace(order::Input::Pay, trace).unwrap();
+    /// for event in events.try_iter() {
+    ///     shipment.transition_caused_by(shipment::Input::Prepare, &event).unwrap();
+    /// }
+    ///
+    /// // The shipment's history carries the order's correlation ID
+    /// assert_eq!(shipment.history_for_correlation(&id).len(), 1);
+    /// ```
+    pub fn transition_caused_by<Other: StateMachine>(
+        &mut self,
+        input: SM::Input,
+        cause: &TransitionEvent<Other>,
+    ) -> Result<SM::State, TransitionError<SM>> {
+        let trace = cause.trace.clone().unwrap_or_default();
+        self.run_transition(input, Some(trace))
+            .map(|outcome| outcome.to)
+    }
+
+    /// Execute a state transition and await its async callbacks
+    ///
+    /// Behaves like [`transition`](Self::transition), then awaits the callbacks
+    /// registered with [`on_transition_async`](Self::on_transition_async) followed by
+    /// those registered with [`on_state_entry_async`](Self::on_state_entry_async), one
+    /// after another in registration order. The transition has already taken effect
+    /// when they run; ignored and deferred inputs run no hooks, and internal
+    /// transitions don't enter a state, so they skip the entry callbacks.
+    ///
+    /// The returned future doesn't depend on a particular runtime.
+    ///
+    /// # Arguments
+    /// - `input`: The input that triggers the transition
+    ///
+    /// # Returns
+    /// - `Ok(new_state)`: Transition succeeded, returns the new state
+    /// - `Err(error)`: Transition failed, see [`transition`](Self::transition)
+    ///
+    /// # Example
+    /// ```ignore
+    /// order.on_state_entry_async(State::Paid, |_| async move {
+    ///     mailer.send_receipt().await;
+    /// });
+    /// order.transition_async(Input::Pay).await?;
+    /// ```
+    #[cfg(feature = "async")]
+    pub async fn transition_async(
+        &mut self,
+        input: SM::Input,
+    ) -> Result<SM::State, TransitionError<SM>> {
+        if SM::is_ignored(&self.current_state, &input)
+            || SM::is_deferred(&self.current_state, &input)
+        {
+            return self.transition(input);
+        }
+
+        let entered = !SM::is_internal(&self.current_state, &input);
+        let (result, hooks) = match self.transition_with_outcome(input) {
+            Ok(outcome) => {
+                let hooks = self.callback_registry.async_hooks(
+                    &outcome.from,
+                    &outcome.input,
+                    &outcome.to,
+                    entered,
+                );
+                (Ok(outcome.to), hooks)
+            }
+            // The transition took effect, so its hooks run before the error is reported
+            Err(TransitionError::InvariantViolated {
+                state,
+                input,
+                target,
+                reason,
+            }) => {
+                let hooks = self
+                    .callback_registry
+                    .async_hooks(&state, &input, &target, entered);
+                let error = TransitionError::InvariantViolated {
+                    state,
+                    input,
+                    target,
+                    reason,
+                };
+                (Err(error), hooks)
+            }
+            Err(error) => return Err(error),
+        };
+        for hook in hooks {
+            hook.await;
+        }
+        result
+    }
+
+    /// Execute a state transition, optionally under a trace context, counting rejections
+    fn run_transition(
+        &mut self,
+        input: SM::Input,
+        trace: Option<TraceContext>,
+    ) -> Result<TransitionOutcome<SM>, TransitionError<SM>> {
+        let result = self.apply_transition(input, trace);
+        // Invariant failures are reported after the transition took effect
+        if let Err(error) = &result
+            && !error.took_effect()
+        {
+            self.rejections_in_state += 1;
+            if let Some(metrics) = self.metrics.as_mut() {
+                metrics.record_rejection(error.state(), error.input());
+            }
+            self.notify(|| MachineEvent::Rejected(error.clone()));
+            #[cfg(feature = "defmt")]
+            defmt::debug!(
+                "yasm: {} rejected {}",
+                SM::state_index(error.state()),
+                SM::input_index(error.input())
+            );
+        }
+        result
+    }
+
+    /// Send a transition event to the subscribers, dropping those that hung up
+    #[cfg(feature = "std")]
+    fn publish(&mut self, event: impl FnOnce() -> TransitionEvent<SM>) {
+        if !self.subscribers.is_empty() {
+            let event = event();
+            self.subscribers
+                .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+        }
+    }
+
+    /// Hand an event to the observer, if one is set
+    fn notify(&mut self, event: impl FnOnce() -> MachineEvent<SM>) {
+        if let Some(observer) = self.observer.as_mut() {
+            observer.on_event(&event());
+        }
+    }
+
+    /// Execute a state transition, optionally under a trace context
+    fn apply_transition(
+        &mut self,
+        input: SM::Input,
+        trace: Option<TraceContext>,
+    ) -> Result<TransitionOutcome<SM>, TransitionError<SM>> {
+        // A machine in a final state has completed and takes no further input
+        if SM::is_final(&self.current_state) {
+            return Err(TransitionError::MachineCompleted {
+                state: self.current_state.clone(),
+                input,
+            });
+        }
+
+        // Inputs ignored by design are accepted without effect
+        if SM::is_ignored(&self.current_state, &input) {
+            if self.record_ignored
+                && self.records_in_history(&self.current_state, &input, &self.current_state)
+            {
+                self.record_history(self.current_state.clone(), input.clone(), trace);
+            }
+            let state = self.current_state.clone();
+            self.notify(|| MachineEvent::Ignored {
+                state: state.clone(),
+                input: input.clone(),
+            });
+            return Ok(TransitionOutcome::new(
+                state.clone(),
+                input,
+                state,
+                Vec::new(),
+            ));
+        }
+
+        // Deferred inputs are set aside until the state changes
+        if SM::is_deferred(&self.current_state, &input) {
+            self.deferred.push_back(input.clone());
+            let state = self.current_state.clone();
+            self.notify(|| MachineEvent::Deferred {
+                state: state.clone(),
+                input: input.clone(),
+            });
+            return Ok(TransitionOutcome::new(
+                state.clone(),
+                input,
+                state,
+                Vec::new(),
+            ));
+        }
+
         // Check if the input is valid for the current state
         if !self.can_accept(&input) {
-            return Err(format!(
-                "Invalid input {:?} for state {:?}",
-                input, self.current_state
-            ));
+            return Err(TransitionError::InvalidInput {
+                state: self.current_state.clone(),
+                input,
+            });
+        }
+
+        // Check the transition guard against the context
+        if !SM::check_guard(&self.current_state, &input, &self.context, &self.config) {
+            return Err(TransitionError::GuardRejected {
+                state: self.current_state.clone(),
+                input,
+            });
         }
 
         // Execute deterministic transition
@@ -91,45 +840,590 @@ impl<SM: StateMachine> StateMachineInstance<SM> {
         match next_state {
             Some(new_state) => {
                 let old_state = self.current_state.clone();
+                let state_changed = old_state != new_state;
+                // External transitions exit and re-enter, even when looping on a state
+                let reenters = !SM::is_internal(&old_state, &input);
+
+                // Give before-transition callbacks a chance to veto
+                if let Err(reason) = self
+                    .callback_registry
+                    .check_before_transition(&old_state, &input, &new_state)
+                {
+                    return Err(TransitionError::Vetoed {
+                        state: old_state,
+                        input,
+                        target: new_state,
+                        reason,
+                    });
+                }
 
-                // Trigger state exit callbacks (only if changing state)
-                if old_state != new_state {
+                // Validate entry into the new state before anything takes effect,
+                // whenever the state is entered, as for entry callbacks
+                if reenters
+                    && let Err(reason) = self
+                        .callback_registry
+                        .validate_entry(&new_state, &self.context)
+                {
+                    return Err(TransitionError::EntryRejected {
+                        state: old_state,
+                        input,
+                        target: new_state,
+                        reason,
+                    });
+                }
+
+                // Append to the transition log before anything takes effect
+                if let Some(log) = self.transition_log.as_mut() {
+                    let record = LogRecord {
+                        seq: self.transition_seq,
+                        input: input.clone(),
+                        state: new_state.clone(),
+                    };
+                    if let Err(reason) = log.append(&record) {
+                        return Err(TransitionError::LogFailed {
+                            state: old_state,
+                            input,
+                            target: new_state,
+                            reason,
+                        });
+                    }
+                }
+                self.transition_seq += 1;
+
+                // Trigger state exit callbacks (not for internal transitions)
+                if reenters {
                     self.callback_registry.trigger_state_exit(&old_state);
+                    self.notify(|| MachineEvent::Exited(old_state.clone()));
                 }
 
                 // Trigger transition callbacks
                 self.callback_registry
                     .trigger_transition(&old_state, &input, &new_state);
+                self.callback_registry.trigger_event(
+                    &old_state,
+                    &input,
+                    &new_state,
+                    trace.as_ref(),
+                );
+                let event = || {
+                    TransitionEvent::new(old_state.clone(), input.clone(), new_state.clone())
+                        .with_trace(trace.clone())
+                };
+                self.notify(|| MachineEvent::Transitioned(event()));
+                #[cfg(feature = "std")]
+                self.publish(event);
+                #[cfg(feature = "defmt")]
+                defmt::trace!(
+                    "yasm: {} --{}--> {}",
+                    SM::state_index(&old_state),
+                    SM::input_index(&input),
+                    SM::state_index(&new_state)
+                );
 
-                // Record transition history
-                self.history.push_back((old_state, input));
+                // Run transition actions, which may update the context
+                self.callback_registry.trigger_actions(
+                    &old_state,
+                    &input,
+                    &new_state,
+                    &mut self.context,
+                );
+
+                // Run transition effects, collecting their results for the caller
+                let results = self.callback_registry.trigger_effects(
+                    &old_state,
+                    &input,
+                    &new_state,
+                    &mut self.context,
+                );
 
-                // Maintain history size limit using efficient ring buffer operations
-                if self.history.len() > self.max_history_size {
-                    self.history.pop_front();
+                // Record transition history
+                if self.records_in_history(&old_state, &input, &new_state) {
+                    self.record_history(old_state.clone(), input.clone(), trace);
+                } else {
+                    self.unrecorded_since_last_entry = true;
                 }
 
                 // Update current state
                 self.current_state = new_state.clone();
+                self.last_transition_at = Some(unix_millis());
+                if let Some(metrics) = self.metrics.as_mut() {
+                    metrics.record_transition(&old_state, &input, &new_state);
+                    if state_changed {
+                        let stay = unix_millis().saturating_sub(self.state_entered_at);
+                        metrics.record_stay(&old_state, stay);
+                    }
+                }
+                if state_changed {
+                    self.state_entered_at = unix_millis();
+                    self.rejections_in_state = 0;
+                    self.requeue_deferred();
+                }
 
-                // Trigger state entry callbacks (only if changing state)
-                if self.current_state != self.history.back().unwrap().0 {
+                // Trigger state entry callbacks (not for internal transitions)
+                if reenters {
                     self.callback_registry.trigger_state_entry(&new_state);
+                    self.notify(|| MachineEvent::Entered(new_state.clone()));
                 }
 
-                Ok(new_state)
+                // Check the invariants against the state and context the transition left
+                if let Err(reason) = self.invariants.check(&new_state, &self.context) {
+                    match self.invariants.policy {
+                        InvariantPolicy::DebugPanic if cfg!(debug_assertions) => panic!(
+                            "invariant {reason} violated by transition from state {old_state:?} to {new_state:?}"
+                        ),
+                        InvariantPolicy::DebugPanic | InvariantPolicy::Error => {
+                            return Err(TransitionError::InvariantViolated {
+                                state: old_state,
+                                input,
+                                target: new_state,
+                                reason,
+                            });
+                        }
+                        InvariantPolicy::Callback => {
+                            if let Some(handler) = &self.invariants.handler {
+                                handler(&reason, &new_state, &self.context);
+                            }
+                        }
+                    }
+                }
+
+                let mut outcome = TransitionOutcome::new(old_state, input, new_state, results);
+                outcome.output = SM::output(&outcome.from, &outcome.input);
+                Ok(outcome)
             }
-            None => Err(format!(
-                "No valid transition from state {:?} with input {:?}",
-                self.current_state, input
-            )),
+            None => Err(TransitionError::NoTransition {
+                state: self.current_state.clone(),
+                input,
+            }),
+        }
+    }
+
+    /// Execute a state transition for the input with the given display name
+    ///
+    /// Inputs that carry a payload are applied with their default payload, as with the
+    /// generated `FromStr` implementation, but this works for any machine.
+    ///
+    /// # Arguments
+    /// - `input_name`: The display name of the input
+    ///
+    /// # Returns
+    /// - `Ok(new_state)`: Transition succeeded, returns the new state
+    /// - `Err(error)`: [`TryTransitionError::UnknownInput`] if no input has this name,
+    ///   otherwise the wrapped [`TransitionError`]
+    pub fn try_transition_by_name(
+        &mut self,
+        input_name: &str,
+    ) -> Result<SM::State, TryTransitionError<SM>> {
+        let input = SM::inputs()
+            .into_iter()
+            .find(|input| SM::input_name(input) == input_name)
+            .ok_or_else(|| TryTransitionError::UnknownInput {
+                name: input_name.to_string(),
+            })?;
+        Ok(self.transition(input)?)
+    }
+
+    /// Execute a state transition for an input given as JSON
+    ///
+    /// Accepts the serialized form of the input, e.g. `"Close"` or `{"Pay": [100]}`.
+    ///
+    /// # Arguments
+    /// - `value`: The JSON value of the input
+    ///
+    /// # Returns
+    /// - `Ok(new_state)`: Transition succeeded, returns the new state
+    /// - `Err(error)`: [`TryTransitionError::MalformedInput`] if the value is not a valid
+    ///   input, otherwise the wrapped [`TransitionError`]
+    #[cfg(feature = "serde")]
+    pub fn try_transition_from_json(
+        &mut self,
+        value: &serde_json::Value,
+    ) -> Result<SM::State, TryTransitionError<SM>>
+    where
+        SM::Input: serde::de::DeserializeOwned,
+    {
+        let input = <SM::Input as serde::Deserialize>::deserialize(value).map_err(|error| {
+            TryTransitionError::MalformedInput {
+                reason: error.to_string(),
+            }
+        })?;
+        Ok(self.transition(input)?)
+    }
+
+    /// Queue an input to be applied later by [`process_one`](Self::process_one) or
+    /// [`process_queue`](Self::process_queue)
+    ///
+    /// The coalescing rules of the machine apply, see [`InputQueue`].
+    ///
+    /// # Returns
+    /// Returns true if the queue grew, false if the input was merged into the last one
+    pub fn enqueue(&mut self, input: SM::Input) -> bool {
+        self.queue.push(input)
+    }
+
+    /// Apply the oldest queued input
+    ///
+    /// If the transition fails, the input is discarded, or set aside until the next
+    /// state change under [`InvalidInputPolicy::Defer`]. An input whose transition
+    /// [took effect](TransitionError::took_effect) before an invariant failed is never
+    /// set aside.
+    ///
+    /// # Returns
+    /// Returns the result of the transition, or None if the queue is empty
+    pub fn process_one(&mut self) -> Option<Result<SM::State, TransitionError<SM>>> {
+        let input = self.queue.pop()?;
+        let result = self.transition(input.clone());
+        if result.as_ref().is_err_and(|error| !error.took_effect())
+            && self.invalid_input_policy == InvalidInputPolicy::Defer
+        {
+            self.deferred.push_back(input);
+        }
+        Some(result)
+    }
+
+    /// Apply queued inputs in order until the queue is empty
+    ///
+    /// Under [`InvalidInputPolicy::Error`] processing stops at the first failed
+    /// transition, leaving the remaining inputs queued. Otherwise failed inputs are
+    /// dropped or deferred and processing carries on. Inputs whose transition took
+    /// effect before an invariant failed count as applied.
+    ///
+    /// # Returns
+    /// - `Ok(count)`: The number of inputs that were applied
+    /// - `Err(error)`: A transition failed under [`InvalidInputPolicy::Error`]; an
+    ///   [`InvariantViolated`](TransitionError::InvariantViolated) input was applied
+    ///
+    /// # Example
+    /// ```rust
+    /// use yasm::*;
+    /// define_state_machine! {
+    ///     name: Order,
+    ///     states: { Placed, Paid, Shipped },
+    ///     inputs: { Pay, Ship },
+    ///     initial: Placed,
+    ///     transitions: {
+    ///         Placed + Pay => Paid,
+    ///         Paid + Ship => Shipped
+    ///     }
+    /// }
+    ///
+    /// let mut order = StateMachineInstance::<Order>::new();
+    /// order.set_invalid_input_policy(InvalidInputPolicy::Defer);
+    /// order.enqueue(Input::Ship);
+    /// order.enqueue(Input::Pay);
+    /// // `Ship` fails before `Pay`, and is retried once the order is paid
+    /// assert_eq!(order.process_queue(), Ok(2));
+    /// assert_eq!(*order.current_state(), State::Shipped);
+    /// ```
+    pub fn process_queue(&mut self) -> Result<usize, TransitionError<SM>> {
+        let mut applied = 0;
+        while let Some(result) = self.process_one() {
+            if result
+                .as_ref()
+                .map_or_else(TransitionError::took_effect, |_| true)
+            {
+                applied += 1;
+            }
+            if let Err(error) = result
+                && self.invalid_input_policy == InvalidInputPolicy::Error
+            {
+                return Err(error);
+            }
+        }
+        Ok(applied)
+    }
+
+    /// Get the queue of inputs waiting to be processed
+    pub fn input_queue(&self) -> &InputQueue<SM> {
+        &self.queue
+    }
+
+    /// Iterate over the inputs set aside until the next state change, oldest first
+    ///
+    /// These are inputs deferred by the machine definition and queued inputs that
+    /// failed under [`InvalidInputPolicy::Defer`]. At the next state change they move to
+    /// the front of the [input queue](Self::input_queue), to be retried by
+    /// [`process_queue`](Self::process_queue).
+    pub fn deferred_inputs(&self) -> impl Iterator<Item = &SM::Input> {
+        self.deferred.iter()
+    }
+
+    /// Set what happens to queued inputs whose transition fails
+    pub fn set_invalid_input_policy(&mut self, policy: InvalidInputPolicy) {
+        self.invalid_input_policy = policy;
+    }
+
+    /// Get what happens to queued inputs whose transition fails
+    pub fn invalid_input_policy(&self) -> InvalidInputPolicy {
+        self.invalid_input_policy
+    }
+
+    /// Move deferred inputs back to the front of the queue, keeping their order
+    fn requeue_deferred(&mut self) {
+        while let Some(input) = self.deferred.pop_back() {
+            self.queue.push_front(input);
+        }
+    }
+
+    /// Append an entry to the history, evicting the oldest one if full
+    fn record_history(
+        &mut self,
+        from_state: SM::State,
+        input: SM::Input,
+        trace: Option<TraceContext>,
+    ) {
+        self.history.push_back((from_state, input));
+        self.history_meta.push_back(HistoryMeta {
+            seq: self.next_seq,
+            annotations: Vec::new(),
+            entered_at: self.state_entered_at,
+            exited_at: unix_millis(),
+            trace,
+            follows_unrecorded: core::mem::take(&mut self.unrecorded_since_last_entry),
+        });
+        self.next_seq += 1;
+
+        // Maintain history size limit using efficient ring buffer operations
+        if self.history.len() > self.max_history_size {
+            self.history.pop_front();
+            self.history_meta.pop_front();
+        }
+    }
+
+    /// Get the sequence number of the most recent history entry
+    ///
+    /// Sequence numbers start at 0 and keep increasing for the lifetime of the
+    /// instance, even when old entries are evicted or the history is reset.
+    pub fn last_history_seq(&self) -> Option<u64> {
+        self.history_meta.back().map(|meta| meta.seq)
+    }
+
+    /// Attach a note to an existing history entry
+    ///
+    /// # Arguments
+    /// - `seq`: The sequence number of the entry
+    /// - `note`: The note to attach
+    ///
+    /// # Returns
+    /// - `Ok(())`: The note was attached
+    /// - `Err(error_message)`: No entry with this sequence number is in the history
+    pub fn annotate_history(&mut self, seq: u64, note: impl Into<String>) -> Result<(), String> {
+        // Entries are stored in ascending sequence order
+        let index = self
+            .history_meta
+            .binary_search_by_key(&seq, |meta| meta.seq)
+            .map_err(|_| format!("No history entry with sequence number {seq}"))?;
+        self.history_meta[index].annotations.push(note.into());
+        Ok(())
+    }
+
+    /// Get the history together with sequence numbers and annotations
+    pub fn history_entries(&self) -> Vec<HistoryEntry<SM>> {
+        self.history
+            .iter()
+            .zip(&self.history_meta)
+            .map(|((from, input), meta)| HistoryEntry {
+                seq: meta.seq,
+                from: from.clone(),
+                input: input.clone(),
+                annotations: meta.annotations.clone(),
+                entered_at: meta.entered_at,
+                exited_at: meta.exited_at,
+                trace: meta.trace.clone(),
+                follows_unrecorded: meta.follows_unrecorded,
+            })
+            .collect()
+    }
+
+    /// Get the history entries made under a correlation ID
+    ///
+    /// # Arguments
+    /// - `id`: The correlation ID, see [`TraceContext::correlated`]
+    pub fn history_for_correlation(&self, id: &str) -> Vec<HistoryEntry<SM>> {
+        self.history_entries()
+            .into_iter()
+            .filter(|entry| entry.correlation_id() == Some(id))
+            .collect()
+    }
+
+    /// Roll back the most recent transition
+    ///
+    /// Pops the last history entry and restores the state it started from, along with
+    /// the time it was entered. If the state changes, the exit callbacks of the current
+    /// state and the entry callbacks of the restored one fire, and the observer receives
+    /// the matching [`Exited`](MachineEvent::Exited) and [`Entered`](MachineEvent::Entered)
+    /// events.
+    ///
+    /// Undoing is not a transition, so everything else is left as it is:
+    /// - the context, as transition actions are not reversed
+    /// - [`transition_seq`](Self::transition_seq) and the sequence numbers of later
+    ///   history entries, which keep counting up
+    /// - the [transition log](Self::set_transition_log), which keeps the undone record,
+    ///   so replaying it redoes the transition
+    /// - the [metrics](Self::metrics) and the time of the last transition
+    /// - queued and deferred inputs
+    ///
+    /// No transition callback, [`Transitioned`](MachineEvent::Transitioned) event or
+    /// [subscriber](Self::subscribe) message is emitted, and invariants are not checked.
+    ///
+    /// Transitions left out of history, because of the history filters, and moves
+    /// made with [`force_state`](Self::force_state) without a recorded entry, cannot
+    /// be rolled back. Undo refuses to step over them rather than jump back past
+    /// effects that took place, see [`can_undo`](Self::can_undo).
+    ///
+    /// # Returns
+    /// Returns the restored state, or None if the history is empty or transitions
+    /// left out of history took effect after its last entry
+    pub fn undo(&mut self) -> Option<SM::State> {
+        if !self.can_undo() {
+            return None;
+        }
+        let (from_state, _) = self.history.pop_back()?;
+        if let Some(meta) = self.history_meta.pop_back() {
+            self.state_entered_at = meta.entered_at;
+            self.unrecorded_since_last_entry = meta.follows_unrecorded;
         }
+
+        let old_state = core::mem::replace(&mut self.current_state, from_state.clone());
+        if old_state != from_state {
+            self.rejections_in_state = 0;
+            self.callback_registry.trigger_state_exit(&old_state);
+            self.notify(|| MachineEvent::Exited(old_state.clone()));
+            self.callback_registry.trigger_state_entry(&from_state);
+            self.notify(|| MachineEvent::Entered(from_state.clone()));
+        }
+
+        Some(from_state)
+    }
+
+    /// Check whether [`undo`](Self::undo) would roll back a transition
+    ///
+    /// # Returns
+    /// Returns false if the history is empty or transitions left out of history took
+    /// effect after its last entry
+    pub fn can_undo(&self) -> bool {
+        !self.history.is_empty() && !self.unrecorded_since_last_entry
+    }
+
+    /// Roll back up to `n` transitions, one at a time
+    ///
+    /// # Returns
+    /// Returns the number of transitions that were rolled back
+    pub fn undo_n(&mut self, n: usize) -> usize {
+        (0..n).take_while(|_| self.undo().is_some()).count()
     }
 
     /// Reset the state machine to its initial state and clear history
+    ///
+    /// Queued and deferred inputs are discarded as well. Reset callbacks run
+    /// afterwards, see [`on_reset`](Self::on_reset).
     pub fn reset(&mut self) {
-        self.current_state = SM::initial_state();
+        self.reset_to(SM::initial_state());
+    }
+
+    /// Reset the state machine to the given state and clear history
+    ///
+    /// This bypasses transitions: the state needn't be reachable, and no guard,
+    /// validator, exit, entry or transition callback runs. It is meant for test
+    /// harnesses and admin tools. Queued and deferred inputs are discarded, and reset
+    /// callbacks run afterwards.
+    ///
+    /// # Arguments
+    /// * `state` - The state to place the instance in
+    pub fn reset_to(&mut self, state: SM::State) {
+        let old_state = core::mem::replace(&mut self.current_state, state);
+        self.state_entered_at = unix_millis();
+        self.last_transition_at = None;
+        self.rejections_in_state = 0;
         self.history.clear();
+        self.history_meta.clear();
+        self.unrecorded_since_last_entry = false;
+        self.queue.clear();
+        self.deferred.clear();
+        self.callback_registry
+            .trigger_reset(&old_state, &self.current_state);
+    }
+
+    /// Place the state machine in the given state, keeping its history
+    ///
+    /// Like [`reset_to`](Self::reset_to) this bypasses transitions, and no callback
+    /// runs, reset callbacks included. Queued inputs are kept, and deferred inputs
+    /// are queued again if the state changes. The move is not appended to the
+    /// [transition log](Self::set_transition_log).
+    ///
+    /// # Arguments
+    /// * `state` - The state to place the instance in
+    /// * `recorded_as` - If given, a synthetic history entry from the previous state
+    ///   with this input is recorded, annotated with `"forced"`, so
+    ///   [`undo`](Self::undo) can move back. Without it, a move to another state
+    ///   stops undo from rolling back past it
+    pub fn force_state(&mut self, state: SM::State, recorded_as: Option<SM::Input>) {
+        if let Some(input) = recorded_as {
+            self.record_history(self.current_state.clone(), input, None);
+            if let Some(meta) = self.history_meta.back_mut() {
+                meta.annotations.push("forced".to_string());
+            }
+        } else if state != self.current_state {
+            self.unrecorded_since_last_entry = true;
+        }
+        if state != self.current_state {
+            self.current_state = state;
+            self.state_entered_at = unix_millis();
+            self.rejections_in_state = 0;
+            self.requeue_deferred();
+        }
+        self.last_transition_at = Some(unix_millis());
+    }
+
+    /// Set how long the instance may stay in a non-terminal state before
+    /// [`health`](Self::health) reports it as stuck
+    ///
+    /// Without a limit, which is the default, time in state is not a stuck heuristic.
+    pub fn set_stuck_after(&mut self, limit: Option<Duration>) {
+        self.stuck_after = limit;
+    }
+
+    /// Get how long the instance may stay in a non-terminal state before it looks stuck
+    pub fn stuck_after(&self) -> Option<Duration> {
+        self.stuck_after
+    }
+
+    /// Summarize the health of the instance, e.g. for a health endpoint
+    ///
+    /// An instance in a non-terminal state looks stuck if it has stayed there for longer
+    /// than the limit set with [`set_stuck_after`](Self::set_stuck_after), or if the
+    /// guards of all its valid inputs reject the current context.
+    pub fn health(&self) -> HealthReport<SM> {
+        let time_in_state_ms = unix_millis().saturating_sub(self.state_entered_at);
+        let valid_inputs = self.valid_inputs();
+        let terminal = valid_inputs.is_empty() || self.is_finished();
+
+        let stuck = if terminal {
+            None
+        } else if self
+            .stuck_after
+            .is_some_and(|limit| time_in_state_ms > limit.as_millis() as u64)
+        {
+            Some(StuckReason::Idle)
+        } else if valid_inputs
+            .iter()
+            .all(|input| !SM::check_guard(&self.current_state, input, &self.context, &self.config))
+        {
+            Some(StuckReason::GuardsClosed)
+        } else {
+            None
+        };
+
+        HealthReport {
+            state: self.current_state.clone(),
+            time_in_state_ms,
+            last_transition_at: self.last_transition_at,
+            recent_rejections: self.rejections_in_state,
+            queue_depth: self.queue.len(),
+            deferred_count: self.deferred.len(),
+            terminal,
+            stuck,
+        }
     }
 
     /// Get the length of the history
@@ -154,69 +1448,205 @@ impl<SM: StateMachine> StateMachineInstance<SM> {
         &self.callback_registry
     }
 
-    // Convenience methods for callback registration - more intuitive API
+    // Convenience methods for callback registration - more intuitive API
+
+    /// Register a callback for when entering a specific state
+    ///
+    /// Fires for external self-loops such as `A + x => A`, which exit and re-enter the
+    /// state, but not for internal transitions declared `=> internal`.
+    ///
+    /// # Arguments
+    /// * `state` - The state to monitor for entry
+    /// * `callback` - The callback function to execute
+    ///
+    /// # Example
+    /// ```ignore
+    /// workflow.on_state_entry(State::Active, |state| {
+    ///     println!("Entered active state: {:?}", state);
+    /// });
+    /// ```
+    pub fn on_state_entry<F>(&mut self, state: SM::State, callback: F) -> CallbackId
+    where
+        F: Fn(&SM::State) + Send + Sync + 'static,
+    {
+        self.callback_registry.on_state_entry(state, callback)
+    }
+
+    /// Register a callback for when exiting a specific state
+    ///
+    /// Fires for external self-loops such as `A + x => A`, which exit and re-enter the
+    /// state, but not for internal transitions declared `=> internal`.
+    ///
+    /// # Arguments
+    /// * `state` - The state to monitor for exit
+    /// * `callback` - The callback function to execute
+    ///
+    /// # Example
+    /// ```ignore
+    /// workflow.on_state_exit(State::Active, |state| {
+    ///     println!("Exiting active state: {:?}", state);
+    /// });
+    /// ```
+    pub fn on_state_exit<F>(&mut self, state: SM::State, callback: F) -> CallbackId
+    where
+        F: Fn(&SM::State) + Send + Sync + 'static,
+    {
+        self.callback_registry.on_state_exit(state, callback)
+    }
+
+    /// Register a callback for a specific transition
+    ///
+    /// # Arguments
+    /// * `from_state` - The source state
+    /// * `input` - The input that triggers the transition
+    /// * `callback` - The callback function to execute
+    ///
+    /// # Example
+    /// ```ignore
+    /// workflow.on_transition(State::Draft, Input::Submit, |from, input, to| {
+    ///     println!("Transition: {:?} --{:?}--> {:?}", from, input, to);
+    /// });
+    /// ```
+    pub fn on_transition<F>(
+        &mut self,
+        from_state: SM::State,
+        input: SM::Input,
+        callback: F,
+    ) -> CallbackId
+    where
+        F: Fn(&SM::State, &SM::Input, &SM::State) + Send + Sync + 'static,
+    {
+        self.callback_registry
+            .on_transition(from_state, input, callback)
+    }
+
+    /// Register an action for a specific transition that may mutate the context
+    ///
+    /// # Arguments
+    /// * `from_state` - The source state
+    /// * `input` - The input that triggers the transition
+    /// * `action` - The action to execute
+    ///
+    /// # Example
+    /// ```ignore
+    /// order.on_transition_action(State::Paid, Input::Ship, |_, _, _, ctx| {
+    ///     ctx.shipments += 1;
+    /// });
+    /// ```
+    pub fn on_transition_action<F>(
+        &mut self,
+        from_state: SM::State,
+        input: SM::Input,
+        action: F,
+    ) -> CallbackId
+    where
+        F: Fn(&SM::State, &SM::Input, &SM::State, &mut SM::Context) + Send + Sync + 'static,
+    {
+        self.callback_registry
+            .on_transition_action(from_state, input, action)
+    }
 
-    /// Register a callback for when entering a specific state
+    /// Register an effect for a specific transition whose result is returned to the caller
     ///
     /// # Arguments
-    /// * `state` - The state to monitor for entry
-    /// * `callback` - The callback function to execute
+    /// * `from_state` - The source state
+    /// * `input` - The input that triggers the transition
+    /// * `effect` - The effect to execute, returning a value for the caller
     ///
     /// # Example
     /// ```ignore
-    /// workflow.on_state_entry(State::Active, |state| {
-    ///     println!("Entered active state: {:?}", state);
+    /// order.on_transition_effect(State::Cart, Input::Checkout, |_, _, _, ctx| {
+    ///     ctx.next_order_id += 1;
+    ///     OrderId(ctx.next_order_id)
     /// });
+    /// let outcome = order.transition_with_outcome(Input::Checkout)?;
+    /// let order_id = outcome.get::<OrderId>();
     /// ```
-    pub fn on_state_entry<F>(&mut self, state: SM::State, callback: F)
+    pub fn on_transition_effect<F, R>(
+        &mut self,
+        from_state: SM::State,
+        input: SM::Input,
+        effect: F,
+    ) -> CallbackId
     where
-        F: Fn(&SM::State) + Send + Sync + 'static,
+        F: Fn(&SM::State, &SM::Input, &SM::State, &mut SM::Context) -> R + Send + Sync + 'static,
+        R: core::any::Any + Send,
     {
-        self.callback_registry.on_state_entry(state, callback);
+        self.callback_registry
+            .on_transition_effect(from_state, input, effect)
     }
 
-    /// Register a callback for when exiting a specific state
+    /// Register an action that runs on any transition and may mutate the context
     ///
     /// # Arguments
-    /// * `state` - The state to monitor for exit
-    /// * `callback` - The callback function to execute
+    /// * `action` - The action to execute
     ///
     /// # Example
     /// ```ignore
-    /// workflow.on_state_exit(State::Active, |state| {
-    ///     println!("Exiting active state: {:?}", state);
+    /// workflow.on_any_transition_action(|_, _, _, ctx| {
+    ///     ctx.transitions += 1;
     /// });
     /// ```
-    pub fn on_state_exit<F>(&mut self, state: SM::State, callback: F)
+    pub fn on_any_transition_action<F>(&mut self, action: F) -> CallbackId
     where
-        F: Fn(&SM::State) + Send + Sync + 'static,
+        F: Fn(&SM::State, &SM::Input, &SM::State, &mut SM::Context) + Send + Sync + 'static,
     {
-        self.callback_registry.on_state_exit(state, callback);
+        self.callback_registry.on_any_transition_action(action)
     }
 
-    /// Register a callback for a specific transition
+    /// Register a callback that can veto a specific transition before it takes effect
     ///
     /// # Arguments
     /// * `from_state` - The source state
     /// * `input` - The input that triggers the transition
-    /// * `callback` - The callback function to execute
+    /// * `callback` - The check to run, receiving the source state, input and target state
     ///
     /// # Example
     /// ```ignore
-    /// workflow.on_transition(State::Draft, Input::Submit, |from, input, to| {
-    ///     println!("Transition: {:?} --{:?}--> {:?}", from, input, to);
+    /// editor.on_before_transition(State::Editing, Input::Close, |_, _, _| {
+    ///     if has_unsaved_changes() { Err("unsaved changes".into()) } else { Ok(()) }
     /// });
     /// ```
-    pub fn on_transition<F>(&mut self, from_state: SM::State, input: SM::Input, callback: F)
+    pub fn on_before_transition<F>(
+        &mut self,
+        from_state: SM::State,
+        input: SM::Input,
+        callback: F,
+    ) -> CallbackId
     where
-        F: Fn(&SM::State, &SM::Input, &SM::State) + Send + Sync + 'static,
+        F: Fn(&SM::State, &SM::Input, &SM::State) -> Result<(), String> + Send + Sync + 'static,
     {
         self.callback_registry
-            .on_transition(from_state, input, callback);
+            .on_before_transition(from_state, input, callback)
+    }
+
+    /// Register a validator that must pass before a state can be entered
+    ///
+    /// Validators run whenever the state is entered, including external self-loops
+    /// such as `A + x => A`, but not for internal transitions.
+    ///
+    /// # Arguments
+    /// * `state` - The state whose entry is validated
+    /// * `validator` - The validation function, receiving the instance context
+    ///
+    /// # Example
+    /// ```ignore
+    /// order.on_validate_entry(State::Shipped, |ctx| {
+    ///     ctx.tracking_number.as_ref().map(|_| ()).ok_or("missing tracking number".into())
+    /// });
+    /// ```
+    pub fn on_validate_entry<F>(&mut self, state: SM::State, validator: F) -> CallbackId
+    where
+        F: Fn(&SM::Context) -> Result<(), String> + Send + Sync + 'static,
+    {
+        self.callback_registry.on_validate_entry(state, validator)
     }
 
     /// Register a global callback that triggers on any state entry
     ///
+    /// Fires for external self-loops such as `A + x => A`, which exit and re-enter the
+    /// state, but not for internal transitions declared `=> internal`.
+    ///
     /// # Arguments
     /// * `callback` - The callback function to execute
     ///
@@ -226,15 +1656,18 @@ impl<SM: StateMachine> StateMachineInstance<SM> {
     ///     println!("Entered state: {:?}", state);
     /// });
     /// ```
-    pub fn on_any_state_entry<F>(&mut self, callback: F)
+    pub fn on_any_state_entry<F>(&mut self, callback: F) -> CallbackId
     where
         F: Fn(&SM::State) + Send + Sync + 'static,
     {
-        self.callback_registry.on_any_state_entry(callback);
+        self.callback_registry.on_any_state_entry(callback)
     }
 
     /// Register a global callback that triggers on any state exit
     ///
+    /// Fires for external self-loops such as `A + x => A`, which exit and re-enter the
+    /// state, but not for internal transitions declared `=> internal`.
+    ///
     /// # Arguments
     /// * `callback` - The callback function to execute
     ///
@@ -244,11 +1677,11 @@ impl<SM: StateMachine> StateMachineInstance<SM> {
     ///     println!("Exiting state: {:?}", state);
     /// });
     /// ```
-    pub fn on_any_state_exit<F>(&mut self, callback: F)
+    pub fn on_any_state_exit<F>(&mut self, callback: F) -> CallbackId
     where
         F: Fn(&SM::State) + Send + Sync + 'static,
     {
-        self.callback_registry.on_any_state_exit(callback);
+        self.callback_registry.on_any_state_exit(callback)
     }
 
     /// Register a global callback that triggers on any transition
@@ -262,11 +1695,115 @@ impl<SM: StateMachine> StateMachineInstance<SM> {
     ///     println!("Transition: {:?} --{:?}--> {:?}", from, input, to);
     /// });
     /// ```
-    pub fn on_any_transition<F>(&mut self, callback: F)
+    pub fn on_any_transition<F>(&mut self, callback: F) -> CallbackId
     where
         F: Fn(&SM::State, &SM::Input, &SM::State) + Send + Sync + 'static,
     {
-        self.callback_registry.on_any_transition(callback);
+        self.callback_registry.on_any_transition(callback)
+    }
+
+    /// Register a callback that triggers when the instance is reset
+    ///
+    /// Runs after [`reset`](Self::reset) and [`reset_to`](Self::reset_to), with the
+    /// state left and the state placed in.
+    ///
+    /// # Arguments
+    /// * `callback` - The callback function to execute
+    ///
+    /// # Example
+    /// ```ignore
+    /// workflow.on_reset(|from, to| {
+    ///     println!("Reset: {:?} => {:?}", from, to);
+    /// });
+    /// ```
+    pub fn on_reset<F>(&mut self, callback: F) -> CallbackId
+    where
+        F: Fn(&SM::State, &SM::State) + Send + Sync + 'static,
+    {
+        self.callback_registry.on_reset(callback)
+    }
+
+    /// Register a global callback that receives every transition as an event
+    ///
+    /// # Arguments
+    /// * `callback` - The callback function to execute
+    ///
+    /// # Example
+    /// ```ignore
+    /// workflow.on_transition_event(|event| {
+    ///     let parent = event.trace.as_ref().and_then(|trace| trace.get("traceparent"));
+    ///     tracer.record_span("transition", parent, &event.input);
+    /// });
+    /// ```
+    pub fn on_transition_event<F>(&mut self, callback: F) -> CallbackId
+    where
+        F: Fn(&TransitionEvent<SM>) + Send + Sync + 'static,
+    {
+        self.callback_registry.on_transition_event(callback)
+    }
+
+    /// Register an async callback for when entering a specific state
+    ///
+    /// Only awaited by [`transition_async`](Self::transition_async).
+    ///
+    /// # Arguments
+    /// * `state` - The state to monitor for entry
+    /// * `callback` - The callback function, returning the future to await
+    ///
+    /// # Example
+    /// ```ignore
+    /// order.on_state_entry_async(State::Paid, |state| async move {
+    ///     audit_log.write(format!("entered {state}")).await;
+    /// });
+    /// ```
+    #[cfg(feature = "async")]
+    pub fn on_state_entry_async<F, Fut>(&mut self, state: SM::State, callback: F) -> CallbackId
+    where
+        F: Fn(SM::State) -> Fut + Send + Sync + 'static,
+        Fut: core::future::Future<Output = ()> + Send + 'static,
+    {
+        self.callback_registry.on_state_entry_async(state, callback)
+    }
+
+    /// Register an async callback for a specific transition
+    ///
+    /// Only awaited by [`transition_async`](Self::transition_async).
+    ///
+    /// # Arguments
+    /// * `from_state` - The source state
+    /// * `input` - The input that triggers the transition
+    /// * `callback` - The callback function, returning the future to await
+    ///
+    /// # Example
+    /// ```ignore
+    /// order.on_transition_async(State::Cart, Input::Pay, |from, input, to| async move {
+    ///     events.publish(from, input, to).await;
+    /// });
+    /// ```
+    #[cfg(feature = "async")]
+    pub fn on_transition_async<F, Fut>(
+        &mut self,
+        from_state: SM::State,
+        input: SM::Input,
+        callback: F,
+    ) -> CallbackId
+    where
+        F: Fn(SM::State, SM::Input, SM::State) -> Fut + Send + Sync + 'static,
+        Fut: core::future::Future<Output = ()> + Send + 'static,
+    {
+        self.callback_registry
+            .on_transition_async(from_state, input, callback)
+    }
+
+    /// Remove a single callback registered with one of the `on_*` methods
+    ///
+    /// # Arguments
+    /// * `id` - The handle returned when the callback was registered
+    ///
+    /// # Returns
+    /// Returns true if the callback was found and removed
+    pub fn remove_callback(&mut self, id: CallbackId) -> bool {
+        self.callback_registry.remove_callback(id)
     }
 
     /// Clear all registered callbacks
@@ -279,6 +1816,464 @@ impl<SM: StateMachine> StateMachineInstance<SM> {
         self.callback_registry.clear();
     }
 
+    /// Set the observer of the instance, replacing the previous one
+    ///
+    /// The observer receives every event of the instance after the callbacks of the
+    /// same hook have run, see [`MachineEvent`].
+    ///
+    /// # Arguments
+    /// * `observer` - The observer, owned by the instance from now on
+    ///
+    /// # Returns
+    /// Returns true if an observer was replaced
+    pub fn set_observer<O: Observer<SM> + 'static>(&mut self, observer: O) -> bool {
+        self.observer.replace(Box::new(observer)).is_some()
+    }
+
+    /// Get a read-only reference to the observer, if it has type `O`
+    pub fn observer<O: Observer<SM> + 'static>(&self) -> Option<&O> {
+        self.observer.as_ref()?.as_any().downcast_ref()
+    }
+
+    /// Get a mutable reference to the observer, if it has type `O`
+    pub fn observer_mut<O: Observer<SM> + 'static>(&mut self) -> Option<&mut O> {
+        self.observer.as_mut()?.as_any_mut().downcast_mut()
+    }
+
+    /// Remove the observer from the instance, if it has type `O`
+    ///
+    /// An observer of another type stays in place.
+    pub fn take_observer<O: Observer<SM> + 'static>(&mut self) -> Option<O> {
+        if self.observer.as_ref()?.as_any().is::<O>() {
+            let observer = self.observer.take()?.into_any();
+            observer.downcast().ok().map(|observer| *observer)
+        } else {
+            None
+        }
+    }
+
+    /// Rebuild an instance by applying an input log from the initial state
+    ///
+    /// This is how an event-sourced aggregate is loaded: the log of accepted inputs is
+    /// the source of truth, and the instance, history included, is derived from it.
+    /// The instance has no callbacks while replaying, so no side effect runs twice.
+    ///
+    /// # Arguments
+    /// - `inputs`: The input log, oldest first
+    ///
+    /// # Returns
+    /// - `Ok(instance)`: Every input was applied
+    /// - `Err(error)`: The position and error of the first input that failed
+    ///
+    /// # Example
+    /// ```rust
+    /// use yasm::*;
+    /// define_state_machine! {
+    ///     name: Order,
+    ///     states: { Placed, Paid, Shipped },
+    ///     inputs: { Pay, Ship },
+    ///     initial: Placed,
+    ///     transitions: {
+    ///         Placed + Pay => Paid,
+    ///         Paid + Ship => Shipped
+    ///     }
+    /// }
+    ///
+    /// // The inputs stored for an order, e.g. loaded from an event store
+    /// let stored = vec![Input::Pay, Input::Ship];
+    /// let order = StateMachineInstance::<Order>::replay(stored).unwrap();
+    /// assert_eq!(*order.current_state(), State::Shipped);
+    /// assert_eq!(order.history_len(), 2);
+    ///
+    /// let error = StateMachineInstance::<Order>::replay([Input::Ship]).unwrap_err();
+    /// assert_eq!(error.index, 0);
+    /// ```
+    pub fn replay(inputs: impl IntoIterator<Item = SM::Input>) -> Result<Self, ReplayError<SM>>
+    where
+        SM::Context: Default,
+        SM::Config: Default,
+    {
+        Self::replay_with(inputs, ReplayMode::Strict).map(|(instance, _)| instance)
+    }
+
+    /// Rebuild an instance from an input log, choosing how failing inputs are treated
+    ///
+    /// # Arguments
+    /// - `inputs`: The input log, oldest first
+    /// - `mode`: Whether failing inputs stop the replay or are skipped
+    ///
+    /// # Returns
+    /// - `Ok((instance, skipped))`: The instance, and the positions of the inputs
+    ///   skipped in lenient mode
+    /// - `Err(error)`: In strict mode, the position and error of the first input that
+    ///   failed
+    pub fn replay_with(
+        inputs: impl IntoIterator<Item = SM::Input>,
+        mode: ReplayMode,
+    ) -> Result<(Self, Vec<usize>), ReplayError<SM>>
+    where
+        SM::Context: Default,
+        SM::Config: Default,
+    {
+        let mut instance = Self::new();
+        let skipped = instance.apply_log(inputs, mode)?;
+        Ok((instance, skipped))
+    }
+
+    /// Apply an input log to this instance, e.g. the inputs stored since a snapshot
+    ///
+    /// Callbacks registered on the instance run as usual.
+    ///
+    /// # Arguments
+    /// - `inputs`: The inputs to apply, oldest first
+    /// - `mode`: Whether failing inputs stop the replay or are skipped
+    ///
+    /// # Returns
+    /// - `Ok(skipped)`: The positions of the inputs skipped in lenient mode; inputs that
+    ///   took effect before an invariant failed are not skipped
+    /// - `Err(error)`: In strict mode, the position and error of the first input that
+    ///   failed; the inputs before it stay applied, as does the input itself if an
+    ///   invariant failed after it took effect
+    pub fn apply_log(
+        &mut self,
+        inputs: impl IntoIterator<Item = SM::Input>,
+        mode: ReplayMode,
+    ) -> Result<Vec<usize>, ReplayError<SM>> {
+        let mut skipped = Vec::new();
+        for (index, input) in inputs.into_iter().enumerate() {
+            if let Err(error) = self.transition(input) {
+                match mode {
+                    ReplayMode::Strict => return Err(ReplayError { index, error }),
+                    ReplayMode::Lenient if !error.took_effect() => skipped.push(index),
+                    ReplayMode::Lenient => {}
+                }
+            }
+        }
+        Ok(skipped)
+    }
+
+    /// Capture the durable part of the instance
+    ///
+    /// The snapshot holds the current state, the history with its metadata and the
+    /// history settings, in the current [format version](Snapshot::FORMAT_VERSION) and
+    /// with no snapshot metadata. Callbacks, the observer, queued and deferred inputs,
+    /// metrics, the context and the configuration are not part of it.
+    pub fn snapshot(&self) -> Snapshot<SM> {
+        Snapshot {
+            version: Snapshot::<SM>::FORMAT_VERSION,
+            current_state: self.current_state.clone(),
+            history: self.history_entries(),
+            next_seq: self.next_seq,
+            state_entered_at: self.state_entered_at,
+            max_history_size: self.max_history_size,
+            record_ignored: self.record_ignored,
+            skip_self_loops_in_history: self.skip_self_loops_in_history,
+            skip_internal_in_history: self.skip_internal_in_history,
+            inputs_ignored_in_history: self.inputs_ignored_in_history.clone(),
+            transition_seq: self.transition_seq,
+            unrecorded_since_last_entry: self.unrecorded_since_last_entry,
+            metadata: BTreeMap::new(),
+        }
+    }
+
+    /// Recreate an instance from a snapshot, with a default context and configuration
+    ///
+    /// # Returns
+    /// - `Ok(instance)`: The instance, without callbacks
+    /// - `Err(error_message)`: The snapshot is inconsistent, see
+    ///   [`restore_with_context_and_config`](Self::restore_with_context_and_config)
+    pub fn restore(snapshot: Snapshot<SM>) -> Result<Self, String>
+    where
+        SM::Context: Default,
+        SM::Config: Default,
+    {
+        Self::restore_with_context_and_config(
+            snapshot,
+            SM::Context::default(),
+            SM::Config::default(),
+        )
+    }
+
+    /// Recreate an instance from a snapshot, with the given context and configuration
+    ///
+    /// # Returns
+    /// - `Ok(instance)`: The instance, without callbacks
+    /// - `Err(error_message)`: The history is longer than its maximum size, or its
+    ///   sequence numbers are out of order
+    pub fn restore_with_context_and_config(
+        snapshot: Snapshot<SM>,
+        context: SM::Context,
+        config: SM::Config,
+    ) -> Result<Self, String> {
+        if snapshot.history.len() > snapshot.max_history_size {
+            return Err(format!(
+                "history has {} entries, more than the maximum of {}",
+                snapshot.history.len(),
+                snapshot.max_history_size
+            ));
+        }
+
+        let mut instance = Self::with_context_and_config(context, config);
+        instance.current_state = snapshot.current_state;
+        instance.next_seq = snapshot.next_seq;
+        instance.state_entered_at = snapshot.state_entered_at;
+        instance.max_history_size = snapshot.max_history_size;
+        instance.record_ignored = snapshot.record_ignored;
+        instance.skip_self_loops_in_history = snapshot.skip_self_loops_in_history;
+        instance.skip_internal_in_history = snapshot.skip_internal_in_history;
+        instance.ignore_in_history(snapshot.inputs_ignored_in_history);
+        instance.transition_seq = snapshot.transition_seq;
+        instance.unrecorded_since_last_entry = snapshot.unrecorded_since_last_entry;
+        for entry in snapshot.history {
+            // Annotation lookups rely on ascending sequence numbers
+            if instance
+                .last_history_seq()
+                .is_some_and(|last| entry.seq <= last)
+                || entry.seq >= snapshot.next_seq
+            {
+                return Err(format!(
+                    "history sequence number {} is out of order",
+                    entry.seq
+                ));
+            }
+            instance.history.push_back((entry.from, entry.input));
+            instance.history_meta.push_back(HistoryMeta {
+                seq: entry.seq,
+                annotations: entry.annotations,
+                entered_at: entry.entered_at,
+                exited_at: entry.exited_at,
+                trace: entry.trace,
+                follows_unrecorded: entry.follows_unrecorded,
+            });
+        }
+        Ok(instance)
+    }
+
+    /// Start or stop collecting metrics
+    ///
+    /// Collection starts from empty metrics, and stopping it drops them.
+    ///
+    /// # Arguments
+    /// * `collect` - Whether to collect metrics
+    pub fn set_collect_metrics(&mut self, collect: bool) {
+        match (collect, &self.metrics) {
+            (true, None) => self.metrics = Some(TransitionMetrics::new()),
+            (false, _) => self.metrics = None,
+            (true, Some(_)) => {}
+        }
+    }
+
+    /// Get the collected metrics, or None if they aren't collected
+    pub fn metrics(&self) -> Option<&TransitionMetrics<SM>> {
+        self.metrics.as_ref()
+    }
+
+    /// Set the write-ahead log of the instance, replacing the previous one
+    ///
+    /// Every transition that passed its checks is appended to the log before the
+    /// state is exited and any other callback runs. If appending fails, the
+    /// transition fails with [`TransitionError::LogFailed`] and the instance is left
+    /// unchanged. Undoing and resetting are not logged.
+    ///
+    /// Records are numbered by [`transition_seq`](Self::transition_seq), so the
+    /// records to replay after restoring a snapshot are those numbered from the
+    /// snapshot's `transition_seq` on.
+    ///
+    /// # Example
+    /// ```rust
+    /// use yasm::*;
+    /// define_state_machine! {
+    ///     name: Order,
+    ///     states: { Placed, Paid, Shipped },
+    ///     inputs: { Pay, Ship },
+    ///     initial: Placed,
+    ///     transitions: {
+    ///         Placed + Pay => Paid,
+    ///         Paid + Ship => Shipped
+    ///     }
+    /// }
+    ///
+    /// // A `FileLog` would keep the records across restarts
+    /// let log = MemoryLog::<Order>::new();
+    /// let mut order = StateMachineInstance::<Order>::new();
+    /// order.set_transition_log(log.clone());
+    /// order.transition(Input::Pay).unwrap();
+    ///
+    /// // Rebuild the order from its log and keep appending to it
+    /// let mut order = StateMachineInstance::<Order>::replay(log.inputs()).unwrap();
+    /// order.set_transition_log(log.clone());
+    /// order.transition(Input::Ship).unwrap();
+    /// assert_eq!(log.inputs(), vec![Input::Pay, Input::Ship]);
+    /// ```
+    ///
+    /// # Returns
+    /// Returns true if a log was replaced
+    pub fn set_transition_log<L: TransitionLog<SM> + 'static>(&mut self, log: L) -> bool {
+        self.transition_log.replace(Box::new(log)).is_some()
+    }
+
+    /// Remove the write-ahead log, so later transitions are not appended anywhere
+    ///
+    /// # Returns
+    /// Returns true if a log was set
+    pub fn remove_transition_log(&mut self) -> bool {
+        self.transition_log.take().is_some()
+    }
+
+    /// Check whether a write-ahead log is set
+    pub fn has_transition_log(&self) -> bool {
+        self.transition_log.is_some()
+    }
+
+    /// Get the sequence number of the next transition, i.e. the number of transitions
+    /// taken since the instance was created, whether logged or not
+    pub fn transition_seq(&self) -> u64 {
+        self.transition_seq
+    }
+
+    /// Register an invariant that must hold after every transition
+    ///
+    /// Invariants catch the context drifting away from the state early, e.g. a paid
+    /// order without a payment reference. They are checked in registration order once
+    /// a transition has taken effect; ignored and deferred inputs don't trigger them.
+    /// A failure is handled according to the [`InvariantPolicy`]. An invariant with the
+    /// same name is replaced.
+    ///
+    /// # Arguments
+    /// - `name`: The name reported when the invariant fails
+    /// - `check`: The predicate, receiving the current state and the context
+    ///
+    /// # Example
+    /// ```rust
+    /// use yasm::*;
+    /// #[derive(Default)]
+    /// pub struct Payment {
+    ///     pub receipt: Option<String>,
+    /// }
+    ///
+    /// define_state_machine! {
+    ///     name: Order,
+    ///     states: { Placed, Paid },
+    ///     inputs: { Pay },
+    ///     initial: Placed,
+    ///     context: Payment,
+    ///     transitions: { Placed + Pay => Paid }
+    /// }
+    ///
+    /// let mut order = StateMachineInstance::<Order>::new();
+    /// order.set_invariant_policy(InvariantPolicy::Error);
+    /// order.add_invariant("paid orders have a receipt", |state, context| {
+    ///     *state != State::Paid || context.receipt.is_some()
+    /// });
+    ///
+    /// // The transition takes effect, but the missing receipt is reported
+    /// assert!(matches!(
+    ///     order.transition(Input::Pay),
+    ///     Err(TransitionError::InvariantViolated { reason, .. }) if reason == "paid orders have a receipt"
+    /// ));
+    /// assert_eq!(*order.current_state(), State::Paid);
+    /// ```
+    pub fn add_invariant<F>(&mut self, name: impl Into<String>, check: F)
+    where
+        F: Fn(&SM::State, &SM::Context) -> bool + Send + Sync + 'static,
+    {
+        self.invariants.add(name.into(), Box::new(check));
+    }
+
+    /// Remove the invariant with the given name
+    ///
+    /// # Returns
+    /// Returns true if an invariant was removed
+    pub fn remove_invariant(&mut self, name: &str) -> bool {
+        self.invariants.remove(name)
+    }
+
+    /// Get the names of the registered invariants, in registration order
+    pub fn invariant_names(&self) -> Vec<&str> {
+        self.invariants.names()
+    }
+
+    /// Check the invariants against the current state and context
+    ///
+    /// # Returns
+    /// - `Ok(())`: Every invariant holds
+    /// - `Err(name)`: The name of the first invariant that fails
+    pub fn check_invariants(&self) -> Result<(), String> {
+        self.invariants.check(&self.current_state, &self.context)
+    }
+
+    /// Set what happens when an invariant fails after a transition
+    pub fn set_invariant_policy(&mut self, policy: InvariantPolicy) {
+        self.invariants.policy = policy;
+    }
+
+    /// Get what happens when an invariant fails after a transition
+    pub fn invariant_policy(&self) -> InvariantPolicy {
+        self.invariants.policy
+    }
+
+    /// Set the handler of invariant failures, used with [`InvariantPolicy::Callback`]
+    ///
+    /// # Arguments
+    /// - `handler`: Receives the name of the failed invariant, the current state and
+    ///   the context
+    pub fn on_invariant_violation<F>(&mut self, handler: F)
+    where
+        F: Fn(&str, &SM::State, &SM::Context) + Send + Sync + 'static,
+    {
+        self.invariants.handler = Some(Box::new(handler));
+    }
+
+    /// Subscribe to the transitions of the instance
+    ///
+    /// Every successful transition, internal ones included, is sent to the returned
+    /// receiver after the observer has seen it. The receiver can be moved to another
+    /// thread, or drained from an async task, so reacting to changes needs no closure
+    /// sharing state through locks. Ignored and deferred inputs are not sent. A
+    /// subscription ends when its receiver is dropped.
+    ///
+    /// # Example
+    /// ```rust
+    /// use yasm::*;
+    /// define_state_machine! {
+    ///     name: Order,
+    ///     states: { Placed, Paid, Shipped },
+    ///     inputs: { Pay, Ship },
+    ///     initial: Placed,
+    ///     transitions: {
+    ///         Placed + Pay => Paid,
+    ///         Paid + Ship => Shipped
+    ///     }
+    /// }
+    ///
+    /// let mut order = StateMachineInstance::<Order>::new();
+    /// let events = order.subscribe();
+    /// let watcher = std::thread::spawn(move || {
+    ///     events
+    ///         .into_iter()
+    ///         .map(|event| format!("{:?} -> {:?}", event.from, event.to))
+    ///         .collect::<Vec<_>>()
+    /// });
+    ///
+    /// order.transition(Input::Pay).unwrap();
+    /// order.transition(Input::Ship).unwrap();
+    /// // Dropping the instance drops the sender, which ends the subscription
+    /// drop(order);
+    /// assert_eq!(watcher.join().unwrap(), ["Placed -> Paid", "Paid -> Shipped"]);
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn subscribe(&mut self) -> mpsc::Receiver<TransitionEvent<SM>> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.push(sender);
+        receiver
+    }
+
+    /// Get the number of subscriptions whose receiver was alive at the last transition
+    #[cfg(feature = "std")]
+    pub fn subscriber_count(&self) -> usize {
+        self.subscribers.len()
+    }
+
     /// Get the total number of registered callbacks
     ///
     /// # Returns
@@ -294,8 +2289,54 @@ impl<SM: StateMachine> StateMachineInstance<SM> {
     }
 }
 
-impl<SM: StateMachine> Default for StateMachineInstance<SM> {
+impl<SM: StateMachine> Default for StateMachineInstance<SM>
+where
+    SM::Context: Default,
+    SM::Config: Default,
+{
     fn default() -> Self {
         Self::new()
     }
 }
+
+impl<SM: StateMachine> core::fmt::Debug for StateMachineInstance<SM> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("StateMachineInstance")
+            .field("current_state", &self.current_state)
+            .field("history", &self.history)
+            .field("max_history_size", &self.max_history_size)
+            .field("callback_registry", &self.callback_registry)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Serializes the current state, the history with its annotations and the history
+/// settings, see [`Snapshot`]. Callbacks, queued inputs, the context and the
+/// configuration are not part of the serialized form.
+#[cfg(feature = "serde")]
+impl<SM> serde::Serialize for StateMachineInstance<SM>
+where
+    SM: StateMachine,
+    SM::State: serde::Serialize,
+    SM::Input: serde::Serialize,
+{
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.snapshot().serialize(serializer)
+    }
+}
+
+/// Restores an instance with no callbacks, a default context and a default configuration.
+#[cfg(feature = "serde")]
+impl<'de, SM> serde::Deserialize<'de> for StateMachineInstance<SM>
+where
+    SM: StateMachine,
+    SM::State: serde::Deserialize<'de>,
+    SM::Input: serde::Deserialize<'de>,
+    SM::Context: Default,
+    SM::Config: Default,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let snapshot = Snapshot::<SM>::deserialize(deserializer)?;
+        Self::restore(snapshot).map_err(serde::de::Error::custom)
+    }
+}
@@ -0,0 +1,87 @@
+use crate::core::StateMachine;
+use alloc::{boxed::Box, string::String, vec::Vec};
+
+/// What happens when an invariant fails after a transition
+///
+/// Used by [`StateMachineInstance::add_invariant`](crate::StateMachineInstance::add_invariant).
+/// In every case the transition has already taken effect when its invariants are checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum InvariantPolicy {
+    /// Panic in debug builds, and report the failure like [`Error`](Self::Error) otherwise
+    #[default]
+    DebugPanic,
+    /// Report the failure as [`TransitionError::InvariantViolated`](crate::TransitionError::InvariantViolated)
+    Error,
+    /// Pass the failure to the handler set with
+    /// [`on_invariant_violation`](crate::StateMachineInstance::on_invariant_violation)
+    /// and let the transition succeed
+    Callback,
+}
+
+/// Predicate over the state and the context that must hold after every transition
+pub type Invariant<SM> =
+    Box<dyn Fn(&<SM as StateMachine>::State, &<SM as StateMachine>::Context) -> bool + Send + Sync>;
+
+/// Handler receiving the name of the failed invariant, the state and the context
+pub type InvariantViolationHandler<SM> =
+    Box<dyn Fn(&str, &<SM as StateMachine>::State, &<SM as StateMachine>::Context) + Send + Sync>;
+
+/// Named invariants of an instance, checked in registration order
+pub(crate) struct Invariants<SM: StateMachine> {
+    /// Registered invariants with their names
+    checks: Vec<(String, Invariant<SM>)>,
+    /// What happens when one of them fails
+    pub(crate) policy: InvariantPolicy,
+    /// Handler for [`InvariantPolicy::Callback`]
+    pub(crate) handler: Option<InvariantViolationHandler<SM>>,
+}
+
+impl<SM: StateMachine> Invariants<SM> {
+    /// Create an empty set with the default policy
+    pub(crate) fn new() -> Self {
+        Self {
+            checks: Vec::new(),
+            policy: InvariantPolicy::default(),
+            handler: None,
+        }
+    }
+
+    /// Register an invariant, replacing any invariant with the same name
+    pub(crate) fn add(&mut self, name: String, check: Invariant<SM>) {
+        match self
+            .checks
+            .iter_mut()
+            .find(|(existing, _)| *existing == name)
+        {
+            Some((_, existing)) => *existing = check,
+            None => self.checks.push((name, check)),
+        }
+    }
+
+    /// Remove the invariant with the given name
+    ///
+    /// # Returns
+    /// Returns true if an invariant was removed
+    pub(crate) fn remove(&mut self, name: &str) -> bool {
+        let before = self.checks.len();
+        self.checks.retain(|(existing, _)| existing != name);
+        self.checks.len() != before
+    }
+
+    /// Get the names of the registered invariants, in registration order
+    pub(crate) fn names(&self) -> Vec<&str> {
+        self.checks.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    /// Check every invariant against the state and the context
+    ///
+    /// # Returns
+    /// - `Ok(())`: Every invariant holds
+    /// - `Err(name)`: The name of the first invariant that fails
+    pub(crate) fn check(&self, state: &SM::State, context: &SM::Context) -> Result<(), String> {
+        match self.checks.iter().find(|(_, check)| !check(state, context)) {
+            Some((name, _)) => Err(name.clone()),
+            None => Ok(()),
+        }
+    }
+}
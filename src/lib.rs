@@ -15,7 +15,7 @@
 //! ## Basic Usage
 //!
 //! ```rust
-//! use yasm::*;
+//! use yasm::prelude::*;
 //!
 //! // Define state machine
 //! define_state_machine! {
@@ -44,25 +44,163 @@
 //! ## Module Structure
 //!
 //! - [`core`][]: Core trait and type definitions
+//! - [`any`][]: Object-safe view of instances for heterogeneous storage
+//! - [`backoff`][]: Exponential backoff for retry loops
+//! - [`callbacks`][]: Callback registry for state machine events
+//! - [`chaos`][]: Randomized robustness tests with invalid input injection
+//! - [`digest`][]: Batched delivery of transition events
 //! - [`instance`][]: State machine instance implementation
+//! - [`invariant`][]: Invariants checked on instances after every transition
+//! - [`observer`][]: Observers owned by instances, receiving events by message
+//! - [`ordering`][]: Contract test of the callback order of transitions
+//! - [`persistence`][]: Snapshots of instances, stores keeping them and transition logs
+//! - [`plan`][]: Drift detection against expected input plans
+//! - [`prelude`][]: Commonly used items, for glob import
 //! - [`query`][]: State machine query and analysis functionality
 //! - [`doc`][]: Documentation generation functionality
+//! - [`dynamic`][]: State machines built at runtime
+//! - `editor`: Editable JSON format for visual editors and tools, behind the `serde` feature
+//! - [`error`][]: Error types
+//! - [`fault`][]: Failure injection for resilience tests
+//! - [`hashing`][]: Hasher of internal maps, deterministic behind a feature
+//! - [`health`][]: Health summaries of instances
+//! - [`queue`][]: Input queue with coalescing rules
+//! - [`registry`][]: Bulk analysis across many machines
+//! - [`state_set`][]: Bitset-backed sets of states returned by queries
+//! - [`supervisor`][]: Fleets of instances with typed metadata
+//! - [`testing`][]: Fluent scenarios for behavior tests
+//! - `timers`: Timeouts fired by a wrapper instance, behind the `timers` feature
+//! - [`trace`][]: Trace context propagation through transitions
+//! - [`metrics`][]: Counters of transitions, time in states and rejections
+//! - `model_check`: Temporal properties with counterexamples, behind the `model-check` feature
 //! - [`macros`][]: Macro definitions
+//!
+//! With the `derive` feature, `state_machine!` accepts the syntax of
+//! [`define_state_machine!`] and reports mistakes in the definition at the offending name.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
 
 // Module declarations
+pub mod action;
+pub mod any;
+#[cfg(feature = "std")]
+pub mod backoff;
 pub mod callbacks;
+pub mod chaos;
 pub mod core;
+#[cfg(feature = "std")]
+pub mod digest;
 pub mod doc;
+pub mod dynamic;
+#[cfg(feature = "serde")]
+pub mod editor;
+pub mod error;
+#[cfg(feature = "std")]
+pub mod fault;
+pub mod hashing;
+pub mod health;
 pub mod instance;
+pub mod invariant;
 pub mod macros;
+pub mod metrics;
+#[cfg(feature = "model-check")]
+pub mod model_check;
+pub mod nfa;
+pub mod observer;
+#[cfg(feature = "std")]
+pub mod ordering;
+pub mod persistence;
+pub mod plan;
+pub mod prelude;
 pub mod query;
+pub mod queue;
+pub mod registry;
+mod rng;
+pub mod state_set;
+pub mod stochastic;
+#[cfg(feature = "proptest")]
+pub mod strategy;
+#[cfg(feature = "std")]
+pub mod supervisor;
+pub mod testing;
+#[cfg(feature = "timers")]
+pub mod timers;
+pub mod trace;
 
-// Re-export public interface
-pub use callbacks::CallbackRegistry;
+// Re-export public interface; see `prelude` for the commonly used subset
+pub use action::BoundAction;
+pub use any::AnyInstance;
+#[cfg(feature = "std")]
+pub use backoff::{BackoffHandle, BackoffPolicy, attach_backoff};
+pub use callbacks::{CallbackId, CallbackRegistry, TransitionEvent, TransitionOutcome};
+pub use chaos::{ChaosReport, ChaosTest, ChaosViolation};
 pub use core::StateMachine;
+#[cfg(feature = "std")]
+pub use digest::TransitionDigest;
 pub use doc::StateMachineDoc;
-pub use instance::StateMachineInstance;
-pub use query::StateMachineQuery;
+#[cfg(feature = "serde")]
+pub use dynamic::{DefinitionFormat, LoadError};
+pub use dynamic::{
+    DynDefinition, DynInstance, DynStateMachine, DynTransitionDefinition, StateMachineBuilder,
+};
+#[cfg(feature = "serde")]
+pub use editor::{
+    EDITOR_FORMAT_VERSION, EditorDocument, EditorInput, EditorLayout, EditorRule, EditorState,
+    EditorTransition,
+};
+pub use error::{ParseNameError, ReplayError, TransitionError, TryTransitionError};
+#[cfg(feature = "std")]
+pub use fault::{Fault, FaultRule, FaultyInstance};
+pub use health::{HealthReport, StuckReason};
+pub use instance::{HistoryEntry, ReplayMode, StateMachineInstance};
+pub use invariant::InvariantPolicy;
+pub use metrics::TransitionMetrics;
+#[cfg(feature = "model-check")]
+pub use model_check::{Counterexample, ModelChecker};
+pub use nfa::{Determinized, Nfa, StateSubset};
+pub use observer::{MachineEvent, Observer};
+#[cfg(feature = "std")]
+pub use ordering::{Hook, HookOrderReport, HookOrderTest, HookOrderViolation};
+#[cfg(feature = "std")]
+pub use persistence::MemoryLog;
+#[cfg(feature = "file-store")]
+pub use persistence::{FileLog, FileStore};
+pub use persistence::{LogRecord, MemoryStore, Persistence, Snapshot, TransitionLog};
+pub use plan::{PlanComparison, PlanDivergence};
+pub use query::{Exploration, InputSequences, Minimization, StateMachineQuery, WalkBias};
+pub use queue::{InputQueue, InvalidInputPolicy};
+pub use registry::{MachineReport, Registry, RegistryReport};
+pub use state_set::StateSet;
+pub use stochastic::{SimulationReport, StochasticInstance};
+#[cfg(feature = "proptest")]
+pub use strategy::Strategies;
+#[cfg(feature = "std")]
+pub use supervisor::{CompletionPolicy, Supervisor};
+pub use testing::Scenario;
+#[cfg(feature = "timers")]
+pub use timers::TimedInstance;
+pub use trace::TraceContext;
+#[cfg(feature = "derive")]
+pub use yasm_derive::state_machine;
+
+// Lets macro expansions build identifiers without depending on `paste` themselves
+#[doc(hidden)]
+pub use paste::paste as __paste;
+
+// Lets macro expansions name `alloc` items in `no_std` crates
+#[doc(hidden)]
+pub mod __private {
+    pub use alloc::format;
+    pub use alloc::string::{String, ToString};
+    pub use alloc::vec;
+    pub use alloc::vec::Vec;
+}
+
+// Lets `state_machine!` expansions refer to `::yasm` in this crate's own tests
+#[cfg(all(test, feature = "derive"))]
+extern crate self as yasm;
 
 /// Default maximum history size
 pub const DEFAULT_MAX_HISTORY_SIZE: usize = 512;
@@ -135,6 +273,30 @@ mod tests {
         assert_eq!(*sm.current_state(), State::Yellow);
     }
 
+    #[test]
+    fn test_transition_error_kinds() {
+        use ignoring_machine::{Input, Order, State};
+
+        let mut sm = StateMachineInstance::<Order>::new();
+        sm.transition(Input::Cancel).unwrap();
+
+        let error = sm.transition(Input::Pay).unwrap_err();
+        assert_eq!(
+            error,
+            TransitionError::InvalidInput {
+                state: State::Cancelled,
+                input: Input::Pay
+            }
+        );
+        assert_eq!(*error.state(), State::Cancelled);
+        assert_eq!(*error.input(), Input::Pay);
+        assert_eq!(error.to_string(), "Invalid input Pay for state Cancelled");
+
+        // Usable as a boxed standard error
+        let boxed: Box<dyn std::error::Error> = Box::new(error);
+        assert!(boxed.to_string().contains("Invalid input"));
+    }
+
     #[test]
     fn test_state_machine_instance_methods() {
         let mut sm = StateMachineInstance::<TrafficLight>::new();
@@ -189,168 +351,2963 @@ mod tests {
     }
 
     #[test]
-    fn test_mermaid_generation() {
-        let mermaid = StateMachineDoc::<TrafficLight>::generate_mermaid();
-        assert!(mermaid.contains("stateDiagram-v2"));
-        assert!(mermaid.contains("Red"));
-        assert!(mermaid.contains("Green"));
-        assert!(mermaid.contains("Yellow"));
-        assert!(mermaid.contains("Timer"));
-        assert!(mermaid.contains("Emergency"));
+    fn test_transition_visitor() {
+        type Query = StateMachineQuery<TrafficLight>;
+
+        let transitions = Query::transitions();
+        assert_eq!(transitions.len(), 6);
+        assert_eq!(transitions[0], (State::Red, Input::Timer, State::Green));
+        assert_eq!(
+            transitions[1],
+            (State::Red, Input::Emergency, State::Yellow)
+        );
+
+        let mut into_red = 0;
+        Query::for_each_transition(|_, _, to| {
+            if *to == State::Red {
+                into_red += 1;
+            }
+        });
+        assert_eq!(into_red, 3);
+
+        // Wildcards expand and final states contribute nothing
+        let transitions = StateMachineQuery::<final_machine::Delivery>::transitions();
+        assert_eq!(transitions.len(), final_machine::Delivery::TRANSITION_COUNT);
     }
 
+    #[cfg(feature = "petgraph")]
     #[test]
-    fn test_history_size_limit() {
-        let mut sm = StateMachineInstance::<TrafficLight>::with_max_history(2);
-        assert_eq!(sm.max_history_size(), 2);
+    fn test_to_graph() {
+        use petgraph::graph::NodeIndex;
 
-        // Execute multiple transitions
-        sm.transition(Input::Timer).unwrap(); // Red -> Green
-        sm.transition(Input::Timer).unwrap(); // Green -> Yellow
-        sm.transition(Input::Timer).unwrap(); // Yellow -> Red
+        let graph = StateMachineQuery::<TrafficLight>::to_graph();
+        assert_eq!(graph.node_count(), 3);
+        assert_eq!(graph.edge_count(), 6);
+        assert_eq!(graph[NodeIndex::new(0)], State::Red);
 
-        // History should only contain the last 2 transitions
-        assert_eq!(sm.history().len(), 2);
-        assert_eq!(sm.history()[0], (State::Green, Input::Timer));
-        assert_eq!(sm.history()[1], (State::Yellow, Input::Timer));
+        let red = NodeIndex::new(0);
+        let green = NodeIndex::new(2);
+        let edge = graph.find_edge(red, green).unwrap();
+        assert_eq!(graph[edge], Input::Timer);
+        assert!(petgraph::algo::has_path_connecting(
+            &graph, green, red, None
+        ));
     }
 
     #[test]
-    fn test_history_size_default() {
-        let sm = StateMachineInstance::<TrafficLight>::new();
-        assert_eq!(sm.max_history_size(), DEFAULT_MAX_HISTORY_SIZE);
+    fn test_distances() {
+        type Query = StateMachineQuery<TrafficLight>;
 
-        let sm_default = StateMachineInstance::<TrafficLight>::default();
-        assert_eq!(sm_default.max_history_size(), DEFAULT_MAX_HISTORY_SIZE);
+        let distances = Query::distances(&State::Red);
+        assert_eq!(distances[&State::Red], 0);
+        assert_eq!(distances[&State::Green], 1);
+        assert_eq!(distances[&State::Yellow], 1);
+        assert_eq!(Query::eccentricity(&State::Green), 1);
+        assert_eq!(Query::eccentricity(&State::Yellow), 2);
+
+        let all_pairs = Query::all_pairs_shortest_paths();
+        assert_eq!(all_pairs.len(), 3);
+        assert_eq!(all_pairs[&State::Yellow][&State::Green], 2);
+        assert_eq!(Query::diameter(), 2);
+
+        // Final states reach nothing else
+        use final_machine::{Delivery, State as DeliveryState};
+        assert_eq!(
+            StateMachineQuery::<Delivery>::eccentricity(&DeliveryState::Placed),
+            2
+        );
+        assert_eq!(
+            StateMachineQuery::<Delivery>::eccentricity(&DeliveryState::Delivered),
+            0
+        );
+        assert_eq!(
+            StateMachineQuery::<Delivery>::distances(&DeliveryState::Cancelled).len(),
+            1
+        );
     }
 
     #[test]
-    fn test_underscore_inputs_excluded_from_docs() {
-        let mermaid = StateMachineDoc::<test_machine::TestMachine>::generate_mermaid();
+    fn test_shortest_path_with_inputs() {
+        let steps = StateMachineQuery::<TrafficLight>::shortest_path_with_inputs(
+            &State::Green,
+            &State::Yellow,
+        )
+        .unwrap();
+        assert_eq!(steps, vec![(State::Green, Input::Timer)]);
 
-        // Should contain normal actions
-        assert!(mermaid.contains("Action"));
+        let steps = StateMachineQuery::<TrafficLight>::shortest_path_with_inputs(
+            &State::Yellow,
+            &State::Green,
+        )
+        .unwrap();
+        assert_eq!(
+            steps,
+            vec![(State::Yellow, Input::Timer), (State::Red, Input::Timer)]
+        );
 
-        // Should not contain underscore-prefixed actions
-        assert!(!mermaid.contains("_HiddenAction"));
-        assert!(!mermaid.contains("_Debug"));
+        // Replaying the inputs drives an instance to the target
+        let mut sm = StateMachineInstance::<TrafficLight>::new();
+        sm.transition(Input::Emergency).unwrap();
+        for (_, input) in steps {
+            sm.transition(input).unwrap();
+        }
+        assert_eq!(*sm.current_state(), State::Green);
 
-        let table = StateMachineDoc::<test_machine::TestMachine>::generate_transition_table();
+        assert_eq!(
+            StateMachineQuery::<TrafficLight>::shortest_path_with_inputs(&State::Red, &State::Red),
+            Some(vec![])
+        );
+        assert!(
+            StateMachineQuery::<workflow_machine::Article>::shortest_path_with_inputs(
+                &workflow_machine::State::Published,
+                &workflow_machine::State::Draft
+            )
+            .is_none()
+        );
+    }
 
-        // Should contain normal actions
-        assert!(table.contains("Action"));
+    #[test]
+    fn test_all_paths() {
+        let paths = StateMachineQuery::<TrafficLight>::all_paths(&State::Red, &State::Yellow, 5);
+        assert_eq!(
+            paths,
+            vec![
+                vec![State::Red, State::Green, State::Yellow],
+                vec![State::Red, State::Yellow]
+            ]
+        );
 
-        // Should not contain underscore-prefixed actions
-        assert!(!table.contains("_HiddenAction"));
-        assert!(!table.contains("_Debug"));
+        let paths = StateMachineQuery::<TrafficLight>::all_paths(&State::Red, &State::Yellow, 1);
+        assert_eq!(paths, vec![vec![State::Red, State::Yellow]]);
+
+        assert_eq!(
+            StateMachineQuery::<TrafficLight>::all_paths(&State::Red, &State::Red, 3),
+            vec![vec![State::Red]]
+        );
+    }
+
+    mod lint_machine {
+        use super::super::*;
+
+        define_state_machine! {
+            name: Ticket,
+            states: { New, Triaged, Closed, Escalated },
+            inputs: { Triage, Close, Escalate, Reopen },
+            initial: New,
+            transitions: {
+                New + Triage => Triaged,
+                Triaged + Close => Closed,
+                Escalated + Close => Closed,
+                Closed ignores Reopen,
+            }
+        }
+    }
+
+    mod trap_machine {
+        use super::super::*;
+
+        define_state_machine! {
+            name: Job,
+            states: { Queued, Running, Retrying, Failed, Polling, Waiting, Done },
+            inputs: { Start, Fail, Retry, Log, Poll, Finish },
+            initial: Queued,
+            transitions: {
+                Queued + Start => Running,
+                Running + Fail => Retrying,
+                Retrying + Retry => Running,
+                Retrying + Fail => Failed,
+                Failed + Log => internal,
+                Running + Poll => Polling,
+                Polling + Poll => Waiting,
+                Waiting + Poll => Polling,
+                Running + Finish => Done,
+            }
+        }
     }
 
     #[test]
-    fn test_underscore_inputs_still_functional() {
-        use test_machine::{Input, State, TestMachine};
+    fn test_inescapable_states() {
+        use trap_machine::{Job, State};
+        type Query = StateMachineQuery<Job>;
 
-        let mut sm = StateMachineInstance::<TestMachine>::new();
-        assert_eq!(*sm.current_state(), State::StateA);
+        assert_eq!(Query::terminal_states().to_vec(), vec![State::Done]);
+        assert_eq!(Query::absorbing_states().to_vec(), vec![State::Failed]);
+        // Running and Retrying form a loop, but it can be left
+        let traps = Query::trap_components();
+        assert_eq!(traps.len(), 1);
+        assert_eq!(traps[0].to_vec(), vec![State::Polling, State::Waiting]);
 
-        // Test that underscore inputs are still valid
-        let valid_inputs = sm.valid_inputs();
-        assert!(valid_inputs.contains(&Input::Action));
-        assert!(valid_inputs.contains(&Input::_HiddenAction));
-        assert!(valid_inputs.contains(&Input::_Debug));
+        assert!(StateMachineQuery::<TrafficLight>::absorbing_states().is_empty());
+        assert_eq!(
+            StateMachineQuery::<TrafficLight>::trap_components()[0].len(),
+            3
+        );
+    }
 
-        // Test underscore input transition functionality
-        let result = sm.transition(Input::_HiddenAction);
-        assert!(result.is_ok());
-        assert_eq!(*sm.current_state(), State::StateA);
+    #[test]
+    fn test_unreachable_states_and_unused_inputs() {
+        use lint_machine::{Input, State, Ticket};
 
-        let result = sm.transition(Input::_Debug);
-        assert!(result.is_ok());
-        assert_eq!(*sm.current_state(), State::StateA);
+        assert_eq!(
+            StateMachineQuery::<Ticket>::unreachable_states().to_vec(),
+            vec![State::Escalated]
+        );
+        // Ignoring an input doesn't count as using it
+        assert_eq!(
+            StateMachineQuery::<Ticket>::unused_inputs(),
+            vec![Input::Escalate, Input::Reopen]
+        );
 
-        // Test normal transition
-        let result = sm.transition(Input::Action);
-        assert!(result.is_ok());
-        assert_eq!(*sm.current_state(), State::StateB);
+        assert!(StateMachineQuery::<TrafficLight>::unreachable_states().is_empty());
+        assert!(StateMachineQuery::<TrafficLight>::unused_inputs().is_empty());
     }
 
     #[test]
-    fn test_display_implementation() {
-        assert_eq!(State::Red.to_string(), "Red");
-        assert_eq!(Input::Timer.to_string(), "Timer");
+    fn test_undefined_pairs() {
+        use lint_machine::{Input, State, Ticket};
+
+        assert!(StateMachineQuery::<TrafficLight>::is_complete());
+        assert!(StateMachineQuery::<TrafficLight>::undefined_pairs().is_empty());
+
+        let pairs = StateMachineQuery::<Ticket>::undefined_pairs();
+        assert!(!StateMachineQuery::<Ticket>::is_complete());
+        assert_eq!(pairs.len(), 12);
+        assert_eq!(pairs[0], (State::New, Input::Close));
+        // Explicitly ignored inputs are handled
+        assert!(!pairs.contains(&(State::Closed, Input::Reopen)));
+        assert!(pairs.contains(&(State::Closed, Input::Close)));
     }
 
     #[test]
-    fn test_documentation_generation() {
-        let stats = StateMachineDoc::<TrafficLight>::generate_statistics();
-        assert!(stats.contains("Number of States"));
-        assert!(stats.contains("Number of Transitions"));
+    fn test_conflicting_transitions() {
+        /// Hand-written machine listing an input twice and ignoring a transition
+        struct Relay;
 
-        let full_doc = StateMachineDoc::<TrafficLight>::generate_full_documentation();
-        assert!(full_doc.contains("State Machine Documentation"));
-        assert!(full_doc.contains("State Transition Table"));
-        assert!(full_doc.contains("State Diagram"));
+        #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+        enum State {
+            Off,
+            On,
+        }
+
+        #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+        enum Input {
+            Toggle,
+            Reset,
+        }
+
+        impl StateMachine for Relay {
+            type State = State;
+            type Input = Input;
+            type Context = ();
+            type Config = ();
+            type Output = ();
+
+            fn states() -> Vec<State> {
+                vec![State::Off, State::On]
+            }
+
+            fn inputs() -> Vec<Input> {
+                vec![Input::Toggle, Input::Reset]
+            }
+
+            fn valid_inputs(state: &State) -> Vec<Input> {
+                match state {
+                    State::Off => vec![Input::Toggle, Input::Toggle],
+                    State::On => vec![Input::Toggle, Input::Reset],
+                }
+            }
+
+            fn next_state(state: &State, input: &Input) -> Option<State> {
+                match (state, input) {
+                    (State::Off, Input::Toggle) => Some(State::On),
+                    (State::On, _) => Some(State::Off),
+                    _ => None,
+                }
+            }
+
+            fn is_ignored(state: &State, input: &Input) -> bool {
+                *state == State::On && *input == Input::Reset
+            }
+
+            fn initial_state() -> State {
+                State::Off
+            }
+
+            fn state_name(state: &State) -> String {
+                format!("{state:?}")
+            }
+
+            fn input_name(input: &Input) -> String {
+                format!("{input:?}")
+            }
+        }
+
+        assert_eq!(
+            StateMachineQuery::<Relay>::conflicting_transitions(),
+            vec![(State::Off, Input::Toggle), (State::On, Input::Reset)]
+        );
+        assert!(StateMachineQuery::<TrafficLight>::conflicting_transitions().is_empty());
+    }
+
+    mod counter_machine {
+        use super::super::*;
+
+        /// Hand-written machine whose states carry a payload
+        pub struct Counter;
+
+        #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+        pub enum State {
+            Counting(u32),
+            Done,
+        }
+
+        #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+        pub enum Input {
+            Inc,
+            Finish,
+        }
+
+        impl StateMachine for Counter {
+            type State = State;
+            type Input = Input;
+            type Context = ();
+            type Config = ();
+            type Output = ();
+
+            fn states() -> Vec<State> {
+                vec![State::Counting(0), State::Done]
+            }
+
+            fn inputs() -> Vec<Input> {
+                vec![Input::Inc, Input::Finish]
+            }
+
+            fn valid_inputs(state: &State) -> Vec<Input> {
+                match state {
+                    State::Counting(_) => Self::inputs(),
+                    State::Done => Vec::new(),
+                }
+            }
+
+            fn next_state(state: &State, input: &Input) -> Option<State> {
+                match (state, input) {
+                    (State::Counting(n), Input::Inc) => Some(State::Counting(n + 1)),
+                    (State::Counting(_), Input::Finish) => Some(State::Done),
+                    (State::Done, _) => None,
+                }
+            }
+
+            fn initial_state() -> State {
+                State::Counting(0)
+            }
+
+            fn state_name(state: &State) -> String {
+                format!("{state:?}")
+            }
+
+            fn input_name(input: &Input) -> String {
+                format!("{input:?}")
+            }
+        }
     }
 
     #[test]
-    fn test_state_from_str() {
-        // Test valid state strings
-        let red_state = State::from("Red");
-        assert_eq!(red_state, State::Red);
+    fn test_explore() {
+        use counter_machine::{Counter, Input, State};
 
-        let yellow_state = State::from("Yellow");
-        assert_eq!(yellow_state, State::Yellow);
+        // Without abstraction the state space is infinite
+        let exploration = StateMachineQuery::<Counter>::explore(5, |state| state.clone());
+        assert!(exploration.is_truncated());
+        assert_eq!(exploration.state_count(), 5);
 
-        let green_state = State::from("Green");
-        assert_eq!(green_state, State::Green);
+        // Bucketing counts >= 3 together makes it finite
+        let exploration = StateMachineQuery::<Counter>::explore(10, |state| match state {
+            State::Counting(n) => Some(*n.min(&3)),
+            State::Done => None,
+        });
+        assert!(!exploration.is_truncated());
+        assert_eq!(
+            exploration.states().cloned().collect::<Vec<_>>(),
+            vec![
+                State::Counting(0),
+                State::Counting(1),
+                State::Done,
+                State::Counting(2),
+                State::Counting(3)
+            ]
+        );
+        assert_eq!(exploration.transition_count(), 8);
+        assert_eq!(
+            exploration.path_to(&State::Done),
+            Some(&[Input::Finish][..])
+        );
+
+        let (state, path) = exploration
+            .find_violation(|state| !matches!(state, State::Counting(n) if *n >= 3))
+            .unwrap();
+        assert_eq!(*state, State::Counting(3));
+        assert_eq!(path, [Input::Inc, Input::Inc, Input::Inc]);
+        assert!(exploration.find_violation(|_| true).is_none());
     }
 
     #[test]
-    #[should_panic(expected = "Invalid state")]
-    fn test_state_from_str_invalid() {
-        // Test invalid state string - should panic
-        let _ = State::from("InvalidState");
+    fn test_enumerate_input_sequences() {
+        let sequences: Vec<_> =
+            StateMachineQuery::<TrafficLight>::enumerate_input_sequences(&State::Red, 2).collect();
+
+        // Two inputs are valid in every state: 2 sequences of length 1, 4 of length 2
+        assert_eq!(sequences.len(), 6);
+        assert_eq!(sequences[0], vec![Input::Timer]);
+        assert_eq!(sequences[1], vec![Input::Timer, Input::Timer]);
+        assert!(sequences.contains(&vec![Input::Emergency, Input::Timer]));
+
+        assert_eq!(
+            StateMachineQuery::<TrafficLight>::enumerate_input_sequences(&State::Red, 0).count(),
+            0
+        );
     }
 
     #[test]
-    fn test_input_from_str() {
-        // Test valid input strings
-        let timer_input = Input::from("Timer");
-        assert_eq!(timer_input, Input::Timer);
+    fn test_random_walk() {
+        let walk = StateMachineQuery::<TrafficLight>::random_walk(&State::Red, 20, 7);
+        assert_eq!(walk.len(), 20);
+        assert_eq!(walk[0].0, State::Red);
+        for pair in walk.windows(2) {
+            let (from, input) = &pair[0];
+            assert_eq!(
+                TrafficLight::next_state(from, input).as_ref(),
+                Some(&pair[1].0)
+            );
+        }
 
-        let emergency_input = Input::from("Emergency");
-        assert_eq!(emergency_input, Input::Emergency);
+        // The same seed yields the same walk
+        assert_eq!(
+            walk,
+            StateMachineQuery::<TrafficLight>::random_walk(&State::Red, 20, 7)
+        );
+        assert!(
+            (0..10)
+                .map(|seed| StateMachineQuery::<TrafficLight>::random_walk(&State::Red, 20, seed))
+                .any(|other| other != walk)
+        );
+
+        // Untaken transitions are taken first
+        for seed in 0..10 {
+            let walk = StateMachineQuery::<TrafficLight>::random_walk_with(
+                &State::Red,
+                20,
+                WalkBias::PreferUnvisitedTransitions,
+                seed,
+            );
+            for (step, (state, input)) in walk.iter().enumerate() {
+                let taken = &walk[..step];
+                let untaken_left = TrafficLight::valid_inputs_iter(state)
+                    .any(|other| !taken.contains(&(state.clone(), other)));
+                if untaken_left {
+                    assert!(!taken.contains(&(state.clone(), input.clone())));
+                }
+            }
+        }
+
+        use workflow_machine::{Article, State as ArticleState};
+        for seed in 0..10 {
+            let walk = StateMachineQuery::<Article>::random_walk_with(
+                &ArticleState::Draft,
+                10,
+                WalkBias::AvoidRevisits,
+                seed,
+            );
+            let mut visited: Vec<_> = walk.iter().map(|(state, _)| state.clone()).collect();
+            let (last, input) = walk.last().unwrap();
+            visited.push(Article::next_state(last, input).unwrap());
+            let count = visited.len();
+            visited.sort_by_key(|state| state.index());
+            visited.dedup();
+            assert_eq!(visited.len(), count);
+        }
+        assert!(
+            StateMachineQuery::<Article>::random_walk(&ArticleState::Published, 5, 0).is_empty()
+        );
+    }
+
+    mod legacy_machine {
+        use super::super::*;
+
+        define_state_machine! {
+            name: Legacy,
+            states: { Idle, CardPending, CashPending, Paid, Refunded },
+            inputs: { PayCard, PayCash, Confirm, Refund },
+            initial: Idle,
+            transitions: {
+                Idle + PayCard => CardPending,
+                Idle + PayCash => CashPending,
+                CardPending + Confirm => Paid,
+                CashPending + Confirm => Paid,
+                Paid + Refund => Refunded
+            }
+        }
+    }
+
+    mod refactored_machines {
+        pub mod merged {
+            use crate::*;
+
+            define_state_machine! {
+                name: Merged,
+                states: { Idle, Pending, Paid, Refunded },
+                inputs: { PayCash, PayCard, Confirm, Refund },
+                initial: Idle,
+                transitions: {
+                    Idle + PayCard => Pending,
+                    Idle + PayCash => Pending,
+                    Pending + Confirm => Paid,
+                    Paid + Refund => Refunded
+                }
+            }
+        }
+
+        pub mod broken {
+            use crate::*;
+
+            define_state_machine! {
+                name: Broken,
+                states: { Idle, Pending, Paid },
+                inputs: { PayCard, PayCash, Confirm },
+                initial: Idle,
+                transitions: {
+                    Idle + PayCard => Pending,
+                    Idle + PayCash => Pending,
+                    Pending + Confirm => Paid
+                }
+            }
+        }
     }
 
     #[test]
-    #[should_panic(expected = "Invalid input")]
-    fn test_input_from_str_invalid() {
-        // Test invalid input string - should panic
-        let _ = Input::from("InvalidInput");
+    fn test_equivalence() {
+        use legacy_machine::Legacy;
+        use refactored_machines::{broken::Broken, merged::Merged};
+
+        assert!(StateMachineQuery::<Legacy>::is_equivalent_to::<Legacy>());
+        assert!(StateMachineQuery::<Legacy>::is_equivalent_to::<Merged>());
+        assert!(StateMachineQuery::<Merged>::is_equivalent_to::<Legacy>());
+
+        assert_eq!(
+            StateMachineQuery::<Legacy>::distinguishing_sequence::<Broken>(),
+            Some(vec!["PayCard".into(), "Confirm".into(), "Refund".into()])
+        );
+        assert_eq!(
+            StateMachineQuery::<TrafficLight>::distinguishing_sequence::<Legacy>(),
+            Some(vec!["Timer".into()])
+        );
     }
 
-    #[cfg(feature = "serde")]
     #[test]
-    fn test_serde_serialization() {
-        // Test state serialization
-        let state = State::Red;
-        let serialized = serde_json::to_string(&state).unwrap();
-        assert_eq!(serialized, "\"Red\"");
+    fn test_minimize() {
+        use legacy_machine::{Legacy, State as LegacyState};
 
-        let deserialized: State = serde_json::from_str(&serialized).unwrap();
-        assert_eq!(deserialized, State::Red);
+        let minimization = StateMachineQuery::<Legacy>::minimize();
+        assert!(!minimization.is_minimal());
+        assert_eq!(
+            minimization.mergeable().collect::<Vec<_>>(),
+            vec![&[LegacyState::CardPending, LegacyState::CashPending][..]]
+        );
+        assert_eq!(minimization.classes().len(), 4);
+        assert_eq!(
+            minimization.representative(&LegacyState::CashPending),
+            Some(&LegacyState::CardPending)
+        );
 
-        // Test input serialization
-        let input = Input::Timer;
-        let serialized = serde_json::to_string(&input).unwrap();
-        assert_eq!(serialized, "\"Timer\"");
+        let quotient = minimization.quotient();
+        assert_eq!(quotient.states, ["Idle", "CardPending", "Paid", "Refunded"]);
+        assert!(quotient.transitions.contains(&DynTransitionDefinition {
+            from: "Idle".into(),
+            input: "PayCash".into(),
+            to: "CardPending".into(),
+        }));
+        let machine = quotient.build().unwrap();
+        assert_eq!(
+            machine.next_state(&"CardPending".to_string(), &"Confirm".to_string()),
+            Some(&"Paid".to_string())
+        );
 
-        let deserialized: Input = serde_json::from_str(&serialized).unwrap();
-        assert_eq!(deserialized, Input::Timer);
+        // Only behavior counts: every light accepts both inputs forever
+        assert_eq!(
+            StateMachineQuery::<TrafficLight>::minimize()
+                .classes()
+                .len(),
+            1
+        );
+        // Terminal states handle no input, so they are all alike
+        use workflow_machine::{Article, State as ArticleState};
+        assert_eq!(
+            StateMachineQuery::<Article>::minimize()
+                .mergeable()
+                .collect::<Vec<_>>(),
+            vec![&[ArticleState::Published, ArticleState::Archived][..]]
+        );
+    }
 
-        // Test multiple states
-        let states = vec![State::Red, State::Yellow, State::Green];
-        let serialized = serde_json::to_string(&states).unwrap();
-        let deserialized: Vec<State> = serde_json::from_str(&serialized).unwrap();
-        assert_eq!(deserialized, states);
+    #[test]
+    fn test_mermaid_generation() {
+        let mermaid = StateMachineDoc::<TrafficLight>::generate_mermaid();
+        assert!(mermaid.contains("stateDiagram-v2"));
+        assert!(mermaid.contains("Red"));
+        assert!(mermaid.contains("Green"));
+        assert!(mermaid.contains("Yellow"));
+        assert!(mermaid.contains("Timer"));
+        assert!(mermaid.contains("Emergency"));
+    }
+
+    #[test]
+    fn test_dot_generation() {
+        let dot = StateMachineDoc::<TrafficLight>::generate_dot();
+        assert!(dot.starts_with("digraph StateMachine {"));
+        assert!(dot.contains("__start -> \"Red\";"));
+        assert!(dot.contains("\"Red\" -> \"Green\" [label=\"Timer\"];"));
+        assert!(dot.contains("\"Green\" -> \"Red\" [label=\"Emergency\"];"));
+        assert!(dot.trim_end().ends_with('}'));
+
+        let dot = StateMachineDoc::<test_machine::TestMachine>::generate_dot();
+        assert!(dot.contains("\"StateA\" -> \"StateB\" [label=\"Action\"];"));
+        assert!(!dot.contains("_HiddenAction"));
+        assert!(!dot.contains("_Debug"));
+    }
+
+    mod saga_machine {
+        use super::super::*;
+
+        define_state_machine! {
+            name: Booking,
+            states: { Started, Reserved, Charged, Refunded, Released },
+            inputs: { Reserve, Charge, Fail, Release },
+            initial: Started,
+            transitions: {
+                Started + Reserve => Reserved,
+                Reserved + Charge => Charged,
+                Reserved + Fail => compensate Released,
+                Charged + Fail => compensate Refunded,
+                Refunded + Release => compensate Released
+            }
+        }
+    }
+
+    #[test]
+    fn test_compensation_docs() {
+        use saga_machine::{Booking, Input, State};
+
+        assert!(Booking::is_compensation(&State::Charged, &Input::Fail));
+        assert!(!Booking::is_compensation(&State::Reserved, &Input::Charge));
+
+        let mut sm = StateMachineInstance::<Booking>::new();
+        for input in [Input::Reserve, Input::Charge, Input::Fail, Input::Release] {
+            sm.transition(input).unwrap();
+        }
+        assert_eq!(*sm.current_state(), State::Released);
+
+        let mermaid = StateMachineDoc::<Booking>::generate_mermaid();
+        assert!(mermaid.contains("    Charged --> Refunded : ↩ Fail\n"));
+        assert!(mermaid.contains("    Reserved --> Charged : Charge\n"));
+
+        let dot = StateMachineDoc::<Booking>::generate_dot();
+        assert!(dot.contains(
+            "\"Charged\" -> \"Refunded\" [label=\"Fail\", style=dotted, color=red, fontcolor=red];"
+        ));
+        assert!(dot.contains("\"Reserved\" -> \"Charged\" [label=\"Charge\"];"));
+
+        let table = StateMachineDoc::<Booking>::generate_rollback_table();
+        assert!(table.contains("| Reserved | Fail | Reserved --Fail--> Released |\n"));
+        assert!(
+            table.contains(
+                "| Charged | Fail | Charged --Fail--> Refunded --Release--> Released |\n"
+            )
+        );
+        assert!(
+            StateMachineDoc::<TrafficLight>::generate_rollback_table()
+                .contains("No compensations declared.")
+        );
+    }
+
+    #[test]
+    fn test_matrix_generation() {
+        let matrix = StateMachineDoc::<TrafficLight>::generate_matrix_table();
+        assert!(matrix.contains("| State | Timer | Emergency |\n|-------|---|---|\n"));
+        assert!(matrix.contains("| Red | Green | Yellow |\n"));
+
+        let matrix = StateMachineDoc::<ignoring_machine::Order>::generate_matrix_table();
+        assert!(matrix.contains("| Paid | — | *(ignored)* |\n"));
+        assert!(matrix.contains("| Cancelled | — | — |\n"));
+
+        let matrix = StateMachineDoc::<test_machine::TestMachine>::generate_matrix_table();
+        assert!(!matrix.contains("_HiddenAction"));
+    }
+
+    #[test]
+    fn test_mermaid_with_current() {
+        let mut sm = StateMachineInstance::<TrafficLight>::new();
+        let diagram = StateMachineDoc::<TrafficLight>::generate_mermaid_with_current(&sm, true);
+        assert!(diagram.starts_with("stateDiagram-v2\n    [*] --> Red\n"));
+        assert!(diagram.contains("    classDef current "));
+        assert!(diagram.ends_with("    class Red current\n"));
+
+        sm.transition(Input::Timer).unwrap();
+        let diagram = StateMachineDoc::<TrafficLight>::generate_mermaid_with_current(&sm, true);
+        assert!(diagram.contains("    class Green current\n"));
+        assert!(diagram.contains("    class Red previous\n"));
+        assert!(diagram.ends_with("    note right of Green : via Timer from Red\n"));
+
+        let diagram = StateMachineDoc::<TrafficLight>::generate_mermaid_with_current(&sm, false);
+        assert!(!diagram.contains("previous"));
+        assert!(!diagram.contains("note"));
+    }
+
+    #[test]
+    fn test_history_path_generation() {
+        let mut sm = StateMachineInstance::<TrafficLight>::new();
+        sm.transition(Input::Timer).unwrap();
+        sm.transition(Input::Emergency).unwrap();
+        sm.transition(Input::Timer).unwrap();
+        let seq = sm.last_history_seq().unwrap();
+        sm.annotate_history(seq, "back to green").unwrap();
+
+        let sequence = StateMachineDoc::<TrafficLight>::generate_history_sequence(&sm);
+        assert_eq!(
+            sequence,
+            "sequenceDiagram\n    participant Red\n    participant Green\n\
+             \x20   Red->>Green: 1. Timer\n    Green->>Red: 2. Emergency\n\
+             \x20   Red->>Green: 3. Timer\n    Note over Green: back to green\n"
+        );
+
+        let path = StateMachineDoc::<TrafficLight>::generate_history_path_mermaid(&sm);
+        assert!(path.starts_with("stateDiagram-v2\n    [*] --> Red\n"));
+        assert!(path.contains("    Red --> Green : 1. Timer\n"));
+        assert!(path.contains("    Green --> Red : 2. Emergency\n"));
+        assert!(path.contains("    Red --> Green : 3. Timer\n"));
+        assert!(path.ends_with("    class Green current\n"));
+
+        let fresh = StateMachineInstance::<TrafficLight>::new();
+        assert_eq!(
+            StateMachineDoc::<TrafficLight>::generate_history_sequence(&fresh),
+            "sequenceDiagram\n    participant Red\n"
+        );
+    }
+
+    #[test]
+    fn test_timeline_generation() {
+        let mut sm = StateMachineInstance::<TrafficLight>::new();
+        for _ in 0..3 {
+            sm.transition(Input::Timer).unwrap();
+        }
+
+        let entries = sm.history_entries();
+        assert!(
+            entries
+                .iter()
+                .all(|entry| entry.entered_at <= entry.exited_at)
+        );
+        assert!(entries[1].entered_at >= entries[0].exited_at);
+        assert!(sm.state_entered_at() >= entries[2].exited_at);
+
+        let timeline = StateMachineDoc::<TrafficLight>::generate_timeline_mermaid(&sm);
+        assert!(timeline.starts_with("gantt\n    title State Timeline\n    dateFormat x\n"));
+        let sections: Vec<_> = timeline
+            .lines()
+            .filter(|line| line.trim_start().starts_with("section"))
+            .collect();
+        assert_eq!(
+            sections,
+            vec!["    section Red", "    section Yellow", "    section Green"]
+        );
+        assert_eq!(timeline.matches("    Timer :").count(), 3);
+        assert!(timeline.contains(&format!(
+            "    section Red\n    Timer :{}, {}\n    current :active, {}, ",
+            entries[0].entered_at,
+            entries[0].exited_at,
+            sm.state_entered_at()
+        )));
+    }
+
+    #[test]
+    fn test_example_code_generation() {
+        let code =
+            StateMachineDoc::<workflow_machine::Article>::generate_example_code("my_app::article");
+        assert!(code.contains("use my_app::article::{Article, Input, State};"));
+        assert!(code.contains("StateMachineInstance::<Article>::new()"));
+        assert!(code.contains("// Happy path: Draft -> Review -> Approved -> Published"));
+        assert!(code.contains(
+            "        Input::Submit,\n        Input::Approve,\n        Input::Publish,\n"
+        ));
+        assert!(code.contains("assert_eq!(*machine.current_state(), State::Published);"));
+        assert!(code.contains("match machine.transition(Input::Submit)"));
+
+        // Without terminal states the path leads to the farthest state
+        let code = StateMachineDoc::<TrafficLight>::generate_example_code("lights");
+        assert!(code.contains("// Happy path: Red -> Yellow\n"));
+        assert!(!code.contains("Rejected as expected"));
+    }
+
+    #[test]
+    fn test_history_size_limit() {
+        let mut sm = StateMachineInstance::<TrafficLight>::with_max_history(2);
+        assert_eq!(sm.max_history_size(), 2);
+
+        // Execute multiple transitions
+        sm.transition(Input::Timer).unwrap(); // Red -> Green
+        sm.transition(Input::Timer).unwrap(); // Green -> Yellow
+        sm.transition(Input::Timer).unwrap(); // Yellow -> Red
+
+        // History should only contain the last 2 transitions
+        assert_eq!(sm.history().len(), 2);
+        assert_eq!(sm.history()[0], (State::Green, Input::Timer));
+        assert_eq!(sm.history()[1], (State::Yellow, Input::Timer));
+    }
+
+    #[test]
+    fn test_history_annotations() {
+        let mut sm = StateMachineInstance::<TrafficLight>::with_max_history(2);
+        assert_eq!(sm.last_history_seq(), None);
+
+        sm.transition(Input::Timer).unwrap();
+        let first = sm.last_history_seq().unwrap();
+        sm.transition(Input::Emergency).unwrap();
+        let second = sm.last_history_seq().unwrap();
+        assert_eq!((first, second), (0, 1));
+
+        sm.annotate_history(second, "manual override").unwrap();
+        sm.annotate_history(second, "incident 1234").unwrap();
+        assert!(sm.annotate_history(7, "unknown").is_err());
+
+        let entries = sm.history_entries();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries.clone(), entries);
+        assert!(entries[0].annotations.is_empty());
+        assert_eq!(entries[1].seq, 1);
+        assert_eq!(entries[1].from, State::Green);
+        assert_eq!(
+            entries[1].annotations,
+            vec!["manual override", "incident 1234"]
+        );
+
+        // Evicted entries can no longer be annotated, sequence numbers keep counting
+        sm.transition(Input::Timer).unwrap();
+        assert!(sm.annotate_history(first, "too late").is_err());
+        assert_eq!(sm.history_entries()[0].seq, 1);
+        assert_eq!(sm.last_history_seq(), Some(2));
+    }
+
+    #[test]
+    fn test_undo() {
+        use std::sync::{Arc, Mutex};
+
+        let mut sm = StateMachineInstance::<TrafficLight>::new();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&events);
+        sm.on_any_state_entry(move |state| log.lock().unwrap().push(format!("enter {state:?}")));
+        let log = Arc::clone(&events);
+        sm.on_any_state_exit(move |state| log.lock().unwrap().push(format!("exit {state:?}")));
+
+        assert_eq!(sm.undo(), None);
+
+        sm.transition(Input::Timer).unwrap();
+        sm.transition(Input::Timer).unwrap();
+        events.lock().unwrap().clear();
+
+        assert_eq!(sm.undo(), Some(State::Green));
+        assert_eq!(*sm.current_state(), State::Green);
+        assert_eq!(sm.history_len(), 1);
+        assert_eq!(*events.lock().unwrap(), vec!["exit Yellow", "enter Green"]);
+
+        assert_eq!(sm.undo_n(5), 1);
+        assert_eq!(*sm.current_state(), State::Red);
+        assert!(sm.history_is_empty());
+    }
+
+    #[test]
+    fn test_undo_stops_at_unrecorded_transitions() {
+        let mut sm = StateMachineInstance::<TrafficLight>::new();
+        sm.ignore_in_history([Input::Emergency]);
+        sm.transition(Input::Timer).unwrap();
+        sm.transition(Input::Emergency).unwrap();
+
+        // Undoing would skip Green -> Red, so it is refused
+        assert!(!sm.can_undo());
+        assert_eq!(sm.undo(), None);
+        assert_eq!(*sm.current_state(), State::Red);
+        assert_eq!(sm.history_len(), 1);
+
+        // Recorded transitions after the gap can be undone, up to the gap
+        sm.transition(Input::Timer).unwrap();
+        assert!(sm.history_entries()[1].follows_unrecorded);
+        let mut restored = StateMachineInstance::<TrafficLight>::restore(sm.snapshot()).unwrap();
+        assert_eq!(sm.undo_n(5), 1);
+        assert_eq!(*sm.current_state(), State::Red);
+        assert!(!sm.can_undo());
+
+        assert_eq!(restored.undo_n(5), 1);
+        assert!(!restored.can_undo());
+
+        // Forcing a state without an entry is a gap too
+        sm.reset();
+        sm.transition(Input::Timer).unwrap();
+        assert!(sm.can_undo());
+        sm.force_state(State::Yellow, None);
+        assert_eq!(sm.undo(), None);
+        sm.force_state(State::Red, Some(Input::Emergency));
+        assert_eq!(sm.undo(), Some(State::Yellow));
+        assert_eq!(sm.undo(), None);
+    }
+
+    #[test]
+    fn test_undo_is_not_a_transition() {
+        #[derive(Default)]
+        struct Events(Vec<String>);
+
+        impl Observer<TrafficLight> for Events {
+            fn on_event(&mut self, event: &MachineEvent<TrafficLight>) {
+                self.0.push(match event {
+                    MachineEvent::Exited(state) => format!("exit {state:?}"),
+                    MachineEvent::Entered(state) => format!("enter {state:?}"),
+                    other => format!("{other:?}"),
+                });
+            }
+        }
+
+        let mut sm = StateMachineInstance::<TrafficLight>::new();
+        let log = MemoryLog::new();
+        sm.set_transition_log(log.clone());
+        sm.set_collect_metrics(true);
+        let events = sm.subscribe();
+        sm.transition(Input::Timer).unwrap();
+        let last_transition_at = sm.health().last_transition_at;
+        sm.set_observer(Events::default());
+
+        assert_eq!(sm.undo(), Some(State::Red));
+
+        // The observer sees the state change, like exit and entry callbacks
+        assert_eq!(
+            sm.observer::<Events>().unwrap().0,
+            vec!["exit Green", "enter Red"]
+        );
+
+        // Transition records are kept, so the log still replays the transition
+        assert_eq!(sm.transition_seq(), 1);
+        assert_eq!(log.inputs(), vec![Input::Timer]);
+        assert_eq!(sm.metrics().unwrap().total_transitions(), 1);
+        assert_eq!(sm.health().last_transition_at, last_transition_at);
+        assert_eq!(events.try_iter().count(), 1);
+
+        // History sequence numbers keep counting up
+        sm.transition(Input::Timer).unwrap();
+        assert_eq!(sm.history_entries()[0].seq, 1);
+    }
+
+    #[test]
+    fn test_history_filtering() {
+        use test_machine::{Input, State, TestMachine};
+
+        let mut sm = StateMachineInstance::<TestMachine>::new();
+        sm.ignore_in_history([Input::_Debug]);
+        sm.transition(Input::_Debug).unwrap();
+        sm.transition(Input::_HiddenAction).unwrap();
+        assert_eq!(sm.history_len(), 1);
+
+        sm.set_skip_self_loops_in_history(true);
+        assert!(sm.skips_self_loops_in_history());
+        sm.transition(Input::_HiddenAction).unwrap();
+        sm.transition(Input::Action).unwrap();
+        assert_eq!(
+            sm.history().iter().cloned().collect::<Vec<_>>(),
+            vec![
+                (State::StateA, Input::_HiddenAction),
+                (State::StateA, Input::Action)
+            ]
+        );
+        assert_eq!(*sm.current_state(), State::StateB);
+        assert_eq!(sm.inputs_ignored_in_history(), &[Input::_Debug]);
+    }
+
+    #[test]
+    fn test_history_size_default() {
+        let sm = StateMachineInstance::<TrafficLight>::new();
+        assert_eq!(sm.max_history_size(), DEFAULT_MAX_HISTORY_SIZE);
+
+        let sm_default = StateMachineInstance::<TrafficLight>::default();
+        assert_eq!(sm_default.max_history_size(), DEFAULT_MAX_HISTORY_SIZE);
+    }
+
+    #[test]
+    fn test_underscore_inputs_excluded_from_docs() {
+        let mermaid = StateMachineDoc::<test_machine::TestMachine>::generate_mermaid();
+
+        // Should contain normal actions
+        assert!(mermaid.contains("Action"));
+
+        // Should not contain underscore-prefixed actions
+        assert!(!mermaid.contains("_HiddenAction"));
+        assert!(!mermaid.contains("_Debug"));
+
+        let table = StateMachineDoc::<test_machine::TestMachine>::generate_transition_table();
+
+        // Should contain normal actions
+        assert!(table.contains("Action"));
+
+        // Should not contain underscore-prefixed actions
+        assert!(!table.contains("_HiddenAction"));
+        assert!(!table.contains("_Debug"));
+    }
+
+    #[test]
+    fn test_underscore_inputs_still_functional() {
+        use test_machine::{Input, State, TestMachine};
+
+        let mut sm = StateMachineInstance::<TestMachine>::new();
+        assert_eq!(*sm.current_state(), State::StateA);
+
+        // Test that underscore inputs are still valid
+        let valid_inputs = sm.valid_inputs();
+        assert!(valid_inputs.contains(&Input::Action));
+        assert!(valid_inputs.contains(&Input::_HiddenAction));
+        assert!(valid_inputs.contains(&Input::_Debug));
+
+        // Test underscore input transition functionality
+        let result = sm.transition(Input::_HiddenAction);
+        assert!(result.is_ok());
+        assert_eq!(*sm.current_state(), State::StateA);
+
+        let result = sm.transition(Input::_Debug);
+        assert!(result.is_ok());
+        assert_eq!(*sm.current_state(), State::StateA);
+
+        // Test normal transition
+        let result = sm.transition(Input::Action);
+        assert!(result.is_ok());
+        assert_eq!(*sm.current_state(), State::StateB);
+    }
+
+    // Sealed machine: editing the definition fails its generated test
+    mod sealed_machine {
+        use super::super::*;
+
+        define_state_machine! {
+            name: Turnstile,
+            states: { Locked, Unlocked },
+            inputs: { Coin, Push },
+            initial: Locked,
+            sealed: 0xeddd_06e8_65fa_bb07,
+            transitions: {
+                Locked + Coin => Unlocked,
+                Unlocked + Push => Locked,
+                Unlocked ignores Coin
+            }
+        }
+
+        // A second sealed machine in the same module gets its own test
+        define_state_machine! {
+            name: Valve,
+            state_name: ValveState,
+            input_name: ValveInput,
+            states: { Shut, Flowing },
+            inputs: { Turn },
+            initial: Shut,
+            sealed: 0x9d85_132c_acc4_d696,
+            transitions: {
+                Shut + Turn => Flowing,
+                Flowing + Turn => Shut
+            }
+        }
+    }
+
+    #[test]
+    fn test_definition_hash() {
+        let hash = sealed_machine::Turnstile::definition_hash();
+        assert_eq!(hash, sealed_machine::Turnstile::definition_hash());
+        assert_ne!(hash, TrafficLight::definition_hash());
+        assert_ne!(
+            test_machine::TestMachine::definition_hash(),
+            TrafficLight::definition_hash()
+        );
+    }
+
+    // Test machines generated into their own modules
+    mod module_machines {
+        use super::super::*;
+
+        pub struct Limits {
+            pub max: u32,
+        }
+
+        define_state_machine! {
+            name: Counter,
+            module: counter,
+            states: { Idle, Full },
+            inputs: { Add },
+            initial: Idle,
+            context: Limits,
+            transitions: {
+                Idle + Add => Full if |ctx| ctx.max > 0
+            }
+        }
+
+        define_state_machine! {
+            name: Switch,
+            module: switch,
+            states: { Off, On },
+            inputs: { Toggle },
+            initial: Off,
+            transitions: {
+                Off + Toggle => On,
+                On + Toggle => Off
+            }
+        }
+    }
+
+    #[test]
+    fn test_generated_modules() {
+        use module_machines::{Limits, counter, switch};
+
+        let mut counter = StateMachineInstance::<counter::Counter>::with_context(Limits { max: 1 });
+        counter.transition(counter::Input::Add).unwrap();
+        assert_eq!(*counter.current_state(), counter::State::Full);
+
+        let mut switch = StateMachineInstance::<switch::Switch>::new();
+        switch.transition(switch::Input::Toggle).unwrap();
+        assert_eq!(*switch.current_state(), switch::State::On);
+    }
+
+    mod named_machines {
+        use super::super::*;
+
+        define_state_machine! {
+            name: Kettle,
+            state_name: KettleState,
+            input_name: KettleInput,
+            output_name: KettleOutput,
+            states: { Cold, Boiling },
+            inputs: { Heat(watts: u32), Unplug },
+            outputs: { Whistle },
+            initial: Cold,
+            transitions: {
+                Cold + Heat => Boiling emits Whistle,
+                _ + Unplug => Cold,
+            }
+        }
+
+        define_state_machine! {
+            name: Lamp,
+            state_name: LampState,
+            input_name: LampInput,
+            output_name: LampOutput,
+            states: { Dark, Lit },
+            state_aliases: { "Off" => Dark, "On" => Lit },
+            inputs: { Toggle },
+            outputs: { Flash },
+            initial: Dark,
+            transitions: {
+                Dark + Toggle => Lit emits Flash,
+                Lit + Toggle => Dark,
+            }
+        }
+    }
+
+    #[test]
+    fn test_named_enums() {
+        use named_machines::*;
+
+        let mut kettle = StateMachineInstance::<Kettle>::new();
+        let outcome = kettle
+            .transition_with_outcome(KettleInput::Heat(2000))
+            .unwrap();
+        assert_eq!(outcome.output, Some(KettleOutput::Whistle));
+        assert_eq!(
+            kettle.transition(KettleInput::Unplug),
+            Ok(KettleState::Cold)
+        );
+        assert_eq!("Heat".parse(), Ok(KettleInput::Heat(0)));
+        assert_eq!(KettleState::iter().len(), Kettle::STATE_COUNT);
+
+        let mut lamp = StateMachineInstance::<Lamp>::new();
+        lamp.transition(LampInput::Toggle).unwrap();
+        assert_eq!(*lamp.current_state(), LampState::Lit);
+        assert_eq!(
+            Lamp::output(&LampState::Dark, &LampInput::Toggle),
+            Some(LampOutput::Flash)
+        );
+
+        // Former names still parse, the current name is used from then on
+        assert_eq!(LampState::try_from("Off"), Ok(LampState::Dark));
+        assert_eq!("On".parse(), Ok(LampState::Lit));
+        assert_eq!(LampState::Lit.to_string(), "Lit");
+        assert_eq!(LampState::all_names(), ["Dark", "Lit"]);
+
+        #[cfg(feature = "serde")]
+        {
+            let json = serde_json::to_string(&KettleInput::Heat(1500)).unwrap();
+            assert_eq!(json, r#"{"Heat":[1500]}"#);
+            assert_eq!(
+                serde_json::from_str::<KettleInput>(&json).unwrap(),
+                KettleInput::Heat(1500)
+            );
+            assert_eq!(
+                serde_json::from_str::<LampState>(r#""Lit""#).unwrap(),
+                LampState::Lit
+            );
+            assert_eq!(
+                serde_json::from_str::<LampState>(r#""On""#).unwrap(),
+                LampState::Lit
+            );
+            assert!(serde_json::from_str::<LampState>(r#""Glowing""#).is_err());
+        }
+    }
+
+    mod ignoring_machine {
+        use super::super::*;
+
+        define_state_machine! {
+            name: Order,
+            states: { Created, Paid, Cancelled },
+            inputs: { Pay, Cancel },
+            initial: Created,
+            transitions: {
+                Created + Pay => Paid,
+                Created + Cancel => Cancelled,
+                Paid ignores Cancel,
+            }
+        }
+    }
+
+    mod workflow_machine {
+        use super::super::*;
+
+        define_state_machine! {
+            name: Article,
+            states: { Draft, Review, Approved, Published, Archived },
+            inputs: { Submit, Reject, Approve, Publish, Shelve },
+            initial: Draft,
+            transitions: {
+                Draft + Submit => Review,
+                Review + Reject => Draft,
+                Review + Approve => Approved,
+                Approved + Publish => Published,
+                Approved + Shelve => Archived
+            }
+        }
+    }
+
+    #[test]
+    fn test_input_sequences() {
+        use workflow_machine::{Article, Input, State};
+        type Query = StateMachineQuery<Article>;
+
+        assert_eq!(
+            Query::input_sequence(&State::Draft, &State::Published),
+            Some(vec![Input::Submit, Input::Approve, Input::Publish])
+        );
+        assert_eq!(
+            Query::input_sequence(&State::Review, &State::Review),
+            Some(vec![])
+        );
+        assert_eq!(Query::input_sequence(&State::Archived, &State::Draft), None);
+
+        // The rework loop gives ever longer ways to publish
+        let sequences = Query::k_shortest_input_sequences(&State::Draft, &State::Published, 2);
+        assert_eq!(
+            sequences,
+            vec![
+                vec![Input::Submit, Input::Approve, Input::Publish],
+                vec![
+                    Input::Submit,
+                    Input::Reject,
+                    Input::Submit,
+                    Input::Approve,
+                    Input::Publish
+                ],
+            ]
+        );
+        let mut sm = StateMachineInstance::<Article>::new();
+        for input in sequences[1].clone() {
+            sm.transition(input).unwrap();
+        }
+        assert_eq!(*sm.current_state(), State::Published);
+
+        // Without loops there are only so many ways
+        assert_eq!(
+            Query::k_shortest_input_sequences(&State::Approved, &State::Archived, 5),
+            vec![vec![Input::Shelve]]
+        );
+        assert!(Query::k_shortest_input_sequences(&State::Published, &State::Draft, 5).is_empty());
+    }
+
+    #[test]
+    fn test_convergence_queries() {
+        use workflow_machine::{Article, State};
+
+        // The rework loop doesn't make review optional
+        assert_eq!(
+            StateMachineQuery::<Article>::inevitable_states(&State::Draft).to_vec(),
+            vec![State::Review, State::Approved]
+        );
+        assert!(StateMachineQuery::<Article>::inevitable_states(&State::Published).is_empty());
+        // Without terminal states nothing is inevitable
+        assert!(StateMachineQuery::<TrafficLight>::inevitable_states(&self::State::Red).is_empty());
+
+        // Every published article was reviewed and approved, despite the rework loop
+        assert_eq!(
+            StateMachineQuery::<Article>::dominators(&State::Published)
+                .unwrap()
+                .to_vec(),
+            vec![State::Draft, State::Review, State::Approved]
+        );
+        assert!(
+            StateMachineQuery::<Article>::dominators(&State::Draft)
+                .unwrap()
+                .is_empty()
+        );
+        // Yellow is reached both directly and through Green
+        assert_eq!(
+            StateMachineQuery::<TrafficLight>::dominators(&self::State::Yellow)
+                .unwrap()
+                .to_vec(),
+            vec![self::State::Red]
+        );
+        assert_eq!(
+            StateMachineQuery::<lint_machine::Ticket>::dominators(&lint_machine::State::Escalated),
+            None
+        );
+
+        assert_eq!(
+            StateMachineQuery::<Article>::common_ancestors(&State::Published, &State::Archived)
+                .to_vec(),
+            vec![State::Draft, State::Review, State::Approved]
+        );
+        assert_eq!(
+            StateMachineQuery::<Article>::common_ancestors(&State::Draft, &State::Published)
+                .to_vec(),
+            vec![State::Draft, State::Review]
+        );
+    }
+
+    #[test]
+    fn test_ignored_inputs() {
+        use ignoring_machine::{Input, Order, State};
+
+        let mut sm = StateMachineInstance::<Order>::new();
+        sm.transition(Input::Pay).unwrap();
+
+        // Ignored input is accepted but has no effect
+        assert!(sm.can_accept(&Input::Cancel));
+        assert!(sm.is_ignored(&Input::Cancel));
+        assert!(!sm.valid_inputs().contains(&Input::Cancel));
+        assert_eq!(sm.transition(Input::Cancel), Ok(State::Paid));
+        assert_eq!(sm.history_len(), 1);
+
+        // Optionally recorded in history
+        sm.set_record_ignored(true);
+        sm.transition(Input::Cancel).unwrap();
+        assert_eq!(sm.history_len(), 2);
+        assert_eq!(sm.history()[1], (State::Paid, Input::Cancel));
+
+        // Not ignored in other states: still rejected where undefined
+        let mut sm = StateMachineInstance::<Order>::new();
+        sm.transition(Input::Cancel).unwrap();
+        assert!(sm.transition(Input::Pay).is_err());
+
+        let table = StateMachineDoc::<Order>::generate_transition_table();
+        assert!(table.contains("| Paid | Cancel | *(ignored)* |"));
+    }
+
+    mod guarded_machine {
+        use super::super::*;
+
+        #[derive(Default)]
+        pub struct Warehouse {
+            pub inventory: u32,
+        }
+
+        define_state_machine! {
+            name: Shipping,
+            states: { Paid, Shipped, Backordered },
+            inputs: { Ship, Restock },
+            initial: Paid,
+            context: Warehouse,
+            transitions: {
+                Paid + Ship => Shipped if |ctx| ctx.inventory > 0,
+                Paid + Restock => Backordered if |ctx| ctx.inventory == 0,
+                Backordered + Restock => Paid
+            }
+        }
+    }
+
+    #[test]
+    fn test_guarded_transitions() {
+        use guarded_machine::{Input, Shipping, State, Warehouse};
+
+        let mut sm = StateMachineInstance::<Shipping>::new();
+        assert_eq!(sm.context().inventory, 0);
+
+        // Guard rejects: state and history are untouched
+        let result = sm.transition(Input::Ship);
+        assert_eq!(
+            result,
+            Err(TransitionError::GuardRejected {
+                state: State::Paid,
+                input: Input::Ship
+            })
+        );
+        assert_eq!(*sm.current_state(), State::Paid);
+        assert!(sm.history_is_empty());
+
+        sm.transition(Input::Restock).unwrap();
+        assert_eq!(*sm.current_state(), State::Backordered);
+
+        let mut sm = StateMachineInstance::<Shipping>::with_context(Warehouse { inventory: 3 });
+        assert!(sm.transition(Input::Restock).is_err());
+        assert_eq!(sm.transition(Input::Ship), Ok(State::Shipped));
+    }
+
+    #[test]
+    fn test_context_actions() {
+        use guarded_machine::{Input, Shipping, State};
+
+        let mut sm = StateMachineInstance::<Shipping>::new();
+        sm.context_mut().inventory = 2;
+
+        // Actions update the context, which later guards observe
+        sm.on_transition_action(State::Paid, Input::Ship, |_, _, _, ctx| {
+            ctx.inventory -= 1;
+        });
+        sm.on_any_transition_action(|from, _, to, ctx| {
+            if from != to {
+                ctx.inventory += 10;
+            }
+        });
+
+        sm.transition(Input::Ship).unwrap();
+        assert_eq!(sm.context().inventory, 11);
+
+        let previous = sm.set_context(guarded_machine::Warehouse { inventory: 0 });
+        assert_eq!(previous.inventory, 11);
+        assert_eq!(sm.context().inventory, 0);
+    }
+
+    #[test]
+    fn test_entry_validation() {
+        use guarded_machine::{Input, Shipping, State, Warehouse};
+        use std::sync::{Arc, Mutex};
+
+        let mut sm = StateMachineInstance::<Shipping>::with_context(Warehouse { inventory: 3 });
+        sm.on_validate_entry(State::Shipped, |ctx| {
+            if ctx.inventory >= 5 {
+                Ok(())
+            } else {
+                Err("not enough inventory for a full pallet".into())
+            }
+        });
+        let exits = Arc::new(Mutex::new(0));
+        let exits_clone = Arc::clone(&exits);
+        sm.on_state_exit(State::Paid, move |_| *exits_clone.lock().unwrap() += 1);
+
+        // Guard passes but validation vetoes: nothing takes effect
+        let error = sm.transition(Input::Ship).unwrap_err();
+        assert!(matches!(
+            &error,
+            TransitionError::EntryRejected { target: State::Shipped, reason, .. }
+                if reason.contains("not enough inventory")
+        ));
+        assert_eq!(
+            error.to_string(),
+            "Entry into state Shipped rejected: not enough inventory for a full pallet"
+        );
+        assert_eq!(*sm.current_state(), State::Paid);
+        assert!(sm.history_is_empty());
+        assert_eq!(*exits.lock().unwrap(), 0);
+        assert_eq!(sm.callback_count(), 2);
+
+        let mut sm = StateMachineInstance::<Shipping>::with_context(Warehouse { inventory: 5 });
+        sm.on_validate_entry(State::Shipped, |ctx| {
+            (ctx.inventory >= 5)
+                .then_some(())
+                .ok_or_else(|| "too few".to_string())
+        });
+        assert_eq!(sm.transition(Input::Ship), Ok(State::Shipped));
+    }
+
+    #[test]
+    fn test_entry_validation_on_self_loops() {
+        use internal_machine::{Input, Session, State};
+
+        let mut sm = StateMachineInstance::<Session>::with_context(1);
+        sm.on_validate_entry(State::Active, |limit| {
+            (*limit > 1)
+                .then_some(())
+                .ok_or_else(|| "limit too low".to_string())
+        });
+
+        // External self-loops re-enter the state and are validated
+        assert!(matches!(
+            sm.transition(Input::Refresh),
+            Err(TransitionError::EntryRejected {
+                target: State::Active,
+                ..
+            })
+        ));
+        assert!(sm.history_is_empty());
+
+        // Internal transitions don't enter the state
+        assert_eq!(sm.transition(Input::Log), Ok(State::Active));
+
+        sm.set_context(2);
+        assert_eq!(sm.transition(Input::Refresh), Ok(State::Active));
+    }
+
+    #[test]
+    fn test_invariants() {
+        use guarded_machine::{Input, Shipping, State, Warehouse};
+        use std::sync::{Arc, Mutex};
+
+        let mut sm = StateMachineInstance::<Shipping>::with_context(Warehouse { inventory: 1 });
+        sm.on_transition_action(State::Paid, Input::Ship, |_, _, _, ctx| {
+            ctx.inventory -= 1;
+        });
+        sm.add_invariant("stock while paid", |state, ctx| {
+            *state != State::Paid || ctx.inventory > 0
+        });
+        sm.add_invariant("shipped from stock", |state, ctx| {
+            *state != State::Shipped || ctx.inventory > 0
+        });
+        assert_eq!(
+            sm.invariant_names(),
+            vec!["stock while paid", "shipped from stock"]
+        );
+        assert_eq!(sm.check_invariants(), Ok(()));
+
+        // The transition takes effect before the failure is reported
+        sm.set_invariant_policy(InvariantPolicy::Error);
+        let error = sm.transition(Input::Ship).unwrap_err();
+        assert_eq!(
+            error,
+            TransitionError::InvariantViolated {
+                state: State::Paid,
+                input: Input::Ship,
+                target: State::Shipped,
+                reason: "shipped from stock".into(),
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "Invariant shipped from stock violated by transition from state Paid to Shipped"
+        );
+        assert_eq!(*sm.current_state(), State::Shipped);
+        assert_eq!(sm.check_invariants(), Err("shipped from stock".into()));
+
+        // Callbacks receive the failure and the transition succeeds
+        let mut sm = StateMachineInstance::<Shipping>::new();
+        sm.add_invariant("stock while paid", |state, ctx| {
+            *state != State::Paid || ctx.inventory > 0
+        });
+        sm.set_invariant_policy(InvariantPolicy::Callback);
+        let violations = Arc::new(Mutex::new(Vec::new()));
+        let violations_clone = Arc::clone(&violations);
+        sm.on_invariant_violation(move |name, state, _| {
+            violations_clone
+                .lock()
+                .unwrap()
+                .push((name.to_string(), state.clone()));
+        });
+        sm.transition(Input::Restock).unwrap();
+        assert_eq!(sm.transition(Input::Restock), Ok(State::Paid));
+        assert_eq!(
+            *violations.lock().unwrap(),
+            vec![("stock while paid".to_string(), State::Paid)]
+        );
+
+        assert!(sm.remove_invariant("stock while paid"));
+        assert!(!sm.remove_invariant("stock while paid"));
+        assert!(sm.invariant_names().is_empty());
+    }
+
+    #[test]
+    fn test_invariant_violations_are_committed() {
+        use guarded_machine::{Input, Shipping, State};
+
+        // `Restock` leads Paid -> Backordered -> Paid; Backordered breaks the invariant
+        let mut sm = StateMachineInstance::<Shipping>::new();
+        sm.set_collect_metrics(true);
+        sm.set_invariant_policy(InvariantPolicy::Error);
+        sm.set_invalid_input_policy(InvalidInputPolicy::Defer);
+        sm.add_invariant("never backordered", |state, _| *state != State::Backordered);
+        sm.enqueue(Input::Restock);
+        sm.enqueue(Input::Restock);
+
+        // The violating input is applied once, counted and not deferred
+        assert_eq!(sm.process_queue(), Ok(2));
+        assert_eq!(*sm.current_state(), State::Paid);
+        assert_eq!(
+            sm.history().iter().cloned().collect::<Vec<_>>(),
+            vec![
+                (State::Paid, Input::Restock),
+                (State::Backordered, Input::Restock)
+            ]
+        );
+        assert_eq!(sm.deferred_inputs().count(), 0);
+        assert_eq!(sm.metrics().unwrap().total_rejections(), 0);
+
+        // Lenient replays don't report it as skipped
+        let mut sm = StateMachineInstance::<Shipping>::new();
+        sm.set_invariant_policy(InvariantPolicy::Error);
+        sm.add_invariant("never backordered", |state, _| *state != State::Backordered);
+        assert_eq!(
+            sm.apply_log([Input::Restock, Input::Ship], ReplayMode::Lenient),
+            Ok(vec![1])
+        );
+        assert_eq!(*sm.current_state(), State::Backordered);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "invariant never shipped violated")]
+    fn test_invariant_panics_in_debug_builds() {
+        use guarded_machine::{Input, Shipping, State, Warehouse};
+
+        let mut sm = StateMachineInstance::<Shipping>::with_context(Warehouse { inventory: 1 });
+        assert_eq!(sm.invariant_policy(), InvariantPolicy::DebugPanic);
+        sm.add_invariant("never shipped", |state, _| *state != State::Shipped);
+        let _ = sm.transition(Input::Ship);
+    }
+
+    #[test]
+    fn test_before_transition_veto() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::{Arc, Mutex};
+
+        let mut sm = StateMachineInstance::<TrafficLight>::new();
+        let locked = Arc::new(AtomicBool::new(true));
+        let lock = Arc::clone(&locked);
+        sm.on_before_transition(State::Red, Input::Timer, move |from, _, to| {
+            assert_eq!((from, to), (&State::Red, &State::Green));
+            if lock.load(Ordering::SeqCst) {
+                Err("intersection locked".into())
+            } else {
+                Ok(())
+            }
+        });
+        let exits = Arc::new(Mutex::new(0));
+        let exits_clone = Arc::clone(&exits);
+        sm.on_state_exit(State::Red, move |_| *exits_clone.lock().unwrap() += 1);
+
+        let error = sm.transition(Input::Timer).unwrap_err();
+        assert_eq!(
+            error,
+            TransitionError::Vetoed {
+                state: State::Red,
+                input: Input::Timer,
+                target: State::Green,
+                reason: "intersection locked".into(),
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "Transition from state Red to Green vetoed: intersection locked"
+        );
+        assert_eq!(*sm.current_state(), State::Red);
+        assert!(sm.history_is_empty());
+        assert_eq!(*exits.lock().unwrap(), 0);
+
+        // Other transitions from the same state are unaffected
+        assert_eq!(sm.transition(Input::Emergency), Ok(State::Yellow));
+        sm.transition(Input::Timer).unwrap();
+
+        locked.store(false, Ordering::SeqCst);
+        assert_eq!(sm.transition(Input::Timer), Ok(State::Green));
+        assert_eq!(sm.callback_count(), 2);
+    }
+
+    #[test]
+    fn test_transition_outcome() {
+        use guarded_machine::{Input, Shipping, State, Warehouse};
+
+        #[derive(Debug, PartialEq)]
+        struct Receipt(u32);
+
+        let mut sm = StateMachineInstance::<Shipping>::with_context(Warehouse { inventory: 2 });
+        sm.on_transition_effect(State::Paid, Input::Ship, |_, _, _, ctx| {
+            ctx.inventory -= 1;
+            Receipt(ctx.inventory)
+        });
+        sm.on_transition_effect(State::Paid, Input::Ship, |_, _, to, _| format!("{to:?}"));
+
+        let outcome = sm.transition_with_outcome(Input::Ship).unwrap();
+        assert_eq!(
+            (&outcome.from, &outcome.to),
+            (&State::Paid, &State::Shipped)
+        );
+        assert_eq!(outcome.get::<Receipt>(), Some(&Receipt(1)));
+        assert_eq!(
+            outcome.results::<String>().collect::<Vec<_>>(),
+            vec!["Shipped"]
+        );
+        assert_eq!(outcome.result_count(), 2);
+        assert_eq!(sm.context().inventory, 1);
+
+        // Failed transitions produce no outcome
+        assert!(sm.transition_with_outcome(Input::Ship).is_err());
+    }
+
+    mod internal_machine {
+        use super::super::*;
+
+        define_state_machine! {
+            name: Session,
+            states: { Active, Closed },
+            inputs: { Log, Refresh, Close },
+            initial: Active,
+            context: u32,
+            transitions: {
+                Active + Log => internal if |limit| *limit > 0,
+                Active + Refresh => Active,
+                Active + Close => Closed
+            }
+        }
+    }
+
+    #[test]
+    fn test_internal_transitions() {
+        use internal_machine::{Input, Session, State};
+        use std::sync::{Arc, Mutex};
+
+        assert!(Session::is_internal(&State::Active, &Input::Log));
+        assert!(!Session::is_internal(&State::Active, &Input::Refresh));
+
+        let mut sm = StateMachineInstance::<Session>::with_context(1);
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let events_clone = Arc::clone(&events);
+        sm.on_any_state_exit(move |state| {
+            events_clone.lock().unwrap().push(format!("exit {state}"))
+        });
+        let events_clone = Arc::clone(&events);
+        sm.on_any_state_entry(move |state| {
+            events_clone.lock().unwrap().push(format!("enter {state}"))
+        });
+        let events_clone = Arc::clone(&events);
+        sm.on_any_transition(move |_, input, _| {
+            events_clone.lock().unwrap().push(format!("{input}"))
+        });
+
+        sm.transition(Input::Log).unwrap();
+        sm.transition(Input::Refresh).unwrap();
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec!["Log", "exit Active", "Refresh", "enter Active"]
+        );
+
+        // Internal transitions are guarded like any other
+        sm.set_context(0);
+        assert!(matches!(
+            sm.transition(Input::Log),
+            Err(TransitionError::GuardRejected { .. })
+        ));
+
+        sm.reset();
+        sm.set_context(1);
+        sm.set_skip_internal_in_history(true);
+        sm.transition(Input::Log).unwrap();
+        sm.transition(Input::Refresh).unwrap();
+        assert_eq!(
+            sm.history()
+                .iter()
+                .map(|(_, input)| input.clone())
+                .collect::<Vec<_>>(),
+            vec![Input::Refresh]
+        );
+
+        let table = StateMachineDoc::<Session>::generate_transition_table();
+        assert!(table.contains("| Active | Log | Active *(internal)* |"));
+        assert!(table.contains("| Active | Refresh | Active |"));
+    }
+
+    #[test]
+    fn test_external_self_loops_reenter() {
+        use internal_machine::{Input, Session, State};
+        use std::sync::{Arc, Mutex};
+
+        // Breaking change: external self-loops used to skip exit and entry callbacks
+        let mut sm = StateMachineInstance::<Session>::with_context(1);
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let events_clone = Arc::clone(&events);
+        sm.on_state_exit(State::Active, move |state| {
+            events_clone.lock().unwrap().push(format!("exit {state}"))
+        });
+        let events_clone = Arc::clone(&events);
+        sm.on_state_entry(State::Active, move |state| {
+            events_clone.lock().unwrap().push(format!("enter {state}"))
+        });
+
+        sm.transition(Input::Refresh).unwrap();
+        sm.transition(Input::Refresh).unwrap();
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec!["exit Active", "enter Active", "exit Active", "enter Active"]
+        );
+
+        // Internal transitions keep the old behavior
+        events.lock().unwrap().clear();
+        sm.transition(Input::Log).unwrap();
+        assert!(events.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_transition_with_trace() {
+        use std::sync::{Arc, Mutex};
+        use std::time::Duration;
+
+        let mut sm = StateMachineInstance::<TrafficLight>::new();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let seen_clone = Arc::clone(&seen);
+        sm.on_transition_event(move |event| {
+            seen_clone.lock().unwrap().push(event.trace.clone());
+        });
+        let batches = Arc::new(Mutex::new(Vec::new()));
+        let batches_clone = Arc::clone(&batches);
+        TransitionDigest::new(2, Duration::from_secs(3600), move |batch| {
+            batches_clone.lock().unwrap().push(batch);
+        })
+        .attach(&mut sm);
+
+        let trace = TraceContext::new().with("traceparent", "00-abc-def-01");
+        sm.transition_with_trace(Input::Timer, trace.clone())
+            .unwrap();
+        sm.transition(Input::Timer).unwrap();
+
+        assert_eq!(*seen.lock().unwrap(), vec![Some(trace.clone()), None]);
+        let entries = sm.history_entries();
+        assert_eq!(entries[0].trace, Some(trace.clone()));
+        assert_eq!(entries[1].trace, None);
+
+        let batches = batches.lock().unwrap();
+        assert_eq!(batches[0][0].trace.as_ref(), Some(&trace));
+        assert_eq!(
+            batches[0][0]
+                .trace
+                .as_ref()
+                .and_then(|trace| trace.get("traceparent")),
+            Some("00-abc-def-01")
+        );
+
+        // Failed transitions leave no trace in history
+        let mut sm = StateMachineInstance::<workflow_machine::Article>::new();
+        assert!(
+            sm.transition_with_trace(workflow_machine::Input::Publish, TraceContext::new())
+                .is_err()
+        );
+        assert!(sm.history_is_empty());
+    }
+
+    #[test]
+    fn test_correlation_across_machines() {
+        use std::sync::mpsc;
+
+        let first = TraceContext::correlated();
+        let id = first.correlation_id().unwrap().to_string();
+        assert_ne!(
+            TraceContext::correlated().correlation_id(),
+            Some(id.as_str())
+        );
+
+        let mut light = StateMachineInstance::<TrafficLight>::new();
+        let mut article = StateMachineInstance::<workflow_machine::Article>::new();
+        let (sender, events) = mpsc::channel();
+        light.on_transition_event(move |event| {
+            let _ = sender.send(event.clone());
+        });
+
+        light
+            .transition_with_trace(Input::Timer, first.with("traceparent", "00-abc-def-01"))
+            .unwrap();
+        light.transition(Input::Timer).unwrap();
+
+        let events: Vec<_> = events.try_iter().collect();
+        assert_eq!(events[0].correlation_id(), Some(id.as_str()));
+        assert_eq!(events[1].correlation_id(), None);
+        article
+            .transition_caused_by(workflow_machine::Input::Submit, &events[0])
+            .unwrap();
+
+        let entries = article.history_for_correlation(&id);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(
+            entries[0]
+                .trace
+                .as_ref()
+                .and_then(|trace| trace.get("traceparent")),
+            Some("00-abc-def-01")
+        );
+        assert_eq!(light.history_for_correlation(&id).len(), 1);
+        assert!(light.history_for_correlation("unknown").is_empty());
+    }
+
+    mod payload_machine {
+        use super::super::*;
+
+        define_state_machine! {
+            name: Checkout,
+            states: { Open, Paid, Closed },
+            inputs: { Pay(amount: u64), Note(author: String, text: String), Close },
+            initial: Open,
+            transitions: {
+                Open + Pay => Paid,
+                Open + Note => Open,
+                Paid + Close => Closed
+            }
+        }
+    }
+
+    #[test]
+    fn test_indices() {
+        use payload_machine::{Checkout, Input, State};
+
+        let mut visits = [0u32; Checkout::STATE_COUNT];
+        for state in Checkout::states() {
+            assert_eq!(State::from_index(state.index()), Some(state.clone()));
+            visits[state.index()] += 1;
+        }
+        assert_eq!(visits, [1, 1, 1]);
+        assert_eq!(State::Closed.index(), 2);
+        assert_eq!(State::from_index(Checkout::STATE_COUNT), None);
+
+        assert_eq!(Input::Note("ops".into(), "VIP".into()).index(), 1);
+        assert_eq!(Input::from_index(0), Some(Input::Pay(0)));
+        assert_eq!(Input::from_index(2), Some(Input::Close));
+        assert_eq!(Input::from_index(Checkout::INPUT_COUNT), None);
+        const CLOSE: usize = Input::Close.index();
+        assert_eq!(CLOSE, 2);
+        assert_eq!(Checkout::input_index(&Input::Pay(42)), Some(0));
+        assert_eq!(Checkout::state_index(&State::Paid), Some(1));
+    }
+
+    #[test]
+    fn test_enum_iteration() {
+        use payload_machine::{Checkout, Input, State};
+
+        assert_eq!(State::iter().collect::<Vec<_>>(), Checkout::states());
+        assert_eq!(Input::iter().collect::<Vec<_>>(), Checkout::inputs());
+        assert_eq!(State::iter().len(), State::COUNT);
+        assert_eq!(Input::COUNT, 3);
+        assert_eq!(State::iter().next_back(), Some(State::Closed));
+
+        assert_eq!(State::Paid.as_str(), "Paid");
+        assert_eq!(Input::Pay(250).as_str(), "Pay");
+        for input in Input::iter() {
+            assert_eq!(input.as_str(), input.to_string());
+        }
+    }
+
+    #[test]
+    fn test_input_payloads() {
+        use payload_machine::{Checkout, Input, State};
+        use std::sync::{Arc, Mutex};
+
+        let mut sm = StateMachineInstance::<Checkout>::new();
+        assert_eq!(
+            sm.valid_inputs(),
+            vec![Input::Pay(0), Input::Note(String::new(), String::new())]
+        );
+
+        // Callbacks registered with any payload match the variant
+        let paid = Arc::new(Mutex::new(Vec::new()));
+        let paid_clone = Arc::clone(&paid);
+        sm.on_transition(State::Open, Input::Pay(0), move |_, input, _| {
+            if let Input::Pay(amount) = input {
+                paid_clone.lock().unwrap().push(*amount);
+            }
+        });
+
+        assert!(sm.can_accept(&Input::Pay(250)));
+        sm.transition(Input::Note("ops".into(), "VIP".into()))
+            .unwrap();
+        assert_eq!(sm.transition(Input::Pay(250)), Ok(State::Paid));
+        assert_eq!(*paid.lock().unwrap(), vec![250]);
+        assert_eq!(sm.history()[1].1, Input::Pay(250));
+        assert!(sm.transition(Input::Pay(1)).is_err());
+
+        assert_eq!(Input::Pay(250).to_string(), "Pay");
+        assert_eq!("Pay".parse(), Ok(Input::Pay(0)));
+        let table = StateMachineDoc::<Checkout>::generate_transition_table();
+        assert!(table.contains("| Open | Pay | Paid |"));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_instance_serde() {
+        let mut sm = StateMachineInstance::<TrafficLight>::with_max_history(8);
+        sm.transition(Input::Timer).unwrap();
+        sm.transition(Input::Emergency).unwrap();
+        sm.annotate_history(1, "manual override").unwrap();
+
+        let serialized = serde_json::to_string(&sm).unwrap();
+        let mut restored: StateMachineInstance<TrafficLight> =
+            serde_json::from_str(&serialized).unwrap();
+        assert_eq!(*restored.current_state(), State::Red);
+        assert_eq!(restored.history(), sm.history());
+        assert_eq!(restored.history_entries(), sm.history_entries());
+        assert_eq!(restored.max_history_size(), 8);
+
+        // Sequence numbers continue where they left off
+        restored.transition(Input::Timer).unwrap();
+        assert_eq!(restored.last_history_seq(), Some(2));
+
+        let corrupted = serialized.replace("\"max_history_size\":8", "\"max_history_size\":1");
+        assert!(serde_json::from_str::<StateMachineInstance<TrafficLight>>(&corrupted).is_err());
+    }
+
+    #[test]
+    fn test_try_transition_by_name() {
+        let mut sm = StateMachineInstance::<TrafficLight>::new();
+        assert_eq!(sm.try_transition_by_name("Timer"), Ok(State::Green));
+        assert_eq!(
+            sm.try_transition_by_name("Honk"),
+            Err(TryTransitionError::UnknownInput {
+                name: "Honk".into()
+            })
+        );
+
+        let mut sm = StateMachineInstance::<workflow_machine::Article>::new();
+        let error = sm.try_transition_by_name("Publish").unwrap_err();
+        assert!(matches!(
+            error,
+            TryTransitionError::Transition(TransitionError::InvalidInput { .. })
+        ));
+        assert_eq!(error.to_string(), "Invalid input Publish for state Draft");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_try_transition_from_json() {
+        use payload_machine::{Checkout, Input, State};
+
+        let mut sm = StateMachineInstance::<Checkout>::new();
+        let error = sm
+            .try_transition_from_json(&serde_json::json!({"Refund": [5]}))
+            .unwrap_err();
+        assert!(matches!(error, TryTransitionError::MalformedInput { .. }));
+
+        let state = sm
+            .try_transition_from_json(&serde_json::json!({"Pay": [100]}))
+            .unwrap();
+        assert_eq!(state, State::Paid);
+        assert_eq!(sm.history()[0].1, Input::Pay(100));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_input_payload_serde() {
+        use payload_machine::Input;
+
+        let serialized = serde_json::to_string(&Input::Pay(100)).unwrap();
+        assert_eq!(serialized, r#"{"Pay":[100]}"#);
+        assert_eq!(
+            serde_json::from_str::<Input>(&serialized).unwrap(),
+            Input::Pay(100)
+        );
+
+        let note = Input::Note("ops".into(), "VIP".into());
+        let serialized = serde_json::to_string(&note).unwrap();
+        assert_eq!(serialized, r#"{"Note":["ops","VIP"]}"#);
+        assert_eq!(serde_json::from_str::<Input>(&serialized).unwrap(), note);
+
+        assert_eq!(serde_json::to_string(&Input::Close).unwrap(), "\"Close\"");
+        assert_eq!(
+            serde_json::from_str::<Input>("\"Close\"").unwrap(),
+            Input::Close
+        );
+        assert!(serde_json::from_str::<Input>("\"Pay\"").is_err());
+        assert!(serde_json::from_str::<Input>("\"Refund\"").is_err());
+    }
+
+    #[test]
+    fn test_display_implementation() {
+        assert_eq!(State::Red.to_string(), "Red");
+        assert_eq!(Input::Timer.to_string(), "Timer");
+    }
+
+    #[test]
+    fn test_documentation_generation() {
+        let stats = StateMachineDoc::<TrafficLight>::generate_statistics();
+        assert!(stats.contains("Number of States"));
+        assert!(stats.contains("Number of Transitions"));
+
+        let full_doc = StateMachineDoc::<TrafficLight>::generate_full_documentation();
+        assert!(full_doc.contains("State Machine Documentation"));
+        assert!(full_doc.contains("State Transition Table"));
+        assert!(full_doc.contains("State Diagram"));
+    }
+
+    #[test]
+    fn test_state_from_str() {
+        // Test valid state strings
+        let red_state: State = "Red".parse().unwrap();
+        assert_eq!(red_state, State::Red);
+
+        let yellow_state = State::try_from("Yellow").unwrap();
+        assert_eq!(yellow_state, State::Yellow);
+
+        let green_state: State = "Green".parse().unwrap();
+        assert_eq!(green_state, State::Green);
+
+        assert_eq!(State::all_names(), ["Red", "Yellow", "Green"]);
+    }
+
+    #[test]
+    fn test_state_from_str_invalid() {
+        // Test invalid state string - should be an error
+        let error = "InvalidState".parse::<State>().unwrap_err();
+        assert_eq!(
+            error,
+            ParseNameError::UnknownState {
+                name: "InvalidState".to_string()
+            }
+        );
+        assert_eq!(error.to_string(), "Invalid state: InvalidState");
+    }
+
+    #[test]
+    fn test_input_from_str() {
+        // Test valid input strings
+        let timer_input: Input = "Timer".parse().unwrap();
+        assert_eq!(timer_input, Input::Timer);
+
+        let emergency_input = Input::try_from("Emergency").unwrap();
+        assert_eq!(emergency_input, Input::Emergency);
+
+        assert_eq!(Input::all_names(), ["Timer", "Emergency"]);
+    }
+
+    #[test]
+    fn test_input_from_str_invalid() {
+        // Test invalid input string - should be an error
+        assert_eq!(
+            Input::try_from("InvalidInput"),
+            Err(ParseNameError::UnknownInput {
+                name: "InvalidInput".to_string()
+            })
+        );
+        assert!("timer".parse::<Input>().is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_serialization() {
+        // Test state serialization
+        let state = State::Red;
+        let serialized = serde_json::to_string(&state).unwrap();
+        assert_eq!(serialized, "\"Red\"");
+
+        let deserialized: State = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(deserialized, State::Red);
+
+        // Test input serialization
+        let input = Input::Timer;
+        let serialized = serde_json::to_string(&input).unwrap();
+        assert_eq!(serialized, "\"Timer\"");
+
+        let deserialized: Input = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(deserialized, Input::Timer);
+
+        // Test multiple states
+        let states = vec![State::Red, State::Yellow, State::Green];
+        let serialized = serde_json::to_string(&states).unwrap();
+        let deserialized: Vec<State> = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(deserialized, states);
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_transition_async() {
+        use internal_machine::{Input, Session, State};
+        use std::future::Future;
+        use std::sync::{Arc, Mutex};
+        use std::task::{Context, Poll, Wake, Waker};
+
+        // Minimal executor, so the test doesn't depend on a runtime
+        struct ThreadWaker(std::thread::Thread);
+
+        impl Wake for ThreadWaker {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+
+        fn block_on<F: Future>(future: F) -> F::Output {
+            let mut future = std::pin::pin!(future);
+            let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+            let mut cx = Context::from_waker(&waker);
+            loop {
+                if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                    return output;
+                }
+                std::thread::park();
+            }
+        }
+
+        let mut sm = StateMachineInstance::<Session>::with_context(1);
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let seen_clone = Arc::clone(&seen);
+        sm.on_state_entry(State::Active, move |_| {
+            seen_clone.lock().unwrap().push("sync entry".to_string());
+        });
+        let seen_clone = Arc::clone(&seen);
+        sm.on_state_entry_async(State::Active, move |state| {
+            let seen = Arc::clone(&seen_clone);
+            async move { seen.lock().unwrap().push(format!("async entry {state}")) }
+        });
+        let seen_clone = Arc::clone(&seen);
+        let id = sm.on_transition_async(State::Active, Input::Log, move |from, input, to| {
+            let seen = Arc::clone(&seen_clone);
+            async move {
+                seen.lock()
+                    .unwrap()
+                    .push(format!("async {from} --{input}--> {to}"))
+            }
+        });
+        assert_eq!(sm.callback_count(), 3);
+
+        // Internal transitions run transition hooks, but enter no state
+        assert_eq!(block_on(sm.transition_async(Input::Log)), Ok(State::Active));
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec!["async Active --Log--> Active".to_string()]
+        );
+
+        // Async hooks run after the synchronous callbacks
+        seen.lock().unwrap().clear();
+        block_on(sm.transition_async(Input::Refresh)).unwrap();
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec!["sync entry".to_string(), "async entry Active".to_string()]
+        );
+
+        // Plain transitions don't await async hooks
+        seen.lock().unwrap().clear();
+        sm.transition(Input::Log).unwrap();
+        assert_eq!(*seen.lock().unwrap(), Vec::<String>::new());
+
+        // Failed transitions run no hooks
+        *sm.context_mut() = 0;
+        assert!(block_on(sm.transition_async(Input::Log)).is_err());
+        assert!(sm.remove_callback(id));
+        assert_eq!(sm.callback_count(), 2);
+        sm.clear_callbacks();
+        assert_eq!(sm.callback_count(), 0);
+    }
+
+    mod config_machine {
+        use super::super::*;
+
+        #[derive(Debug, Default)]
+        pub struct RetryPolicy {
+            pub max_attempts: u32,
+            pub retries_enabled: bool,
+        }
+
+        define_state_machine! {
+            name: Upload,
+            states: { Sending, Failed, Done },
+            inputs: { Error, Retry, Ack },
+            initial: Sending,
+            context: u32,
+            config: RetryPolicy,
+            transitions: {
+                Sending + Error => Failed,
+                Sending + Ack => Done,
+                Failed + Retry => Sending if |attempts, policy| {
+                    policy.retries_enabled && *attempts < policy.max_attempts
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_config() {
+        use config_machine::{Input, RetryPolicy, State, Upload};
+
+        let policy = RetryPolicy {
+            max_attempts: 2,
+            retries_enabled: true,
+        };
+        let mut sm = StateMachineInstance::<Upload>::with_config(policy);
+        assert_eq!(sm.config().max_attempts, 2);
+
+        let config = sm.shared_config();
+        sm.on_transition_action(State::Failed, Input::Retry, move |_, _, _, attempts| {
+            assert!(config.retries_enabled);
+            *attempts += 1;
+        });
+
+        for _ in 0..2 {
+            sm.transition(Input::Error).unwrap();
+            assert_eq!(sm.transition(Input::Retry), Ok(State::Sending));
+        }
+        sm.transition(Input::Error).unwrap();
+        assert!(matches!(
+            sm.transition(Input::Retry),
+            Err(TransitionError::GuardRejected { .. })
+        ));
+        assert_eq!(*sm.context(), 2);
+
+        // The default configuration disables retries
+        let mut sm = StateMachineInstance::<Upload>::new();
+        sm.transition(Input::Error).unwrap();
+        assert!(sm.transition(Input::Retry).is_err());
+
+        let mut sm = StateMachineInstance::<Upload>::with_context_and_config(
+            1,
+            RetryPolicy {
+                max_attempts: 1,
+                retries_enabled: true,
+            },
+        );
+        sm.transition(Input::Error).unwrap();
+        assert!(sm.transition(Input::Retry).is_err());
+    }
+
+    #[test]
+    fn test_process_queue() {
+        use workflow_machine::{Article, Input, State};
+
+        // Errors stop processing and leave the rest queued
+        let mut sm = StateMachineInstance::<Article>::new();
+        assert_eq!(sm.invalid_input_policy(), InvalidInputPolicy::Error);
+        sm.enqueue(Input::Submit);
+        sm.enqueue(Input::Publish);
+        sm.enqueue(Input::Approve);
+        assert!(matches!(
+            sm.process_queue(),
+            Err(TransitionError::InvalidInput { .. })
+        ));
+        assert_eq!(*sm.current_state(), State::Review);
+        assert_eq!(sm.input_queue().len(), 1);
+        assert_eq!(sm.process_one(), Some(Ok(State::Approved)));
+        assert_eq!(sm.process_one(), None);
+
+        // Dropped inputs are skipped
+        let mut sm = StateMachineInstance::<Article>::new();
+        sm.set_invalid_input_policy(InvalidInputPolicy::Drop);
+        for input in [Input::Publish, Input::Submit, Input::Approve] {
+            sm.enqueue(input);
+        }
+        assert_eq!(sm.process_queue(), Ok(2));
+        assert_eq!(*sm.current_state(), State::Approved);
+        assert_eq!(sm.deferred_inputs().count(), 0);
+
+        // Deferred inputs are retried after the next state change
+        let mut sm = StateMachineInstance::<Article>::new();
+        sm.set_invalid_input_policy(InvalidInputPolicy::Defer);
+        for input in [Input::Publish, Input::Approve, Input::Submit] {
+            sm.enqueue(input);
+        }
+        assert_eq!(sm.process_queue(), Ok(3));
+        assert_eq!(*sm.current_state(), State::Published);
+
+        sm.reset();
+        sm.enqueue(Input::Publish);
+        assert_eq!(sm.process_queue(), Ok(0));
+        assert_eq!(
+            sm.deferred_inputs().cloned().collect::<Vec<_>>(),
+            vec![Input::Publish]
+        );
+        sm.transition(Input::Submit).unwrap();
+        assert_eq!(sm.input_queue().peek(), Some(&Input::Publish));
+        sm.reset();
+        assert!(sm.input_queue().is_empty());
+    }
+
+    #[test]
+    fn test_reset_to_and_force_state() {
+        use std::sync::{Arc, Mutex};
+        use workflow_machine::*;
+
+        let resets = Arc::new(Mutex::new(Vec::new()));
+        let entries = Arc::new(Mutex::new(0));
+        let mut sm = StateMachineInstance::<Article>::new();
+        let log = resets.clone();
+        sm.on_reset(move |from, to| log.lock().unwrap().push((from.clone(), to.clone())));
+        let count = entries.clone();
+        sm.on_any_state_entry(move |_| *count.lock().unwrap() += 1);
+
+        sm.transition(Input::Submit).unwrap();
+        sm.reset();
+        assert_eq!(*sm.current_state(), State::Draft);
+        assert!(sm.history_is_empty());
+
+        // Placing the machine runs no transition callbacks
+        sm.reset_to(State::Published);
+        assert_eq!(*sm.current_state(), State::Published);
+        assert_eq!(
+            *resets.lock().unwrap(),
+            vec![
+                (State::Review, State::Draft),
+                (State::Draft, State::Published)
+            ]
+        );
+        assert_eq!(*entries.lock().unwrap(), 1);
+
+        sm.force_state(State::Review, None);
+        assert!(sm.history_is_empty());
+        sm.force_state(State::Approved, Some(Input::Approve));
+        assert_eq!(*sm.current_state(), State::Approved);
+        let entry = sm.history_entries().pop().unwrap();
+        assert_eq!(
+            (entry.from, entry.input, entry.annotations),
+            (State::Review, Input::Approve, vec!["forced".to_string()])
+        );
+        assert_eq!(sm.undo(), Some(State::Review));
+        assert_eq!(resets.lock().unwrap().len(), 2);
+    }
+
+    mod wildcard_machine {
+        use super::super::*;
+
+        define_state_machine! {
+            name: Character,
+            states: { Idle, Walking, Fighting, Dead },
+            inputs: { Walk, Stop, Attack, Die, Tick, Heal },
+            outputs: { Respawned },
+            initial: Idle,
+            transitions: {
+                Idle + Walk => Walking,
+                Walking + Stop => Idle,
+                _ + Attack => Fighting,
+                Fighting + Attack => internal,
+                _ + Die => Dead,
+                Dead ignores Die,
+                _ + Tick => internal,
+                _ + Heal => Idle emits Respawned if |_| true,
+            }
+        }
+    }
+
+    #[test]
+    fn test_wildcard_transitions() {
+        use wildcard_machine::{Character, Input, Output, State};
+
+        for state in Character::states() {
+            let expected = if state == State::Dead {
+                None
+            } else {
+                Some(State::Dead)
+            };
+            assert_eq!(Character::next_state(&state, &Input::Die), expected);
+            assert_eq!(
+                Character::next_state(&state, &Input::Tick),
+                Some(state.clone())
+            );
+            assert!(Character::is_internal(&state, &Input::Tick));
+        }
+        assert!(Character::is_internal(&State::Fighting, &Input::Attack));
+        assert!(!Character::is_internal(&State::Idle, &Input::Attack));
+        assert!(Character::is_ignored(&State::Dead, &Input::Die));
+        assert_eq!(
+            Character::output(&State::Dead, &Input::Heal),
+            Some(Output::Respawned)
+        );
+        assert_eq!(Character::output(&State::Idle, &Input::Walk), None);
+        assert_eq!(
+            Character::valid_inputs(&State::Walking),
+            vec![
+                Input::Stop,
+                Input::Attack,
+                Input::Die,
+                Input::Tick,
+                Input::Heal
+            ]
+        );
+        assert!(!Character::valid_inputs(&State::Dead).contains(&Input::Die));
+        // 3 plain rules, 3 + 3 + 4 + 4 wildcard expansions
+        assert_eq!(Character::TRANSITION_COUNT, 17);
+
+        let mut hero = StateMachineInstance::<Character>::new();
+        assert_eq!(hero.transition(Input::Attack), Ok(State::Fighting));
+        assert_eq!(hero.transition(Input::Die), Ok(State::Dead));
+        assert_eq!(hero.transition(Input::Die), Ok(State::Dead));
+        assert!(hero.transition(Input::Walk).is_err());
+    }
+
+    mod multi_input_machine {
+        use super::super::*;
+
+        define_state_machine! {
+            name: Crossing,
+            states: { Red, Yellow, Green, Off },
+            inputs: { Timer, Emergency, Reset, Ping, Fault, Repair },
+            initial: Red,
+            transitions: {
+                Red + Timer | Emergency => Yellow,
+                Yellow + Timer => Green,
+                Green + Timer | Emergency => Red if |_| true,
+                _ + Reset | Fault => Off,
+                Off + Ping | Repair => internal,
+            }
+        }
+    }
+
+    #[test]
+    fn test_multi_input_transitions() {
+        use multi_input_machine::{Crossing, Input, State};
+
+        assert_eq!(
+            Crossing::next_state(&State::Red, &Input::Emergency),
+            Some(State::Yellow)
+        );
+        assert_eq!(
+            Crossing::next_state(&State::Green, &Input::Emergency),
+            Some(State::Red)
+        );
+        assert_eq!(
+            Crossing::next_state(&State::Yellow, &Input::Fault),
+            Some(State::Off)
+        );
+        assert!(Crossing::is_internal(&State::Off, &Input::Repair));
+        assert_eq!(
+            Crossing::valid_inputs(&State::Red),
+            vec![Input::Timer, Input::Emergency, Input::Reset, Input::Fault]
+        );
+        // 7 plain rules, 4 + 4 wildcard expansions
+        assert_eq!(Crossing::TRANSITION_COUNT, 15);
+    }
+
+    mod final_machine {
+        use super::super::*;
+
+        define_state_machine! {
+            name: Delivery,
+            states: { Placed, Shipped, Delivered, Cancelled },
+            inputs: { Ship, Deliver, Cancel },
+            initial: Placed,
+            final: { Delivered, Cancelled },
+            transitions: {
+                Placed + Ship => Shipped,
+                Shipped + Deliver => Delivered,
+                _ + Cancel => Cancelled,
+            }
+        }
+    }
+
+    #[test]
+    fn test_final_states() {
+        use final_machine::{Delivery, Input, State};
+
+        assert!(Delivery::is_final(&State::Delivered));
+        assert!(!Delivery::is_final(&State::Shipped));
+        assert_eq!(
+            Delivery::next_state(&State::Cancelled, &Input::Cancel),
+            None
+        );
+        assert!(Delivery::valid_inputs(&State::Delivered).is_empty());
+        // 2 plain rules, 2 wildcard expansions
+        assert_eq!(Delivery::TRANSITION_COUNT, 4);
+
+        let mut order = StateMachineInstance::<Delivery>::new();
+        order.transition(Input::Ship).unwrap();
+        assert!(!order.is_finished());
+        order.transition(Input::Deliver).unwrap();
+        assert!(order.is_finished());
+        assert!(!order.can_accept(&Input::Cancel));
+        assert_eq!(
+            order.transition(Input::Cancel),
+            Err(TransitionError::MachineCompleted {
+                state: State::Delivered,
+                input: Input::Cancel,
+            })
+        );
+        assert!(order.health().terminal);
+
+        order.reset();
+        assert!(!order.is_finished());
+
+        let mermaid = StateMachineDoc::<Delivery>::generate_mermaid();
+        assert!(mermaid.contains("    Delivered --> [*]\n"));
+        assert!(mermaid.contains("    Cancelled --> [*]\n"));
+        assert!(!mermaid.contains("    Shipped --> [*]"));
+    }
+
+    mod accepting_machine {
+        use super::super::*;
+
+        define_state_machine! {
+            name: Parity,
+            states: { Even, Odd, Stopped },
+            inputs: { Bit, Pad, Stop },
+            initial: Even,
+            final: { Stopped },
+            accepting: { Even, Stopped },
+            transitions: {
+                Even + Bit => Odd,
+                Odd + Bit => Even,
+                Odd + Stop => Stopped,
+                Even ignores Pad,
+            }
+        }
+    }
+
+    #[test]
+    fn test_accepting_states() {
+        use accepting_machine::{Input, Parity, State};
+        type Query = StateMachineQuery<Parity>;
+
+        assert!(Query::accepts(&[]));
+        assert!(Query::accepts(&[Input::Bit, Input::Bit, Input::Pad]));
+        assert!(!Query::accepts(&[Input::Bit]));
+        assert!(!Query::accepts(&[Input::Bit, Input::Pad]));
+        assert!(Query::accepts(&[Input::Bit, Input::Stop]));
+        assert!(!Query::accepts(&[Input::Bit, Input::Stop, Input::Bit]));
+
+        let mut instance = StateMachineInstance::<Parity>::new();
+        assert!(instance.is_accepting());
+        instance.transition(Input::Bit).unwrap();
+        assert!(!instance.is_accepting());
+
+        assert!(
+            StateMachineDoc::<Parity>::generate_dot()
+                .contains("    \"Even\" [shape=doublecircle];\n")
+        );
+        assert!(Parity::is_accepting(&State::Stopped));
+    }
+
+    #[test]
+    fn test_valid_inputs_iter() {
+        fn check<SM: StateMachine>() {
+            for state in SM::states() {
+                assert_eq!(
+                    SM::valid_inputs_iter(&state).collect::<Vec<_>>(),
+                    SM::valid_inputs(&state),
+                    "{state:?}"
+                );
+            }
+        }
+        check::<wildcard_machine::Character>();
+        check::<multi_input_machine::Crossing>();
+        check::<final_machine::Delivery>();
+        check::<accepting_machine::Parity>();
+
+        use wildcard_machine::{Character, Input, State};
+        let mut inputs = Character::valid_inputs_iter(&State::Dead);
+        assert_eq!(inputs.next(), Some(Input::Attack));
+        assert_eq!(inputs.next(), Some(Input::Tick));
+    }
+
+    mod described_machine {
+        use super::super::*;
+
+        define_state_machine! {
+            name: Ticket,
+            states: {
+                /// Waiting for triage
+                Open,
+                /// Assigned to an engineer,
+                /// who is working on it
+                InProgress,
+                Closed
+            },
+            inputs: {
+                /// Engineer picks the ticket up
+                Assign,
+                Close(reason: String),
+                /// Debugging aid
+                _Poke
+            },
+            initial: Open,
+            transitions: {
+                Open + Assign => InProgress,
+                InProgress + Close => Closed,
+                Open + _Poke => Open,
+            }
+        }
+    }
+
+    #[test]
+    fn test_descriptions() {
+        use described_machine::{Input, State, Ticket};
+
+        assert_eq!(
+            Ticket::state_description(&State::InProgress).as_deref(),
+            Some("Assigned to an engineer, who is working on it")
+        );
+        assert_eq!(Ticket::state_description(&State::Closed), None);
+        assert_eq!(
+            Ticket::input_description(&Input::Assign).as_deref(),
+            Some("Engineer picks the ticket up")
+        );
+        assert_eq!(
+            Ticket::input_description(&Input::Close("done".to_string())),
+            None
+        );
+        // Descriptions are documentation only
+        assert_eq!(StateMachineQuery::<Ticket>::undefined_pairs().len(), 6);
+
+        let table = StateMachineDoc::<Ticket>::generate_transition_table();
+        assert!(table.contains(
+            "## States\n\n| State | Description |\n|-------|-------------|\n\
+             | Open | Waiting for triage |\n"
+        ));
+        assert!(table.contains("## Inputs\n\n| Input | Description |"));
+        assert!(table.contains("| Assign | Engineer picks the ticket up |"));
+        // Underscore-prefixed inputs stay out of the documentation
+        assert!(!table.contains("Debugging aid"));
+
+        let mermaid = StateMachineDoc::<Ticket>::generate_mermaid();
+        assert!(mermaid.contains("    Open : Waiting for triage\n"));
+        assert!(!mermaid.contains("\n    Closed :"));
+
+        let dot = StateMachineDoc::<Ticket>::generate_dot();
+        assert!(dot.contains("\"Open\" [tooltip=\"Waiting for triage\"];"));
+        assert!(dot.contains("    \"Closed\";\n"));
+    }
+
+    #[cfg(feature = "derive")]
+    mod derived_machine {
+        use super::super::*;
+
+        state_machine! {
+            #[deny(unreachable_state, unused_input)]
+            name: Door,
+            states: {
+                Closed,
+                Open,
+                Locked,
+                /// Kept for old snapshots
+                #[allow(unreachable_state)]
+                Jammed
+            },
+            inputs: { Push, Pull, Lock },
+            initial: Closed,
+            transitions: {
+                Closed + Push => Open,
+                Open + Pull => Closed,
+                Closed + Lock => Locked,
+                Locked ignores Push,
+                Jammed + Pull => Closed,
+            }
+        }
+    }
+
+    #[cfg(feature = "derive")]
+    #[test]
+    fn test_derived_machine() {
+        use derived_machine::{Door, Input, State};
+
+        let mut door = StateMachineInstance::<Door>::new();
+        door.transition(Input::Push).unwrap();
+        door.transition(Input::Pull).unwrap();
+        assert_eq!(door.transition(Input::Lock), Ok(State::Locked));
+        assert_eq!(door.transition(Input::Push), Ok(State::Locked));
+        assert_eq!(Door::TRANSITION_COUNT, 4);
+        assert_eq!(
+            Door::state_description(&State::Jammed).as_deref(),
+            Some("Kept for old snapshots")
+        );
+    }
+
+    mod deferring_machine {
+        use super::super::*;
+
+        define_state_machine! {
+            name: Order,
+            states: { Created, Paid, Shipped },
+            inputs: { Pay, Ship },
+            initial: Created,
+            transitions: {
+                Created + Pay => Paid,
+                Created defers Ship,
+                Paid + Ship => Shipped,
+            }
+        }
+    }
+
+    #[test]
+    fn test_deferred_inputs() {
+        use deferring_machine::{Input, Order, State};
+
+        let mut sm = StateMachineInstance::<Order>::new();
+        assert!(sm.can_accept(&Input::Ship));
+        assert!(sm.is_deferred(&Input::Ship));
+        assert!(!sm.valid_inputs().contains(&Input::Ship));
+
+        // Deferred inputs are accepted without effect and set aside
+        assert_eq!(sm.transition(Input::Ship), Ok(State::Created));
+        assert!(sm.history_is_empty());
+        assert_eq!(
+            sm.deferred_inputs().cloned().collect::<Vec<_>>(),
+            vec![Input::Ship]
+        );
+        assert!(sm.input_queue().is_empty());
+
+        // The next state change queues them again, without retrying them
+        assert_eq!(sm.transition(Input::Pay), Ok(State::Paid));
+        assert_eq!(sm.deferred_inputs().count(), 0);
+        assert_eq!(sm.input_queue().len(), 1);
+        assert_eq!(*sm.current_state(), State::Paid);
+        assert_eq!(sm.process_queue(), Ok(1));
+        assert_eq!(*sm.current_state(), State::Shipped);
+
+        // Queued inputs can be deferred as well
+        let mut sm = StateMachineInstance::<Order>::new();
+        sm.enqueue(Input::Ship);
+        sm.enqueue(Input::Pay);
+        assert_eq!(sm.process_queue(), Ok(3));
+        assert_eq!(*sm.current_state(), State::Shipped);
+
+        let table = StateMachineDoc::<Order>::generate_transition_table();
+        assert!(table.contains("| Created | Ship | *(deferred)* |"));
+        assert!(
+            StateMachineDoc::<Order>::generate_matrix_table()
+                .contains("| Created | Paid | *(deferred)* |")
+        );
+    }
+
+    #[test]
+    fn test_health() {
+        use config_machine::{Input, State, Upload};
+        use std::time::Duration;
+
+        let mut sm = StateMachineInstance::<Upload>::new();
+        let report = sm.health();
+        assert_eq!(report.state, State::Sending);
+        assert_eq!(report.last_transition_at, None);
+        assert!(!report.terminal);
+        assert!(report.is_healthy());
+
+        sm.enqueue(Input::Ack);
+        assert!(sm.transition(Input::Retry).is_err());
+        assert!(sm.transition(Input::Retry).is_err());
+        let report = sm.health();
+        assert_eq!(report.recent_rejections, 2);
+        assert_eq!(report.queue_depth, 1);
+
+        // Entering a state resets the rejection count; retries are disabled by default,
+        // so nothing can leave Failed
+        sm.transition(Input::Error).unwrap();
+        let report = sm.health();
+        assert_eq!(report.recent_rejections, 0);
+        assert!(report.last_transition_at.is_some());
+        assert_eq!(report.stuck, Some(StuckReason::GuardsClosed));
+
+        // Staying too long in a non-terminal state looks stuck
+        let mut sm = StateMachineInstance::<Upload>::new();
+        sm.set_stuck_after(Some(Duration::ZERO));
+        std::thread::sleep(Duration::from_millis(2));
+        assert_eq!(sm.health().stuck, Some(StuckReason::Idle));
+
+        // Terminal states never look stuck
+        sm.transition(Input::Ack).unwrap();
+        std::thread::sleep(Duration::from_millis(2));
+        let report = sm.health();
+        assert!(report.terminal);
+        assert!(report.is_healthy());
+
+        #[cfg(feature = "serde")]
+        {
+            let json = serde_json::to_value(&report).unwrap();
+            assert_eq!(json["state"], "Done");
+            assert_eq!(json["stuck"], serde_json::Value::Null);
+        }
+    }
+
+    mod transducer_machine {
+        use super::super::*;
+
+        define_state_machine! {
+            name: Codec,
+            states: { Idle, Header, Body },
+            inputs: { Byte, End, Noise },
+            outputs: { FrameStart, Payload, FrameEnd },
+            initial: Idle,
+            context: bool,
+            transitions: {
+                Idle + Byte => Header emits FrameStart,
+                Header + Byte => Body emits Payload if |valid| *valid,
+                Body + Byte => internal emits Payload,
+                Body + End => Idle emits FrameEnd,
+                Header + End => Idle,
+                Body ignores Noise,
+            }
+        }
+    }
+
+    #[test]
+    fn test_transition_outputs() {
+        use transducer_machine::{Codec, Input, Output, State};
+
+        let mut sm = StateMachineInstance::<Codec>::with_context(true);
+        assert_eq!(
+            sm.transition_with_output(Input::Byte),
+            Ok((State::Header, Some(Output::FrameStart)))
+        );
+        assert_eq!(
+            sm.transition_with_output(Input::Byte),
+            Ok((State::Body, Some(Output::Payload)))
+        );
+        assert_eq!(
+            sm.transition_with_output(Input::Byte),
+            Ok((State::Body, Some(Output::Payload)))
+        );
+        // Ignored inputs emit nothing
+        assert_eq!(
+            sm.transition_with_output(Input::Noise),
+            Ok((State::Body, None))
+        );
+
+        let outcome = sm.transition_with_outcome(Input::End).unwrap();
+        assert_eq!(outcome.output, Some(Output::FrameEnd));
+        assert_eq!(Output::FrameEnd.to_string(), "FrameEnd");
+
+        sm.transition(Input::Byte).unwrap();
+        assert_eq!(
+            sm.transition_with_output(Input::End),
+            Ok((State::Idle, None))
+        );
+        assert!(sm.transition_with_output(Input::End).is_err());
+
+        // Machines without outputs use ()
+        let mut light = StateMachineInstance::<TrafficLight>::new();
+        assert_eq!(
+            light.transition_with_output(self::Input::Timer),
+            Ok((self::State::Green, None))
+        );
+
+        let table = StateMachineDoc::<Codec>::generate_transition_table();
+        assert!(table.contains("| Idle | Byte | Header *(emits FrameStart)* |"));
+        assert!(table.contains("| Body | Byte | Body *(internal)* *(emits Payload)* |"));
+    }
+
+    #[test]
+    fn test_available_actions() {
+        use config_machine::{Input, State, Upload};
+
+        let mut sm = StateMachineInstance::<Upload>::new();
+        let labels: Vec<_> = sm
+            .available_actions()
+            .iter()
+            .map(|action| action.label().to_string())
+            .collect();
+        assert_eq!(labels, vec!["Error", "Ack"]);
+
+        let error = sm.available_actions().remove(0);
+        assert_eq!(error.input(), &Input::Error);
+        assert_eq!(error.apply(&mut sm), Ok(State::Failed));
+
+        // Guards are evaluated when the action is offered
+        let actions = sm.available_actions();
+        assert_eq!(actions.len(), 1);
+        assert!(!actions[0].is_enabled());
+        assert!(matches!(
+            actions[0].clone().apply(&mut sm),
+            Err(TransitionError::GuardRejected { .. })
+        ));
+
+        // Internal inputs are not offered
+        let sm = StateMachineInstance::<TrafficLight>::new();
+        assert!(
+            sm.available_actions()
+                .iter()
+                .all(|action| action.is_enabled())
+        );
     }
 }
@@ -1,167 +1,1973 @@
+/// Internal helper macro - normalizes the rules in the `transitions` and `queue` blocks
+///
+/// Munches one rule at a time and sorts it into a list by kind, then hands the
+/// normalized lists to `__define_state_machine_common!`. The lists are, in order:
+/// transitions, ignored inputs, deferred inputs, coalescing inputs, superseding
+/// input pairs and wildcard transitions.
+#[macro_export]
+#[doc(hidden)]
+macro_rules! __define_state_machine_rules {
+    // All rules consumed
+    (
+        @header $header:tt
+        @lists $transitions:tt $ignores:tt $defers:tt $coalesce:tt $supersedes:tt $wildcards:tt
+        @queue []
+        @rest
+    ) => {
+        $crate::__define_state_machine_common!(
+            $header,
+            $transitions,
+            $ignores,
+            $defers,
+            $coalesce,
+            $supersedes,
+            $wildcards
+        );
+    };
+
+    // `State + Input | Other => internal`: one internal transition per input; in these
+    // multi-input forms `State` may also be `_`
+    (
+        @header $header:tt
+        @lists $transitions:tt $ignores:tt $defers:tt $coalesce:tt $supersedes:tt $wildcards:tt
+        @queue $queue:tt
+        @rest $from:tt + $inp:ident | $next:ident $(| $more:ident)* => internal $(if $guard:expr)? $(, $($rest:tt)*)?
+    ) => {
+        $crate::__define_state_machine_rules!(
+            @header $header
+            @lists $transitions $ignores $defers $coalesce $supersedes $wildcards
+            @queue $queue
+            @rest $from + $inp => internal $(if $guard)?, $from + $next $(| $more)* => internal $(if $guard)? $(, $($rest)*)?
+        );
+    };
+
+    // `State + Input | Other => compensate Next`: one compensation per input
+    (
+        @header $header:tt
+        @lists $transitions:tt $ignores:tt $defers:tt $coalesce:tt $supersedes:tt $wildcards:tt
+        @queue $queue:tt
+        @rest $from:tt + $inp:ident | $next:ident $(| $more:ident)* => compensate $to:ident $(emits $out:ident)? $(if $guard:expr)? $(, $($rest:tt)*)?
+    ) => {
+        $crate::__define_state_machine_rules!(
+            @header $header
+            @lists $transitions $ignores $defers $coalesce $supersedes $wildcards
+            @queue $queue
+            @rest $from + $inp => compensate $to $(emits $out)? $(if $guard)?, $from + $next $(| $more)* => compensate $to $(emits $out)? $(if $guard)? $(, $($rest)*)?
+        );
+    };
+
+    // `State + Input | Other => Next`: one transition per input
+    (
+        @header $header:tt
+        @lists $transitions:tt $ignores:tt $defers:tt $coalesce:tt $supersedes:tt $wildcards:tt
+        @queue $queue:tt
+        @rest $from:tt + $inp:ident | $next:ident $(| $more:ident)* => $to:ident $(emits $out:ident)? $(if $guard:expr)? $(, $($rest:tt)*)?
+    ) => {
+        $crate::__define_state_machine_rules!(
+            @header $header
+            @lists $transitions $ignores $defers $coalesce $supersedes $wildcards
+            @queue $queue
+            @rest $from + $inp => $to $(emits $out)? $(if $guard)?, $from + $next $(| $more)* => $to $(emits $out)? $(if $guard)? $(, $($rest)*)?
+        );
+    };
+
+    // `_ + Input => internal if guard`: guarded internal wildcard transition
+    (
+        @header $header:tt
+        @lists $transitions:tt $ignores:tt $defers:tt $coalesce:tt $supersedes:tt [ $($wildcards:tt)* ]
+        @queue $queue:tt
+        @rest _ + $inp:ident => internal if $guard:expr $(, $($rest:tt)*)?
+    ) => {
+        $crate::__define_state_machine_rules!(
+            @header $header
+            @lists $transitions $ignores $defers $coalesce $supersedes [ $($wildcards)* ($inp, {}, { $guard }, internal, {}) ]
+            @queue $queue
+            @rest $($($rest)*)?
+        );
+    };
+
+    // `_ + Input => internal`: internal wildcard transition
+    (
+        @header $header:tt
+        @lists $transitions:tt $ignores:tt $defers:tt $coalesce:tt $supersedes:tt [ $($wildcards:tt)* ]
+        @queue $queue:tt
+        @rest _ + $inp:ident => internal $(, $($rest:tt)*)?
+    ) => {
+        $crate::__define_state_machine_rules!(
+            @header $header
+            @lists $transitions $ignores $defers $coalesce $supersedes [ $($wildcards)* ($inp, {}, {}, internal, {}) ]
+            @queue $queue
+            @rest $($($rest)*)?
+        );
+    };
+
+    // `_ + Input => Next emits Output if guard`: guarded wildcard transition with output
+    (
+        @header $header:tt
+        @lists $transitions:tt $ignores:tt $defers:tt $coalesce:tt $supersedes:tt [ $($wildcards:tt)* ]
+        @queue $queue:tt
+        @rest _ + $inp:ident => $to:ident emits $out:ident if $guard:expr $(, $($rest:tt)*)?
+    ) => {
+        $crate::__define_state_machine_rules!(
+            @header $header
+            @lists $transitions $ignores $defers $coalesce $supersedes [ $($wildcards)* ($inp, { $to }, { $guard }, external, { $out }) ]
+            @queue $queue
+            @rest $($($rest)*)?
+        );
+    };
+
+    // `_ + Input => Next emits Output`: wildcard transition with output
+    (
+        @header $header:tt
+        @lists $transitions:tt $ignores:tt $defers:tt $coalesce:tt $supersedes:tt [ $($wildcards:tt)* ]
+        @queue $queue:tt
+        @rest _ + $inp:ident => $to:ident emits $out:ident $(, $($rest:tt)*)?
+    ) => {
+        $crate::__define_state_machine_rules!(
+            @header $header
+            @lists $transitions $ignores $defers $coalesce $supersedes [ $($wildcards)* ($inp, { $to }, {}, external, { $out }) ]
+            @queue $queue
+            @rest $($($rest)*)?
+        );
+    };
+
+    // `_ + Input => Next if guard`: guarded wildcard transition
+    (
+        @header $header:tt
+        @lists $transitions:tt $ignores:tt $defers:tt $coalesce:tt $supersedes:tt [ $($wildcards:tt)* ]
+        @queue $queue:tt
+        @rest _ + $inp:ident => $to:ident if $guard:expr $(, $($rest:tt)*)?
+    ) => {
+        $crate::__define_state_machine_rules!(
+            @header $header
+            @lists $transitions $ignores $defers $coalesce $supersedes [ $($wildcards)* ($inp, { $to }, { $guard }, external, {}) ]
+            @queue $queue
+            @rest $($($rest)*)?
+        );
+    };
+
+    // `_ + Input => Next`: transition from every state without its own rule for the input
+    (
+        @header $header:tt
+        @lists $transitions:tt $ignores:tt $defers:tt $coalesce:tt $supersedes:tt [ $($wildcards:tt)* ]
+        @queue $queue:tt
+        @rest _ + $inp:ident => $to:ident $(, $($rest:tt)*)?
+    ) => {
+        $crate::__define_state_machine_rules!(
+            @header $header
+            @lists $transitions $ignores $defers $coalesce $supersedes [ $($wildcards)* ($inp, { $to }, {}, external, {}) ]
+            @queue $queue
+            @rest $($($rest)*)?
+        );
+    };
+
+    // `State ignores Input`: accepted without effect
+    (
+        @header $header:tt
+        @lists $transitions:tt [ $($ignores:tt)* ] $defers:tt $coalesce:tt $supersedes:tt $wildcards:tt
+        @queue $queue:tt
+        @rest $from:ident ignores $inp:ident $(, $($rest:tt)*)?
+    ) => {
+        $crate::__define_state_machine_rules!(
+            @header $header
+            @lists $transitions [ $($ignores)* ($from, $inp) ] $defers $coalesce $supersedes $wildcards
+            @queue $queue
+            @rest $($($rest)*)?
+        );
+    };
+
+    // `State defers Input`: set aside until the next state change
+    (
+        @header $header:tt
+        @lists $transitions:tt $ignores:tt [ $($defers:tt)* ] $coalesce:tt $supersedes:tt $wildcards:tt
+        @queue $queue:tt
+        @rest $from:ident defers $inp:ident $(, $($rest:tt)*)?
+    ) => {
+        $crate::__define_state_machine_rules!(
+            @header $header
+            @lists $transitions $ignores [ $($defers)* ($from, $inp) ] $coalesce $supersedes $wildcards
+            @queue $queue
+            @rest $($($rest)*)?
+        );
+    };
+
+    // `State + Input => internal emits Output if guard`: guarded internal transition with an output
+    (
+        @header $header:tt
+        @lists [ $($transitions:tt)* ] $ignores:tt $defers:tt $coalesce:tt $supersedes:tt $wildcards:tt
+        @queue $queue:tt
+        @rest $from:ident + $inp:ident => internal emits $out:ident if $guard:expr $(, $($rest:tt)*)?
+    ) => {
+        $crate::__define_state_machine_rules!(
+            @header $header
+            @lists [ $($transitions)* ($from, $inp, $from, { $guard }, internal, { $out }) ] $ignores $defers $coalesce $supersedes $wildcards
+            @queue $queue
+            @rest $($($rest)*)?
+        );
+    };
+
+    // `State + Input => internal emits Output`: internal transition with an output
+    (
+        @header $header:tt
+        @lists [ $($transitions:tt)* ] $ignores:tt $defers:tt $coalesce:tt $supersedes:tt $wildcards:tt
+        @queue $queue:tt
+        @rest $from:ident + $inp:ident => internal emits $out:ident $(, $($rest:tt)*)?
+    ) => {
+        $crate::__define_state_machine_rules!(
+            @header $header
+            @lists [ $($transitions)* ($from, $inp, $from, {}, internal, { $out }) ] $ignores $defers $coalesce $supersedes $wildcards
+            @queue $queue
+            @rest $($($rest)*)?
+        );
+    };
+
+    // `State + Input => compensate Next emits Output if guard`: guarded compensation with an output
+    (
+        @header $header:tt
+        @lists [ $($transitions:tt)* ] $ignores:tt $defers:tt $coalesce:tt $supersedes:tt $wildcards:tt
+        @queue $queue:tt
+        @rest $from:ident + $inp:ident => compensate $to:ident emits $out:ident if $guard:expr $(, $($rest:tt)*)?
+    ) => {
+        $crate::__define_state_machine_rules!(
+            @header $header
+            @lists [ $($transitions)* ($from, $inp, $to, { $guard }, compensation, { $out }) ] $ignores $defers $coalesce $supersedes $wildcards
+            @queue $queue
+            @rest $($($rest)*)?
+        );
+    };
+
+    // `State + Input => compensate Next emits Output`: compensation with an output
+    (
+        @header $header:tt
+        @lists [ $($transitions:tt)* ] $ignores:tt $defers:tt $coalesce:tt $supersedes:tt $wildcards:tt
+        @queue $queue:tt
+        @rest $from:ident + $inp:ident => compensate $to:ident emits $out:ident $(, $($rest:tt)*)?
+    ) => {
+        $crate::__define_state_machine_rules!(
+            @header $header
+            @lists [ $($transitions)* ($from, $inp, $to, {}, compensation, { $out }) ] $ignores $defers $coalesce $supersedes $wildcards
+            @queue $queue
+            @rest $($($rest)*)?
+        );
+    };
+
+    // `State + Input => Next emits Output if guard`: guarded transition with an output
+    (
+        @header $header:tt
+        @lists [ $($transitions:tt)* ] $ignores:tt $defers:tt $coalesce:tt $supersedes:tt $wildcards:tt
+        @queue $queue:tt
+        @rest $from:ident + $inp:ident => $to:ident emits $out:ident if $guard:expr $(, $($rest:tt)*)?
+    ) => {
+        $crate::__define_state_machine_rules!(
+            @header $header
+            @lists [ $($transitions)* ($from, $inp, $to, { $guard }, external, { $out }) ] $ignores $defers $coalesce $supersedes $wildcards
+            @queue $queue
+            @rest $($($rest)*)?
+        );
+    };
+
+    // `State + Input => Next emits Output`: transition with an output
+    (
+        @header $header:tt
+        @lists [ $($transitions:tt)* ] $ignores:tt $defers:tt $coalesce:tt $supersedes:tt $wildcards:tt
+        @queue $queue:tt
+        @rest $from:ident + $inp:ident => $to:ident emits $out:ident $(, $($rest:tt)*)?
+    ) => {
+        $crate::__define_state_machine_rules!(
+            @header $header
+            @lists [ $($transitions)* ($from, $inp, $to, {}, external, { $out }) ] $ignores $defers $coalesce $supersedes $wildcards
+            @queue $queue
+            @rest $($($rest)*)?
+        );
+    };
+
+    // `State + Input => internal if guard`: guarded internal transition
+    (
+        @header $header:tt
+        @lists [ $($transitions:tt)* ] $ignores:tt $defers:tt $coalesce:tt $supersedes:tt $wildcards:tt
+        @queue $queue:tt
+        @rest $from:ident + $inp:ident => internal if $guard:expr $(, $($rest:tt)*)?
+    ) => {
+        $crate::__define_state_machine_rules!(
+            @header $header
+            @lists [ $($transitions)* ($from, $inp, $from, { $guard }, internal, {}) ] $ignores $defers $coalesce $supersedes $wildcards
+            @queue $queue
+            @rest $($($rest)*)?
+        );
+    };
+
+    // `State + Input => internal`: stays in the state without exit/entry
+    (
+        @header $header:tt
+        @lists [ $($transitions:tt)* ] $ignores:tt $defers:tt $coalesce:tt $supersedes:tt $wildcards:tt
+        @queue $queue:tt
+        @rest $from:ident + $inp:ident => internal $(, $($rest:tt)*)?
+    ) => {
+        $crate::__define_state_machine_rules!(
+            @header $header
+            @lists [ $($transitions)* ($from, $inp, $from, {}, internal, {}) ] $ignores $defers $coalesce $supersedes $wildcards
+            @queue $queue
+            @rest $($($rest)*)?
+        );
+    };
+
+    // `State + Input => compensate Next if guard`: guarded compensating transition
+    (
+        @header $header:tt
+        @lists [ $($transitions:tt)* ] $ignores:tt $defers:tt $coalesce:tt $supersedes:tt $wildcards:tt
+        @queue $queue:tt
+        @rest $from:ident + $inp:ident => compensate $to:ident if $guard:expr $(, $($rest:tt)*)?
+    ) => {
+        $crate::__define_state_machine_rules!(
+            @header $header
+            @lists [ $($transitions)* ($from, $inp, $to, { $guard }, compensation, {}) ] $ignores $defers $coalesce $supersedes $wildcards
+            @queue $queue
+            @rest $($($rest)*)?
+        );
+    };
+
+    // `State + Input => compensate Next`: undoes the effects of earlier steps
+    (
+        @header $header:tt
+        @lists [ $($transitions:tt)* ] $ignores:tt $defers:tt $coalesce:tt $supersedes:tt $wildcards:tt
+        @queue $queue:tt
+        @rest $from:ident + $inp:ident => compensate $to:ident $(, $($rest:tt)*)?
+    ) => {
+        $crate::__define_state_machine_rules!(
+            @header $header
+            @lists [ $($transitions)* ($from, $inp, $to, {}, compensation, {}) ] $ignores $defers $coalesce $supersedes $wildcards
+            @queue $queue
+            @rest $($($rest)*)?
+        );
+    };
+
+    // `State + Input => Next if guard`: guarded transition
+    (
+        @header $header:tt
+        @lists [ $($transitions:tt)* ] $ignores:tt $defers:tt $coalesce:tt $supersedes:tt $wildcards:tt
+        @queue $queue:tt
+        @rest $from:ident + $inp:ident => $to:ident if $guard:expr $(, $($rest:tt)*)?
+    ) => {
+        $crate::__define_state_machine_rules!(
+            @header $header
+            @lists [ $($transitions)* ($from, $inp, $to, { $guard }, external, {}) ] $ignores $defers $coalesce $supersedes $wildcards
+            @queue $queue
+            @rest $($($rest)*)?
+        );
+    };
+
+    // `State + Input => Next`: plain transition
+    (
+        @header $header:tt
+        @lists [ $($transitions:tt)* ] $ignores:tt $defers:tt $coalesce:tt $supersedes:tt $wildcards:tt
+        @queue $queue:tt
+        @rest $from:ident + $inp:ident => $to:ident $(, $($rest:tt)*)?
+    ) => {
+        $crate::__define_state_machine_rules!(
+            @header $header
+            @lists [ $($transitions)* ($from, $inp, $to, {}, external, {}) ] $ignores $defers $coalesce $supersedes $wildcards
+            @queue $queue
+            @rest $($($rest)*)?
+        );
+    };
+
+    // `coalesce Input`: consecutive queued copies are merged
+    (
+        @header $header:tt
+        @lists $transitions:tt $ignores:tt $defers:tt [ $($coalesce:tt)* ] $supersedes:tt $wildcards:tt
+        @queue [ coalesce $inp:ident $(, $($queue:tt)*)? ]
+        @rest
+    ) => {
+        $crate::__define_state_machine_rules!(
+            @header $header
+            @lists $transitions $ignores $defers [ $($coalesce)* ($inp) ] $supersedes $wildcards
+            @queue [ $($($queue)*)? ]
+            @rest
+        );
+    };
+
+    // `Newer supersedes Older`: queuing `Newer` drops queued `Older` inputs
+    (
+        @header $header:tt
+        @lists $transitions:tt $ignores:tt $defers:tt $coalesce:tt [ $($supersedes:tt)* ] $wildcards:tt
+        @queue [ $newer:ident supersedes $older:ident $(, $($queue:tt)*)? ]
+        @rest
+    ) => {
+        $crate::__define_state_machine_rules!(
+            @header $header
+            @lists $transitions $ignores $defers $coalesce [ $($supersedes)* ($newer, $older) ] $wildcards
+            @queue [ $($($queue)*)? ]
+            @rest
+        );
+    };
+}
+
+/// Internal helper macro - optionally wraps the generated items in a module
+#[macro_export]
+#[doc(hidden)]
+macro_rules! __define_state_machine_module {
+    ({} { $($items:tt)* }) => {
+        $($items)*
+    };
+    ({ $module:ident } { $($items:tt)* }) => {
+        /// Generated state machine definition
+        pub mod $module {
+            #[allow(unused_imports)]
+            use super::*;
+
+            $($items)*
+        }
+    };
+}
+
+/// Internal helper macro - generates the test guarding a `sealed` definition
+#[macro_export]
+#[doc(hidden)]
+macro_rules! __define_state_machine_sealed {
+    ($name:ident {}) => {};
+    ($name:ident { $sealed:expr }) => {
+        $crate::__paste! {
+            #[cfg(test)]
+            #[test]
+            fn [<$name:snake _sealed_definition>]() {
+                let hash = <$name as $crate::StateMachine>::definition_hash();
+                assert!(
+                    hash == $sealed,
+                    "the definition of {} changed; if intended, update `sealed` to {:#018x}",
+                    stringify!($name),
+                    hash
+                );
+            }
+        }
+    };
+}
+
+/// Internal helper macro - resolves the target of a wildcard transition, staying in
+/// the current state for internal ones
+#[macro_export]
+#[doc(hidden)]
+macro_rules! __define_state_machine_wildcard_target {
+    ($state_ty:ident, $state:ident) => {
+        $state.clone()
+    };
+    ($state_ty:ident, $state:ident $to:ident) => {
+        $state_ty::$to
+    };
+}
+
+/// Internal helper macro - tells whether a transition kind is internal
+#[macro_export]
+#[doc(hidden)]
+macro_rules! __define_state_machine_is_internal {
+    (internal) => {
+        true
+    };
+    ($kind:ident) => {
+        false
+    };
+}
+
+/// Internal helper macro - tells whether a transition kind is a compensation
+#[macro_export]
+#[doc(hidden)]
+macro_rules! __define_state_machine_is_compensation {
+    (compensation) => {
+        true
+    };
+    ($kind:ident) => {
+        false
+    };
+}
+
+/// Internal helper macro - resolves the optional `context` type, defaulting to `()`
+#[macro_export]
+#[doc(hidden)]
+macro_rules! __define_state_machine_context {
+    () => {
+        ()
+    };
+    ($context:ty) => {
+        $context
+    };
+}
+
+/// Internal helper macro - resolves the optional `config` type, defaulting to `()`
+#[macro_export]
+#[doc(hidden)]
+macro_rules! __define_state_machine_config {
+    ({}) => {
+        ()
+    };
+    ({ $config:ty }) => {
+        $config
+    };
+}
+
+/// Internal helper macro - resolves the `Output` type, defaulting to `()` without
+/// an `outputs` block
+#[macro_export]
+#[doc(hidden)]
+macro_rules! __define_state_machine_output {
+    ($output_ty:ident {}) => {
+        ()
+    };
+    ($output_ty:ident { $($output:ident),+ }) => {
+        $output_ty
+    };
+}
+
+/// Internal helper macro - builds the output of a transition, if it emits one
+#[macro_export]
+#[doc(hidden)]
+macro_rules! __define_state_machine_emits {
+    ($output_ty:ident) => {
+        None
+    };
+    ($output_ty:ident $out:ident) => {
+        Some($output_ty::$out)
+    };
+}
+
+/// Internal helper macro - generates the `Output` enumeration if outputs are declared
+#[macro_export]
+#[doc(hidden)]
+macro_rules! __define_state_machine_output_enum {
+    ($output_ty:ident {}) => {};
+    ($output_ty:ident { $($output:ident),+ }) => {
+        /// Output enumeration type
+        #[derive(Debug, Clone, Hash, PartialEq, Eq)]
+        pub enum $output_ty {
+            $($output),+
+        }
+
+        impl ::core::fmt::Display for $output_ty {
+            fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                match self {
+                    $($output_ty::$output => write!(f, stringify!($output)),)+
+                }
+            }
+        }
+    };
+}
+
+/// Internal helper macro - evaluates a guard, which also receives the configuration
+/// if the machine declares one
+#[macro_export]
+#[doc(hidden)]
+macro_rules! __define_state_machine_guard {
+    ({} $guard:expr, $context:ident, $config:ident) => {{
+        let guard: fn(&Self::Context) -> bool = $guard;
+        guard($context)
+    }};
+    ({ $config_ty:ty } $guard:expr, $context:ident, $config:ident) => {{
+        let guard: fn(&Self::Context, &Self::Config) -> bool = $guard;
+        guard($context, $config)
+    }};
+}
+
+/// Internal helper macro - builds an input with default values for its payload fields
+#[macro_export]
+#[doc(hidden)]
+macro_rules! __define_state_machine_default_input {
+    ($input_ty:ident, $input:ident) => {
+        $input_ty::$input
+    };
+    ($input_ty:ident, $input:ident ( $($field_ty:ty),* )) => {
+        $input_ty::$input($(<$field_ty as ::core::default::Default>::default()),*)
+    };
+}
+
 /// Internal helper macro - generates common parts of state machine
 #[macro_export]
 #[doc(hidden)] // Hide internal macro
 macro_rules! __define_state_machine_common {
     (
-        $name:ident,
-        { $($state:ident),* },
-        { $($input:ident),* },
-        $initial:ident,
-        { $( $from:ident + $inp:ident => $to:ident ),* }
+        {
+            [$state_ty:ident] [$input_ty:ident] [$output_ty:ident],
+            $name:ident,
+            { $($(#[doc = $state_doc:expr])* $state:ident),* },
+            { $($alias:literal => $alias_state:ident),* },
+            { $($(#[doc = $input_doc:expr])* $input:ident $(( $($field:ident : $field_ty:ty),* ))?),* },
+            $initial:ident,
+            { $($final_state:ident),* },
+            { $($accepting_state:ident),* },
+            { $($context:ty)? },
+            $config:tt,
+            { $( ($timeout_state:ident, $after:expr, $timeout_inp:ident) )* },
+            { $( ($weight_state:ident, $weight_inp:ident, $weight:expr) )* },
+            $outputs:tt
+        },
+        [ $( ($from:ident, $inp:ident, $to:ident, { $($guard:expr)? }, $kind:ident, { $($out:ident)? }) )* ],
+        [ $( ($ignore_from:ident, $ignore_inp:ident) )* ],
+        [ $( ($defer_from:ident, $defer_inp:ident) )* ],
+        [ $( ($coalesce_inp:ident) )* ],
+        [ $( ($newer_inp:ident, $older_inp:ident) )* ],
+        [ $( ($any_inp:ident, { $($any_to:ident)? }, { $($any_guard:expr)? }, $any_kind:ident, { $($any_out:ident)? }) )* ]
     ) => {
         /// State enumeration type
         #[derive(Debug, Clone, Hash, PartialEq, Eq)]
-        pub enum State {
-            $($state),*
+        pub enum $state_ty {
+            $($(#[doc = $state_doc])* $state),*
         }
 
         /// Input enumeration type
         #[derive(Debug, Clone, Hash, PartialEq, Eq)]
-        pub enum Input {
-            $($input),*
+        pub enum $input_ty {
+            $($(#[doc = $input_doc])* $input $(( $($field_ty),* ))?),*
         }
 
-        impl std::fmt::Display for State {
-            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        impl ::core::fmt::Display for $state_ty {
+            fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
                 match self {
-                    $(State::$state => write!(f, stringify!($state)),)*
+                    $($state_ty::$state => write!(f, stringify!($state)),)*
                 }
             }
         }
 
-        impl From<&str> for State {
-            fn from(s: &str) -> Self {
+        impl ::core::str::FromStr for $state_ty {
+            type Err = $crate::ParseNameError;
+
+            fn from_str(s: &str) -> Result<Self, $crate::ParseNameError> {
                 match s {
-                    $(stringify!($state) => State::$state,)*
-                    _ => panic!("Invalid state: {}", s),
+                    $(stringify!($state) => Ok($state_ty::$state),)*
+                    $($alias => Ok($state_ty::$alias_state),)*
+                    _ => Err($crate::ParseNameError::UnknownState { name: $crate::__private::ToString::to_string(s) }),
                 }
             }
         }
 
-        impl std::fmt::Display for Input {
-            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        impl TryFrom<&str> for $state_ty {
+            type Error = $crate::ParseNameError;
+
+            fn try_from(s: &str) -> Result<Self, $crate::ParseNameError> {
+                s.parse()
+            }
+        }
+
+        impl ::core::fmt::Display for $input_ty {
+            fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
                 match self {
-                    $(Input::$input => write!(f, stringify!($input)),)*
+                    $($input_ty::$input { .. } => write!(f, stringify!($input)),)*
                 }
             }
         }
 
-        impl From<&str> for Input {
-            fn from(s: &str) -> Self {
+        impl ::core::str::FromStr for $input_ty {
+            type Err = $crate::ParseNameError;
+
+            /// Parse an input name, inputs that carry a payload get their default payload
+            fn from_str(s: &str) -> Result<Self, $crate::ParseNameError> {
                 match s {
-                    $(stringify!($input) => Input::$input,)*
-                    _ => panic!("Invalid input: {}", s),
+                    $(stringify!($input) => Ok($crate::__define_state_machine_default_input!(
+                        $input_ty, $input $(( $($field_ty),* ))?
+                    )),)*
+                    _ => Err($crate::ParseNameError::UnknownInput { name: $crate::__private::ToString::to_string(s) }),
                 }
             }
         }
 
-        /// State machine struct
-        pub struct $name;
+        impl TryFrom<&str> for $input_ty {
+            type Error = $crate::ParseNameError;
 
-        impl $crate::StateMachine for $name {
-            type State = State;
-            type Input = Input;
+            fn try_from(s: &str) -> Result<Self, $crate::ParseNameError> {
+                s.parse()
+            }
+        }
 
-            fn states() -> Vec<Self::State> {
-                vec![$(State::$state),*]
+        #[allow(dead_code)]
+        impl $state_ty {
+            /// Number of declared states, same as `STATE_COUNT` on the machine
+            pub const COUNT: usize = $name::STATE_COUNT;
+
+            /// Iterate over all states in declaration order, without allocating
+            pub fn iter() -> ::core::array::IntoIter<Self, { $name::STATE_COUNT }> {
+                [$($state_ty::$state),*].into_iter()
             }
 
-            fn inputs() -> Vec<Self::Input> {
-                vec![$(Input::$input),*]
+            /// Get the names of all states, in declaration order
+            pub const fn all_names() -> &'static [&'static str] {
+                &[$(stringify!($state)),*]
             }
 
-            fn initial_state() -> Self::State {
-                State::$initial
+            /// Get the name of the state
+            pub const fn as_str(&self) -> &'static str {
+                Self::all_names()[self.index()]
             }
 
-            fn state_name(state: &Self::State) -> String {
-                format!("{:?}", state)
+            /// Get the position of the state in declaration order, below `STATE_COUNT`
+            pub const fn index(&self) -> usize {
+                match self {
+                    $($state_ty::$state => $state_ty::$state as usize,)*
+                }
             }
 
-            fn input_name(input: &Self::Input) -> String {
-                format!("{:?}", input)
+            /// Get the state at a position in declaration order
+            ///
+            /// # Returns
+            /// Returns None if `index` is not below `STATE_COUNT`
+            pub fn from_index(index: usize) -> Option<Self> {
+                Self::iter().nth(index)
             }
+        }
 
-            fn valid_inputs(state: &Self::State) -> Vec<Self::Input> {
-                let mut inputs = Vec::new();
-                $(
-                    if matches!(state, State::$from) {
-                        inputs.push(Input::$inp);
-                    }
-                )*
-                inputs
+        #[allow(dead_code)]
+        impl $input_ty {
+            /// Number of declared inputs, same as `INPUT_COUNT` on the machine
+            pub const COUNT: usize = $name::INPUT_COUNT;
+
+            /// Iterate over all inputs in declaration order, with default payloads,
+            /// without allocating
+            pub fn iter() -> ::core::array::IntoIter<Self, { $name::INPUT_COUNT }> {
+                [$($crate::__define_state_machine_default_input!(
+                    $input_ty, $input $(( $($field_ty),* ))?
+                )),*]
+                .into_iter()
             }
 
-            /// Deterministic state transition implementation
-            fn next_state(state: &Self::State, input: &Self::Input) -> Option<Self::State> {
-                #[allow(unreachable_patterns)]
-                match (state, input) {
-                    $(
-                        (State::$from, Input::$inp) => Some(State::$to),
-                    )*
-                    _ => None,
+            /// Get the names of all inputs, in declaration order
+            pub const fn all_names() -> &'static [&'static str] {
+                $name::__INPUT_NAMES
+            }
+
+            /// Get the name of the input, regardless of its payload
+            pub const fn as_str(&self) -> &'static str {
+                Self::all_names()[self.index()]
+            }
+
+            /// Get the position of the input in declaration order, below `INPUT_COUNT`
+            pub const fn index(&self) -> usize {
+                match self {
+                    $($input_ty::$input { .. } => const {
+                        $crate::macros::__position($name::__INPUT_NAMES, stringify!($input))
+                    },)*
                 }
             }
+
+            /// Get the input at a position in declaration order, with default payload
+            ///
+            /// # Returns
+            /// Returns None if `index` is not below `INPUT_COUNT`
+            pub fn from_index(index: usize) -> Option<Self> {
+                Self::iter().nth(index)
+            }
         }
+
+        $crate::__define_state_machine_output_enum!($output_ty $outputs);
+
+        /// State machine struct
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+        pub struct $name;
+
+        #[allow(dead_code)]
+        impl $name {
+            /// Number of declared states
+            pub const STATE_COUNT: usize = [$(stringify!($state)),*].len();
+
+            /// Number of declared inputs
+            pub const INPUT_COUNT: usize = [$(stringify!($input)),*].len();
+
+            /// Number of declared transitions, not counting ignored and deferred inputs
+            ///
+            /// A wildcard rule counts once for every state that is not final and has no
+            /// rule of its own for the input.
+            pub const TRANSITION_COUNT: usize = [$(stringify!($from)),*].len()
+                $(+ Self::STATE_COUNT
+                    - Self::__FINAL.len()
+                    - $crate::macros::__count_input_rules(Self::__RULES, stringify!($any_inp)))*;
+
+            /// Names of the final states, for compile-time checks
+            #[doc(hidden)]
+            const __FINAL: &[&str] = &[$(stringify!($final_state)),*];
+
+            /// Names of the declared inputs, in declaration order
+            #[doc(hidden)]
+            const __INPUT_NAMES: &[&str] = &[$(stringify!($input)),*];
+
+            /// State and input index of every transition, in declaration order
+            #[doc(hidden)]
+            const __TRANSITION_INPUTS: &[(usize, usize)] = &[
+                $(($state_ty::$from as usize, $crate::macros::__position(Self::__INPUT_NAMES, stringify!($inp))),)*
+            ];
+
+            /// State and input index of every rule naming a state, wildcards excluded
+            #[doc(hidden)]
+            const __HANDLED_INPUTS: &[(usize, usize)] = &[
+                $(($state_ty::$from as usize, $crate::macros::__position(Self::__INPUT_NAMES, stringify!($inp))),)*
+                $(($state_ty::$ignore_from as usize, $crate::macros::__position(Self::__INPUT_NAMES, stringify!($ignore_inp))),)*
+                $(($state_ty::$defer_from as usize, $crate::macros::__position(Self::__INPUT_NAMES, stringify!($defer_inp))),)*
+            ];
+
+            /// Input index of every wildcard transition, in declaration order
+            #[doc(hidden)]
+            const __WILDCARD_INPUTS: &[usize] = &[
+                $($crate::macros::__position(Self::__INPUT_NAMES, stringify!($any_inp)),)*
+            ];
+
+            /// Get the canonical value of the input at an index
+            #[doc(hidden)]
+            fn __input_at(index: usize) -> $input_ty {
+                match Self::__INPUT_NAMES[index] {
+                    $(stringify!($input) => $crate::__define_state_machine_default_input!(
+                        $input_ty, $input $(( $($field_ty),* ))?
+                    ),)*
+                    _ => unreachable!("input index out of range"),
+                }
+            }
+
+            /// State and input of every rule naming a state, for compile-time checks
+            #[doc(hidden)]
+            const __RULES: &[(&str, &str)] = &[
+                $((stringify!($from), stringify!($inp)),)*
+                $((stringify!($ignore_from), stringify!($ignore_inp)),)*
+                $((stringify!($defer_from), stringify!($defer_inp)),)*
+            ];
+        }
+
+        impl $crate::StateMachine for $name {
+            type State = $state_ty;
+            type Input = $input_ty;
+            type Context = $crate::__define_state_machine_context!($($context)?);
+            type Config = $crate::__define_state_machine_config!($config);
+            type Output = $crate::__define_state_machine_output!($output_ty $outputs);
+
+            fn states() -> $crate::__private::Vec<Self::State> {
+                $crate::__private::vec![$($state_ty::$state),*]
+            }
+
+            fn state_index(state: &Self::State) -> Option<usize> {
+                Some(state.clone() as usize)
+            }
+
+            fn input_index(input: &Self::Input) -> Option<usize> {
+                Some(input.index())
+            }
+
+            fn inputs() -> $crate::__private::Vec<Self::Input> {
+                $crate::__private::vec![$($crate::__define_state_machine_default_input!(
+                    $input_ty, $input $(( $($field_ty),* ))?
+                )),*]
+            }
+
+            fn initial_state() -> Self::State {
+                $state_ty::$initial
+            }
+
+            fn is_final(state: &Self::State) -> bool {
+                #[allow(unreachable_patterns)]
+                match state {
+                    $($state_ty::$final_state => true,)*
+                    _ => false,
+                }
+            }
+
+            fn is_accepting(state: &Self::State) -> bool {
+                #[allow(unreachable_patterns)]
+                match state {
+                    $($state_ty::$accepting_state => true,)*
+                    _ => false,
+                }
+            }
+
+            fn timeout(
+                state: &Self::State,
+            ) -> Option<(::core::time::Duration, Self::Input)> {
+                #[allow(unreachable_patterns)]
+                match state {
+                    $(
+                        $state_ty::$timeout_state => Self::inputs()
+                            .into_iter()
+                            .find(|input| matches!(input, $input_ty::$timeout_inp { .. }))
+                            .map(|input| ($after, input)),
+                    )*
+                    _ => None,
+                }
+            }
+
+            fn weight(state: &Self::State, input: &Self::Input) -> u32 {
+                #[allow(unreachable_patterns)]
+                match (state, input) {
+                    $(
+                        ($state_ty::$weight_state, $input_ty::$weight_inp { .. }) => $weight,
+                    )*
+                    _ => 1,
+                }
+            }
+
+            fn state_name(state: &Self::State) -> $crate::__private::String {
+                $crate::__private::format!("{:?}", state)
+            }
+
+            fn state_description(state: &Self::State) -> Option<$crate::__private::String> {
+                let lines: &[&str] = match state {
+                    $($state_ty::$state => &[$($state_doc),*],)*
+                };
+                $crate::macros::__description(lines)
+            }
+
+            fn input_name(input: &Self::Input) -> $crate::__private::String {
+                $crate::__private::ToString::to_string(input)
+            }
+
+            fn input_description(input: &Self::Input) -> Option<$crate::__private::String> {
+                let lines: &[&str] = match input {
+                    $($input_ty::$input { .. } => &[$($input_doc),*],)*
+                };
+                $crate::macros::__description(lines)
+            }
+
+            fn canonical_input(input: &Self::Input) -> Self::Input {
+                match input {
+                    $($input_ty::$input { .. } => $crate::__define_state_machine_default_input!(
+                        $input_ty, $input $(( $($field_ty),* ))?
+                    ),)*
+                }
+            }
+
+            fn valid_inputs(state: &Self::State) -> $crate::__private::Vec<Self::Input> {
+                Self::valid_inputs_iter(state).collect()
+            }
+
+            fn valid_inputs_iter(state: &Self::State) -> impl Iterator<Item = Self::Input> {
+                // Transitions of the state first, then wildcards it has no rule of its own for
+                let index = state.clone() as usize;
+                let is_final = Self::is_final(state);
+                let transitions = Self::__TRANSITION_INPUTS
+                    .iter()
+                    .filter(move |(from, _)| *from == index)
+                    .map(|(_, input)| *input);
+                let wildcards = Self::__WILDCARD_INPUTS.iter().copied().filter(move |input| {
+                    !is_final && !Self::__HANDLED_INPUTS.contains(&(index, *input))
+                });
+                transitions.chain(wildcards).map(Self::__input_at)
+            }
+
+            /// Deterministic state transition implementation
+            fn next_state(state: &Self::State, input: &Self::Input) -> Option<Self::State> {
+                #[allow(unreachable_patterns)]
+                match (state, input) {
+                    $(
+                        ($state_ty::$from, $input_ty::$inp { .. }) => Some($state_ty::$to),
+                    )*
+                    $(
+                        (_, $input_ty::$any_inp { .. })
+                            if !Self::is_final(state)
+                                && !Self::is_ignored(state, input)
+                                && !Self::is_deferred(state, input) =>
+                        {
+                            Some($crate::__define_state_machine_wildcard_target!($state_ty, state $($any_to)?))
+                        }
+                    )*
+                    _ => None,
+                }
+            }
+
+            fn check_guard(
+                state: &Self::State,
+                input: &Self::Input,
+                #[allow(unused_variables)] context: &Self::Context,
+                #[allow(unused_variables)] config: &Self::Config,
+            ) -> bool {
+                #[allow(unreachable_patterns)]
+                match (state, input) {
+                    $(
+                        ($state_ty::$from, $input_ty::$inp { .. }) => {
+                            true $(&& $crate::__define_state_machine_guard!(
+                                $config $guard, context, config
+                            ))?
+                        }
+                    )*
+                    $(
+                        (_, $input_ty::$any_inp { .. }) => {
+                            true $(&& $crate::__define_state_machine_guard!(
+                                $config $any_guard, context, config
+                            ))?
+                        }
+                    )*
+                    _ => true,
+                }
+            }
+
+            fn is_internal(state: &Self::State, input: &Self::Input) -> bool {
+                #[allow(unreachable_patterns)]
+                match (state, input) {
+                    $(
+                        ($state_ty::$from, $input_ty::$inp { .. }) => {
+                            $crate::__define_state_machine_is_internal!($kind)
+                        }
+                    )*
+                    $(
+                        (_, $input_ty::$any_inp { .. }) => {
+                            $crate::__define_state_machine_is_internal!($any_kind)
+                        }
+                    )*
+                    _ => false,
+                }
+            }
+
+            fn is_compensation(state: &Self::State, input: &Self::Input) -> bool {
+                #[allow(unreachable_patterns)]
+                match (state, input) {
+                    $(
+                        ($state_ty::$from, $input_ty::$inp { .. }) => {
+                            $crate::__define_state_machine_is_compensation!($kind)
+                        }
+                    )*
+                    $(
+                        (_, $input_ty::$any_inp { .. }) => {
+                            $crate::__define_state_machine_is_compensation!($any_kind)
+                        }
+                    )*
+                    _ => false,
+                }
+            }
+
+            fn output(state: &Self::State, input: &Self::Input) -> Option<Self::Output> {
+                #[allow(unreachable_patterns)]
+                match (state, input) {
+                    $(
+                        ($state_ty::$from, $input_ty::$inp { .. }) => {
+                            $crate::__define_state_machine_emits!($output_ty $($out)?)
+                        }
+                    )*
+                    $(
+                        (_, $input_ty::$any_inp { .. }) if Self::next_state(state, input).is_some() => {
+                            $crate::__define_state_machine_emits!($output_ty $($any_out)?)
+                        }
+                    )*
+                    _ => None,
+                }
+            }
+
+            fn is_ignored(state: &Self::State, input: &Self::Input) -> bool {
+                #[allow(unreachable_patterns)]
+                match (state, input) {
+                    $(
+                        ($state_ty::$ignore_from, $input_ty::$ignore_inp { .. }) => true,
+                    )*
+                    _ => false,
+                }
+            }
+
+            fn is_deferred(state: &Self::State, input: &Self::Input) -> bool {
+                #[allow(unreachable_patterns)]
+                match (state, input) {
+                    $(
+                        ($state_ty::$defer_from, $input_ty::$defer_inp { .. }) => true,
+                    )*
+                    _ => false,
+                }
+            }
+
+            fn coalesces(input: &Self::Input) -> bool {
+                #[allow(unreachable_patterns)]
+                match input {
+                    $(
+                        $input_ty::$coalesce_inp { .. } => true,
+                    )*
+                    _ => false,
+                }
+            }
+
+            fn supersedes(newer: &Self::Input, older: &Self::Input) -> bool {
+                #[allow(unreachable_patterns)]
+                match (newer, older) {
+                    $(
+                        ($input_ty::$newer_inp { .. }, $input_ty::$older_inp { .. }) => true,
+                    )*
+                    _ => false,
+                }
+            }
+        }
+
+        // Reject rules that would be shadowed by an earlier arm or could never apply
+        const _: () = {
+            const RULES: &[(&str, &str)] = $name::__RULES;
+            #[allow(dead_code)]
+            const WILDCARDS: &[(&str, &str)] = &[$(("_", stringify!($any_inp)),)*];
+            $(
+                assert!(
+                    $crate::macros::__count_rules(RULES, stringify!($from), stringify!($inp)) == 1,
+                    concat!(
+                        "Conflicting transitions from ",
+                        stringify!($from),
+                        " with input ",
+                        stringify!($inp),
+                    )
+                );
+            )*
+            $(
+                assert!(
+                    $crate::macros::__count_rules(WILDCARDS, "_", stringify!($any_inp)) == 1,
+                    concat!(
+                        "Conflicting wildcard transitions with input ",
+                        stringify!($any_inp),
+                    )
+                );
+            )*
+            $(
+                assert!(
+                    $crate::macros::__count_rules(RULES, stringify!($weight_state), stringify!($weight_inp)) == 1
+                        || $crate::macros::__count_rules(WILDCARDS, "_", stringify!($weight_inp)) == 1,
+                    concat!(
+                        "Weight given for undeclared transition from ",
+                        stringify!($weight_state),
+                        " with input ",
+                        stringify!($weight_inp),
+                    )
+                );
+            )*
+            $(
+                assert!(
+                    $crate::macros::__count_state_rules(RULES, stringify!($final_state)) == 0,
+                    concat!(
+                        "Final state ",
+                        stringify!($final_state),
+                        " cannot have transition rules",
+                    )
+                );
+            )*
+        };
+    };
+
+    // Default the names of the generated enums
+    ({ [] $($header:tt)* }, $($lists:tt)*) => {
+        $crate::__define_state_machine_common!({ [State] $($header)* }, $($lists)*);
+    };
+    ({ $state_ty:tt [] $($header:tt)* }, $($lists:tt)*) => {
+        $crate::__define_state_machine_common!({ $state_ty [Input] $($header)* }, $($lists)*);
+    };
+    ({ $state_ty:tt $input_ty:tt [] $($header:tt)* }, $($lists:tt)*) => {
+        $crate::__define_state_machine_common!(
+            { $state_ty $input_ty [Output] $($header)* },
+            $($lists)*
+        );
     };
 }
 
+/// Internal helper - joins the lines of a doc comment into a description
+///
+/// Called by the generated `state_description` and `input_description`.
+#[doc(hidden)]
+pub fn __description(lines: &[&str]) -> Option<alloc::string::String> {
+    let description = lines
+        .iter()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+        .collect::<alloc::vec::Vec<_>>()
+        .join(" ");
+    (!description.is_empty()).then_some(description)
+}
+
+/// Internal helper - counts the rules declared for a state and input
+///
+/// Evaluated at compile time by `__define_state_machine_common!`.
+#[doc(hidden)]
+pub const fn __count_rules(rules: &[(&str, &str)], state: &str, input: &str) -> usize {
+    let mut count = 0;
+    let mut i = 0;
+    while i < rules.len() {
+        if __str_eq(rules[i].0, state) && __str_eq(rules[i].1, input) {
+            count += 1;
+        }
+        i += 1;
+    }
+    count
+}
+
+/// Internal helper - compares strings at compile time
+#[doc(hidden)]
+pub const fn __str_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+/// Internal helper - finds the index of a name at compile time
+///
+/// Evaluated at compile time by `__define_state_machine_common!`.
+#[doc(hidden)]
+pub const fn __position(names: &[&str], name: &str) -> usize {
+    let mut i = 0;
+    while i < names.len() {
+        if __str_eq(names[i], name) {
+            return i;
+        }
+        i += 1;
+    }
+    panic!("Unknown name")
+}
+
+/// Internal helper - counts the rules declared for a state, with any input
+///
+/// Evaluated at compile time by `__define_state_machine_common!`.
+#[doc(hidden)]
+pub const fn __count_state_rules(rules: &[(&str, &str)], state: &str) -> usize {
+    let mut count = 0;
+    let mut i = 0;
+    while i < rules.len() {
+        if __str_eq(rules[i].0, state) {
+            count += 1;
+        }
+        i += 1;
+    }
+    count
+}
+
+/// Internal helper - counts the rules declared for an input, in any state
+///
+/// Evaluated at compile time by `__define_state_machine_common!`.
+#[doc(hidden)]
+pub const fn __count_input_rules(rules: &[(&str, &str)], input: &str) -> usize {
+    let mut count = 0;
+    let mut i = 0;
+    while i < rules.len() {
+        if __str_eq(rules[i].1, input) {
+            count += 1;
+        }
+        i += 1;
+    }
+    count
+}
+
 /// Serde support helper macro
 #[macro_export]
 #[doc(hidden)]
 macro_rules! __define_state_machine_serde {
-    ({ $($state:ident),* }, { $($input:ident),* }) => {
-        impl serde::Serialize for State {
+    (
+        [$state_ty:ident] [$input_ty:ident],
+        { $($state:ident),* },
+        { $($input:ident $(( $($field:ident : $field_ty:ty),* ))?),* }
+    ) => {
+        impl serde::Serialize for $state_ty {
             fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
             where
                 S: serde::Serializer,
             {
                 match self {
-                    $(State::$state => serializer.serialize_str(stringify!($state)),)*
+                    $($state_ty::$state => serializer.serialize_str(stringify!($state)),)*
                 }
             }
         }
 
-        impl<'de> serde::Deserialize<'de> for State {
+        impl<'de> serde::Deserialize<'de> for $state_ty {
             fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
             where
                 D: serde::Deserializer<'de>,
             {
+                // Parse like `FromStr`, so that state aliases are accepted
                 let s = String::deserialize(deserializer)?;
-                match s.as_str() {
-                    $(stringify!($state) => Ok(State::$state),)*
-                    _ => Err(serde::de::Error::custom(format!("Unknown state: {}", s))),
-                }
+                s.parse()
+                    .map_err(|_| serde::de::Error::custom(format!("Unknown state: {}", s)))
             }
         }
 
-        impl serde::Serialize for Input {
+        impl serde::Serialize for $input_ty {
             fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
             where
                 S: serde::Serializer,
             {
                 match self {
-                    $(Input::$input => serializer.serialize_str(stringify!($input)),)*
+                    $($input_ty::$input $(( $($field),* ))? => $crate::__define_state_machine_serialize_input!(
+                        serializer, $input $(, $($field),*)?
+                    ),)*
                 }
             }
         }
 
-        impl<'de> serde::Deserialize<'de> for Input {
+        impl<'de> serde::Deserialize<'de> for $input_ty {
             fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
             where
                 D: serde::Deserializer<'de>,
             {
-                let s = String::deserialize(deserializer)?;
-                match s.as_str() {
-                    $(stringify!($input) => Ok(Input::$input),)*
-                    _ => Err(serde::de::Error::custom(format!("Unknown input: {}", s))),
+                struct InputVisitor;
+
+                impl<'de> serde::de::Visitor<'de> for InputVisitor {
+                    type Value = $input_ty;
+
+                    fn expecting(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                        f.write_str("an input name or a map from input name to payload")
+                    }
+
+                    fn visit_str<E>(self, s: &str) -> Result<$input_ty, E>
+                    where
+                        E: serde::de::Error,
+                    {
+                        match s {
+                            $(stringify!($input) => $crate::__define_state_machine_deserialize_input!(
+                                @name $input_ty, $input $(( $($field_ty),* ))?
+                            ),)*
+                            _ => Err(serde::de::Error::custom(format!("Unknown input: {}", s))),
+                        }
+                    }
+
+                    fn visit_map<A>(self, mut map: A) -> Result<$input_ty, A::Error>
+                    where
+                        A: serde::de::MapAccess<'de>,
+                    {
+                        let name: String = map
+                            .next_key()?
+                            .ok_or_else(|| serde::de::Error::custom("Empty input map"))?;
+                        match name.as_str() {
+                            $(stringify!($input) => $crate::__define_state_machine_deserialize_input!(
+                                @map map, $input_ty, $input $(( $($field : $field_ty),* ))?
+                            ),)*
+                            _ => Err(serde::de::Error::custom(format!("Unknown input: {}", name))),
+                        }
+                    }
+                }
+
+                deserializer.deserialize_any(InputVisitor)
+            }
+        }
+    };
+
+    // Default the names of the generated enums
+    ([] $input_ty:tt, $($rest:tt)*) => {
+        $crate::__define_state_machine_serde!([State] $input_ty, $($rest)*);
+    };
+    ($state_ty:tt [], $($rest:tt)*) => {
+        $crate::__define_state_machine_serde!($state_ty [Input], $($rest)*);
+    };
+}
+
+/// Serde support helper macro - serializes one input
+///
+/// Unit inputs serialize as their name, inputs with a payload as a single-entry map
+/// from name to the payload fields.
+#[macro_export]
+#[doc(hidden)]
+macro_rules! __define_state_machine_serialize_input {
+    ($serializer:ident, $input:ident) => {
+        $serializer.serialize_str(stringify!($input))
+    };
+    ($serializer:ident, $input:ident, $($field:ident),*) => {{
+        use serde::ser::SerializeMap;
+        let mut map = $serializer.serialize_map(Some(1))?;
+        map.serialize_entry(stringify!($input), &($($field,)*))?;
+        map.end()
+    }};
+}
+
+/// Serde support helper macro - deserializes one input from its name or payload map
+#[macro_export]
+#[doc(hidden)]
+macro_rules! __define_state_machine_deserialize_input {
+    (@name $input_ty:ident, $input:ident) => {
+        Ok($input_ty::$input)
+    };
+    (@name $input_ty:ident, $input:ident ( $($field_ty:ty),* )) => {
+        Err(serde::de::Error::custom(format!(
+            "Input {} requires a payload",
+            stringify!($input)
+        )))
+    };
+    (@map $map:ident, $input_ty:ident, $input:ident) => {{
+        $map.next_value::<serde::de::IgnoredAny>()?;
+        Ok($input_ty::$input)
+    }};
+    (@map $map:ident, $input_ty:ident, $input:ident ( $($field:ident : $field_ty:ty),* )) => {{
+        let ($($field,)*): ($($field_ty,)*) = $map.next_value()?;
+        Ok($input_ty::$input($($field),*))
+    }};
+}
+
+/// Macro for defining deterministic state machines - serde-enabled version
+///
+/// This macro is used to quickly define deterministic state machines where each state+input
+/// combination can have at most one next state.
+///
+/// # Syntax
+/// ```rust
+/// use yasm::define_state_machine;
+/// define_state_machine! {
+///     name: MyStateMachine,
+///     states: { State1, State2, State3 },
+///     inputs: { Input1, Input2 },
+///     initial: State1,
+///     transitions: {
+///         State1 + Input1 => State2,
+///         State2 + Input2 => State3,
+///     }
+/// }
+/// ```
+///
+/// # Parameters
+/// - `name`: Name of the state machine struct
+/// - `module` (optional): Name of a module to generate all items in, so several machines
+///   can be defined side by side without clashing `State`/`Input` types
+/// - `state_name`, `input_name`, `output_name` (optional): Names of the generated
+///   `State`, `Input` and `Output` enums, so several machines can share a module
+/// - `states`: List of all possible states
+/// - `state_aliases` (optional): Former names of states in the format `"OldName" => State`,
+///   accepted when parsing and deserializing states, so data written before a state was
+///   renamed keeps loading
+/// - `inputs`: List of all possible inputs; an input may carry a payload, e.g. `Pay(amount: u64)`
+/// - `outputs` (optional): List of outputs that transitions can emit
+/// - `initial`: Initial state
+/// - `final` (optional): States in which the machine has completed and takes no further input
+/// - `accepting` (optional): States in which an input word is accepted, see
+///   [`StateMachineQuery::accepts`](crate::StateMachineQuery::accepts)
+/// - `context` (optional): Context type that guards are evaluated against, defaults to `()`
+/// - `config` (optional): Configuration type that guards can read, defaults to `()`
+/// - `sealed` (optional): The expected [`definition_hash`](crate::StateMachine::definition_hash),
+///   checked by a generated `<name>_sealed_definition` unit test
+/// - `transitions`: State transition rules in the format `from_state + input => to_state`,
+///   optionally followed by `if guard`, `from_state + input => internal` for an internal
+///   transition, `from_state + input => compensate to_state` for a saga compensation,
+///   `state ignores input` to accept an input without any effect, or `state defers input`
+///   to retry an input after the next state change; transitions may emit an output with
+///   `emits output` after the target state; `_ + input => to_state` applies to every
+///   state without its own rule for the input, and `from_state + input | other => ...`
+///   declares the same rule for several inputs
+/// - `queue` (optional): Coalescing rules applied by [`InputQueue`](crate::queue::InputQueue),
+///   either `coalesce input` or `newer supersedes older`
+/// - `timeouts` (optional): Timeout rules in the format `state after duration => input`,
+///   fired by `timers::TimedInstance` behind the `timers` feature
+/// - `weights` (optional): Transition weights in the format `state + input => weight`,
+///   used by [`StochasticInstance`](crate::StochasticInstance); unlisted transitions weigh 1
+///
+/// # Ignored Inputs
+/// An input declared with `ignores` is accepted by the instance (no error) but leaves the
+/// state unchanged and fires no callbacks, which distinguishes "ignored by design" from
+/// an invalid input:
+/// ```rust
+/// use yasm::*;
+/// define_state_machine! {
+///     name: Order,
+///     states: { Created, Paid },
+///     inputs: { Pay, Cancel },
+///     initial: Created,
+///     transitions: {
+///         Created + Pay => Paid,
+///         Paid ignores Cancel
+///     }
+/// }
+///
+/// let mut order = StateMachineInstance::<Order>::new();
+/// order.transition(Input::Pay).unwrap();
+/// assert_eq!(order.transition(Input::Cancel), Ok(State::Paid));
+/// ```
+///
+/// # Deferred Inputs
+/// An input declared with `defers` is accepted without changing state, like an ignored
+/// input, but is set aside instead of dropped. After the next state change it is put back
+/// at the front of the instance's input queue, so that
+/// [`process_queue`](crate::StateMachineInstance::process_queue) retries it in the new state:
+/// ```rust
+/// use yasm::*;
+/// define_state_machine! {
+///     name: Order,
+///     states: { Created, Paid, Shipped },
+///     inputs: { Pay, Ship },
+///     initial: Created,
+///     transitions: {
+///         Created + Pay => Paid,
+///         Created defers Ship,
+///         Paid + Ship => Shipped
+///     }
+/// }
+///
+/// let mut order = StateMachineInstance::<Order>::new();
+/// assert_eq!(order.transition(Input::Ship), Ok(State::Created));
+/// order.transition(Input::Pay).unwrap();
+/// assert_eq!(order.process_queue(), Ok(1));
+/// assert_eq!(*order.current_state(), State::Shipped);
+/// ```
+///
+/// # Outputs
+/// Transitions can emit one of the declared `outputs`, which makes the machine a Mealy
+/// machine (a transducer). The output is returned by
+/// [`transition_with_output`](crate::StateMachineInstance::transition_with_output):
+/// ```rust
+/// use yasm::*;
+/// define_state_machine! {
+///     name: TrafficLight,
+///     states: { Red, Green },
+///     inputs: { Timer },
+///     outputs: { StartTraffic, StopTraffic },
+///     initial: Red,
+///     transitions: {
+///         Red + Timer => Green emits StartTraffic,
+///         Green + Timer => Red emits StopTraffic
+///     }
+/// }
+///
+/// let mut light = StateMachineInstance::<TrafficLight>::new();
+/// assert_eq!(
+///     light.transition_with_output(Input::Timer),
+///     Ok((State::Green, Some(Output::StartTraffic)))
+/// );
+/// ```
+///
+/// # Internal Transitions
+/// A transition declared as `state + input => internal` stays in the state without
+/// leaving it: exit and entry callbacks don't fire, whereas an external self-transition
+/// (`state + input => state`) exits and re-enters the state, as in UML. Internal
+/// transitions still run transition callbacks and actions, and can be left out of
+/// history with `set_skip_internal_in_history`:
+/// ```rust
+/// use yasm::*;
+/// use std::sync::{Arc, atomic::{AtomicUsize, Ordering}};
+/// define_state_machine! {
+///     name: Session,
+///     states: { Active },
+///     inputs: { Log, Refresh },
+///     initial: Active,
+///     transitions: {
+///         Active + Log => internal,
+///         Active + Refresh => Active
+///     }
+/// }
+///
+/// let entries = Arc::new(AtomicUsize::new(0));
+/// let counter = Arc::clone(&entries);
+/// let mut session = StateMachineInstance::<Session>::new();
+/// session.on_state_entry(State::Active, move |_| {
+///     counter.fetch_add(1, Ordering::SeqCst);
+/// });
+/// session.transition(Input::Log).unwrap();
+/// assert_eq!(entries.load(Ordering::SeqCst), 0);
+/// session.transition(Input::Refresh).unwrap();
+/// assert_eq!(entries.load(Ordering::SeqCst), 1);
+/// ```
+///
+/// # Compensations
+/// In a saga, a transition declared as `state + input => compensate to_state` undoes
+/// the effects of earlier steps. It behaves like any other transition, but the
+/// documentation generators draw it apart from the happy path and list the rollback
+/// paths it forms:
+/// ```rust
+/// use yasm::*;
+/// define_state_machine! {
+///     name: Booking,
+///     states: { Started, Reserved, Charged, Released, Refunded },
+///     inputs: { Reserve, Charge, Fail },
+///     initial: Started,
+///     transitions: {
+///         Started + Reserve => Reserved,
+///         Reserved + Charge => Charged,
+///         Reserved + Fail => compensate Released,
+///         Charged + Fail => compensate Refunded
+///     }
+/// }
+///
+/// assert!(Booking::is_compensation(&State::Charged, &Input::Fail));
+/// assert!(!Booking::is_compensation(&State::Reserved, &Input::Charge));
+/// ```
+///
+/// # Generated Module
+/// With `module`, the machine struct and its `State`/`Input` types are generated inside
+/// a public module of that name, which glob-imports its parent so that context types
+/// resolve as usual:
+/// ```rust
+/// use yasm::*;
+/// define_state_machine! {
+///     name: Door,
+///     module: door,
+///     states: { Closed, Open },
+///     inputs: { Push },
+///     initial: Closed,
+///     transitions: { Closed + Push => Open }
+/// }
+///
+/// define_state_machine! {
+///     name: Light,
+///     module: light,
+///     states: { Off, On },
+///     inputs: { Push },
+///     initial: Off,
+///     transitions: { Off + Push => On }
+/// }
+///
+/// let mut door = StateMachineInstance::<door::Door>::new();
+/// door.transition(door::Input::Push).unwrap();
+/// assert_eq!(*door.current_state(), door::State::Open);
+/// ```
+///
+/// # Enum Names
+/// Alternatively, `state_name`, `input_name` and `output_name` rename the generated
+/// enums, so machines can share a module. The machine's `StateMachine` implementation
+/// still refers to them as `State`, `Input` and `Output`:
+/// ```rust
+/// use yasm::*;
+/// define_state_machine! {
+///     name: Door,
+///     state_name: DoorState,
+///     input_name: DoorInput,
+///     states: { Closed, Open },
+///     inputs: { Push },
+///     initial: Closed,
+///     transitions: { Closed + Push => Open }
+/// }
+///
+/// define_state_machine! {
+///     name: Light,
+///     state_name: LightState,
+///     input_name: LightInput,
+///     states: { Off, On },
+///     inputs: { Push },
+///     initial: Off,
+///     transitions: { Off + Push => On }
+/// }
+///
+/// let mut door = StateMachineInstance::<Door>::new();
+/// door.transition(DoorInput::Push).unwrap();
+/// assert_eq!(*door.current_state(), DoorState::Open);
+/// assert_eq!(<Light as StateMachine>::initial_state(), LightState::Off);
+/// ```
+///
+/// # Input Payloads
+/// Inputs may carry data. Transitions match on the input variant regardless of its
+/// payload, and payload field types must implement `Default` so that `inputs()` and
+/// `valid_inputs()` can produce a representative value:
+/// ```rust
+/// use yasm::*;
+/// define_state_machine! {
+///     name: Order,
+///     states: { Created, Paid },
+///     inputs: { Pay(amount: u64), Cancel },
+///     initial: Created,
+///     transitions: {
+///         Created + Pay => Paid
+///     }
+/// }
+///
+/// let mut order = StateMachineInstance::<Order>::new();
+/// order.transition(Input::Pay(100)).unwrap();
+/// assert_eq!(order.history()[0].1, Input::Pay(100));
+/// ```
+///
+/// # Queue Rules
+/// The optional `queue` block declares how an [`InputQueue`](crate::queue::InputQueue)
+/// thins out redundant inputs: `coalesce _Log` merges consecutive queued `_Log` inputs,
+/// and `Emergency supersedes Timer` drops queued `Timer` inputs when `Emergency` is queued.
+/// ```rust
+/// use yasm::*;
+/// define_state_machine! {
+///     name: Sensor,
+///     states: { Normal, Alarm },
+///     inputs: { Reading, Emergency, _Log },
+///     initial: Normal,
+///     transitions: {
+///         Normal + Reading => Normal,
+///         Normal + Emergency => Alarm,
+///         Normal + _Log => Normal
+///     },
+///     queue: {
+///         coalesce _Log,
+///         Emergency supersedes Reading
+///     }
+/// }
+///
+/// let mut queue = queue::InputQueue::<Sensor>::new();
+/// queue.push(Input::_Log);
+/// queue.push(Input::_Log);
+/// queue.push(Input::Reading);
+/// queue.push(Input::Emergency);
+/// assert_eq!(queue.iter().collect::<Vec<_>>(), vec![&Input::_Log, &Input::Emergency]);
+/// ```
+///
+/// # Sealed Definitions
+/// With `sealed`, the macro generates a test named after the machine, such as
+/// `fn door_sealed_definition()` for `Door`, in the same module. It fails as soon as
+/// the definition no longer matches the given hash. Editing a sealed workflow then
+/// requires deliberately updating the constant, which shows up in review. Start with any value; the failing test prints the current hash:
+/// ```rust
+/// use yasm::*;
+/// define_state_machine! {
+///     name: Door,
+///     states: { Closed, Open },
+///     inputs: { Push },
+///     initial: Closed,
+///     sealed: 0x3a6f_e612_a5d6_2985,
+///     transitions: { Closed + Push => Open }
+/// }
+///
+/// assert_eq!(Door::definition_hash(), 0x3a6f_e612_a5d6_2985);
+/// ```
+///
+/// # Descriptions
+/// Doc comments on states and inputs become their descriptions, returned by
+/// `state_description` and `input_description` and shown by
+/// [`StateMachineDoc`](crate::StateMachineDoc) in tables and diagrams. Lines of a
+/// comment are joined with spaces:
+/// ```rust
+/// use yasm::*;
+/// define_state_machine! {
+///     name: Order,
+///     states: {
+///         /// Waiting for the customer
+///         /// to pay
+///         Created,
+///         Paid
+///     },
+///     inputs: {
+///         /// Payment confirmed by the provider
+///         Pay
+///     },
+///     initial: Created,
+///     transitions: { Created + Pay => Paid }
+/// }
+///
+/// assert_eq!(
+///     Order::state_description(&State::Created).as_deref(),
+///     Some("Waiting for the customer to pay")
+/// );
+/// assert_eq!(Order::state_description(&State::Paid), None);
+/// assert!(StateMachineDoc::<Order>::generate_mermaid().contains("Created : Waiting"));
+/// ```
+///
+/// # Multiple Inputs
+/// Inputs that lead to the same target with the same options can share a rule, joined
+/// with `|`. The rule stands for one rule per input:
+/// ```rust
+/// use yasm::*;
+/// define_state_machine! {
+///     name: Light,
+///     states: { Red, Yellow, Green },
+///     inputs: { Timer, Emergency },
+///     initial: Red,
+///     transitions: {
+///         Red + Timer | Emergency => Yellow,
+///         Yellow + Timer => Green,
+///         Green + Timer | Emergency => Red
+///     }
+/// }
+///
+/// assert_eq!(Light::next_state(&State::Red, &Input::Emergency), Some(State::Yellow));
+/// assert_eq!(Light::TRANSITION_COUNT, 5);
+/// ```
+///
+/// # Wildcard Transitions
+/// A rule starting with `_` applies to every state that has no rule of its own for the
+/// input, whether a transition, `ignores` or `defers`, wherever that rule is declared.
+/// Wildcard transitions may be internal, guarded and emit outputs like any other:
+/// ```rust
+/// use yasm::*;
+/// define_state_machine! {
+///     name: Hero,
+///     states: { Idle, Walking, Dead },
+///     inputs: { Walk, Die },
+///     initial: Idle,
+///     transitions: {
+///         Idle + Walk => Walking,
+///         _ + Die => Dead,
+///         Dead ignores Die
+///     }
+/// }
+///
+/// assert_eq!(Hero::next_state(&State::Walking, &Input::Die), Some(State::Dead));
+/// assert_eq!(Hero::next_state(&State::Dead, &Input::Die), None);
+/// assert_eq!(Hero::TRANSITION_COUNT, 3);
+/// ```
+///
+/// # Final States
+/// States listed under `final` complete the machine: an instance in one of them
+/// rejects every input with [`TransitionError::MachineCompleted`](crate::TransitionError::MachineCompleted)
+/// and reports [`is_finished`](crate::StateMachineInstance::is_finished). Final states
+/// may not have rules of their own, and wildcard transitions skip them:
+/// ```rust
+/// use yasm::*;
+/// define_state_machine! {
+///     name: Order,
+///     states: { Placed, Shipped, Delivered, Cancelled },
+///     inputs: { Ship, Deliver, Cancel },
+///     initial: Placed,
+///     final: { Delivered, Cancelled },
+///     transitions: {
+///         Placed + Ship => Shipped,
+///         Shipped + Deliver => Delivered,
+///         _ + Cancel => Cancelled
+///     }
+/// }
+///
+/// let mut order = StateMachineInstance::<Order>::new();
+/// order.transition(Input::Cancel).unwrap();
+/// assert!(order.is_finished());
+/// assert!(matches!(
+///     order.transition(Input::Ship),
+///     Err(TransitionError::MachineCompleted { .. })
+/// ));
+/// assert_eq!(Order::TRANSITION_COUNT, 4);
+/// ```
+///
+/// # Accepting States
+/// States listed under `accepting` turn the machine into a recognizer: an input word
+/// is accepted if it leads from the initial state to an accepting state. This machine
+/// accepts binary numbers divisible by three, most significant bit first:
+/// ```rust
+/// use yasm::*;
+/// define_state_machine! {
+///     name: DivisibleByThree,
+///     states: { Rem0, Rem1, Rem2 },
+///     inputs: { Zero, One },
+///     initial: Rem0,
+///     accepting: { Rem0 },
+///     transitions: {
+///         Rem0 + Zero => Rem0,
+///         Rem0 + One => Rem1,
+///         Rem1 + Zero => Rem2,
+///         Rem1 + One => Rem0,
+///         Rem2 + Zero => Rem1,
+///         Rem2 + One => Rem2
+///     }
+/// }
+///
+/// type Query = StateMachineQuery<DivisibleByThree>;
+/// assert!(Query::accepts(&[Input::One, Input::One, Input::Zero]));
+/// assert!(!Query::accepts(&[Input::One, Input::Zero]));
+/// ```
+///
+/// # Conflicting Rules
+/// Each state and input pair may appear in at most one transition, and a transition's
+/// pair may not also be ignored or deferred. Likewise an input may have at most one
+/// wildcard transition. Otherwise only the first rule would ever apply, so the
+/// definition is rejected at compile time with an error naming the pair:
+/// ```compile_fail
+/// use yasm::*;
+/// define_state_machine! {
+///     name: Door,
+///     states: { Closed, Open, Locked },
+///     inputs: { Push },
+///     initial: Closed,
+///     transitions: {
+///         Closed + Push => Open,
+///         Closed + Push => Locked
+///     }
+/// }
+/// ```
+///
+/// # Guards
+/// A transition may carry a guard, a non-capturing closure over the machine's `context`
+/// type. The transition only fires if the guard returns `true` for the instance's context:
+/// ```rust
+/// use yasm::*;
+/// #[derive(Default)]
+/// pub struct Stock {
+///     pub inventory: u32,
+/// }
+///
+/// define_state_machine! {
+///     name: Order,
+///     states: { Paid, Shipped },
+///     inputs: { Ship },
+///     initial: Paid,
+///     context: Stock,
+///     transitions: {
+///         Paid + Ship => Shipped if |ctx| ctx.inventory > 0
+///     }
+/// }
+///
+/// let mut order = StateMachineInstance::<Order>::new();
+/// assert!(order.transition(Input::Ship).is_err());
+///
+/// let mut order = StateMachineInstance::<Order>::with_context(Stock { inventory: 1 });
+/// assert_eq!(order.transition(Input::Ship), Ok(State::Shipped));
+/// ```
+///
+/// # Configuration
+/// Settings that don't change while the machine runs, such as retry limits, belong in a
+/// `config` type instead of the context. Guards of a machine with a configuration take
+/// it as a second argument:
+/// ```rust
+/// use yasm::*;
+/// #[derive(Default)]
+/// pub struct Limits {
+///     pub max_retries: u32,
+/// }
+///
+/// define_state_machine! {
+///     name: Job,
+///     states: { Failed, Running },
+///     inputs: { Retry },
+///     initial: Failed,
+///     context: u32,
+///     config: Limits,
+///     transitions: {
+///         Failed + Retry => Running if |attempts, limits| *attempts < limits.max_retries
+///     }
+/// }
+///
+/// let mut job = StateMachineInstance::<Job>::new();
+/// assert!(job.transition(Input::Retry).is_err());
+///
+/// let mut job = StateMachineInstance::<Job>::with_config(Limits { max_retries: 3 });
+/// assert_eq!(job.transition(Input::Retry), Ok(State::Running));
+/// ```
+#[cfg(feature = "serde")]
+#[macro_export]
+macro_rules! define_state_machine {
+    (
+        name: $name:ident,
+        $(module: $module:ident,)?
+        $(state_name: $state_ty:ident,)?
+        $(input_name: $input_ty:ident,)?
+        $(output_name: $output_ty:ident,)?
+        states: { $($(#[doc = $state_doc:expr])* $state:ident),* $(,)? },
+        $(state_aliases: { $($alias:literal => $alias_state:ident),* $(,)? },)?
+        inputs: {
+            $($(#[doc = $input_doc:expr])* $input:ident $(( $($field:ident : $field_ty:ty),* $(,)? ))?),*
+            $(,)?
+        },
+        $(outputs: { $($output:ident),* $(,)? },)?
+        initial: $initial:ident,
+        $(final: { $($final_state:ident),* $(,)? },)?
+        $(accepting: { $($accepting_state:ident),* $(,)? },)?
+        $(context: $context:ty,)?
+        $(config: $config:ty,)?
+        $(sealed: $sealed:expr,)?
+        transitions: { $($rules:tt)* }
+        $(, queue: { $($queue_rules:tt)* })?
+        $(, timeouts: { $($timeout_state:ident after $after:expr => $timeout_inp:ident),* $(,)? })?
+        $(, weights: { $($weight_state:ident + $weight_inp:ident => $weight:expr),* $(,)? })?
+        $(,)?
+    ) => {
+        $crate::__define_state_machine_module!({ $($module)? } {
+            // Parse transition rules, then generate the common part
+            $crate::__define_state_machine_rules!(
+                @header {
+                    [$($state_ty)?] [$($input_ty)?] [$($output_ty)?],
+                    $name,
+                    { $($(#[doc = $state_doc])* $state),* },
+                    { $($($alias => $alias_state),*)? },
+                    { $($(#[doc = $input_doc])* $input $(( $($field : $field_ty),* ))?),* },
+                    $initial,
+                    { $($($final_state),*)? },
+                    { $($($accepting_state),*)? },
+                    { $($context)? },
+                    { $($config)? },
+                    { $($( ($timeout_state, $after, $timeout_inp) )*)? },
+                    { $($( ($weight_state, $weight_inp, $weight) )*)? },
+                    { $($($output),*)? }
                 }
-            }
-        }
+                @lists [] [] [] [] [] []
+                @queue [ $($($queue_rules)*)? ]
+                @rest $($rules)*
+            );
+
+            // Guard the definition against accidental edits
+            $crate::__define_state_machine_sealed!($name { $($sealed)? });
+
+            // Add serde support
+            $crate::__define_state_machine_serde!(
+                [$($state_ty)?] [$($input_ty)?],
+                { $($state),* },
+                { $($input $(( $($field : $field_ty),* ))?),* }
+            );
+        });
     };
 }
 
-/// Macro for defining deterministic state machines - serde-enabled version
+/// Macro for defining deterministic state machines - non-serde version
 ///
 /// This macro is used to quickly define deterministic state machines where each state+input
 /// combination can have at most one next state.
@@ -183,88 +1989,771 @@ macro_rules! __define_state_machine_serde {
 ///
 /// # Parameters
 /// - `name`: Name of the state machine struct
+/// - `module` (optional): Name of a module to generate all items in, so several machines
+///   can be defined side by side without clashing `State`/`Input` types
+/// - `state_name`, `input_name`, `output_name` (optional): Names of the generated
+///   `State`, `Input` and `Output` enums, so several machines can share a module
 /// - `states`: List of all possible states
-/// - `inputs`: List of all possible inputs
+/// - `state_aliases` (optional): Former names of states in the format `"OldName" => State`,
+///   accepted when parsing and deserializing states, so data written before a state was
+///   renamed keeps loading
+/// - `inputs`: List of all possible inputs; an input may carry a payload, e.g. `Pay(amount: u64)`
+/// - `outputs` (optional): List of outputs that transitions can emit
 /// - `initial`: Initial state
-/// - `transitions`: State transition rules in the format `from_state + input => to_state`
-#[cfg(feature = "serde")]
+/// - `final` (optional): States in which the machine has completed and takes no further input
+/// - `accepting` (optional): States in which an input word is accepted, see
+///   [`StateMachineQuery::accepts`](crate::StateMachineQuery::accepts)
+/// - `context` (optional): Context type that guards are evaluated against, defaults to `()`
+/// - `config` (optional): Configuration type that guards can read, defaults to `()`
+/// - `sealed` (optional): The expected [`definition_hash`](crate::StateMachine::definition_hash),
+///   checked by a generated `<name>_sealed_definition` unit test
+/// - `transitions`: State transition rules in the format `from_state + input => to_state`,
+///   optionally followed by `if guard`, `from_state + input => internal` for an internal
+///   transition, `from_state + input => compensate to_state` for a saga compensation,
+///   `state ignores input` to accept an input without any effect, or `state defers input`
+///   to retry an input after the next state change; transitions may emit an output with
+///   `emits output` after the target state; `_ + input => to_state` applies to every
+///   state without its own rule for the input, and `from_state + input | other => ...`
+///   declares the same rule for several inputs
+/// - `queue` (optional): Coalescing rules applied by [`InputQueue`](crate::queue::InputQueue),
+///   either `coalesce input` or `newer supersedes older`
+/// - `timeouts` (optional): Timeout rules in the format `state after duration => input`,
+///   fired by `timers::TimedInstance` behind the `timers` feature
+/// - `weights` (optional): Transition weights in the format `state + input => weight`,
+///   used by [`StochasticInstance`](crate::StochasticInstance); unlisted transitions weigh 1
+///
+/// # Ignored Inputs
+/// An input declared with `ignores` is accepted by the instance (no error) but leaves the
+/// state unchanged and fires no callbacks, which distinguishes "ignored by design" from
+/// an invalid input:
+/// ```rust
+/// use yasm::*;
+/// define_state_machine! {
+///     name: Order,
+///     states: { Created, Paid },
+///     inputs: { Pay, Cancel },
+///     initial: Created,
+///     transitions: {
+///         Created + Pay => Paid,
+///         Paid ignores Cancel
+///     }
+/// }
+///
+/// let mut order = StateMachineInstance::<Order>::new();
+/// order.transition(Input::Pay).unwrap();
+/// assert_eq!(order.transition(Input::Cancel), Ok(State::Paid));
+/// ```
+///
+/// # Deferred Inputs
+/// An input declared with `defers` is accepted without changing state, like an ignored
+/// input, but is set aside instead of dropped. After the next state change it is put back
+/// at the front of the instance's input queue, so that
+/// [`process_queue`](crate::StateMachineInstance::process_queue) retries it in the new state:
+/// ```rust
+/// use yasm::*;
+/// define_state_machine! {
+///     name: Order,
+///     states: { Created, Paid, Shipped },
+///     inputs: { Pay, Ship },
+///     initial: Created,
+///     transitions: {
+///         Created + Pay => Paid,
+///         Created defers Ship,
+///         Paid + Ship => Shipped
+///     }
+/// }
+///
+/// let mut order = StateMachineInstance::<Order>::new();
+/// assert_eq!(order.transition(Input::Ship), Ok(State::Created));
+/// order.transition(Input::Pay).unwrap();
+/// assert_eq!(order.process_queue(), Ok(1));
+/// assert_eq!(*order.current_state(), State::Shipped);
+/// ```
+///
+/// # Outputs
+/// Transitions can emit one of the declared `outputs`, which makes the machine a Mealy
+/// machine (a transducer). The output is returned by
+/// [`transition_with_output`](crate::StateMachineInstance::transition_with_output):
+/// ```rust
+/// use yasm::*;
+/// define_state_machine! {
+///     name: TrafficLight,
+///     states: { Red, Green },
+///     inputs: { Timer },
+///     outputs: { StartTraffic, StopTraffic },
+///     initial: Red,
+///     transitions: {
+///         Red + Timer => Green emits StartTraffic,
+///         Green + Timer => Red emits StopTraffic
+///     }
+/// }
+///
+/// let mut light = StateMachineInstance::<TrafficLight>::new();
+/// assert_eq!(
+///     light.transition_with_output(Input::Timer),
+///     Ok((State::Green, Some(Output::StartTraffic)))
+/// );
+/// ```
+///
+/// # Internal Transitions
+/// A transition declared as `state + input => internal` stays in the state without
+/// leaving it: exit and entry callbacks don't fire, whereas an external self-transition
+/// (`state + input => state`) exits and re-enters the state, as in UML. Internal
+/// transitions still run transition callbacks and actions, and can be left out of
+/// history with `set_skip_internal_in_history`:
+/// ```rust
+/// use yasm::*;
+/// use std::sync::{Arc, atomic::{AtomicUsize, Ordering}};
+/// define_state_machine! {
+///     name: Session,
+///     states: { Active },
+///     inputs: { Log, Refresh },
+///     initial: Active,
+///     transitions: {
+///         Active + Log => internal,
+///         Active + Refresh => Active
+///     }
+/// }
+///
+/// let entries = Arc::new(AtomicUsize::new(0));
+/// let counter = Arc::clone(&entries);
+/// let mut session = StateMachineInstance::<Session>::new();
+/// session.on_state_entry(State::Active, move |_| {
+///     counter.fetch_add(1, Ordering::SeqCst);
+/// });
+/// session.transition(Input::Log).unwrap();
+/// assert_eq!(entries.load(Ordering::SeqCst), 0);
+/// session.transition(Input::Refresh).unwrap();
+/// assert_eq!(entries.load(Ordering::SeqCst), 1);
+/// ```
+///
+/// # Compensations
+/// In a saga, a transition declared as `state + input => compensate to_state` undoes
+/// the effects of earlier steps. It behaves like any other transition, but the
+/// documentation generators draw it apart from the happy path and list the rollback
+/// paths it forms:
+/// ```rust
+/// use yasm::*;
+/// define_state_machine! {
+///     name: Booking,
+///     states: { Started, Reserved, Charged, Released, Refunded },
+///     inputs: { Reserve, Charge, Fail },
+///     initial: Started,
+///     transitions: {
+///         Started + Reserve => Reserved,
+///         Reserved + Charge => Charged,
+///         Reserved + Fail => compensate Released,
+///         Charged + Fail => compensate Refunded
+///     }
+/// }
+///
+/// assert!(Booking::is_compensation(&State::Charged, &Input::Fail));
+/// assert!(!Booking::is_compensation(&State::Reserved, &Input::Charge));
+/// ```
+///
+/// # Generated Module
+/// With `module`, the machine struct and its `State`/`Input` types are generated inside
+/// a public module of that name, which glob-imports its parent so that context types
+/// resolve as usual:
+/// ```rust
+/// use yasm::*;
+/// define_state_machine! {
+///     name: Door,
+///     module: door,
+///     states: { Closed, Open },
+///     inputs: { Push },
+///     initial: Closed,
+///     transitions: { Closed + Push => Open }
+/// }
+///
+/// define_state_machine! {
+///     name: Light,
+///     module: light,
+///     states: { Off, On },
+///     inputs: { Push },
+///     initial: Off,
+///     transitions: { Off + Push => On }
+/// }
+///
+/// let mut door = StateMachineInstance::<door::Door>::new();
+/// door.transition(door::Input::Push).unwrap();
+/// assert_eq!(*door.current_state(), door::State::Open);
+/// ```
+///
+/// # Enum Names
+/// Alternatively, `state_name`, `input_name` and `output_name` rename the generated
+/// enums, so machines can share a module. The machine's `StateMachine` implementation
+/// still refers to them as `State`, `Input` and `Output`:
+/// ```rust
+/// use yasm::*;
+/// define_state_machine! {
+///     name: Door,
+///     state_name: DoorState,
+///     input_name: DoorInput,
+///     states: { Closed, Open },
+///     inputs: { Push },
+///     initial: Closed,
+///     transitions: { Closed + Push => Open }
+/// }
+///
+/// define_state_machine! {
+///     name: Light,
+///     state_name: LightState,
+///     input_name: LightInput,
+///     states: { Off, On },
+///     inputs: { Push },
+///     initial: Off,
+///     transitions: { Off + Push => On }
+/// }
+///
+/// let mut door = StateMachineInstance::<Door>::new();
+/// door.transition(DoorInput::Push).unwrap();
+/// assert_eq!(*door.current_state(), DoorState::Open);
+/// assert_eq!(<Light as StateMachine>::initial_state(), LightState::Off);
+/// ```
+///
+/// # Input Payloads
+/// Inputs may carry data. Transitions match on the input variant regardless of its
+/// payload, and payload field types must implement `Default` so that `inputs()` and
+/// `valid_inputs()` can produce a representative value:
+/// ```rust
+/// use yasm::*;
+/// define_state_machine! {
+///     name: Order,
+///     states: { Created, Paid },
+///     inputs: { Pay(amount: u64), Cancel },
+///     initial: Created,
+///     transitions: {
+///         Created + Pay => Paid
+///     }
+/// }
+///
+/// let mut order = StateMachineInstance::<Order>::new();
+/// order.transition(Input::Pay(100)).unwrap();
+/// assert_eq!(order.history()[0].1, Input::Pay(100));
+/// ```
+///
+/// # Queue Rules
+/// The optional `queue` block declares how an [`InputQueue`](crate::queue::InputQueue)
+/// thins out redundant inputs: `coalesce _Log` merges consecutive queued `_Log` inputs,
+/// and `Emergency supersedes Timer` drops queued `Timer` inputs when `Emergency` is queued.
+/// ```rust
+/// use yasm::*;
+/// define_state_machine! {
+///     name: Sensor,
+///     states: { Normal, Alarm },
+///     inputs: { Reading, Emergency, _Log },
+///     initial: Normal,
+///     transitions: {
+///         Normal + Reading => Normal,
+///         Normal + Emergency => Alarm,
+///         Normal + _Log => Normal
+///     },
+///     queue: {
+///         coalesce _Log,
+///         Emergency supersedes Reading
+///     }
+/// }
+///
+/// let mut queue = queue::InputQueue::<Sensor>::new();
+/// queue.push(Input::_Log);
+/// queue.push(Input::_Log);
+/// queue.push(Input::Reading);
+/// queue.push(Input::Emergency);
+/// assert_eq!(queue.iter().collect::<Vec<_>>(), vec![&Input::_Log, &Input::Emergency]);
+/// ```
+///
+/// # Sealed Definitions
+/// With `sealed`, the macro generates a test named after the machine, such as
+/// `fn door_sealed_definition()` for `Door`, in the same module. It fails as soon as
+/// the definition no longer matches the given hash. Editing a sealed workflow then
+/// requires deliberately updating the constant, which shows up in review. Start with any value; the failing test prints the current hash:
+/// ```rust
+/// use yasm::*;
+/// define_state_machine! {
+///     name: Door,
+///     states: { Closed, Open },
+///     inputs: { Push },
+///     initial: Closed,
+///     sealed: 0x3a6f_e612_a5d6_2985,
+///     transitions: { Closed + Push => Open }
+/// }
+///
+/// assert_eq!(Door::definition_hash(), 0x3a6f_e612_a5d6_2985);
+/// ```
+///
+/// # Descriptions
+/// Doc comments on states and inputs become their descriptions, returned by
+/// `state_description` and `input_description` and shown by
+/// [`StateMachineDoc`](crate::StateMachineDoc) in tables and diagrams. Lines of a
+/// comment are joined with spaces:
+/// ```rust
+/// use yasm::*;
+/// define_state_machine! {
+///     name: Order,
+///     states: {
+///         /// Waiting for the customer
+///         /// to pay
+///         Created,
+///         Paid
+///     },
+///     inputs: {
+///         /// Payment confirmed by the provider
+///         Pay
+///     },
+///     initial: Created,
+///     transitions: { Created + Pay => Paid }
+/// }
+///
+/// assert_eq!(
+///     Order::state_description(&State::Created).as_deref(),
+///     Some("Waiting for the customer to pay")
+/// );
+/// assert_eq!(Order::state_description(&State::Paid), None);
+/// assert!(StateMachineDoc::<Order>::generate_mermaid().contains("Created : Waiting"));
+/// ```
+///
+/// # Multiple Inputs
+/// Inputs that lead to the same target with the same options can share a rule, joined
+/// with `|`. The rule stands for one rule per input:
+/// ```rust
+/// use yasm::*;
+/// define_state_machine! {
+///     name: Light,
+///     states: { Red, Yellow, Green },
+///     inputs: { Timer, Emergency },
+///     initial: Red,
+///     transitions: {
+///         Red + Timer | Emergency => Yellow,
+///         Yellow + Timer => Green,
+///         Green + Timer | Emergency => Red
+///     }
+/// }
+///
+/// assert_eq!(Light::next_state(&State::Red, &Input::Emergency), Some(State::Yellow));
+/// assert_eq!(Light::TRANSITION_COUNT, 5);
+/// ```
+///
+/// # Wildcard Transitions
+/// A rule starting with `_` applies to every state that has no rule of its own for the
+/// input, whether a transition, `ignores` or `defers`, wherever that rule is declared.
+/// Wildcard transitions may be internal, guarded and emit outputs like any other:
+/// ```rust
+/// use yasm::*;
+/// define_state_machine! {
+///     name: Hero,
+///     states: { Idle, Walking, Dead },
+///     inputs: { Walk, Die },
+///     initial: Idle,
+///     transitions: {
+///         Idle + Walk => Walking,
+///         _ + Die => Dead,
+///         Dead ignores Die
+///     }
+/// }
+///
+/// assert_eq!(Hero::next_state(&State::Walking, &Input::Die), Some(State::Dead));
+/// assert_eq!(Hero::next_state(&State::Dead, &Input::Die), None);
+/// assert_eq!(Hero::TRANSITION_COUNT, 3);
+/// ```
+///
+/// # Final States
+/// States listed under `final` complete the machine: an instance in one of them
+/// rejects every input with [`TransitionError::MachineCompleted`](crate::TransitionError::MachineCompleted)
+/// and reports [`is_finished`](crate::StateMachineInstance::is_finished). Final states
+/// may not have rules of their own, and wildcard transitions skip them:
+/// ```rust
+/// use yasm::*;
+/// define_state_machine! {
+///     name: Order,
+///     states: { Placed, Shipped, Delivered, Cancelled },
+///     inputs: { Ship, Deliver, Cancel },
+///     initial: Placed,
+///     final: { Delivered, Cancelled },
+///     transitions: {
+///         Placed + Ship => Shipped,
+///         Shipped + Deliver => Delivered,
+///         _ + Cancel => Cancelled
+///     }
+/// }
+///
+/// let mut order = StateMachineInstance::<Order>::new();
+/// order.transition(Input::Cancel).unwrap();
+/// assert!(order.is_finished());
+/// assert!(matches!(
+///     order.transition(Input::Ship),
+///     Err(TransitionError::MachineCompleted { .. })
+/// ));
+/// assert_eq!(Order::TRANSITION_COUNT, 4);
+/// ```
+///
+/// # Accepting States
+/// States listed under `accepting` turn the machine into a recognizer: an input word
+/// is accepted if it leads from the initial state to an accepting state. This machine
+/// accepts binary numbers divisible by three, most significant bit first:
+/// ```rust
+/// use yasm::*;
+/// define_state_machine! {
+///     name: DivisibleByThree,
+///     states: { Rem0, Rem1, Rem2 },
+///     inputs: { Zero, One },
+///     initial: Rem0,
+///     accepting: { Rem0 },
+///     transitions: {
+///         Rem0 + Zero => Rem0,
+///         Rem0 + One => Rem1,
+///         Rem1 + Zero => Rem2,
+///         Rem1 + One => Rem0,
+///         Rem2 + Zero => Rem1,
+///         Rem2 + One => Rem2
+///     }
+/// }
+///
+/// type Query = StateMachineQuery<DivisibleByThree>;
+/// assert!(Query::accepts(&[Input::One, Input::One, Input::Zero]));
+/// assert!(!Query::accepts(&[Input::One, Input::Zero]));
+/// ```
+///
+/// # Conflicting Rules
+/// Each state and input pair may appear in at most one transition, and a transition's
+/// pair may not also be ignored or deferred. Likewise an input may have at most one
+/// wildcard transition. Otherwise only the first rule would ever apply, so the
+/// definition is rejected at compile time with an error naming the pair:
+/// ```compile_fail
+/// use yasm::*;
+/// define_state_machine! {
+///     name: Door,
+///     states: { Closed, Open, Locked },
+///     inputs: { Push },
+///     initial: Closed,
+///     transitions: {
+///         Closed + Push => Open,
+///         Closed + Push => Locked
+///     }
+/// }
+/// ```
+///
+/// # Guards
+/// A transition may carry a guard, a non-capturing closure over the machine's `context`
+/// type. The transition only fires if the guard returns `true` for the instance's context:
+/// ```rust
+/// use yasm::*;
+/// #[derive(Default)]
+/// pub struct Stock {
+///     pub inventory: u32,
+/// }
+///
+/// define_state_machine! {
+///     name: Order,
+///     states: { Paid, Shipped },
+///     inputs: { Ship },
+///     initial: Paid,
+///     context: Stock,
+///     transitions: {
+///         Paid + Ship => Shipped if |ctx| ctx.inventory > 0
+///     }
+/// }
+///
+/// let mut order = StateMachineInstance::<Order>::new();
+/// assert!(order.transition(Input::Ship).is_err());
+///
+/// let mut order = StateMachineInstance::<Order>::with_context(Stock { inventory: 1 });
+/// assert_eq!(order.transition(Input::Ship), Ok(State::Shipped));
+/// ```
+///
+/// # Configuration
+/// Settings that don't change while the machine runs, such as retry limits, belong in a
+/// `config` type instead of the context. Guards of a machine with a configuration take
+/// it as a second argument:
+/// ```rust
+/// use yasm::*;
+/// #[derive(Default)]
+/// pub struct Limits {
+///     pub max_retries: u32,
+/// }
+///
+/// define_state_machine! {
+///     name: Job,
+///     states: { Failed, Running },
+///     inputs: { Retry },
+///     initial: Failed,
+///     context: u32,
+///     config: Limits,
+///     transitions: {
+///         Failed + Retry => Running if |attempts, limits| *attempts < limits.max_retries
+///     }
+/// }
+///
+/// let mut job = StateMachineInstance::<Job>::new();
+/// assert!(job.transition(Input::Retry).is_err());
+///
+/// let mut job = StateMachineInstance::<Job>::with_config(Limits { max_retries: 3 });
+/// assert_eq!(job.transition(Input::Retry), Ok(State::Running));
+/// ```
+#[cfg(not(feature = "serde"))]
 #[macro_export]
 macro_rules! define_state_machine {
     (
         name: $name:ident,
-        states: { $($state:ident),* $(,)? },
-        inputs: { $($input:ident),* $(,)? },
+        $(module: $module:ident,)?
+        $(state_name: $state_ty:ident,)?
+        $(input_name: $input_ty:ident,)?
+        $(output_name: $output_ty:ident,)?
+        states: { $($(#[doc = $state_doc:expr])* $state:ident),* $(,)? },
+        $(state_aliases: { $($alias:literal => $alias_state:ident),* $(,)? },)?
+        inputs: {
+            $($(#[doc = $input_doc:expr])* $input:ident $(( $($field:ident : $field_ty:ty),* $(,)? ))?),*
+            $(,)?
+        },
+        $(outputs: { $($output:ident),* $(,)? },)?
         initial: $initial:ident,
-        transitions: {
-            $(
-                $from:ident + $inp:ident => $to:ident
-            ),* $(,)?
-        }
+        $(final: { $($final_state:ident),* $(,)? },)?
+        $(accepting: { $($accepting_state:ident),* $(,)? },)?
+        $(context: $context:ty,)?
+        $(config: $config:ty,)?
+        $(sealed: $sealed:expr,)?
+        transitions: { $($rules:tt)* }
+        $(, queue: { $($queue_rules:tt)* })?
+        $(, timeouts: { $($timeout_state:ident after $after:expr => $timeout_inp:ident),* $(,)? })?
+        $(, weights: { $($weight_state:ident + $weight_inp:ident => $weight:expr),* $(,)? })?
+        $(,)?
     ) => {
-        // Call common part
-        $crate::__define_state_machine_common!(
-            $name,
-            { $($state),* },
-            { $($input),* },
-            $initial,
-            { $( $from + $inp => $to ),* }
-        );
+        $crate::__define_state_machine_module!({ $($module)? } {
+            // Parse transition rules, then generate the common part
+            $crate::__define_state_machine_rules!(
+                @header {
+                    [$($state_ty)?] [$($input_ty)?] [$($output_ty)?],
+                    $name,
+                    { $($(#[doc = $state_doc])* $state),* },
+                    { $($($alias => $alias_state),*)? },
+                    { $($(#[doc = $input_doc])* $input $(( $($field : $field_ty),* ))?),* },
+                    $initial,
+                    { $($($final_state),*)? },
+                    { $($($accepting_state),*)? },
+                    { $($context)? },
+                    { $($config)? },
+                    { $($( ($timeout_state, $after, $timeout_inp) )*)? },
+                    { $($( ($weight_state, $weight_inp, $weight) )*)? },
+                    { $($($output),*)? }
+                }
+                @lists [] [] [] [] [] []
+                @queue [ $($($queue_rules)*)? ]
+                @rest $($rules)*
+            );
 
-        // Add serde support
-        $crate::__define_state_machine_serde!(
-            { $($state),* },
-            { $($input),* }
-        );
+            // Guard the definition against accidental edits
+            $crate::__define_state_machine_sealed!($name { $($sealed)? });
+        });
     };
 }
 
-/// Macro for defining deterministic state machines - non-serde version
+/// Exhaustive match over the states of a machine defined with `define_state_machine!`
 ///
-/// This macro is used to quickly define deterministic state machines where each state+input
-/// combination can have at most one next state.
+/// Expands to a `match` with one arm per listed state and no wildcard arm, and the
+/// syntax doesn't allow one. Per-state logic written with it outside the machine stops
+/// compiling when a state is added, until the new state is handled. Several states can
+/// share an arm with `|`.
 ///
-/// # Syntax
+/// # Arguments
+/// - The path of the machine's `State` type
+/// - The state to match on
+/// - The arms, each mapping one or more state names to an expression
+///
+/// # Example
 /// ```rust
-/// use yasm::define_state_machine;
-/// define_state_machine! {
-///     name: MyStateMachine,
-///     states: { State1, State2, State3 },
-///     inputs: { Input1, Input2 },
-///     initial: State1,
-///     transitions: {
-///         State1 + Input1 => State2,
-///         State2 + Input2 => State3,
+/// use yasm::*;
+/// mod door {
+///     use yasm::*;
+///     define_state_machine! {
+///         name: Door,
+///         states: { Closed, Open, Locked },
+///         inputs: { Open, Close, Lock },
+///         initial: Closed,
+///         transitions: {
+///             Closed + Open => Open,
+///             Open + Close => Closed,
+///             Closed + Lock => Locked
+///         }
 ///     }
 /// }
+///
+/// fn icon(state: &door::State) -> &'static str {
+///     match_all_states!(door::State, state, {
+///         Closed | Locked => "🚪",
+///         Open => "🔓",
+///     })
+/// }
+///
+/// assert_eq!(icon(&door::State::Open), "🔓");
+/// assert_eq!(door::Door::STATE_COUNT, 3);
+/// assert_eq!(door::Door::INPUT_COUNT, 3);
+/// assert_eq!(door::State::Locked.index(), 2);
+/// assert_eq!(door::State::from_index(1), Some(door::State::Open));
+/// assert_eq!(door::Door::TRANSITION_COUNT, 3);
+/// ```
+///
+/// Leaving out a state is a compile error:
+/// ```compile_fail
+/// # use yasm::*;
+/// # mod door {
+/// #     use yasm::*;
+/// #     define_state_machine! {
+/// #         name: Door,
+/// #         states: { Closed, Open },
+/// #         inputs: { Open },
+/// #         initial: Closed,
+/// #         transitions: { Closed + Open => Open }
+/// #     }
+/// # }
+/// fn icon(state: &door::State) -> &'static str {
+///     match_all_states!(door::State, state, {
+///         Closed => "🚪",
+///     })
+/// }
 /// ```
+#[macro_export]
+macro_rules! match_all_states {
+    (
+        $($state_type:ident)::+,
+        $state:expr,
+        { $($($variant:ident)|+ => $body:expr),+ $(,)? }
+    ) => {{
+        use $($state_type)::+ as __State;
+        match $state {
+            $($(__State::$variant)|+ => $body,)+
+        }
+    }};
+}
+
+/// Macro for defining nondeterministic finite automata
+///
+/// Defines `State` and `Input` enums and a struct implementing [`Nfa`](crate::nfa::Nfa).
+/// Unlike `define_state_machine!`, a state and input may lead to several states, either
+/// by listing them in braces or by repeating the rule, and a rule without an input is an
+/// epsilon move taken without consuming input. Run the automaton through its
+/// [`Determinized`](crate::nfa::Determinized) machine.
 ///
 /// # Parameters
-/// - `name`: Name of the state machine struct
+/// - `name`: Name of the automaton struct
 /// - `states`: List of all possible states
 /// - `inputs`: List of all possible inputs
 /// - `initial`: Initial state
-/// - `transitions`: State transition rules in the format `from_state + input => to_state`
-#[cfg(not(feature = "serde"))]
+/// - `accepting` (optional): States in which an input word is accepted
+/// - `transitions`: Rules in the format `from_state + input => to_state`,
+///   `from_state + input => { to_state, ... }`, or `from_state => to_state` for epsilon
+///   moves
+///
+/// # Example
+/// ```rust
+/// use yasm::*;
+/// define_nfa! {
+///     name: EndsWithAb,
+///     states: { Start, SawA, Done },
+///     inputs: { A, B },
+///     initial: Start,
+///     accepting: { Done },
+///     transitions: {
+///         Start + A => { Start, SawA },
+///         Start + B => Start,
+///         SawA + B => Done
+///     }
+/// }
+///
+/// assert!(EndsWithAb::accepts(&[Input::B, Input::A, Input::B]));
+/// assert!(!EndsWithAb::accepts(&[Input::A, Input::B, Input::A]));
+///
+/// let mut matcher = EndsWithAb::determinize();
+/// matcher.transition(Input::A).unwrap();
+/// matcher.transition(Input::B).unwrap();
+/// assert!(matcher.is_accepting());
+/// assert_eq!(Determinized::<EndsWithAb>::states().len(), 3);
+/// ```
 #[macro_export]
-macro_rules! define_state_machine {
+macro_rules! define_nfa {
     (
         name: $name:ident,
         states: { $($state:ident),* $(,)? },
         inputs: { $($input:ident),* $(,)? },
         initial: $initial:ident,
-        transitions: {
-            $(
-                $from:ident + $inp:ident => $to:ident
-            ),* $(,)?
-        }
+        $(accepting: { $($accepting_state:ident),* $(,)? },)?
+        transitions: { $($from:ident $(+ $inp:ident)? => $to:tt),* $(,)? }
+        $(,)?
     ) => {
-        // Call common part
-        $crate::__define_state_machine_common!(
-            $name,
-            { $($state),* },
-            { $($input),* },
-            $initial,
-            { $( $from + $inp => $to ),* }
-        );
+        /// State enumeration type
+        #[derive(Debug, Clone, Hash, PartialEq, Eq)]
+        pub enum State {
+            $($state),*
+        }
+
+        /// Input enumeration type
+        #[derive(Debug, Clone, Hash, PartialEq, Eq)]
+        pub enum Input {
+            $($input),*
+        }
+
+        /// Nondeterministic automaton struct
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+        pub struct $name;
+
+        impl $crate::nfa::Nfa for $name {
+            type State = State;
+            type Input = Input;
+
+            fn states() -> $crate::__private::Vec<Self::State> {
+                $crate::__private::vec![$(State::$state),*]
+            }
+
+            fn inputs() -> $crate::__private::Vec<Self::Input> {
+                $crate::__private::vec![$(Input::$input),*]
+            }
+
+            fn initial_state() -> Self::State {
+                State::$initial
+            }
+
+            fn targets(state: &Self::State, input: Option<&Self::Input>) -> $crate::__private::Vec<Self::State> {
+                let mut targets = $crate::__private::Vec::new();
+                $(
+                    if matches!(state, State::$from)
+                        && $crate::__define_nfa_input!(input $($inp)?)
+                    {
+                        targets.extend($crate::__define_nfa_targets!($to));
+                    }
+                )*
+                targets
+            }
+
+            fn is_accepting(state: &Self::State) -> bool {
+                #[allow(unreachable_patterns)]
+                match state {
+                    $($(State::$accepting_state => true,)*)?
+                    _ => false,
+                }
+            }
+        }
+    };
+}
+
+/// Internal helper - checks the input of an NFA rule, None for epsilon moves
+#[macro_export]
+#[doc(hidden)]
+macro_rules! __define_nfa_input {
+    ($input:ident) => {
+        $input.is_none()
+    };
+    ($input:ident $inp:ident) => {
+        matches!($input, Some(Input::$inp))
+    };
+}
+
+/// Internal helper - lists the targets of an NFA rule
+#[macro_export]
+#[doc(hidden)]
+macro_rules! __define_nfa_targets {
+    ({ $($to:ident),* $(,)? }) => {
+        [$(State::$to),*]
+    };
+    ($to:ident) => {
+        [State::$to]
     };
 }
@@ -0,0 +1,387 @@
+use crate::core::StateMachine;
+use crate::hashing::HashMap;
+#[cfg(feature = "prometheus")]
+use alloc::{format, string::String, vec::Vec};
+use core::time::Duration;
+
+/// Key of a transition counter: (from, input, to)
+type TransitionKey<SM> = (
+    <SM as StateMachine>::State,
+    <SM as StateMachine>::Input,
+    <SM as StateMachine>::State,
+);
+
+/// Counters collected by an instance, see
+/// [`set_collect_metrics`](crate::StateMachineInstance::set_collect_metrics)
+///
+/// Counts every transition taken by `(from, input, to)`, internal ones included, the
+/// time spent in each state, and the rejected inputs by state. Ignored and deferred
+/// inputs are neither transitions nor rejections. Time is accumulated when a state is
+/// left, so the stay in the current state is not part of it yet.
+///
+/// # Example
+/// ```ignore
+/// order.set_collect_metrics(true);
+/// order.transition(Input::Pay)?;
+/// let metrics = order.metrics().unwrap();
+/// assert_eq!(metrics.transition_count(&State::Created, &Input::Pay, &State::Paid), 1);
+/// ```
+pub struct TransitionMetrics<SM: StateMachine> {
+    /// Number of transitions by (from, input, to)
+    transitions: HashMap<TransitionKey<SM>, u64>,
+    /// Milliseconds spent in each state, over completed stays
+    time_in_state: HashMap<SM::State, u64>,
+    /// Number of rejected inputs by (state, input)
+    rejections: HashMap<(SM::State, SM::Input), u64>,
+}
+
+impl<SM: StateMachine> TransitionMetrics<SM> {
+    /// Create empty metrics
+    pub fn new() -> Self {
+        Self {
+            transitions: HashMap::default(),
+            time_in_state: HashMap::default(),
+            rejections: HashMap::default(),
+        }
+    }
+
+    /// Get how often a transition was taken
+    pub fn transition_count(&self, from: &SM::State, input: &SM::Input, to: &SM::State) -> u64 {
+        self.transitions
+            .get(&(from.clone(), input.clone(), to.clone()))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Iterate over the transitions taken, with their counts, in no particular order
+    pub fn transitions(&self) -> impl Iterator<Item = (&SM::State, &SM::Input, &SM::State, u64)> {
+        self.transitions
+            .iter()
+            .map(|((from, input, to), count)| (from, input, to, *count))
+    }
+
+    /// Get the number of transitions taken
+    pub fn total_transitions(&self) -> u64 {
+        self.transitions.values().sum()
+    }
+
+    /// Get the time spent in a state over the stays that have ended
+    pub fn time_in(&self, state: &SM::State) -> Duration {
+        Duration::from_millis(self.time_in_state.get(state).copied().unwrap_or(0))
+    }
+
+    /// Get how often an input was rejected in a state
+    pub fn rejection_count(&self, state: &SM::State, input: &SM::Input) -> u64 {
+        self.rejections
+            .get(&(state.clone(), input.clone()))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Get the number of rejected inputs
+    pub fn total_rejections(&self) -> u64 {
+        self.rejections.values().sum()
+    }
+
+    /// Add the counters of other metrics to these, e.g. to aggregate a fleet
+    pub fn merge(&mut self, other: &Self) {
+        for (key, count) in &other.transitions {
+            *self.transitions.entry(key.clone()).or_insert(0) += count;
+        }
+        for (state, millis) in &other.time_in_state {
+            *self.time_in_state.entry(state.clone()).or_insert(0) += millis;
+        }
+        for (key, count) in &other.rejections {
+            *self.rejections.entry(key.clone()).or_insert(0) += count;
+        }
+    }
+
+    /// Count a transition
+    pub(crate) fn record_transition(
+        &mut self,
+        from: &SM::State,
+        input: &SM::Input,
+        to: &SM::State,
+    ) {
+        *self
+            .transitions
+            .entry((from.clone(), input.clone(), to.clone()))
+            .or_insert(0) += 1;
+    }
+
+    /// Add an ended stay in a state
+    pub(crate) fn record_stay(&mut self, state: &SM::State, millis: u64) {
+        *self.time_in_state.entry(state.clone()).or_insert(0) += millis;
+    }
+
+    /// Count a rejected input
+    pub(crate) fn record_rejection(&mut self, state: &SM::State, input: &SM::Input) {
+        *self
+            .rejections
+            .entry((state.clone(), input.clone()))
+            .or_insert(0) += 1;
+    }
+
+    /// Render the metrics in the Prometheus text exposition format
+    ///
+    /// Exposes the counters `yasm_transitions_total`, `yasm_state_seconds_total` and
+    /// `yasm_rejections_total`, labelled with the machine name and the state and input
+    /// names. Lines of a metric are sorted, so the output is stable.
+    ///
+    /// # Arguments
+    /// - `machine`: The value of the `machine` label, e.g. the name of the machine
+    #[cfg(feature = "prometheus")]
+    pub fn to_prometheus(&self, machine: &str) -> String {
+        self.to_prometheus_with_labels(machine, &[])
+    }
+
+    /// Render the metrics in the Prometheus text exposition format, with extra labels
+    ///
+    /// Behaves like [`to_prometheus`](Self::to_prometheus), adding the given labels
+    /// to every sample after the `machine` label, e.g. the tenant of the instance. For
+    /// a whole fleet labelled by metadata, see
+    /// [`Supervisor::to_prometheus`](crate::Supervisor::to_prometheus).
+    ///
+    /// # Arguments
+    /// - `machine`: The value of the `machine` label
+    /// - `labels`: Extra (name, value) pairs; names must be valid Prometheus label names
+    #[cfg(feature = "prometheus")]
+    pub fn to_prometheus_with_labels(&self, machine: &str, labels: &[(&str, &str)]) -> String {
+        let mut samples = PrometheusSamples::default();
+        samples.add(self, &prometheus_labels(machine, labels));
+        samples.render()
+    }
+}
+
+/// Samples of the exported counters, gathered from one or more label sets
+#[cfg(feature = "prometheus")]
+#[derive(Debug, Default)]
+pub(crate) struct PrometheusSamples {
+    /// Samples of `yasm_instances`, a gauge only exported for fleets
+    pub(crate) instances: Vec<String>,
+    /// Samples of `yasm_transitions_total`
+    transitions: Vec<String>,
+    /// Samples of `yasm_state_seconds_total`
+    state_seconds: Vec<String>,
+    /// Samples of `yasm_rejections_total`
+    rejections: Vec<String>,
+}
+
+#[cfg(feature = "prometheus")]
+impl PrometheusSamples {
+    /// Add the samples of metrics under the given rendered labels
+    pub(crate) fn add<SM: StateMachine>(&mut self, metrics: &TransitionMetrics<SM>, labels: &str) {
+        self.transitions
+            .extend(metrics.transitions.iter().map(|((from, input, to), count)| {
+                format!(
+                    "yasm_transitions_total{{{labels},from=\"{}\",input=\"{}\",to=\"{}\"}} {count}\n",
+                    escape_label(&SM::state_name(from)),
+                    escape_label(&SM::input_name(input)),
+                    escape_label(&SM::state_name(to))
+                )
+            }));
+        self.state_seconds
+            .extend(metrics.time_in_state.iter().map(|(state, millis)| {
+                format!(
+                    "yasm_state_seconds_total{{{labels},state=\"{}\"}} {}\n",
+                    escape_label(&SM::state_name(state)),
+                    *millis as f64 / 1000.0
+                )
+            }));
+        self.rejections
+            .extend(metrics.rejections.iter().map(|((state, input), count)| {
+                format!(
+                    "yasm_rejections_total{{{labels},state=\"{}\",input=\"{}\"}} {count}\n",
+                    escape_label(&SM::state_name(state)),
+                    escape_label(&SM::input_name(input))
+                )
+            }));
+    }
+
+    /// Render the samples, each metric with its help and type lines
+    pub(crate) fn render(mut self) -> String {
+        let mut text = String::new();
+        if !self.instances.is_empty() {
+            push_metric(
+                &mut text,
+                "yasm_instances",
+                "Instances by state",
+                "gauge",
+                &mut self.instances,
+            );
+        }
+        push_metric(
+            &mut text,
+            "yasm_transitions_total",
+            "Transitions taken",
+            "counter",
+            &mut self.transitions,
+        );
+        push_metric(
+            &mut text,
+            "yasm_state_seconds_total",
+            "Time spent in states, over ended stays",
+            "counter",
+            &mut self.state_seconds,
+        );
+        push_metric(
+            &mut text,
+            "yasm_rejections_total",
+            "Rejected inputs",
+            "counter",
+            &mut self.rejections,
+        );
+        text
+    }
+}
+
+/// Render the `machine` label followed by extra labels, without braces
+#[cfg(feature = "prometheus")]
+pub(crate) fn prometheus_labels(machine: &str, labels: &[(&str, &str)]) -> String {
+    let mut rendered = format!("machine=\"{}\"", escape_label(machine));
+    for (name, value) in labels {
+        rendered.push_str(&format!(",{name}=\"{}\"", escape_label(value)));
+    }
+    rendered
+}
+
+/// Append a metric with its help and type lines, and its sorted samples
+#[cfg(feature = "prometheus")]
+fn push_metric(text: &mut String, name: &str, help: &str, kind: &str, lines: &mut [String]) {
+    text.push_str(&format!("# HELP {name} {help}\n# TYPE {name} {kind}\n"));
+    lines.sort();
+    for line in lines.iter() {
+        text.push_str(line);
+    }
+}
+
+/// Escape a Prometheus label value
+#[cfg(feature = "prometheus")]
+pub(crate) fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+impl<SM: StateMachine> Default for TransitionMetrics<SM> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<SM: StateMachine> Clone for TransitionMetrics<SM> {
+    fn clone(&self) -> Self {
+        Self {
+            transitions: self.transitions.clone(),
+            time_in_state: self.time_in_state.clone(),
+            rejections: self.rejections.clone(),
+        }
+    }
+}
+
+impl<SM: StateMachine> core::fmt::Debug for TransitionMetrics<SM> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("TransitionMetrics")
+            .field("transitions", &self.transitions)
+            .field("time_in_state", &self.time_in_state)
+            .field("rejections", &self.rejections)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    define_state_machine! {
+        name: Order,
+        states: { Created, Paid, Shipped },
+        inputs: { Pay, Ship, Note, Poke },
+        initial: Created,
+        transitions: {
+            Created + Pay => Paid,
+            Paid + Ship => Shipped,
+            Paid + Note => internal,
+            Shipped ignores Poke,
+        }
+    }
+
+    #[test]
+    fn test_metrics() {
+        let mut order = StateMachineInstance::<Order>::new();
+        assert!(order.metrics().is_none());
+        order.transition(Input::Pay).unwrap();
+        order.undo();
+
+        order.set_collect_metrics(true);
+        order.transition(Input::Pay).unwrap();
+        order.transition(Input::Note).unwrap();
+        order.transition(Input::Note).unwrap();
+        assert!(order.transition(Input::Pay).is_err());
+        std::thread::sleep(Duration::from_millis(5));
+        order.transition(Input::Ship).unwrap();
+        order.transition(Input::Poke).unwrap();
+
+        let metrics = order.metrics().unwrap();
+        assert_eq!(
+            metrics.transition_count(&State::Created, &Input::Pay, &State::Paid),
+            1
+        );
+        assert_eq!(
+            metrics.transition_count(&State::Paid, &Input::Note, &State::Paid),
+            2
+        );
+        assert_eq!(metrics.total_transitions(), 4);
+        assert_eq!(metrics.rejection_count(&State::Paid, &Input::Pay), 1);
+        assert_eq!(metrics.total_rejections(), 1);
+        assert!(metrics.time_in(&State::Paid) >= Duration::from_millis(5));
+        assert_eq!(metrics.time_in(&State::Shipped), Duration::ZERO);
+
+        // Merging adds up the counters
+        let mut merged = metrics.clone();
+        merged.merge(metrics);
+        assert_eq!(merged.total_transitions(), 8);
+        assert_eq!(merged.rejection_count(&State::Paid, &Input::Pay), 2);
+        assert_eq!(
+            merged.time_in(&State::Paid),
+            metrics.time_in(&State::Paid) * 2
+        );
+
+        order.set_collect_metrics(false);
+        assert!(order.metrics().is_none());
+    }
+
+    #[cfg(feature = "prometheus")]
+    #[test]
+    fn test_prometheus_exposition() {
+        let mut order = StateMachineInstance::<Order>::new();
+        order.set_collect_metrics(true);
+        order.transition(Input::Pay).unwrap();
+        let _ = order.transition(Input::Pay);
+
+        let text = order.metrics().unwrap().to_prometheus("Order \"v2\"");
+        assert!(text.starts_with(
+            "# HELP yasm_transitions_total Transitions taken\n\
+             # TYPE yasm_transitions_total counter\n\
+             yasm_transitions_total{machine=\"Order \\\"v2\\\"\",from=\"Created\",input=\"Pay\",to=\"Paid\"} 1\n"
+        ));
+        assert!(
+            text.contains(
+                "yasm_state_seconds_total{machine=\"Order \\\"v2\\\"\",state=\"Created\"} "
+            )
+        );
+        assert!(text.ends_with(
+            "yasm_rejections_total{machine=\"Order \\\"v2\\\"\",state=\"Paid\",input=\"Pay\"} 1\n"
+        ));
+
+        let text = order
+            .metrics()
+            .unwrap()
+            .to_prometheus_with_labels("Order", &[("tenant", "acme"), ("region", "eu")]);
+        assert!(text.contains(
+            "yasm_transitions_total{machine=\"Order\",tenant=\"acme\",region=\"eu\",from=\"Created\",input=\"Pay\",to=\"Paid\"} 1\n"
+        ));
+    }
+}
@@ -0,0 +1,373 @@
+//! Checks of temporal properties over the transition graph, behind the `model-check`
+//! feature
+//!
+//! [`ModelChecker`] explores the states reachable from the initial state and checks
+//! properties in the spirit of LTL and CTL, such as "from any state, `Terminated` is
+//! reachable" or "never `Shipped` without previously `Paid`". A violated property
+//! yields a [`Counterexample`]: the inputs that drive the machine from its initial
+//! state into the violation, ready to be replayed in a test.
+//!
+//! ```ignore
+//! type Checker = ModelChecker<Order>;
+//!
+//! Checker::always_reachable(|state| *state == State::Terminated)?;
+//! Checker::precedes(|state| *state == State::Paid, |state| *state == State::Shipped)?;
+//! ```
+//!
+//! Like the other queries, checks follow the declared transitions and don't evaluate
+//! guards, so a counterexample may need a context that opens its guards.
+
+use crate::core::StateMachine;
+use crate::hashing::{HashMap, HashSet};
+use crate::query::StateMachineQuery;
+use alloc::collections::VecDeque;
+use alloc::{vec, vec::Vec};
+
+/// Model checker of temporal properties over a machine's declared transitions
+///
+/// Each check returns `Ok(())` if the property holds in every run from the initial
+/// state, or the shortest [`Counterexample`] found otherwise.
+pub struct ModelChecker<SM: StateMachine> {
+    _phantom: core::marker::PhantomData<SM>,
+}
+
+impl<SM: StateMachine> ModelChecker<SM> {
+    /// Check that every reachable state satisfies a predicate (CTL `AG p`)
+    ///
+    /// # Arguments
+    /// - `holds`: The predicate every reachable state must satisfy
+    ///
+    /// # Returns
+    /// Returns the shortest path to a state that violates it
+    pub fn always<F>(holds: F) -> Result<(), Counterexample<SM>>
+    where
+        F: Fn(&SM::State) -> bool,
+    {
+        match Self::find_path(&SM::initial_state(), |state| !holds(state), |_| true) {
+            Some(counterexample) => Err(counterexample),
+            None => Ok(()),
+        }
+    }
+
+    /// Check that no reachable state satisfies a predicate (CTL `AG !p`)
+    ///
+    /// # Arguments
+    /// - `bad`: The predicate no reachable state may satisfy
+    ///
+    /// # Returns
+    /// Returns the shortest path to a state that satisfies it
+    pub fn never<F>(bad: F) -> Result<(), Counterexample<SM>>
+    where
+        F: Fn(&SM::State) -> bool,
+    {
+        Self::always(|state| !bad(state))
+    }
+
+    /// Check that a goal can be reached from every reachable state (CTL `AG EF p`)
+    ///
+    /// This rules out runs getting stuck, e.g. "from any state, `Terminated` is
+    /// reachable".
+    ///
+    /// # Arguments
+    /// - `goal`: The predicate some state reachable from anywhere must satisfy
+    ///
+    /// # Returns
+    /// Returns the shortest path to a state from which no goal state is reachable
+    pub fn always_reachable<F>(goal: F) -> Result<(), Counterexample<SM>>
+    where
+        F: Fn(&SM::State) -> bool,
+    {
+        Self::always(|state| {
+            StateMachineQuery::<SM>::reachable_states(state)
+                .iter()
+                .any(|to| goal(&to))
+        })
+    }
+
+    /// Check that a state is never reached without previously passing through
+    /// another (LTL `!target W required`)
+    ///
+    /// E.g. "never `Shipped` without previously `Paid`". A state satisfying both
+    /// predicates counts as passing through `required`.
+    ///
+    /// # Arguments
+    /// - `required`: The predicate a run must satisfy first
+    /// - `target`: The predicate a run may only satisfy after `required`
+    ///
+    /// # Returns
+    /// Returns the shortest path to a `target` state that avoids `required` states
+    pub fn precedes<R, T>(required: R, target: T) -> Result<(), Counterexample<SM>>
+    where
+        R: Fn(&SM::State) -> bool,
+        T: Fn(&SM::State) -> bool,
+    {
+        let avoiding = |state: &SM::State| !required(state);
+        let initial = SM::initial_state();
+        if !avoiding(&initial) {
+            return Ok(());
+        }
+        match Self::find_path(&initial, |state| target(state) && avoiding(state), avoiding) {
+            Some(counterexample) => Err(counterexample),
+            None => Ok(()),
+        }
+    }
+
+    /// Check that every run eventually reaches a goal (CTL `AF p`)
+    ///
+    /// A run avoids the goal forever if it ends in a state without transitions
+    /// before reaching it, or loops through states that don't satisfy it.
+    ///
+    /// # Arguments
+    /// - `goal`: The predicate every run must eventually satisfy
+    ///
+    /// # Returns
+    /// Returns a run avoiding the goal, with the [`cycle`](Counterexample::cycle) it
+    /// repeats forever if it doesn't end
+    pub fn eventually<F>(goal: F) -> Result<(), Counterexample<SM>>
+    where
+        F: Fn(&SM::State) -> bool,
+    {
+        let avoiding = |state: &SM::State| !goal(state);
+        let initial = SM::initial_state();
+        if !avoiding(&initial) {
+            return Ok(());
+        }
+
+        // A run that stops short of the goal
+        let stuck = |state: &SM::State| avoiding(state) && Self::successors(state).is_empty();
+        if let Some(counterexample) = Self::find_path(&initial, stuck, avoiding) {
+            return Err(counterexample);
+        }
+
+        // A run that loops short of the goal, through the first state on a loop
+        let mut visited = vec![initial.clone()];
+        let mut next = 0;
+        while next < visited.len() {
+            let state = visited[next].clone();
+            next += 1;
+            let on_loop = Self::successors(&state)
+                .into_iter()
+                .find_map(|(input, to)| {
+                    let rest = Self::find_path(&to, |reached| *reached == state, avoiding)?;
+                    let mut cycle = vec![(state.clone(), input)];
+                    cycle.extend(rest.steps);
+                    Some(cycle)
+                });
+            if let Some(cycle) = on_loop {
+                let mut counterexample =
+                    Self::find_path(&initial, |reached| *reached == state, avoiding)
+                        .expect("state was reached avoiding the goal");
+                counterexample.cycle = cycle;
+                return Err(counterexample);
+            }
+            for (_, to) in Self::successors(&state) {
+                if avoiding(&to) && !visited.contains(&to) {
+                    visited.push(to);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the declared transitions of a state, as input and next state
+    fn successors(state: &SM::State) -> Vec<(SM::Input, SM::State)> {
+        SM::valid_inputs_iter(state)
+            .filter_map(|input| SM::next_state(state, &input).map(|to| (input, to)))
+            .collect()
+    }
+
+    /// Find the shortest path to a goal state through allowed states
+    ///
+    /// The search starts in `from` and only expands states that are allowed; the goal
+    /// is checked before, so `from` itself may be the goal.
+    fn find_path<G, A>(from: &SM::State, is_goal: G, allowed: A) -> Option<Counterexample<SM>>
+    where
+        G: Fn(&SM::State) -> bool,
+        A: Fn(&SM::State) -> bool,
+    {
+        let mut parent: HashMap<SM::State, (SM::State, SM::Input)> = HashMap::default();
+        let mut visited = HashSet::from_iter([from.clone()]);
+        let mut queue = VecDeque::from([from.clone()]);
+
+        while let Some(current) = queue.pop_front() {
+            if is_goal(&current) {
+                // Reconstruct the steps backwards from the goal
+                let mut steps = Vec::new();
+                let mut state = current.clone();
+                while let Some((previous, input)) = parent.remove(&state) {
+                    steps.push((previous.clone(), input));
+                    state = previous;
+                }
+                steps.reverse();
+                return Some(Counterexample {
+                    steps,
+                    state: current,
+                    cycle: Vec::new(),
+                });
+            }
+            if !allowed(&current) {
+                continue;
+            }
+            for (input, to) in Self::successors(&current) {
+                if visited.insert(to.clone()) {
+                    parent.insert(to.clone(), (current.clone(), input));
+                    queue.push_back(to);
+                }
+            }
+        }
+
+        None
+    }
+}
+
+/// Run of a machine that violates a property, returned by [`ModelChecker`]
+pub struct Counterexample<SM: StateMachine> {
+    /// Steps from the initial state to `state`, each a state and the input applied in it
+    pub steps: Vec<(SM::State, SM::Input)>,
+    /// The state the run ends in, or enters its cycle from
+    pub state: SM::State,
+    /// Steps from `state` back to itself that the run repeats forever, empty if the
+    /// run ends in `state`
+    pub cycle: Vec<(SM::State, SM::Input)>,
+}
+
+impl<SM: StateMachine> Counterexample<SM> {
+    /// Get the inputs that drive an instance from the initial state to `state`
+    pub fn inputs(&self) -> Vec<SM::Input> {
+        self.steps.iter().map(|(_, input)| input.clone()).collect()
+    }
+}
+
+impl<SM: StateMachine> core::fmt::Display for Counterexample<SM> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        for (state, input) in &self.steps {
+            write!(
+                f,
+                "{} --{}--> ",
+                SM::state_name(state),
+                SM::input_name(input)
+            )?;
+        }
+        write!(f, "{}", SM::state_name(&self.state))?;
+        if !self.cycle.is_empty() {
+            write!(f, " (then forever:")?;
+            for (state, input) in &self.cycle {
+                write!(
+                    f,
+                    " {} --{}-->",
+                    SM::state_name(state),
+                    SM::input_name(input)
+                )?;
+            }
+            write!(f, " {})", SM::state_name(&self.state))?;
+        }
+        Ok(())
+    }
+}
+
+impl<SM: StateMachine> core::fmt::Debug for Counterexample<SM> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Counterexample")
+            .field("steps", &self.steps)
+            .field("state", &self.state)
+            .field("cycle", &self.cycle)
+            .finish()
+    }
+}
+
+impl<SM: StateMachine> Clone for Counterexample<SM> {
+    fn clone(&self) -> Self {
+        Self {
+            steps: self.steps.clone(),
+            state: self.state.clone(),
+            cycle: self.cycle.clone(),
+        }
+    }
+}
+
+impl<SM: StateMachine> PartialEq for Counterexample<SM> {
+    fn eq(&self, other: &Self) -> bool {
+        self.steps == other.steps && self.state == other.state && self.cycle == other.cycle
+    }
+}
+
+impl<SM: StateMachine> Eq for Counterexample<SM> {}
+
+impl<SM: StateMachine> core::error::Error for Counterexample<SM> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    define_state_machine! {
+        name: Order,
+        states: { Created, Paid, Shipped, Lost, Refunded, Terminated },
+        inputs: { Pay, Ship, Lose, Search, Refund, Close },
+        initial: Created,
+        transitions: {
+            Created + Pay => Paid,
+            Created + Ship => Shipped,
+            Paid + Ship => Shipped,
+            Shipped + Lose => Lost,
+            Lost + Search => internal,
+            Paid + Refund => Refunded,
+            Refunded + Close => Terminated,
+            Shipped + Close => Terminated,
+        }
+    }
+
+    type Checker = ModelChecker<Order>;
+
+    #[test]
+    fn test_safety_properties() {
+        assert_eq!(Checker::always(|_| true), Ok(()));
+        let counterexample = Checker::never(|state| *state == State::Refunded).unwrap_err();
+        assert_eq!(counterexample.inputs(), vec![Input::Pay, Input::Refund]);
+        assert_eq!(counterexample.state, State::Refunded);
+
+        // Shipping unpaid orders is allowed by mistake
+        let counterexample = Checker::precedes(
+            |state| *state == State::Paid,
+            |state| *state == State::Shipped,
+        )
+        .unwrap_err();
+        assert_eq!(counterexample.inputs(), vec![Input::Ship]);
+        assert_eq!(counterexample.to_string(), "Created --Ship--> Shipped");
+        assert_eq!(
+            Checker::precedes(
+                |state| *state == State::Paid,
+                |state| *state == State::Refunded
+            ),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_liveness_properties() {
+        // Lost orders never terminate
+        let counterexample =
+            Checker::always_reachable(|state| *state == State::Terminated).unwrap_err();
+        assert_eq!(counterexample.inputs(), vec![Input::Ship, Input::Lose]);
+        assert!(Checker::always_reachable(|state| *state != State::Created).is_ok());
+
+        // Searching forever never terminates either
+        let counterexample = Checker::eventually(|state| *state == State::Terminated).unwrap_err();
+        assert_eq!(counterexample.state, State::Lost);
+        assert_eq!(counterexample.cycle, vec![(State::Lost, Input::Search)]);
+        assert_eq!(
+            counterexample.to_string(),
+            "Created --Ship--> Shipped --Lose--> Lost (then forever: Lost --Search--> Lost)"
+        );
+        assert_eq!(
+            Checker::eventually(|state| *state != State::Created),
+            Ok(())
+        );
+
+        // Refunded orders stop short of shipping
+        let counterexample =
+            Checker::eventually(|state| matches!(state, State::Shipped | State::Lost)).unwrap_err();
+        assert_eq!(counterexample.state, State::Terminated);
+        assert!(counterexample.cycle.is_empty());
+    }
+}
@@ -0,0 +1,312 @@
+use crate::core::StateMachine;
+use crate::hashing::HashSet;
+use crate::instance::StateMachineInstance;
+use alloc::{format, string::String, vec, vec::Vec};
+use core::fmt::Debug;
+use core::hash::Hash;
+
+/// Nondeterministic finite automaton, usually defined with
+/// [`define_nfa!`](crate::define_nfa)
+///
+/// A state and input may lead to several states, and epsilon moves change state
+/// without consuming input. The automaton runs on the set of states it could be in.
+/// [`Determinized`] turns it into an equivalent deterministic [`StateMachine`], so it
+/// can be driven by a [`StateMachineInstance`] like any other machine.
+pub trait Nfa {
+    /// State type
+    type State: Clone + Debug + Hash + Eq;
+
+    /// Input type
+    type Input: Clone + Debug + Hash + Eq;
+
+    /// Get all states, in declaration order
+    fn states() -> Vec<Self::State>;
+
+    /// Get all inputs, in declaration order
+    fn inputs() -> Vec<Self::Input>;
+
+    /// Get the initial state
+    fn initial_state() -> Self::State;
+
+    /// Get the states a state moves to on an input, or on an epsilon move if `input`
+    /// is None
+    fn targets(state: &Self::State, input: Option<&Self::Input>) -> Vec<Self::State>;
+
+    /// Check whether a state is accepting
+    fn is_accepting(_state: &Self::State) -> bool {
+        false
+    }
+
+    /// Get the states reachable from some states through epsilon moves, them included
+    ///
+    /// # Arguments
+    /// - `states`: The states to start from
+    ///
+    /// # Returns
+    /// Returns the closure in declaration order
+    fn epsilon_closure(states: &[Self::State]) -> Vec<Self::State> {
+        let mut closure: HashSet<Self::State> = HashSet::default();
+        let mut to_visit = states.to_vec();
+        while let Some(state) = to_visit.pop() {
+            if closure.insert(state.clone()) {
+                to_visit.extend(Self::targets(&state, None));
+            }
+        }
+        Self::states()
+            .into_iter()
+            .filter(|state| closure.contains(state))
+            .collect()
+    }
+
+    /// Get the states an input leads to from some states, epsilon moves included
+    ///
+    /// # Arguments
+    /// - `states`: The states the automaton could be in
+    /// - `input`: The input to consume
+    ///
+    /// # Returns
+    /// Returns the states in declaration order, empty if no state takes the input
+    fn step(states: &[Self::State], input: &Self::Input) -> Vec<Self::State> {
+        let moved: Vec<Self::State> = states
+            .iter()
+            .flat_map(|state| Self::targets(state, Some(input)))
+            .collect();
+        Self::epsilon_closure(&moved)
+    }
+
+    /// Check whether the automaton accepts an input word
+    ///
+    /// # Arguments
+    /// - `word`: The inputs to run, in order
+    ///
+    /// # Returns
+    /// Returns true if some run of the word ends in an accepting state
+    fn accepts(word: &[Self::Input]) -> bool {
+        let mut states = Self::epsilon_closure(&[Self::initial_state()]);
+        for input in word {
+            states = Self::step(&states, input);
+        }
+        states.iter().any(Self::is_accepting)
+    }
+
+    /// Determinize the automaton and create an instance of the result
+    ///
+    /// # Returns
+    /// Returns an instance of [`Determinized<Self>`](Determinized) in its initial state
+    fn determinize() -> StateMachineInstance<Determinized<Self>>
+    where
+        Self: Sized,
+    {
+        StateMachineInstance::new()
+    }
+}
+
+/// Set of states an [`Nfa`] could be in, the state type of [`Determinized`]
+///
+/// Members are kept in declaration order, so equal sets compare and hash equal.
+pub struct StateSubset<N: Nfa> {
+    /// Member states, in declaration order
+    states: Vec<N::State>,
+}
+
+impl<N: Nfa> StateSubset<N> {
+    /// Get the member states, in declaration order
+    pub fn states(&self) -> &[N::State] {
+        &self.states
+    }
+
+    /// Check whether a state is a member
+    pub fn contains(&self, state: &N::State) -> bool {
+        self.states.contains(state)
+    }
+}
+
+impl<N: Nfa> Clone for StateSubset<N> {
+    fn clone(&self) -> Self {
+        Self {
+            states: self.states.clone(),
+        }
+    }
+}
+
+impl<N: Nfa> PartialEq for StateSubset<N> {
+    fn eq(&self, other: &Self) -> bool {
+        self.states == other.states
+    }
+}
+
+impl<N: Nfa> Eq for StateSubset<N> {}
+
+impl<N: Nfa> Hash for StateSubset<N> {
+    fn hash<H: core::hash::Hasher>(&self, hasher: &mut H) {
+        self.states.hash(hasher);
+    }
+}
+
+impl<N: Nfa> Debug for StateSubset<N> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_set().entries(&self.states).finish()
+    }
+}
+
+/// Deterministic machine equivalent to an [`Nfa`], built by subset construction
+///
+/// Each state is the [`StateSubset`] of automaton states reachable by the inputs so
+/// far. Only subsets reachable from the initial one are declared, and inputs that
+/// lead nowhere are invalid instead of leading to an empty subset. A subset is
+/// accepting if any member is. States are named after their members joined with
+/// underscores, so generated diagrams stay valid.
+///
+/// # Example
+/// ```ignore
+/// let mut matcher = StateMachineInstance::<Determinized<EndsWithAb>>::new();
+/// matcher.transition(Input::A).unwrap();
+/// matcher.transition(Input::B).unwrap();
+/// assert!(matcher.is_accepting());
+/// ```
+pub struct Determinized<N: Nfa> {
+    _phantom: core::marker::PhantomData<N>,
+}
+
+impl<N: Nfa> Determinized<N> {
+    /// Create the subset of some automaton states
+    fn subset(states: Vec<N::State>) -> StateSubset<N> {
+        StateSubset { states }
+    }
+}
+
+impl<N: Nfa> StateMachine for Determinized<N> {
+    type State = StateSubset<N>;
+    type Input = N::Input;
+    type Context = ();
+    type Config = ();
+    type Output = ();
+
+    fn states() -> Vec<Self::State> {
+        let initial = Self::initial_state();
+        let mut seen: HashSet<Self::State> = HashSet::default();
+        seen.insert(initial.clone());
+        let mut states = vec![initial];
+        let mut next = 0;
+        while next < states.len() {
+            for input in N::inputs() {
+                if let Some(to) = Self::next_state(&states[next], &input)
+                    && seen.insert(to.clone())
+                {
+                    states.push(to);
+                }
+            }
+            next += 1;
+        }
+        states
+    }
+
+    fn inputs() -> Vec<Self::Input> {
+        N::inputs()
+    }
+
+    fn valid_inputs(state: &Self::State) -> Vec<Self::Input> {
+        N::inputs()
+            .into_iter()
+            .filter(|input| Self::next_state(state, input).is_some())
+            .collect()
+    }
+
+    fn next_state(state: &Self::State, input: &Self::Input) -> Option<Self::State> {
+        let states = N::step(&state.states, input);
+        (!states.is_empty()).then(|| Self::subset(states))
+    }
+
+    fn is_accepting(state: &Self::State) -> bool {
+        state.states.iter().any(N::is_accepting)
+    }
+
+    fn initial_state() -> Self::State {
+        Self::subset(N::epsilon_closure(&[N::initial_state()]))
+    }
+
+    fn state_name(state: &Self::State) -> String {
+        state
+            .states
+            .iter()
+            .map(|state| format!("{state:?}"))
+            .collect::<Vec<_>>()
+            .join("_")
+    }
+
+    fn input_name(input: &Self::Input) -> String {
+        format!("{input:?}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    // Words over a and b whose third symbol from the end is a, or that are empty
+    define_nfa! {
+        name: ThirdFromEnd,
+        states: { Start, Empty, Loop, One, Two, Three },
+        inputs: { A, B },
+        initial: Start,
+        accepting: { Empty, Three },
+        transitions: {
+            Start => { Empty, Loop },
+            Loop + A => Loop,
+            Loop + B => Loop,
+            Loop + A => One,
+            One + A => Two,
+            One + B => Two,
+            Two + A => Three,
+            Two + B => Three,
+        }
+    }
+
+    #[test]
+    fn test_determinize() {
+        let initial = Determinized::<ThirdFromEnd>::initial_state();
+        assert_eq!(initial.states(), [State::Start, State::Empty, State::Loop]);
+        assert!(Determinized::<ThirdFromEnd>::is_accepting(&initial));
+        // The initial subset, and one subset per choice of the last three inputs
+        assert_eq!(Determinized::<ThirdFromEnd>::states().len(), 9);
+
+        let mut words: Vec<Vec<Input>> = vec![Vec::new()];
+        for _ in 0..5 {
+            words = words
+                .into_iter()
+                .flat_map(|word| {
+                    [Input::A, Input::B].map(|input| {
+                        let mut word = word.clone();
+                        word.push(input);
+                        word
+                    })
+                })
+                .chain(std::iter::once(Vec::new()))
+                .collect();
+        }
+        for word in &words {
+            let expected = word.is_empty() || word.len() >= 3 && word[word.len() - 3] == Input::A;
+            assert_eq!(ThirdFromEnd::accepts(word), expected, "{word:?}");
+            assert_eq!(
+                StateMachineQuery::<Determinized<ThirdFromEnd>>::accepts(word),
+                expected,
+                "{word:?}"
+            );
+        }
+
+        let mut instance = ThirdFromEnd::determinize();
+        for input in [Input::B, Input::A, Input::B, Input::B] {
+            instance.transition(input).unwrap();
+        }
+        assert!(instance.is_accepting());
+        assert_eq!(
+            Determinized::<ThirdFromEnd>::state_name(instance.current_state()),
+            "Loop_Three"
+        );
+        assert!(
+            StateMachineDoc::<Determinized<ThirdFromEnd>>::generate_mermaid()
+                .contains("    Start_Empty_Loop --> Loop_One : A\n")
+        );
+    }
+}
@@ -0,0 +1,244 @@
+use crate::callbacks::TransitionEvent;
+use crate::core::StateMachine;
+use crate::error::TransitionError;
+use alloc::boxed::Box;
+use core::any::Any;
+
+/// Something that happened to an instance, as delivered to its [`Observer`]
+///
+/// Events of a transition arrive in the order of the corresponding callbacks, see
+/// [`Hook`](crate::Hook): the source state is exited, the transition happens and the
+/// target state is entered. Internal transitions produce no exit and entry events.
+pub enum MachineEvent<SM: StateMachine> {
+    /// The instance left a state
+    Exited(SM::State),
+    /// A transition took effect
+    Transitioned(TransitionEvent<SM>),
+    /// The instance entered a state
+    Entered(SM::State),
+    /// An input was ignored by design in the current state
+    Ignored {
+        /// The current state
+        state: SM::State,
+        /// The ignored input
+        input: SM::Input,
+    },
+    /// An input was deferred until the next state change
+    Deferred {
+        /// The current state
+        state: SM::State,
+        /// The deferred input
+        input: SM::Input,
+    },
+    /// A transition failed and left the instance unchanged
+    Rejected(TransitionError<SM>),
+}
+
+/// Observer owned by an instance, receiving its events with mutable access to itself
+///
+/// Callbacks are closures that can only share state through `Arc` and locks. An
+/// observer is a value the instance owns instead, so it can keep counters, buffers or
+/// connections as plain fields. Register it once with
+/// [`set_observer`](crate::StateMachineInstance::set_observer) and read it back with
+/// [`observer`](crate::StateMachineInstance::observer).
+///
+/// # Example
+/// ```ignore
+/// #[derive(Default)]
+/// struct Audit {
+///     rejected: Vec<String>,
+/// }
+///
+/// impl Observer<Order> for Audit {
+///     fn on_event(&mut self, event: &MachineEvent<Order>) {
+///         if let MachineEvent::Rejected(error) = event {
+///             self.rejected.push(error.to_string());
+///         }
+///     }
+/// }
+///
+/// let mut order = StateMachineInstance::<Order>::new();
+/// order.set_observer(Audit::default());
+/// let _ = order.transition(Input::Ship);
+/// assert_eq!(order.observer::<Audit>().unwrap().rejected.len(), 1);
+/// ```
+pub trait Observer<SM: StateMachine>: Send + Sync {
+    /// Handle an event of the instance
+    fn on_event(&mut self, event: &MachineEvent<SM>);
+}
+
+/// Observer with its concrete type erased, as stored by the instance
+pub(crate) trait ErasedObserver<SM: StateMachine>: Send + Sync {
+    /// Handle an event of the instance
+    fn on_event(&mut self, event: &MachineEvent<SM>);
+    /// Get the observer for downcasting
+    fn as_any(&self) -> &dyn Any;
+    /// Get the observer for mutable downcasting
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    /// Turn the observer into a box for downcasting
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+
+impl<SM: StateMachine, O: Observer<SM> + 'static> ErasedObserver<SM> for O {
+    fn on_event(&mut self, event: &MachineEvent<SM>) {
+        Observer::on_event(self, event);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
+
+impl<SM: StateMachine> Clone for MachineEvent<SM> {
+    fn clone(&self) -> Self {
+        match self {
+            Self::Exited(state) => Self::Exited(state.clone()),
+            Self::Transitioned(event) => Self::Transitioned(event.clone()),
+            Self::Entered(state) => Self::Entered(state.clone()),
+            Self::Ignored { state, input } => Self::Ignored {
+                state: state.clone(),
+                input: input.clone(),
+            },
+            Self::Deferred { state, input } => Self::Deferred {
+                state: state.clone(),
+                input: input.clone(),
+            },
+            Self::Rejected(error) => Self::Rejected(error.clone()),
+        }
+    }
+}
+
+impl<SM: StateMachine> core::fmt::Debug for MachineEvent<SM> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Exited(state) => f.debug_tuple("Exited").field(state).finish(),
+            Self::Transitioned(event) => f.debug_tuple("Transitioned").field(event).finish(),
+            Self::Entered(state) => f.debug_tuple("Entered").field(state).finish(),
+            Self::Ignored { state, input } => f
+                .debug_struct("Ignored")
+                .field("state", state)
+                .field("input", input)
+                .finish(),
+            Self::Deferred { state, input } => f
+                .debug_struct("Deferred")
+                .field("state", state)
+                .field("input", input)
+                .finish(),
+            Self::Rejected(error) => f.debug_tuple("Rejected").field(error).finish(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    define_state_machine! {
+        name: Door,
+        states: { Closed, Open, Locked },
+        inputs: { Open, Close, Lock, Knock, Ring },
+        initial: Closed,
+        transitions: {
+            Closed + Open => Open,
+            Open + Close => Closed,
+            Closed + Lock => Locked,
+            Closed + Knock => internal,
+            Open ignores Knock,
+            Open defers Lock,
+        }
+    }
+
+    /// Observer keeping its log as a plain field
+    #[derive(Default)]
+    struct Log {
+        events: Vec<String>,
+    }
+
+    impl Observer<Door> for Log {
+        fn on_event(&mut self, event: &MachineEvent<Door>) {
+            self.events.push(match event {
+                MachineEvent::Exited(state) => format!("exit {state:?}"),
+                MachineEvent::Transitioned(event) => {
+                    format!("{:?} -{:?}-> {:?}", event.from, event.input, event.to)
+                }
+                MachineEvent::Entered(state) => format!("enter {state:?}"),
+                MachineEvent::Ignored { input, .. } => format!("ignore {input:?}"),
+                MachineEvent::Deferred { input, .. } => format!("defer {input:?}"),
+                MachineEvent::Rejected(error) => format!("reject {:?}", error.input()),
+            });
+        }
+    }
+
+    #[test]
+    fn test_subscribe() {
+        let mut door = StateMachineInstance::<Door>::new();
+        let events = door.subscribe();
+        let dropped = door.subscribe();
+        drop(dropped);
+        assert_eq!(door.subscriber_count(), 2);
+
+        door.transition(Input::Open).unwrap();
+        door.transition(Input::Knock).unwrap();
+        door.transition(Input::Close).unwrap();
+        assert_eq!(door.subscriber_count(), 1);
+
+        let received = std::thread::spawn(move || {
+            events
+                .try_iter()
+                .map(|event| (event.from, event.input, event.to))
+                .collect::<Vec<_>>()
+        })
+        .join()
+        .unwrap();
+        assert_eq!(
+            received,
+            vec![
+                (State::Closed, Input::Open, State::Open),
+                (State::Open, Input::Close, State::Closed),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_observer_events() {
+        let mut door = StateMachineInstance::<Door>::new();
+        assert!(!door.set_observer(Log::default()));
+
+        door.transition(Input::Knock).unwrap();
+        door.transition(Input::Open).unwrap();
+        door.transition(Input::Knock).unwrap();
+        door.transition(Input::Lock).unwrap();
+        assert!(door.transition(Input::Ring).is_err());
+        door.transition(Input::Close).unwrap();
+
+        assert_eq!(
+            door.observer::<Log>().unwrap().events,
+            vec![
+                "Closed -Knock-> Closed",
+                "exit Closed",
+                "Closed -Open-> Open",
+                "enter Open",
+                "ignore Knock",
+                "defer Lock",
+                "reject Ring",
+                "exit Open",
+                "Open -Close-> Closed",
+                "enter Closed",
+            ]
+        );
+
+        door.observer_mut::<Log>().unwrap().events.clear();
+        door.transition(Input::Lock).unwrap();
+        assert_eq!(door.take_observer::<Log>().unwrap().events.len(), 3);
+        assert!(door.observer::<Log>().is_none());
+    }
+}
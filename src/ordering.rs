@@ -0,0 +1,320 @@
+use crate::core::StateMachine;
+use crate::instance::StateMachineInstance;
+use crate::query::StateMachineQuery;
+use std::sync::{Arc, Mutex};
+
+/// A callback hook of a transition, in the order hooks run
+///
+/// The instance engine runs the hooks of a transition in declaration order of this
+/// enumeration: the checks that may still reject the transition come first, then the
+/// transition is appended to the [transition log](crate::TransitionLog), if any, the
+/// state is exited, the transition callbacks, actions and effects run, the history entry
+/// is recorded and the current state updated, and finally the target state is entered.
+/// Internal transitions skip [`ValidateEntry`](Hook::ValidateEntry), [`Exit`](Hook::Exit)
+/// and [`Entry`](Hook::Entry), and ignored or deferred inputs run no hooks at all.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Hook {
+    /// Before-transition callbacks, which may veto
+    Before,
+    /// Entry validators of the target state
+    ValidateEntry,
+    /// Exit callbacks of the source state
+    Exit,
+    /// Transition callbacks
+    Transition,
+    /// Transition event callbacks
+    Event,
+    /// Transition actions, which may mutate the context
+    Action,
+    /// Transition effects, whose results go to the caller
+    Effect,
+    /// Entry callbacks of the target state
+    Entry,
+}
+
+/// Test of the documented hook ordering across every transition of a machine
+///
+/// For each state reachable from the initial state and each input, a fresh instance
+/// is driven into the state along the shortest path, recording hooks are registered
+/// and the input is applied. The hooks that fired must match the order of [`Hook`],
+/// and a successful transition must have updated the state and the history. States
+/// whose path is blocked by guards under the default context are skipped.
+///
+/// # Example
+/// ```ignore
+/// #[test]
+/// fn order_hooks_run_in_order() {
+///     let report = HookOrderTest::<Order>::new().run().unwrap();
+///     assert_eq!(report.skipped_states, 0);
+/// }
+/// ```
+pub struct HookOrderTest<SM: StateMachine> {
+    /// The machine under test
+    _machine: std::marker::PhantomData<SM>,
+}
+
+/// Summary of a [`HookOrderTest`] run that found no violation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct HookOrderReport {
+    /// Number of state and input combinations applied
+    pub checked: usize,
+    /// Number of reachable states that could not be driven into
+    pub skipped_states: usize,
+}
+
+/// A transition whose hooks didn't run in the documented order
+pub struct HookOrderViolation<SM: StateMachine> {
+    /// The state the transition started from
+    pub state: SM::State,
+    /// The input applied
+    pub input: SM::Input,
+    /// The hooks that should have fired, in order
+    pub expected: Vec<Hook>,
+    /// The hooks that did fire, in order
+    pub actual: Vec<Hook>,
+    /// What else went wrong, if the hooks fired as expected
+    pub reason: Option<String>,
+}
+
+impl<SM: StateMachine> Default for HookOrderTest<SM> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<SM: StateMachine> HookOrderTest<SM> {
+    /// Create a test of the machine
+    pub fn new() -> Self {
+        Self {
+            _machine: std::marker::PhantomData,
+        }
+    }
+
+    /// Get the hooks a transition is expected to run, in order
+    ///
+    /// # Arguments
+    /// - `state`: The state the transition starts from
+    /// - `input`: The input applied
+    ///
+    /// # Returns
+    /// Returns the expected hooks, empty for inputs that are invalid, ignored or deferred
+    pub fn expected_hooks(state: &SM::State, input: &SM::Input) -> Vec<Hook> {
+        if SM::is_ignored(state, input) || SM::is_deferred(state, input) {
+            return Vec::new();
+        }
+        if SM::next_state(state, input).is_none() {
+            return Vec::new();
+        }
+
+        let internal = SM::is_internal(state, input);
+        let mut hooks = vec![Hook::Before];
+        if !internal {
+            hooks.extend([Hook::ValidateEntry, Hook::Exit]);
+        }
+        hooks.extend([Hook::Transition, Hook::Event, Hook::Action, Hook::Effect]);
+        if !internal {
+            hooks.push(Hook::Entry);
+        }
+        hooks
+    }
+
+    /// Run the test on fresh instances with default context and configuration
+    ///
+    /// # Returns
+    /// - `Ok(report)`: Every transition ran its hooks in order
+    /// - `Err(violation)`: The first transition that didn't
+    pub fn run(&self) -> Result<HookOrderReport, HookOrderViolation<SM>>
+    where
+        SM::Context: Default,
+        SM::Config: Default,
+    {
+        let initial = SM::initial_state();
+        let mut report = HookOrderReport::default();
+
+        for state in StateMachineQuery::<SM>::reachable_states(&initial) {
+            let Some(path) = StateMachineQuery::<SM>::shortest_path_with_inputs(&initial, &state)
+            else {
+                continue;
+            };
+
+            for input in SM::inputs() {
+                let mut instance = StateMachineInstance::<SM>::new();
+                if path
+                    .iter()
+                    .any(|(_, step)| instance.transition(step.clone()).is_err())
+                    || *instance.current_state() != state
+                {
+                    report.skipped_states += 1;
+                    break;
+                }
+
+                let (expected, actual, reason) = Self::check(&mut instance, &input);
+                report.checked += 1;
+                if expected != actual || reason.is_some() {
+                    return Err(HookOrderViolation {
+                        state,
+                        input,
+                        expected,
+                        actual,
+                        reason,
+                    });
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Apply an input with recording hooks, returning the expected and recorded hooks
+    /// and any inconsistency found afterwards
+    fn check(
+        instance: &mut StateMachineInstance<SM>,
+        input: &SM::Input,
+    ) -> (Vec<Hook>, Vec<Hook>, Option<String>) {
+        let state = instance.current_state().clone();
+        let recorded = Arc::new(Mutex::new(Vec::new()));
+        let record = |hook: Hook| {
+            let recorded = Arc::clone(&recorded);
+            move || recorded.lock().unwrap().push(hook)
+        };
+
+        let before = record(Hook::Before);
+        instance.on_before_transition(state.clone(), input.clone(), move |_, _, _| {
+            before();
+            Ok(())
+        });
+        if let Some(next_state) = SM::next_state(&state, input) {
+            let validate = record(Hook::ValidateEntry);
+            instance.on_validate_entry(next_state, move |_| {
+                validate();
+                Ok(())
+            });
+        }
+        let exit = record(Hook::Exit);
+        instance.on_any_state_exit(move |_| exit());
+        let transition = record(Hook::Transition);
+        instance.on_any_transition(move |_, _, _| transition());
+        let event = record(Hook::Event);
+        instance.on_transition_event(move |_| event());
+        let action = record(Hook::Action);
+        instance.on_any_transition_action(move |_, _, _, _| action());
+        let effect = record(Hook::Effect);
+        instance.on_transition_effect(state.clone(), input.clone(), move |_, _, _, _| effect());
+        let entry = record(Hook::Entry);
+        instance.on_any_state_entry(move |_| entry());
+
+        let history_len = instance.history_len();
+        let result = instance.transition(input.clone());
+        let actual = recorded.lock().unwrap().clone();
+        let mut expected = Self::expected_hooks(&state, input);
+
+        let reason = match result {
+            Ok(to) if *instance.current_state() != to => Some(format!(
+                "returned state {to:?} but the instance is in {:?}",
+                instance.current_state()
+            )),
+            Ok(_) if !expected.is_empty() && instance.history_len() != history_len + 1 => {
+                Some("the transition was not recorded in history".to_string())
+            }
+            Ok(_) => None,
+            Err(_) => {
+                // Rejected by a guard: no hook may run
+                expected.clear();
+                None
+            }
+        };
+        (expected, actual, reason)
+    }
+}
+
+impl<SM: StateMachine> std::fmt::Debug for HookOrderTest<SM> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HookOrderTest").finish()
+    }
+}
+
+impl<SM: StateMachine> std::fmt::Display for HookOrderViolation<SM> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Input {:?} in state {:?}: ", self.input, self.state)?;
+        match &self.reason {
+            Some(reason) => write!(f, "{reason}"),
+            None => write!(
+                f,
+                "expected hooks {:?} but got {:?}",
+                self.expected, self.actual
+            ),
+        }
+    }
+}
+
+impl<SM: StateMachine> std::fmt::Debug for HookOrderViolation<SM> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HookOrderViolation")
+            .field("state", &self.state)
+            .field("input", &self.input)
+            .field("expected", &self.expected)
+            .field("actual", &self.actual)
+            .field("reason", &self.reason)
+            .finish()
+    }
+}
+
+impl<SM: StateMachine> std::error::Error for HookOrderViolation<SM> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    define_state_machine! {
+        name: Player,
+        states: { Stopped, Playing, Paused },
+        inputs: { Play, Pause, Stop, Seek, Volume, Eject },
+        initial: Stopped,
+        context: u32,
+        transitions: {
+            Stopped + Play => Playing,
+            Playing + Pause => Paused,
+            Playing + Seek => Playing,
+            Playing + Volume => internal,
+            Paused + Play => Playing,
+            Paused + Stop => Stopped,
+            Playing + Stop => Stopped,
+            Stopped ignores Stop,
+            Paused defers Seek,
+            Stopped + Eject => Stopped if |discs| *discs > 0,
+        }
+    }
+
+    #[test]
+    fn test_hook_ordering() {
+        let report = HookOrderTest::<Player>::new().run().unwrap();
+        assert_eq!(report.checked, 18);
+        assert_eq!(report.skipped_states, 0);
+
+        assert_eq!(
+            HookOrderTest::<Player>::expected_hooks(&State::Playing, &Input::Seek),
+            vec![
+                Hook::Before,
+                Hook::ValidateEntry,
+                Hook::Exit,
+                Hook::Transition,
+                Hook::Event,
+                Hook::Action,
+                Hook::Effect,
+                Hook::Entry
+            ]
+        );
+        assert_eq!(
+            HookOrderTest::<Player>::expected_hooks(&State::Playing, &Input::Volume),
+            vec![
+                Hook::Before,
+                Hook::Transition,
+                Hook::Event,
+                Hook::Action,
+                Hook::Effect
+            ]
+        );
+        assert!(HookOrderTest::<Player>::expected_hooks(&State::Paused, &Input::Seek).is_empty());
+    }
+}
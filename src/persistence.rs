@@ -0,0 +1,797 @@
+//! Durable state of instances
+//!
+//! [`StateMachineInstance::snapshot`](crate::StateMachineInstance::snapshot) captures what an instance needs to survive a
+//! restart as a [`Snapshot`], and
+//! [`StateMachineInstance::restore`](crate::StateMachineInstance::restore) recreates the
+//! instance from it. A [`Persistence`] store keeps the latest snapshot somewhere:
+//! [`MemoryStore`] in memory for tests, and `FileStore` in a JSON file behind the
+//! `file-store` feature.
+//!
+//! ```ignore
+//! let mut store = FileStore::new("orders/42.json");
+//! let mut order = match store.load()? {
+//!     Some(snapshot) => StateMachineInstance::<Order>::restore(snapshot)?,
+//!     None => StateMachineInstance::<Order>::new(),
+//! };
+//! order.transition(Input::Pay)?;
+//! store.save(&order.snapshot())?;
+//! ```
+//!
+//! Event-sourced aggregates keep the inputs instead, and rebuild the instance with
+//! [`StateMachineInstance::replay`](crate::StateMachineInstance::replay). A
+//! [`TransitionLog`] set on the instance writes those inputs ahead: every transition is
+//! appended before any callback runs, so after a crash the log holds at least what
+//! the callbacks saw, and replaying it recovers the instance.
+
+use crate::core::StateMachine;
+use crate::instance::HistoryEntry;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// Durable part of an instance, see
+/// [`StateMachineInstance::snapshot`](crate::StateMachineInstance::snapshot)
+///
+/// With the `serde` feature a snapshot serializes the same way as the instance itself.
+/// The encoding is forward compatible: unknown fields are ignored and fields added
+/// after a snapshot was written take default values. Snapshots of a machine whose
+/// states were renamed since are read with
+/// [`from_json_migrating`](Self::from_json_migrating).
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(
+        serialize = "SM::State: serde::Serialize, SM::Input: serde::Serialize",
+        deserialize = "SM::State: serde::Deserialize<'de>, SM::Input: serde::Deserialize<'de>"
+    ))
+)]
+pub struct Snapshot<SM: StateMachine> {
+    /// Format version the snapshot was written in, 0 for snapshots older than
+    /// versioning, see [`FORMAT_VERSION`](Self::FORMAT_VERSION)
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub version: u32,
+    /// The current state
+    pub current_state: SM::State,
+    /// The history, oldest entry first
+    pub history: Vec<HistoryEntry<SM>>,
+    /// Sequence number of the next history entry
+    pub next_seq: u64,
+    /// When the current state was entered, in milliseconds since the Unix epoch
+    #[cfg_attr(feature = "serde", serde(default = "crate::instance::unix_millis"))]
+    pub state_entered_at: u64,
+    /// Maximum history size
+    pub max_history_size: usize,
+    /// Whether ignored inputs are recorded in history
+    pub record_ignored: bool,
+    /// Whether self-loop transitions are left out of history
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub skip_self_loops_in_history: bool,
+    /// Whether internal transitions are left out of history
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub skip_internal_in_history: bool,
+    /// Inputs left out of history
+    #[cfg_attr(feature = "serde", serde(default = "Vec::new"))]
+    pub inputs_ignored_in_history: Vec<SM::Input>,
+    /// Sequence number of the next transition, see [`LogRecord::seq`]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub transition_seq: u64,
+    /// Whether transitions left out of history took effect after its last entry
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub unrecorded_since_last_entry: bool,
+    /// Free-form metadata, e.g. the revision of the machine definition
+    ///
+    /// Stores keep it, but it isn't restored into the instance.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub metadata: BTreeMap<String, String>,
+}
+
+impl<SM: StateMachine> Snapshot<SM> {
+    /// Format version of the snapshots taken by this version of the crate
+    pub const FORMAT_VERSION: u32 = 1;
+
+    /// Attach a metadata entry, replacing any entry with the same key
+    pub fn with_metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.metadata.insert(key.into(), value.into());
+        self
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<SM> Snapshot<SM>
+where
+    SM: StateMachine,
+    SM::State: serde::Serialize + serde::de::DeserializeOwned,
+    SM::Input: serde::de::DeserializeOwned,
+{
+    /// Deserialize a snapshot from JSON, mapping states that no longer exist
+    ///
+    /// Every state of the snapshot, the current state and the `from` state of each
+    /// history entry, that is serialized as a string naming no state of the machine is
+    /// passed to `rename_state`. The state it returns takes its place; names it doesn't
+    /// map are left alone and fail deserialization. Former names declared in the
+    /// definition with `state_aliases` are accepted without a hook.
+    ///
+    /// # Example
+    /// ```ignore
+    /// // `InReview` was renamed to `Review`
+    /// let snapshot = Snapshot::<Article>::from_json_migrating(json, |name| {
+    ///     (name == "InReview").then_some(State::Review)
+    /// })?;
+    /// ```
+    pub fn from_json_migrating<F>(
+        mut value: serde_json::Value,
+        rename_state: F,
+    ) -> Result<Self, serde_json::Error>
+    where
+        F: Fn(&str) -> Option<SM::State>,
+    {
+        let known = SM::states()
+            .iter()
+            .map(serde_json::to_value)
+            .collect::<Result<Vec<_>, _>>()?;
+        let migrate = |state: &mut serde_json::Value| -> Result<(), serde_json::Error> {
+            if !known.contains(state)
+                && let Some(name) = state.as_str()
+                && let Some(renamed) = rename_state(name)
+            {
+                *state = serde_json::to_value(renamed)?;
+            }
+            Ok(())
+        };
+
+        if let Some(state) = value.get_mut("current_state") {
+            migrate(state)?;
+        }
+        if let Some(serde_json::Value::Array(history)) = value.get_mut("history") {
+            for entry in history {
+                if let Some(state) = entry.get_mut("from") {
+                    migrate(state)?;
+                }
+            }
+        }
+        serde_json::from_value(value)
+    }
+}
+
+impl<SM: StateMachine> Clone for Snapshot<SM> {
+    fn clone(&self) -> Self {
+        Self {
+            version: self.version,
+            current_state: self.current_state.clone(),
+            history: self.history.clone(),
+            next_seq: self.next_seq,
+            state_entered_at: self.state_entered_at,
+            max_history_size: self.max_history_size,
+            record_ignored: self.record_ignored,
+            skip_self_loops_in_history: self.skip_self_loops_in_history,
+            skip_internal_in_history: self.skip_internal_in_history,
+            inputs_ignored_in_history: self.inputs_ignored_in_history.clone(),
+            transition_seq: self.transition_seq,
+            unrecorded_since_last_entry: self.unrecorded_since_last_entry,
+            metadata: self.metadata.clone(),
+        }
+    }
+}
+
+impl<SM: StateMachine> PartialEq for Snapshot<SM> {
+    fn eq(&self, other: &Self) -> bool {
+        self.version == other.version
+            && self.current_state == other.current_state
+            && self.history == other.history
+            && self.next_seq == other.next_seq
+            && self.state_entered_at == other.state_entered_at
+            && self.max_history_size == other.max_history_size
+            && self.record_ignored == other.record_ignored
+            && self.skip_self_loops_in_history == other.skip_self_loops_in_history
+            && self.skip_internal_in_history == other.skip_internal_in_history
+            && self.inputs_ignored_in_history == other.inputs_ignored_in_history
+            && self.transition_seq == other.transition_seq
+            && self.unrecorded_since_last_entry == other.unrecorded_since_last_entry
+            && self.metadata == other.metadata
+    }
+}
+
+impl<SM: StateMachine> core::fmt::Debug for Snapshot<SM> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Snapshot")
+            .field("version", &self.version)
+            .field("current_state", &self.current_state)
+            .field("history", &self.history)
+            .field("next_seq", &self.next_seq)
+            .field("state_entered_at", &self.state_entered_at)
+            .field("max_history_size", &self.max_history_size)
+            .field("record_ignored", &self.record_ignored)
+            .field(
+                "skip_self_loops_in_history",
+                &self.skip_self_loops_in_history,
+            )
+            .field("skip_internal_in_history", &self.skip_internal_in_history)
+            .field("inputs_ignored_in_history", &self.inputs_ignored_in_history)
+            .field("transition_seq", &self.transition_seq)
+            .field(
+                "unrecorded_since_last_entry",
+                &self.unrecorded_since_last_entry,
+            )
+            .field("metadata", &self.metadata)
+            .finish()
+    }
+}
+
+/// Store keeping the latest snapshot of an instance
+pub trait Persistence<SM: StateMachine> {
+    /// Error of the underlying storage
+    type Error;
+
+    /// Store a snapshot, replacing the previous one
+    fn save(&mut self, snapshot: &Snapshot<SM>) -> Result<(), Self::Error>;
+
+    /// Get the stored snapshot
+    ///
+    /// # Returns
+    /// - `Ok(Some(snapshot))`: The latest saved snapshot
+    /// - `Ok(None)`: Nothing was saved yet
+    /// - `Err(error)`: The storage failed
+    fn load(&mut self) -> Result<Option<Snapshot<SM>>, Self::Error>;
+}
+
+/// Store keeping the snapshot in memory, e.g. for tests
+pub struct MemoryStore<SM: StateMachine> {
+    /// The saved snapshot
+    snapshot: Option<Snapshot<SM>>,
+}
+
+impl<SM: StateMachine> MemoryStore<SM> {
+    /// Create an empty store
+    pub fn new() -> Self {
+        Self { snapshot: None }
+    }
+}
+
+impl<SM: StateMachine> Default for MemoryStore<SM> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<SM: StateMachine> Persistence<SM> for MemoryStore<SM> {
+    type Error = core::convert::Infallible;
+
+    fn save(&mut self, snapshot: &Snapshot<SM>) -> Result<(), Self::Error> {
+        self.snapshot = Some(snapshot.clone());
+        Ok(())
+    }
+
+    fn load(&mut self) -> Result<Option<Snapshot<SM>>, Self::Error> {
+        Ok(self.snapshot.clone())
+    }
+}
+
+impl<SM: StateMachine> core::fmt::Debug for MemoryStore<SM> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("MemoryStore")
+            .field("snapshot", &self.snapshot)
+            .finish()
+    }
+}
+
+/// Store keeping the snapshot in a JSON file
+///
+/// Saving writes a temporary file next to the target and renames it over the target,
+/// so a crash never leaves a half-written snapshot behind.
+#[cfg(feature = "file-store")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileStore {
+    /// The file holding the snapshot
+    path: std::path::PathBuf,
+}
+
+#[cfg(feature = "file-store")]
+impl FileStore {
+    /// Create a store backed by a file, which needn't exist yet
+    pub fn new(path: impl Into<std::path::PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Get the file holding the snapshot
+    pub fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+}
+
+#[cfg(feature = "file-store")]
+impl<SM> Persistence<SM> for FileStore
+where
+    SM: StateMachine,
+    SM::State: serde::Serialize + serde::de::DeserializeOwned,
+    SM::Input: serde::Serialize + serde::de::DeserializeOwned,
+{
+    type Error = std::io::Error;
+
+    fn save(&mut self, snapshot: &Snapshot<SM>) -> Result<(), Self::Error> {
+        let json = serde_json::to_vec_pretty(snapshot).map_err(std::io::Error::other)?;
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+        std::fs::write(&temporary, json)?;
+        std::fs::rename(&temporary, &self.path)
+    }
+
+    fn load(&mut self) -> Result<Option<Snapshot<SM>>, Self::Error> {
+        match std::fs::read(&self.path) {
+            Ok(json) => serde_json::from_slice(&json)
+                .map(Some)
+                .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidData, error)),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+}
+
+/// Transition appended to a [`TransitionLog`]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(
+        serialize = "SM::State: serde::Serialize, SM::Input: serde::Serialize",
+        deserialize = "SM::State: serde::Deserialize<'de>, SM::Input: serde::Deserialize<'de>"
+    ))
+)]
+pub struct LogRecord<SM: StateMachine> {
+    /// Sequence number of the transition, see
+    /// [`transition_seq`](crate::StateMachineInstance::transition_seq)
+    pub seq: u64,
+    /// The input that triggered the transition
+    pub input: SM::Input,
+    /// The state the transition leads to
+    pub state: SM::State,
+}
+
+impl<SM: StateMachine> Clone for LogRecord<SM> {
+    fn clone(&self) -> Self {
+        Self {
+            seq: self.seq,
+            input: self.input.clone(),
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<SM: StateMachine> PartialEq for LogRecord<SM> {
+    fn eq(&self, other: &Self) -> bool {
+        self.seq == other.seq && self.input == other.input && self.state == other.state
+    }
+}
+
+impl<SM: StateMachine> core::fmt::Debug for LogRecord<SM> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("LogRecord")
+            .field("seq", &self.seq)
+            .field("input", &self.input)
+            .field("state", &self.state)
+            .finish()
+    }
+}
+
+/// Write-ahead log of the transitions of an instance, see
+/// [`set_transition_log`](crate::StateMachineInstance::set_transition_log)
+///
+/// Only transitions that take effect are appended, internal ones and self-loops
+/// included, so the inputs of the log replay to the same state.
+pub trait TransitionLog<SM: StateMachine>: Send + Sync {
+    /// Append a transition, durably if the log is meant to survive crashes
+    ///
+    /// # Returns
+    /// - `Ok(())`: The transition may take effect
+    /// - `Err(reason)`: The transition fails with
+    ///   [`TransitionError::LogFailed`](crate::TransitionError::LogFailed)
+    fn append(&mut self, record: &LogRecord<SM>) -> Result<(), String>;
+}
+
+/// Transition log kept in memory, e.g. for tests
+///
+/// Clones share the records, so a clone kept outside the instance sees what the
+/// instance appends.
+#[cfg(feature = "std")]
+pub struct MemoryLog<SM: StateMachine> {
+    /// The appended records
+    records: alloc::sync::Arc<std::sync::Mutex<Vec<LogRecord<SM>>>>,
+}
+
+#[cfg(feature = "std")]
+impl<SM: StateMachine> MemoryLog<SM> {
+    /// Create an empty log
+    pub fn new() -> Self {
+        Self {
+            records: Default::default(),
+        }
+    }
+
+    /// Get the appended records, oldest first
+    pub fn records(&self) -> Vec<LogRecord<SM>> {
+        self.records.lock().unwrap().clone()
+    }
+
+    /// Get the inputs of the appended records, oldest first, ready for replay
+    pub fn inputs(&self) -> Vec<SM::Input> {
+        self.records
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|record| record.input.clone())
+            .collect()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<SM: StateMachine> Default for MemoryLog<SM> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<SM: StateMachine> Clone for MemoryLog<SM> {
+    fn clone(&self) -> Self {
+        Self {
+            records: alloc::sync::Arc::clone(&self.records),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<SM> TransitionLog<SM> for MemoryLog<SM>
+where
+    SM: StateMachine,
+    SM::State: Send,
+    SM::Input: Send,
+{
+    fn append(&mut self, record: &LogRecord<SM>) -> Result<(), String> {
+        self.records.lock().unwrap().push(record.clone());
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<SM: StateMachine> core::fmt::Debug for MemoryLog<SM> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("MemoryLog")
+            .field("records", &self.records.lock().unwrap())
+            .finish()
+    }
+}
+
+/// Transition log appending JSON lines to a file
+///
+/// Every append is synced to disk before the transition takes effect. A crash while
+/// appending can leave a partial last line, which [`read`](Self::read) drops; the
+/// file must be cut back to its complete lines before appending again.
+#[cfg(feature = "file-store")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileLog {
+    /// The file holding the log
+    path: std::path::PathBuf,
+}
+
+#[cfg(feature = "file-store")]
+impl FileLog {
+    /// Create a log backed by a file, which needn't exist yet
+    pub fn new(path: impl Into<std::path::PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Get the file holding the log
+    pub fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+
+    /// Read the records of the log, oldest first
+    ///
+    /// # Returns
+    /// - `Ok(records)`: The complete records, none if the file doesn't exist
+    /// - `Err(error)`: The file couldn't be read, or a complete line isn't a record
+    pub fn read<SM>(&self) -> std::io::Result<Vec<LogRecord<SM>>>
+    where
+        SM: StateMachine,
+        SM::State: serde::de::DeserializeOwned,
+        SM::Input: serde::de::DeserializeOwned,
+    {
+        let text = match std::fs::read_to_string(&self.path) {
+            Ok(text) => text,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(error) => return Err(error),
+        };
+        let complete = text.rfind('\n').map_or("", |end| &text[..end]);
+        complete
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                serde_json::from_str(line)
+                    .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidData, error))
+            })
+            .collect()
+    }
+}
+
+#[cfg(feature = "file-store")]
+impl<SM> TransitionLog<SM> for FileLog
+where
+    SM: StateMachine,
+    SM::State: serde::Serialize,
+    SM::Input: serde::Serialize,
+{
+    fn append(&mut self, record: &LogRecord<SM>) -> Result<(), String> {
+        use std::io::Write;
+
+        let mut line = serde_json::to_vec(record).map_err(|error| error.to_string())?;
+        line.push(b'\n');
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|error| error.to_string())?;
+        file.write_all(&line)
+            .and_then(|()| file.sync_data())
+            .map_err(|error| error.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    define_state_machine! {
+        name: Order,
+        states: { Created, Paid, Shipped },
+        inputs: { Pay, Ship },
+        initial: Created,
+        transitions: {
+            Created + Pay => Paid,
+            Paid + Ship => Shipped,
+        }
+    }
+
+    #[test]
+    fn test_snapshot_restore() {
+        let mut store = MemoryStore::<Order>::new();
+        assert_eq!(store.load(), Ok(None));
+
+        let mut order = StateMachineInstance::<Order>::with_max_history(8);
+        order.transition(Input::Pay).unwrap();
+        order.annotate_history(0, "card").unwrap();
+        store.save(&order.snapshot()).unwrap();
+
+        let snapshot = store.load().unwrap().unwrap();
+        let mut restored = StateMachineInstance::<Order>::restore(snapshot.clone()).unwrap();
+        assert_eq!(*restored.current_state(), State::Paid);
+        assert_eq!(restored.history_entries(), order.history_entries());
+        assert_eq!(restored.max_history_size(), 8);
+        assert_eq!(restored.snapshot(), snapshot);
+        restored.transition(Input::Ship).unwrap();
+        assert_eq!(restored.last_history_seq(), Some(1));
+
+        let mut broken = snapshot.clone();
+        broken.max_history_size = 0;
+        assert!(StateMachineInstance::<Order>::restore(broken).is_err());
+        let mut broken = snapshot;
+        broken.next_seq = 0;
+        assert_eq!(
+            StateMachineInstance::<Order>::restore(broken).unwrap_err(),
+            "history sequence number 0 is out of order"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_snapshot_versions() {
+        let mut order = StateMachineInstance::<Order>::new();
+        order.transition(Input::Pay).unwrap();
+        let snapshot = order.snapshot().with_metadata("revision", "2");
+        assert_eq!(snapshot.version, Snapshot::<Order>::FORMAT_VERSION);
+
+        let json = serde_json::to_value(&snapshot).unwrap();
+        assert_eq!(json["version"], 1);
+        assert_eq!(json["metadata"]["revision"], "2");
+        assert_eq!(
+            serde_json::from_value::<Snapshot<Order>>(json).unwrap(),
+            snapshot
+        );
+
+        // Snapshots from before versioning, or with fields unknown to this version
+        let json = serde_json::json!({
+            "current_state": "Paid",
+            "history": [],
+            "next_seq": 0,
+            "max_history_size": 16,
+            "record_ignored": false,
+            "written_by_a_later_version": true
+        });
+        let old = serde_json::from_value::<Snapshot<Order>>(json).unwrap();
+        assert_eq!(old.version, 0);
+        assert_eq!(old.current_state, State::Paid);
+        assert!(old.metadata.is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_snapshot_migration() {
+        let mut json =
+            serde_json::to_value(StateMachineInstance::<Order>::new().snapshot()).unwrap();
+        json["current_state"] = "Settled".into();
+        json["history"] = serde_json::json!([
+            { "seq": 0, "from": "Created", "input": "Pay", "annotations": [] },
+            { "seq": 1, "from": "Settled", "input": "Ship", "annotations": [] }
+        ]);
+        json["next_seq"] = 2.into();
+
+        assert!(serde_json::from_value::<Snapshot<Order>>(json.clone()).is_err());
+        assert!(Snapshot::<Order>::from_json_migrating(json.clone(), |_| None).is_err());
+
+        let snapshot = Snapshot::<Order>::from_json_migrating(json, |name| {
+            assert_ne!(name, "Created");
+            (name == "Settled").then_some(State::Paid)
+        })
+        .unwrap();
+        assert_eq!(snapshot.current_state, State::Paid);
+        assert_eq!(snapshot.history[1].from, State::Paid);
+        let order = StateMachineInstance::<Order>::restore(snapshot).unwrap();
+        assert_eq!(order.history_len(), 2);
+    }
+
+    #[test]
+    fn test_replay() {
+        let order = StateMachineInstance::<Order>::replay([Input::Pay, Input::Ship]).unwrap();
+        assert_eq!(*order.current_state(), State::Shipped);
+        assert_eq!(order.history_len(), 2);
+
+        let log = [Input::Ship, Input::Pay, Input::Pay, Input::Ship];
+        assert_eq!(
+            StateMachineInstance::<Order>::replay(log.clone()).unwrap_err(),
+            ReplayError {
+                index: 0,
+                error: TransitionError::InvalidInput {
+                    state: State::Created,
+                    input: Input::Ship,
+                },
+            }
+        );
+
+        let (order, skipped) =
+            StateMachineInstance::<Order>::replay_with(log, ReplayMode::Lenient).unwrap();
+        assert_eq!(*order.current_state(), State::Shipped);
+        assert_eq!(skipped, vec![0, 2]);
+
+        // Continue from a snapshot with the inputs logged after it
+        let mut order = StateMachineInstance::<Order>::replay([Input::Pay]).unwrap();
+        let snapshot = order.snapshot();
+        order = StateMachineInstance::restore(snapshot).unwrap();
+        assert_eq!(
+            order.apply_log([Input::Ship], ReplayMode::Strict),
+            Ok(vec![])
+        );
+        assert_eq!(*order.current_state(), State::Shipped);
+    }
+
+    /// Log whose disk is full
+    struct FullLog;
+
+    impl TransitionLog<Order> for FullLog {
+        fn append(&mut self, _record: &LogRecord<Order>) -> Result<(), String> {
+            Err("disk full".into())
+        }
+    }
+
+    #[test]
+    fn test_transition_log() {
+        let log = MemoryLog::<Order>::new();
+        let mut order = StateMachineInstance::<Order>::new();
+        assert!(!order.set_transition_log(log.clone()));
+        order.transition(Input::Pay).unwrap();
+        let snapshot = order.snapshot();
+        assert!(order.transition(Input::Pay).is_err());
+        order.transition(Input::Ship).unwrap();
+        assert_eq!(order.transition_seq(), 2);
+        assert_eq!(
+            log.records(),
+            vec![
+                LogRecord {
+                    seq: 0,
+                    input: Input::Pay,
+                    state: State::Paid,
+                },
+                LogRecord {
+                    seq: 1,
+                    input: Input::Ship,
+                    state: State::Shipped,
+                },
+            ]
+        );
+
+        // Recover from the snapshot and the records appended after it
+        let mut recovered = StateMachineInstance::<Order>::restore(snapshot.clone()).unwrap();
+        let since = log
+            .records()
+            .into_iter()
+            .filter(|record| record.seq >= snapshot.transition_seq)
+            .map(|record| record.input);
+        recovered.apply_log(since, ReplayMode::Strict).unwrap();
+        assert_eq!(*recovered.current_state(), State::Shipped);
+        assert_eq!(
+            *StateMachineInstance::<Order>::replay(log.inputs())
+                .unwrap()
+                .current_state(),
+            State::Shipped
+        );
+
+        // A failing log fails the transition before any callback runs
+        let mut order = StateMachineInstance::<Order>::new();
+        order.set_transition_log(FullLog);
+        let exits = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = exits.clone();
+        order.on_any_state_exit(move |_| {
+            counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        });
+        assert_eq!(
+            order.transition(Input::Pay),
+            Err(TransitionError::LogFailed {
+                state: State::Created,
+                input: Input::Pay,
+                target: State::Paid,
+                reason: "disk full".into(),
+            })
+        );
+        assert_eq!(*order.current_state(), State::Created);
+        assert_eq!(order.transition_seq(), 0);
+        assert_eq!(exits.load(std::sync::atomic::Ordering::SeqCst), 0);
+        assert!(order.remove_transition_log());
+        assert!(!order.has_transition_log());
+        assert_eq!(order.transition(Input::Pay), Ok(State::Paid));
+    }
+
+    #[cfg(feature = "file-store")]
+    #[test]
+    fn test_file_log() {
+        let path = std::env::temp_dir().join(format!("yasm-log-{}.jsonl", std::process::id()));
+        let log = FileLog::new(&path);
+        assert!(log.read::<Order>().unwrap().is_empty());
+
+        let mut order = StateMachineInstance::<Order>::new();
+        order.set_transition_log(log.clone());
+        order.transition(Input::Pay).unwrap();
+        order.transition(Input::Ship).unwrap();
+
+        // A crash in the middle of an append leaves a partial line behind
+        let mut text = std::fs::read_to_string(&path).unwrap();
+        text.push_str("{\"seq\":2,");
+        std::fs::write(&path, text).unwrap();
+
+        let records = log.read::<Order>().unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].state, State::Shipped);
+        let recovered =
+            StateMachineInstance::<Order>::replay(records.into_iter().map(|record| record.input))
+                .unwrap();
+        assert_eq!(*recovered.current_state(), State::Shipped);
+        assert_eq!(recovered.transition_seq(), 2);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "file-store")]
+    #[test]
+    fn test_file_store() {
+        let path = std::env::temp_dir().join(format!("yasm-store-{}.json", std::process::id()));
+        let mut store = FileStore::new(&path);
+        assert!(Persistence::<Order>::load(&mut store).unwrap().is_none());
+
+        let mut order = StateMachineInstance::<Order>::new();
+        order.transition(Input::Pay).unwrap();
+        store.save(&order.snapshot()).unwrap();
+
+        let snapshot: Snapshot<Order> = store.load().unwrap().unwrap();
+        assert_eq!(snapshot, order.snapshot());
+
+        std::fs::write(&path, "not json").unwrap();
+        let error = Persistence::<Order>::load(&mut store).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
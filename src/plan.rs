@@ -0,0 +1,182 @@
+use crate::core::StateMachine;
+use crate::instance::StateMachineInstance;
+
+/// The first point where an instance's history departs from an expected plan
+pub struct PlanDivergence<SM: StateMachine> {
+    /// Index into the plan (and the history) where the divergence happened
+    pub index: usize,
+    /// The input the plan expected, or None if the history ran past the end of the plan
+    pub expected: Option<SM::Input>,
+    /// The input that was actually applied
+    pub actual: SM::Input,
+    /// The state the instance was in when the unexpected input arrived
+    pub state: SM::State,
+}
+
+/// Result of [`StateMachineInstance::compare_to_plan`]
+pub struct PlanComparison<SM: StateMachine> {
+    /// Number of leading history entries that follow the plan
+    pub matched: usize,
+    /// Total number of inputs in the plan
+    pub planned: usize,
+    /// Where the history departed from the plan, if it did
+    pub divergence: Option<PlanDivergence<SM>>,
+}
+
+impl<SM: StateMachine> PlanComparison<SM> {
+    /// Check if the history follows the plan so far
+    pub fn is_on_plan(&self) -> bool {
+        self.divergence.is_none()
+    }
+
+    /// Check if the whole plan has been carried out without divergence
+    pub fn is_complete(&self) -> bool {
+        self.is_on_plan() && self.matched == self.planned
+    }
+
+    /// Get the number of planned inputs that are still outstanding
+    ///
+    /// Returns 0 once the history has diverged.
+    pub fn remaining(&self) -> usize {
+        if self.is_on_plan() {
+            self.planned - self.matched
+        } else {
+            0
+        }
+    }
+}
+
+impl<SM: StateMachine> StateMachineInstance<SM> {
+    /// Compare the history against an expected sequence of inputs
+    ///
+    /// Inputs are compared by kind, so payloads don't need to match. The comparison
+    /// starts at the oldest entry still in the history, so older entries evicted by the
+    /// history size limit are not taken into account.
+    ///
+    /// # Arguments
+    /// - `plan`: The expected inputs, in order
+    ///
+    /// # Returns
+    /// Returns how far the history follows the plan and where it diverged
+    pub fn compare_to_plan(&self, plan: &[SM::Input]) -> PlanComparison<SM> {
+        let mut matched = 0;
+        let mut divergence = None;
+
+        for (index, (state, actual)) in self.history().iter().enumerate() {
+            let expected = plan.get(index);
+            if expected.is_some_and(|expected| {
+                SM::canonical_input(expected) == SM::canonical_input(actual)
+            }) {
+                matched += 1;
+                continue;
+            }
+
+            divergence = Some(PlanDivergence {
+                index,
+                expected: expected.cloned(),
+                actual: actual.clone(),
+                state: state.clone(),
+            });
+            break;
+        }
+
+        PlanComparison {
+            matched,
+            planned: plan.len(),
+            divergence,
+        }
+    }
+}
+
+impl<SM: StateMachine> core::fmt::Display for PlanDivergence<SM> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match &self.expected {
+            Some(expected) => write!(
+                f,
+                "Step {}: expected {expected:?} but got {:?} in state {:?}",
+                self.index, self.actual, self.state
+            ),
+            None => write!(
+                f,
+                "Step {}: unplanned input {:?} in state {:?} after the plan ended",
+                self.index, self.actual, self.state
+            ),
+        }
+    }
+}
+
+impl<SM: StateMachine> core::fmt::Debug for PlanDivergence<SM> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("PlanDivergence")
+            .field("index", &self.index)
+            .field("expected", &self.expected)
+            .field("actual", &self.actual)
+            .field("state", &self.state)
+            .finish()
+    }
+}
+
+impl<SM: StateMachine> core::fmt::Debug for PlanComparison<SM> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("PlanComparison")
+            .field("matched", &self.matched)
+            .field("planned", &self.planned)
+            .field("divergence", &self.divergence)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    define_state_machine! {
+        name: Deployment,
+        states: { Idle, Draining, Upgrading, Serving },
+        inputs: { Drain, Upgrade, Resume, Rollback },
+        initial: Idle,
+        transitions: {
+            Idle + Drain => Draining,
+            Draining + Upgrade => Upgrading,
+            Draining + Resume => Serving,
+            Upgrading + Resume => Serving,
+            Upgrading + Rollback => Draining
+        }
+    }
+
+    #[test]
+    fn test_compare_to_plan() {
+        let plan = [Input::Drain, Input::Upgrade, Input::Resume];
+        let mut sm = StateMachineInstance::<Deployment>::new();
+
+        sm.transition(Input::Drain).unwrap();
+        let comparison = sm.compare_to_plan(&plan);
+        assert!(comparison.is_on_plan());
+        assert!(!comparison.is_complete());
+        assert_eq!(comparison.remaining(), 2);
+
+        sm.transition(Input::Upgrade).unwrap();
+        sm.transition(Input::Rollback).unwrap();
+        let comparison = sm.compare_to_plan(&plan);
+        let divergence = comparison.divergence.as_ref().unwrap();
+        assert_eq!(divergence.index, 2);
+        assert_eq!(divergence.expected, Some(Input::Resume));
+        assert_eq!(divergence.actual, Input::Rollback);
+        assert_eq!(
+            divergence.to_string(),
+            "Step 2: expected Resume but got Rollback in state Upgrading"
+        );
+        assert_eq!(comparison.matched, 2);
+        assert_eq!(comparison.remaining(), 0);
+
+        // Running past the end of the plan is a divergence too
+        let mut sm = StateMachineInstance::<Deployment>::new();
+        for input in plan.iter().cloned() {
+            sm.transition(input).unwrap();
+        }
+        assert!(sm.compare_to_plan(&plan).is_complete());
+        let comparison = sm.compare_to_plan(&plan[..2]);
+        assert_eq!(comparison.divergence.unwrap().expected, None);
+    }
+}
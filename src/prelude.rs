@@ -0,0 +1,38 @@
+//! Commonly used items, for glob import
+//!
+//! Covers defining machines, running instances, handling transition errors and
+//! analyzing or documenting definitions. Subsystems with a narrower audience, such as
+//! backoff, supervisors or registries, stay in their own modules.
+//!
+//! ```rust
+//! use yasm::prelude::*;
+//!
+//! define_state_machine! {
+//!     name: Door,
+//!     states: { Closed, Open },
+//!     inputs: { Push, Pull },
+//!     initial: Closed,
+//!     transitions: {
+//!         Closed + Push => Open,
+//!         Open + Pull => Closed
+//!     }
+//! }
+//!
+//! let mut door = StateMachineInstance::<Door>::new();
+//! assert_eq!(door.try_transition_by_name("Push"), Ok(State::Open));
+//! assert!(matches!(
+//!     door.transition(Input::Push),
+//!     Err(TransitionError::InvalidInput { .. })
+//! ));
+//! assert!(StateMachineQuery::<Door>::unreachable_states().is_empty());
+//! ```
+
+pub use crate::any::AnyInstance;
+pub use crate::callbacks::{CallbackId, TransitionOutcome};
+pub use crate::core::StateMachine;
+pub use crate::define_state_machine;
+pub use crate::doc::StateMachineDoc;
+pub use crate::dynamic::StateMachineBuilder;
+pub use crate::error::{TransitionError, TryTransitionError};
+pub use crate::instance::StateMachineInstance;
+pub use crate::query::StateMachineQuery;
@@ -1,15 +1,75 @@
 use crate::core::StateMachine;
-use std::collections::HashSet;
+use crate::dynamic::{DynDefinition, DynTransitionDefinition};
+use crate::hashing::{HashMap, HashSet};
+use crate::rng::Rng;
+use crate::state_set::StateSet;
+use alloc::{format, string::String, vec, vec::Vec};
+use core::hash::Hash;
 
 /// State machine query utilities
 ///
 /// Provides various state machine analysis capabilities such as reachability analysis
 /// and path finding.
 pub struct StateMachineQuery<SM: StateMachine> {
-    _phantom: std::marker::PhantomData<SM>,
+    _phantom: core::marker::PhantomData<SM>,
 }
 
 impl<SM: StateMachine> StateMachineQuery<SM> {
+    /// Visit every declared transition
+    ///
+    /// Calls the visitor once for each state and valid input that lead to a next
+    /// state, by state then input in declaration order. Guards are not evaluated.
+    ///
+    /// # Arguments
+    /// - `visit`: Called with the source state, the input and the target state
+    pub fn for_each_transition<F>(mut visit: F)
+    where
+        F: FnMut(&SM::State, &SM::Input, &SM::State),
+    {
+        for state in SM::states() {
+            for input in SM::valid_inputs_iter(&state) {
+                if let Some(next_state) = SM::next_state(&state, &input) {
+                    visit(&state, &input, &next_state);
+                }
+            }
+        }
+    }
+
+    /// Get every declared transition
+    ///
+    /// # Returns
+    /// Returns the `(from, input, to)` triples in the order of
+    /// [`for_each_transition`](Self::for_each_transition)
+    pub fn transitions() -> Vec<(SM::State, SM::Input, SM::State)> {
+        let mut transitions = Vec::new();
+        Self::for_each_transition(|from, input, to| {
+            transitions.push((from.clone(), input.clone(), to.clone()));
+        });
+        transitions
+    }
+
+    /// Export the transition graph to `petgraph`, e.g. for its graph algorithms
+    ///
+    /// Every declared state becomes a node, in declaration order, so the node at
+    /// index `i` is the `i`-th state. Every declared transition becomes an edge
+    /// weighted by its input, in the order of
+    /// [`for_each_transition`](Self::for_each_transition).
+    ///
+    /// # Returns
+    /// Returns the directed graph of states and transitions
+    #[cfg(feature = "petgraph")]
+    pub fn to_graph() -> petgraph::graph::DiGraph<SM::State, SM::Input> {
+        let mut graph = petgraph::graph::DiGraph::new();
+        let nodes: HashMap<SM::State, _> = SM::states()
+            .into_iter()
+            .map(|state| (state.clone(), graph.add_node(state)))
+            .collect();
+        Self::for_each_transition(|from, input, to| {
+            graph.add_edge(nodes[from], nodes[to], input.clone());
+        });
+        graph
+    }
+
     /// Get all states that can reach the target state
     ///
     /// Traverses all state and input combinations to find all states that can
@@ -19,16 +79,16 @@ impl<SM: StateMachine> StateMachineQuery<SM> {
     /// - `target`: The target state
     ///
     /// # Returns
-    /// Returns a list of all states that can reach the target state
+    /// Returns the set of all states that can reach the target state
     #[allow(clippy::collapsible_if)]
-    pub fn states_leading_to(target: &SM::State) -> Vec<SM::State> {
-        let mut result = Vec::new();
+    pub fn states_leading_to(target: &SM::State) -> StateSet<SM> {
+        let mut result = StateSet::new();
 
         for state in SM::states() {
-            for input in SM::valid_inputs(&state) {
+            for input in SM::valid_inputs_iter(&state) {
                 if let Some(next_state) = SM::next_state(&state, &input) {
                     if next_state == *target {
-                        result.push(state.clone());
+                        result.insert(state.clone());
                         break; // Found one transition, avoid duplicate additions
                     }
                 }
@@ -46,10 +106,10 @@ impl<SM: StateMachine> StateMachineQuery<SM> {
     /// - `from`: The starting state
     ///
     /// # Returns
-    /// Returns a list of all states reachable from the starting state (including the starting state itself)
+    /// Returns the set of all states reachable from the starting state (including the starting state itself)
     #[allow(clippy::collapsible_if)]
-    pub fn reachable_states(from: &SM::State) -> Vec<SM::State> {
-        let mut reachable = HashSet::new();
+    pub fn reachable_states(from: &SM::State) -> StateSet<SM> {
+        let mut reachable = StateSet::new();
         let mut to_visit = vec![from.clone()];
 
         while let Some(current) = to_visit.pop() {
@@ -59,7 +119,7 @@ impl<SM: StateMachine> StateMachineQuery<SM> {
             reachable.insert(current.clone());
 
             // Explore all possible next states
-            for input in SM::valid_inputs(&current) {
+            for input in SM::valid_inputs_iter(&current) {
                 if let Some(next_state) = SM::next_state(&current, &input) {
                     if !reachable.contains(&next_state) {
                         to_visit.push(next_state);
@@ -68,7 +128,7 @@ impl<SM: StateMachine> StateMachineQuery<SM> {
             }
         }
 
-        reachable.into_iter().collect()
+        reachable
     }
 
     /// Check if a path exists from one state to another
@@ -88,19 +148,99 @@ impl<SM: StateMachine> StateMachineQuery<SM> {
     /// Terminal states are states that have no valid inputs that can trigger transitions.
     ///
     /// # Returns
-    /// Returns a list of all terminal states
-    pub fn terminal_states() -> Vec<SM::State> {
-        let mut terminal_states = Vec::new();
+    /// Returns the set of all terminal states
+    pub fn terminal_states() -> StateSet<SM> {
+        let mut terminal_states = StateSet::new();
 
         for state in SM::states() {
-            if SM::valid_inputs(&state).is_empty() {
-                terminal_states.push(state);
+            if SM::valid_inputs_iter(&state).next().is_none() {
+                terminal_states.insert(state);
             }
         }
 
         terminal_states
     }
 
+    /// Get all absorbing states: states with transitions, all of them self-loops
+    ///
+    /// Unlike terminal states, absorbing states still take input, e.g. a `Failed`
+    /// state that only logs retries, but can never be left.
+    ///
+    /// # Returns
+    /// Returns the set of all absorbing states
+    pub fn absorbing_states() -> StateSet<SM> {
+        SM::states()
+            .into_iter()
+            .filter(|state| {
+                let mut targets = SM::valid_inputs_iter(state)
+                    .filter_map(|input| SM::next_state(state, &input))
+                    .peekable();
+                targets.peek().is_some() && targets.all(|to| to == *state)
+            })
+            .collect()
+    }
+
+    /// Get the groups of two or more states that can't be left once entered
+    ///
+    /// Each group is a strongly connected component with no transition leaving it,
+    /// e.g. two states that only hand over to each other. Single inescapable states
+    /// are reported by [`terminal_states`](Self::terminal_states) and
+    /// [`absorbing_states`](Self::absorbing_states) instead.
+    ///
+    /// # Returns
+    /// Returns the trap components, ordered by their first state in declaration order
+    pub fn trap_components() -> Vec<StateSet<SM>> {
+        let mut components: Vec<StateSet<SM>> = Vec::new();
+        for state in SM::states() {
+            if components
+                .iter()
+                .any(|component| component.contains(&state))
+            {
+                continue;
+            }
+            // Closed if every state reachable from here can come back
+            let reachable = Self::reachable_states(&state);
+            if reachable.len() > 1 && reachable.iter().all(|to| Self::has_path(&to, &state)) {
+                components.push(reachable);
+            }
+        }
+        components
+    }
+
+    /// Check whether the machine accepts an input word
+    ///
+    /// Runs the word from the initial state and checks that it ends in an accepting
+    /// state. Ignored and deferred inputs leave the state unchanged, as they do for
+    /// [`transition`](crate::StateMachineInstance::transition), and the word is rejected
+    /// as soon as an input is not valid or follows a final state. Guards are not
+    /// evaluated, as no context is at hand.
+    ///
+    /// # Arguments
+    /// - `word`: The inputs to run, in order
+    ///
+    /// # Returns
+    /// Returns true if the word leads to an accepting state, otherwise false
+    pub fn accepts(word: &[SM::Input]) -> bool {
+        let mut state = SM::initial_state();
+        for input in word {
+            if SM::is_final(&state) {
+                return false;
+            }
+            if SM::is_ignored(&state, input) || SM::is_deferred(&state, input) {
+                continue;
+            }
+            let canonical = SM::canonical_input(input);
+            if !SM::valid_inputs_iter(&state).any(|valid| valid == canonical) {
+                return false;
+            }
+            match SM::next_state(&state, input) {
+                Some(next_state) => state = next_state,
+                None => return false,
+            }
+        }
+        SM::is_accepting(&state)
+    }
+
     /// Check if the state machine is strongly connected
     ///
     /// Strong connectivity means that from any state, you can reach any other state.
@@ -141,21 +281,21 @@ impl<SM: StateMachine> StateMachineQuery<SM> {
     /// Returns the state sequence of the shortest path, or None if unreachable
     #[allow(clippy::collapsible_if)]
     pub fn shortest_path(from: &SM::State, to: &SM::State) -> Option<Vec<SM::State>> {
-        use std::collections::{HashMap, VecDeque};
+        use alloc::collections::VecDeque;
 
         if from == to {
             return Some(vec![from.clone()]);
         }
 
         let mut queue = VecDeque::new();
-        let mut visited = HashSet::new();
-        let mut parent = HashMap::new();
+        let mut visited = HashSet::default();
+        let mut parent = HashMap::default();
 
         queue.push_back(from.clone());
         visited.insert(from.clone());
 
         while let Some(current) = queue.pop_front() {
-            for input in SM::valid_inputs(&current) {
+            for input in SM::valid_inputs_iter(&current) {
                 if let Some(next_state) = SM::next_state(&current, &input) {
                     if !visited.contains(&next_state) {
                         visited.insert(next_state.clone());
@@ -183,4 +323,992 @@ impl<SM: StateMachine> StateMachineQuery<SM> {
 
         None
     }
+
+    /// Find the shortest path together with the inputs that drive it
+    ///
+    /// Like [`shortest_path`](Self::shortest_path), but each step pairs a state with the
+    /// input to apply in it, so the inputs can be replayed on an instance in `from` to
+    /// bring it to `to`. When several inputs lead to the same state, the first one in
+    /// declaration order is used.
+    ///
+    /// # Arguments
+    /// - `from`: The starting state
+    /// - `to`: The target state
+    ///
+    /// # Returns
+    /// Returns the (state, input) steps, empty if `from == to`, or None if unreachable
+    pub fn shortest_path_with_inputs(
+        from: &SM::State,
+        to: &SM::State,
+    ) -> Option<Vec<(SM::State, SM::Input)>> {
+        use alloc::collections::VecDeque;
+
+        let mut queue = VecDeque::from([from.clone()]);
+        let mut parent: HashMap<SM::State, (SM::State, SM::Input)> = HashMap::default();
+        let mut visited = HashSet::from_iter([from.clone()]);
+
+        while let Some(current) = queue.pop_front() {
+            if current == *to {
+                // Reconstruct the steps backwards from the target
+                let mut steps = Vec::new();
+                let mut state = current;
+                while let Some((previous, input)) = parent.remove(&state) {
+                    steps.push((previous.clone(), input));
+                    state = previous;
+                }
+                steps.reverse();
+                return Some(steps);
+            }
+
+            for input in SM::valid_inputs_iter(&current) {
+                if let Some(next_state) = SM::next_state(&current, &input)
+                    && visited.insert(next_state.clone())
+                {
+                    parent.insert(next_state.clone(), (current.clone(), input));
+                    queue.push_back(next_state);
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Find the shortest input sequence that drives a state to another
+    ///
+    /// Replaying the inputs on an instance in `from` brings it to `to`, unless a guard
+    /// rejects one of them. Guards are not evaluated.
+    ///
+    /// # Arguments
+    /// - `from`: The starting state
+    /// - `to`: The target state
+    ///
+    /// # Returns
+    /// Returns the inputs, empty if `from == to`, or None if `to` is unreachable
+    pub fn input_sequence(from: &SM::State, to: &SM::State) -> Option<Vec<SM::Input>> {
+        Self::shortest_path_with_inputs(from, to)
+            .map(|steps| steps.into_iter().map(|(_, input)| input).collect())
+    }
+
+    /// Find up to `k` shortest input sequences that drive a state to another
+    ///
+    /// Sequences are distinct, ordered by length, then by input declaration order.
+    /// They may pass through a state several times, e.g. around a rework loop, but
+    /// never through `to` before their end.
+    ///
+    /// # Arguments
+    /// - `from`: The starting state
+    /// - `to`: The target state
+    /// - `k`: The maximum number of sequences
+    ///
+    /// # Returns
+    /// Returns the sequences, fewer than `k` if there aren't as many, and none if
+    /// `to` is unreachable
+    pub fn k_shortest_input_sequences(
+        from: &SM::State,
+        to: &SM::State,
+        k: usize,
+    ) -> Vec<Vec<SM::Input>> {
+        use alloc::collections::VecDeque;
+
+        // Only expand states that can still reach the target, so the search ends
+        // once every way there is found
+        let leading_to: HashSet<SM::State> = SM::states()
+            .into_iter()
+            .filter(|state| Self::has_path(state, to))
+            .collect();
+
+        let mut sequences = Vec::new();
+        let mut queue = VecDeque::new();
+        if leading_to.contains(from) {
+            queue.push_back((from.clone(), Vec::new()));
+        }
+        while sequences.len() < k
+            && let Some((state, sequence)) = queue.pop_front()
+        {
+            if state == *to {
+                sequences.push(sequence);
+                continue;
+            }
+            for input in SM::valid_inputs_iter(&state) {
+                if let Some(next_state) = SM::next_state(&state, &input)
+                    && leading_to.contains(&next_state)
+                {
+                    let mut extended = sequence.clone();
+                    extended.push(input);
+                    queue.push_back((next_state, extended));
+                }
+            }
+        }
+        sequences
+    }
+
+    /// Get the length of the shortest path to every state reachable from a state
+    ///
+    /// Lengths count transitions. Guards are not evaluated.
+    ///
+    /// # Arguments
+    /// - `from`: The starting state
+    ///
+    /// # Returns
+    /// Returns the distance of every reachable state, `from` included at 0
+    pub fn distances(from: &SM::State) -> HashMap<SM::State, usize> {
+        use alloc::collections::VecDeque;
+
+        let mut distances = HashMap::from_iter([(from.clone(), 0)]);
+        let mut queue = VecDeque::from([(from.clone(), 0)]);
+
+        while let Some((current, distance)) = queue.pop_front() {
+            for input in SM::valid_inputs_iter(&current) {
+                if let Some(next_state) = SM::next_state(&current, &input)
+                    && !distances.contains_key(&next_state)
+                {
+                    distances.insert(next_state.clone(), distance + 1);
+                    queue.push_back((next_state, distance + 1));
+                }
+            }
+        }
+
+        distances
+    }
+
+    /// Get the shortest path lengths between all pairs of states
+    ///
+    /// # Returns
+    /// Returns the [`distances`](Self::distances) from every declared state
+    pub fn all_pairs_shortest_paths() -> HashMap<SM::State, HashMap<SM::State, usize>> {
+        SM::states()
+            .into_iter()
+            .map(|state| {
+                let distances = Self::distances(&state);
+                (state, distances)
+            })
+            .collect()
+    }
+
+    /// Get the eccentricity of a state: the most transitions needed to reach any
+    /// state reachable from it
+    ///
+    /// The eccentricity of the initial state is the maximum number of steps a run
+    /// needs to reach any state, e.g. completion.
+    ///
+    /// # Arguments
+    /// - `state`: The state to measure from
+    ///
+    /// # Returns
+    /// Returns the greatest distance from `state`, 0 if it reaches no other state
+    pub fn eccentricity(state: &SM::State) -> usize {
+        Self::distances(state).into_values().max().unwrap_or(0)
+    }
+
+    /// Get the diameter of the machine: the greatest eccentricity of any state
+    ///
+    /// Unreachable pairs are left out rather than counting as infinitely far.
+    ///
+    /// # Returns
+    /// Returns the longest shortest path length between two states, 0 without
+    /// transitions
+    pub fn diameter() -> usize {
+        SM::states()
+            .iter()
+            .map(Self::eccentricity)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Find all acyclic paths from the starting state to the target state
+    ///
+    /// A path never visits the same state twice. Paths are returned in depth-first
+    /// order, following transitions in declaration order.
+    ///
+    /// # Arguments
+    /// - `from`: The starting state
+    /// - `to`: The target state
+    /// - `max_len`: The maximum number of transitions in a path
+    ///
+    /// # Returns
+    /// Returns the state sequences of all paths, each including `from` and `to`
+    pub fn all_paths(from: &SM::State, to: &SM::State, max_len: usize) -> Vec<Vec<SM::State>> {
+        fn visit<SM: StateMachine>(
+            path: &mut Vec<SM::State>,
+            to: &SM::State,
+            max_len: usize,
+            paths: &mut Vec<Vec<SM::State>>,
+        ) {
+            let current = path.last().unwrap().clone();
+            if current == *to {
+                paths.push(path.clone());
+                return;
+            }
+            if path.len() > max_len {
+                return;
+            }
+
+            let mut seen = HashSet::default();
+            for input in SM::valid_inputs_iter(&current) {
+                if let Some(next_state) = SM::next_state(&current, &input)
+                    && !path.contains(&next_state)
+                    && seen.insert(next_state.clone())
+                {
+                    path.push(next_state);
+                    visit::<SM>(path, to, max_len, paths);
+                    path.pop();
+                }
+            }
+        }
+
+        let mut paths = Vec::new();
+        visit::<SM>(&mut vec![from.clone()], to, max_len, &mut paths);
+        paths
+    }
+
+    /// Get the states that every path from a state to a terminal state must visit
+    ///
+    /// Only paths that end in a terminal state are considered, so loops such as
+    /// rework cycles don't make a checkpoint optional. The starting state itself is
+    /// not included.
+    ///
+    /// # Arguments
+    /// - `from`: The starting state
+    ///
+    /// # Returns
+    /// Returns the inevitable states, or an empty set if no terminal state is
+    /// reachable from `from`
+    pub fn inevitable_states(from: &SM::State) -> StateSet<SM> {
+        let terminal = Self::terminal_states();
+        let reachable = Self::reachable_states(from);
+        if reachable.intersection(&terminal).is_empty() {
+            return StateSet::new();
+        }
+
+        SM::states()
+            .into_iter()
+            .filter(|state| state != from && reachable.contains(state))
+            // Inevitable if no terminal state can be reached while avoiding it
+            .filter(|avoid| !Self::reaches_avoiding(from, avoid, |state| terminal.contains(state)))
+            .collect()
+    }
+
+    /// Get the states that every path from the initial state to a target must visit
+    ///
+    /// These are the dominators of the target: e.g. if `Paid` dominates `Delivered`,
+    /// every delivered order was necessarily paid. The initial state dominates every
+    /// other reachable state. The target itself is not included.
+    ///
+    /// # Arguments
+    /// - `target`: The state to reach
+    ///
+    /// # Returns
+    /// Returns the dominators in declaration order, or None if the target is not
+    /// reachable from the initial state
+    pub fn dominators(target: &SM::State) -> Option<StateSet<SM>> {
+        let initial = SM::initial_state();
+        let reachable = Self::reachable_states(&initial);
+        if !reachable.contains(target) {
+            return None;
+        }
+        if *target == initial {
+            return Some(StateSet::new());
+        }
+
+        Some(
+            SM::states()
+                .into_iter()
+                .filter(|state| state != target && reachable.contains(state))
+                .filter(|avoid| {
+                    *avoid == initial
+                        || !Self::reaches_avoiding(&initial, avoid, |state| state == target)
+                })
+                .collect(),
+        )
+    }
+
+    /// Check whether a goal state can be reached without passing through a state
+    fn reaches_avoiding<F>(from: &SM::State, avoid: &SM::State, is_goal: F) -> bool
+    where
+        F: Fn(&SM::State) -> bool,
+    {
+        let mut visited = HashSet::default();
+        let mut to_visit = vec![from.clone()];
+        while let Some(current) = to_visit.pop() {
+            if !visited.insert(current.clone()) {
+                continue;
+            }
+            if is_goal(&current) {
+                return true;
+            }
+            for input in SM::valid_inputs_iter(&current) {
+                if let Some(next_state) = SM::next_state(&current, &input)
+                    && next_state != *avoid
+                {
+                    to_visit.push(next_state);
+                }
+            }
+        }
+        false
+    }
+
+    /// Get the states from which both given states are reachable
+    ///
+    /// A state counts as reaching itself, so `a` is included if it can reach `b`
+    /// and vice versa. These are the points where two branches of a workflow
+    /// split off.
+    ///
+    /// # Arguments
+    /// - `a`: The first state
+    /// - `b`: The second state
+    ///
+    /// # Returns
+    /// Returns the set of common ancestors
+    pub fn common_ancestors(a: &SM::State, b: &SM::State) -> StateSet<SM> {
+        SM::states()
+            .into_iter()
+            .filter(|state| {
+                let reachable = Self::reachable_states(state);
+                reachable.contains(a) && reachable.contains(b)
+            })
+            .collect()
+    }
+
+    /// Get all declared states that can never be reached from the initial state
+    ///
+    /// # Returns
+    /// Returns the set of unreachable states, empty for a well-formed machine
+    pub fn unreachable_states() -> StateSet<SM> {
+        !Self::reachable_states(&SM::initial_state())
+    }
+
+    /// Get all declared inputs that trigger no transition in any state
+    ///
+    /// Inputs that are only ignored count as unused. Unreachable states are
+    /// still considered, so an input used only there is reported by
+    /// [`unreachable_states`](Self::unreachable_states) instead.
+    ///
+    /// # Returns
+    /// Returns the unused inputs in declaration order
+    pub fn unused_inputs() -> Vec<SM::Input> {
+        let mut used = HashSet::default();
+        Self::for_each_transition(|_, input, _| {
+            used.insert(SM::canonical_input(input));
+        });
+        SM::inputs()
+            .into_iter()
+            .filter(|input| !used.contains(input))
+            .collect()
+    }
+
+    /// Get every state and input combination the machine doesn't handle
+    ///
+    /// An input is handled in a state if it triggers a transition there, or is
+    /// explicitly ignored or deferred. Guards are not evaluated, so a guarded transition
+    /// counts as handling its input.
+    ///
+    /// # Returns
+    /// Returns the unhandled pairs, by state then input in declaration order
+    pub fn undefined_pairs() -> Vec<(SM::State, SM::Input)> {
+        let inputs = SM::inputs();
+        let mut pairs = Vec::new();
+        for state in SM::states() {
+            for input in &inputs {
+                if SM::next_state(&state, input).is_none()
+                    && !SM::is_ignored(&state, input)
+                    && !SM::is_deferred(&state, input)
+                {
+                    pairs.push((state.clone(), input.clone()));
+                }
+            }
+        }
+        pairs
+    }
+
+    /// Check if the machine handles every input in every state
+    ///
+    /// See [`undefined_pairs`](Self::undefined_pairs) for what counts as handled.
+    pub fn is_complete() -> bool {
+        Self::undefined_pairs().is_empty()
+    }
+
+    /// Get every state and input combination declared by conflicting rules
+    ///
+    /// A combination conflicts if `valid_inputs` lists the input more than once for
+    /// the state, or if its transition is also ignored or deferred. In both cases only
+    /// one of the rules can take effect. `define_state_machine!` rejects such
+    /// definitions at compile time, so this is mostly useful for hand-written machines.
+    ///
+    /// # Returns
+    /// Returns the conflicting pairs, by state in declaration order
+    pub fn conflicting_transitions() -> Vec<(SM::State, SM::Input)> {
+        let mut pairs = Vec::new();
+        for state in SM::states() {
+            let mut seen = HashSet::default();
+            for input in SM::valid_inputs_iter(&state) {
+                let input = SM::canonical_input(&input);
+                let shadowed = SM::next_state(&state, &input).is_some()
+                    && (SM::is_ignored(&state, &input) || SM::is_deferred(&state, &input));
+                if (!seen.insert(input.clone()) || shadowed)
+                    && !pairs.contains(&(state.clone(), input.clone()))
+                {
+                    pairs.push((state.clone(), input));
+                }
+            }
+        }
+        pairs
+    }
+
+    /// Enumerate all valid input sequences of length 1 to `max_len` starting from a state
+    ///
+    /// Sequences are produced lazily in depth-first order, so a sequence is always
+    /// followed by its extensions. Each sequence can be replayed on an instance in
+    /// `from` without any transition failing.
+    ///
+    /// # Arguments
+    /// - `from`: The starting state
+    /// - `max_len`: The maximum sequence length
+    ///
+    /// # Returns
+    /// Returns an iterator over input sequences
+    pub fn enumerate_input_sequences(from: &SM::State, max_len: usize) -> InputSequences<SM> {
+        InputSequences {
+            stack: vec![(from.clone(), Vec::new())],
+            max_len,
+        }
+    }
+
+    /// Explore the state space breadth-first from the initial state, up to a bound
+    ///
+    /// For machines whose states carry data, `states()` cannot list every state and
+    /// the state space may be infinite. The explorer instead follows `valid_inputs`
+    /// and `next_state` from the initial state and merges states that map to the same
+    /// abstract key, keeping the first one found as representative. Exploration stops
+    /// once `max_states` distinct abstract states have been found.
+    ///
+    /// # Arguments
+    /// - `max_states`: The maximum number of abstract states to visit
+    /// - `abstraction`: Maps a state to its abstract key, e.g. a payload bucket
+    ///
+    /// # Returns
+    /// Returns the explored abstract states with the shortest input path to each
+    pub fn explore<K, F>(max_states: usize, abstraction: F) -> Exploration<SM>
+    where
+        K: Hash + Eq,
+        F: Fn(&SM::State) -> K,
+    {
+        let initial = SM::initial_state();
+        let mut seen = HashSet::default();
+        seen.insert(abstraction(&initial));
+        let mut states = vec![(initial, Vec::new())];
+        let mut truncated = max_states == 0;
+        let mut transition_count = 0;
+
+        let mut next = 0;
+        while next < states.len() && max_states > 0 {
+            let (state, path) = states[next].clone();
+            next += 1;
+
+            for input in SM::valid_inputs_iter(&state) {
+                let Some(next_state) = SM::next_state(&state, &input) else {
+                    continue;
+                };
+                transition_count += 1;
+                if !seen.insert(abstraction(&next_state)) {
+                    continue;
+                }
+                if states.len() >= max_states {
+                    truncated = true;
+                    continue;
+                }
+
+                let mut extended = path.clone();
+                extended.push(input);
+                states.push((next_state, extended));
+            }
+        }
+        states.truncate(max_states);
+
+        Exploration {
+            states,
+            transition_count,
+            truncated,
+        }
+    }
+
+    /// Sample a random walk along the transitions of the machine
+    ///
+    /// Each step picks one of the inputs of the current state that lead to a next
+    /// state, uniformly at random. Guards are not evaluated, as no context is at hand.
+    /// The walk ends early in a state without outgoing transitions.
+    ///
+    /// # Arguments
+    /// - `from`: The starting state
+    /// - `steps`: The maximum number of steps
+    /// - `seed`: Seed of the random choices; the same seed yields the same walk
+    ///
+    /// # Returns
+    /// Returns the (source state, input) pair of every step taken
+    pub fn random_walk(from: &SM::State, steps: usize, seed: u64) -> Vec<(SM::State, SM::Input)> {
+        Self::random_walk_with(from, steps, WalkBias::Uniform, seed)
+    }
+
+    /// Sample a random walk, choosing inputs according to a bias
+    ///
+    /// Behaves like [`random_walk`](Self::random_walk), but with
+    /// [`WalkBias::AvoidRevisits`] the walk also ends once every transition leads back
+    /// to a visited state.
+    ///
+    /// # Arguments
+    /// - `from`: The starting state
+    /// - `steps`: The maximum number of steps
+    /// - `bias`: How inputs are chosen
+    /// - `seed`: Seed of the random choices
+    ///
+    /// # Returns
+    /// Returns the (source state, input) pair of every step taken
+    pub fn random_walk_with(
+        from: &SM::State,
+        steps: usize,
+        bias: WalkBias,
+        seed: u64,
+    ) -> Vec<(SM::State, SM::Input)> {
+        let mut rng = Rng::new(seed);
+        let mut walk: Vec<(SM::State, SM::Input)> = Vec::with_capacity(steps);
+        let mut visited = StateSet::<SM>::new();
+        visited.insert(from.clone());
+        let mut state = from.clone();
+
+        while walk.len() < steps {
+            let transitions: Vec<_> = SM::valid_inputs_iter(&state)
+                .filter_map(|input| SM::next_state(&state, &input).map(|to| (input, to)))
+                .collect();
+            let candidates: Vec<_> = match bias {
+                WalkBias::Uniform => transitions.iter().collect(),
+                WalkBias::AvoidRevisits => transitions
+                    .iter()
+                    .filter(|(_, to)| !visited.contains(to))
+                    .collect(),
+                WalkBias::PreferUnvisitedTransitions => {
+                    let untaken: Vec<_> = transitions
+                        .iter()
+                        .filter(|(input, _)| !walk.iter().any(|(s, i)| *s == state && i == input))
+                        .collect();
+                    if untaken.is_empty() {
+                        transitions.iter().collect()
+                    } else {
+                        untaken
+                    }
+                }
+            };
+            if candidates.is_empty() {
+                break;
+            }
+
+            let (input, to) = candidates[rng.index(candidates.len())].clone();
+            walk.push((state, input));
+            visited.insert(to.clone());
+            state = to;
+        }
+
+        walk
+    }
+
+    /// Find the declared states that behave the same and can be merged
+    ///
+    /// Two states are equivalent if every input is handled the same way in both,
+    /// transition or not, ignored, deferred, internal or compensating, with equal
+    /// outputs and timeouts, and leads to equivalent states. Final and accepting states
+    /// are only equivalent to states of the same kind. The classes are found by
+    /// partition refinement: states start out grouped by how they handle each input,
+    /// and groups are split until all members agree on the group of every successor.
+    /// Guards are not evaluated, as no context is at hand.
+    ///
+    /// # Returns
+    /// Returns the equivalence classes together with the quotient machine
+    pub fn minimize() -> Minimization<SM> {
+        let states = SM::states();
+        let inputs = SM::inputs();
+        let index: HashMap<SM::State, usize> = states
+            .iter()
+            .enumerate()
+            .map(|(index, state)| (state.clone(), index))
+            .collect();
+
+        // Local behavior of each state, and the successor of each input
+        let behavior: Vec<String> = states
+            .iter()
+            .map(|state| {
+                let handling: Vec<_> = inputs
+                    .iter()
+                    .map(|input| {
+                        (
+                            SM::valid_inputs_iter(state).any(|valid| &valid == input),
+                            SM::is_ignored(state, input),
+                            SM::is_deferred(state, input),
+                            SM::is_internal(state, input),
+                            SM::is_compensation(state, input),
+                            SM::output(state, input),
+                        )
+                    })
+                    .collect();
+                format!(
+                    "{handling:?} {:?} {} {}",
+                    SM::timeout(state),
+                    SM::is_final(state),
+                    SM::is_accepting(state)
+                )
+            })
+            .collect();
+        let successors: Vec<Vec<Option<usize>>> = states
+            .iter()
+            .map(|state| {
+                inputs
+                    .iter()
+                    .map(|input| {
+                        SM::next_state(state, input)
+                            .map(|to| index.get(&to).copied().unwrap_or(usize::MAX))
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let mut block = Self::number_blocks(&behavior);
+        loop {
+            let signatures: Vec<_> = (0..states.len())
+                .map(|state| {
+                    let targets: Vec<_> = successors[state]
+                        .iter()
+                        .map(|to| to.map(|to| block.get(to).copied()))
+                        .collect();
+                    (block[state], targets)
+                })
+                .collect();
+            let refined = Self::number_blocks(&signatures);
+            let stable = refined.iter().max() == block.iter().max();
+            block = refined;
+            if stable {
+                break;
+            }
+        }
+
+        let mut classes: Vec<Vec<SM::State>> = Vec::new();
+        for (state, block) in states.into_iter().zip(block) {
+            match classes.get_mut(block) {
+                Some(class) => class.push(state),
+                None => classes.push(vec![state]),
+            }
+        }
+        Minimization { classes }
+    }
+
+    /// Check whether another machine behaves the same as this one
+    ///
+    /// See [`distinguishing_sequence`](Self::distinguishing_sequence).
+    pub fn is_equivalent_to<Other: StateMachine>() -> bool {
+        Self::distinguishing_sequence::<Other>().is_none()
+    }
+
+    /// Find an input sequence on which another machine behaves differently
+    ///
+    /// Inputs of the two machines are matched by name, so a refactored machine can be
+    /// compared with the original even though its types differ. Both machines are run
+    /// side by side from their initial states. At every pair of states they reach, each
+    /// input must be handled the same way by both: rejected, ignored, deferred, or
+    /// taken as an internal or external transition. As the machines are deterministic,
+    /// this makes them bisimilar as well as trace equivalent. Guards and outputs are
+    /// not compared.
+    ///
+    /// # Returns
+    /// Returns the names of the shortest input sequence whose last input is handled
+    /// differently, or None if the machines are equivalent
+    pub fn distinguishing_sequence<Other: StateMachine>() -> Option<Vec<String>> {
+        let other_inputs: HashMap<String, Other::Input> = Other::inputs()
+            .into_iter()
+            .map(|input| (Other::input_name(&input), input))
+            .collect();
+        let mut names: Vec<String> = SM::inputs().iter().map(SM::input_name).collect();
+        for name in Other::inputs().iter().map(Other::input_name) {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        let inputs: HashMap<String, SM::Input> = SM::inputs()
+            .into_iter()
+            .map(|input| (SM::input_name(&input), input))
+            .collect();
+
+        let start = (SM::initial_state(), Other::initial_state());
+        let mut seen = HashSet::default();
+        seen.insert(start.clone());
+        let mut queue = alloc::collections::VecDeque::from([(start, Vec::new())]);
+
+        while let Some(((state, other_state), path)) = queue.pop_front() {
+            for name in &names {
+                let handling = inputs.get(name).map_or(Handling::Rejected, |input| {
+                    Handling::of::<SM>(&state, input)
+                });
+                let other_handling = other_inputs.get(name).map_or(Handling::Rejected, |input| {
+                    Handling::of::<Other>(&other_state, input)
+                });
+
+                let mut extended: Vec<String> = path.clone();
+                extended.push(name.clone());
+                match (handling, other_handling) {
+                    (
+                        Handling::Transition(to, internal),
+                        Handling::Transition(other_to, other_internal),
+                    ) if internal == other_internal => {
+                        if seen.insert((to.clone(), other_to.clone())) {
+                            queue.push_back(((to, other_to), extended));
+                        }
+                    }
+                    (Handling::Rejected, Handling::Rejected)
+                    | (Handling::Ignored, Handling::Ignored)
+                    | (Handling::Deferred, Handling::Deferred) => {}
+                    _ => return Some(extended),
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Number distinct keys in order of first appearance
+    fn number_blocks<K: Hash + Eq + Clone>(keys: &[K]) -> Vec<usize> {
+        let mut numbers = HashMap::default();
+        keys.iter()
+            .map(|key| {
+                let next = numbers.len();
+                *numbers.entry(key.clone()).or_insert(next)
+            })
+            .collect()
+    }
+}
+
+/// How a state handles an input, as compared by
+/// [`StateMachineQuery::distinguishing_sequence`]
+enum Handling<S> {
+    /// The input fails
+    Rejected,
+    /// The input is ignored by design
+    Ignored,
+    /// The input is deferred until the next state change
+    Deferred,
+    /// The input leads to a state, internally or not
+    Transition(S, bool),
+}
+
+impl<S> Handling<S> {
+    /// Get how a state of a machine handles an input
+    fn of<M: StateMachine<State = S>>(state: &S, input: &M::Input) -> Self {
+        if M::is_ignored(state, input) {
+            Self::Ignored
+        } else if M::is_deferred(state, input) {
+            Self::Deferred
+        } else if !M::valid_inputs_iter(state).any(|valid| &valid == input) {
+            Self::Rejected
+        } else {
+            match M::next_state(state, input) {
+                Some(to) => Self::Transition(to, M::is_internal(state, input)),
+                None => Self::Rejected,
+            }
+        }
+    }
+}
+
+/// Result of [`StateMachineQuery::minimize`]
+pub struct Minimization<SM: StateMachine> {
+    /// Classes of equivalent states, each in declaration order, ordered by first member
+    classes: Vec<Vec<SM::State>>,
+}
+
+impl<SM: StateMachine> Minimization<SM> {
+    /// Get the classes of equivalent states
+    ///
+    /// Classes are ordered by their first member, which represents the class in the
+    /// quotient machine. Members are in declaration order.
+    pub fn classes(&self) -> &[Vec<SM::State>] {
+        &self.classes
+    }
+
+    /// Iterate over the classes with more than one state, i.e. the states to merge
+    pub fn mergeable(&self) -> impl Iterator<Item = &[SM::State]> {
+        self.classes
+            .iter()
+            .filter(|class| class.len() > 1)
+            .map(Vec::as_slice)
+    }
+
+    /// Check whether no two states are equivalent
+    pub fn is_minimal(&self) -> bool {
+        self.mergeable().next().is_none()
+    }
+
+    /// Get the state representing the class of a state
+    pub fn representative(&self, state: &SM::State) -> Option<&SM::State> {
+        self.classes
+            .iter()
+            .find(|class| class.contains(state))
+            .map(|class| &class[0])
+    }
+
+    /// Describe the quotient machine, with one state per class
+    ///
+    /// Each class is named after its representative, and the transitions of the
+    /// representatives are redirected to the representatives of their targets. The
+    /// description can be built into a [`DynStateMachine`](crate::DynStateMachine).
+    pub fn quotient(&self) -> DynDefinition {
+        let representative =
+            |state: &SM::State| SM::state_name(self.representative(state).unwrap_or(state));
+        let mut transitions = Vec::new();
+        for class in &self.classes {
+            let state = &class[0];
+            for input in SM::valid_inputs_iter(state) {
+                if let Some(to) = SM::next_state(state, &input) {
+                    transitions.push(DynTransitionDefinition {
+                        from: SM::state_name(state),
+                        input: SM::input_name(&input),
+                        to: representative(&to),
+                    });
+                }
+            }
+        }
+
+        DynDefinition {
+            states: self
+                .classes
+                .iter()
+                .map(|class| SM::state_name(&class[0]))
+                .collect(),
+            inputs: SM::inputs().iter().map(SM::input_name).collect(),
+            initial: representative(&SM::initial_state()),
+            transitions,
+        }
+    }
+}
+
+impl<SM: StateMachine> core::fmt::Debug for Minimization<SM> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Minimization")
+            .field("classes", &self.classes)
+            .finish()
+    }
+}
+
+/// How [`StateMachineQuery::random_walk_with`] chooses among the inputs of a state
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WalkBias {
+    /// Every input leading to a next state is equally likely
+    #[default]
+    Uniform,
+    /// Only inputs leading to states not visited yet are chosen
+    AvoidRevisits,
+    /// Inputs not yet taken from the current state are chosen while there are any,
+    /// so the walk covers more transitions
+    PreferUnvisitedTransitions,
+}
+
+/// Result of [`StateMachineQuery::explore`]
+pub struct Exploration<SM: StateMachine> {
+    /// Representative of each abstract state, with the shortest input path to it
+    states: Vec<(SM::State, Vec<SM::Input>)>,
+    /// Number of transitions followed, including those into known abstract states
+    transition_count: usize,
+    /// Whether more abstract states were reachable than the bound allowed
+    truncated: bool,
+}
+
+impl<SM: StateMachine> Exploration<SM> {
+    /// Get the representatives of the explored abstract states, in discovery order
+    pub fn states(&self) -> impl Iterator<Item = &SM::State> {
+        self.states.iter().map(|(state, _)| state)
+    }
+
+    /// Get the number of explored abstract states
+    pub fn state_count(&self) -> usize {
+        self.states.len()
+    }
+
+    /// Get the number of transitions followed during exploration
+    pub fn transition_count(&self) -> usize {
+        self.transition_count
+    }
+
+    /// Check if exploration stopped at the bound before covering every abstract state
+    ///
+    /// If true, invariants that hold over the explored states may still be violated
+    /// further out.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Get the shortest input path from the initial state to an explored state
+    ///
+    /// Returns None if the state is not a representative found by the exploration.
+    pub fn path_to(&self, state: &SM::State) -> Option<&[SM::Input]> {
+        self.states
+            .iter()
+            .find(|(explored, _)| explored == state)
+            .map(|(_, path)| path.as_slice())
+    }
+
+    /// Check an invariant over the explored states
+    ///
+    /// # Arguments
+    /// - `invariant`: Returns true for states that satisfy the invariant
+    ///
+    /// # Returns
+    /// Returns the first violating state in discovery order together with the input
+    /// path that leads to it, or None if every explored state satisfies the invariant
+    pub fn find_violation<F>(&self, invariant: F) -> Option<(&SM::State, &[SM::Input])>
+    where
+        F: Fn(&SM::State) -> bool,
+    {
+        self.states
+            .iter()
+            .find(|(state, _)| !invariant(state))
+            .map(|(state, path)| (state, path.as_slice()))
+    }
+}
+
+impl<SM: StateMachine> core::fmt::Debug for Exploration<SM> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Exploration")
+            .field("states", &self.states)
+            .field("transition_count", &self.transition_count)
+            .field("truncated", &self.truncated)
+            .finish()
+    }
+}
+
+/// Iterator over valid input sequences, created by
+/// [`StateMachineQuery::enumerate_input_sequences`]
+pub struct InputSequences<SM: StateMachine> {
+    /// Pending (state reached, sequence so far) pairs
+    stack: Vec<(SM::State, Vec<SM::Input>)>,
+    /// Maximum sequence length
+    max_len: usize,
+}
+
+impl<SM: StateMachine> Iterator for InputSequences<SM> {
+    type Item = Vec<SM::Input>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((state, sequence)) = self.stack.pop() {
+            if sequence.len() < self.max_len {
+                let pushed_from = self.stack.len();
+                for input in SM::valid_inputs_iter(&state) {
+                    if let Some(next_state) = SM::next_state(&state, &input) {
+                        let mut extended = sequence.clone();
+                        extended.push(input);
+                        self.stack.push((next_state, extended));
+                    }
+                }
+                // Reverse the pushed entries so inputs are visited in declaration order
+                self.stack[pushed_from..].reverse();
+            }
+
+            if !sequence.is_empty() {
+                return Some(sequence);
+            }
+        }
+
+        None
+    }
 }
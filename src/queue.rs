@@ -0,0 +1,173 @@
+use crate::core::StateMachine;
+use alloc::collections::VecDeque;
+
+/// What happens to a queued input whose transition fails
+///
+/// Used by [`StateMachineInstance::process_one`](crate::StateMachineInstance::process_one)
+/// and [`StateMachineInstance::process_queue`](crate::StateMachineInstance::process_queue).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum InvalidInputPolicy {
+    /// Discard the input and stop processing, reporting the error
+    #[default]
+    Error,
+    /// Discard the input and carry on with the next one
+    Drop,
+    /// Set the input aside and queue it again after the next state change
+    Defer,
+}
+
+/// FIFO queue of pending inputs that applies the machine's coalescing rules
+///
+/// High-frequency producers often post redundant inputs. When an input is pushed:
+/// - queued inputs it supersedes (see [`StateMachine::supersedes`]) are dropped
+/// - if it coalesces (see [`StateMachine::coalesces`]) and the most recently queued
+///   input is of the same kind, it replaces that input instead of being appended
+///
+/// Both rules are declared in the `queue` block of `define_state_machine!`.
+pub struct InputQueue<SM: StateMachine> {
+    /// Pending inputs, oldest first
+    inputs: VecDeque<SM::Input>,
+    /// Number of inputs dropped or merged by coalescing rules
+    coalesced_count: usize,
+}
+
+impl<SM: StateMachine> Default for InputQueue<SM> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<SM: StateMachine> InputQueue<SM> {
+    /// Create an empty queue
+    pub fn new() -> Self {
+        Self {
+            inputs: VecDeque::new(),
+            coalesced_count: 0,
+        }
+    }
+
+    /// Queue an input, applying the coalescing rules
+    ///
+    /// # Returns
+    /// Returns true if the queue grew, false if the input was merged into the last one
+    pub fn push(&mut self, input: SM::Input) -> bool {
+        // Drop queued inputs superseded by the new one
+        let before = self.inputs.len();
+        self.inputs.retain(|queued| !SM::supersedes(&input, queued));
+        self.coalesced_count += before - self.inputs.len();
+
+        // Merge with an identical predecessor, keeping the newest payload
+        if SM::coalesces(&input)
+            && let Some(last) = self.inputs.back_mut()
+            && SM::canonical_input(last) == SM::canonical_input(&input)
+        {
+            *last = input;
+            self.coalesced_count += 1;
+            return false;
+        }
+
+        self.inputs.push_back(input);
+        true
+    }
+
+    /// Put an input at the front of the queue, bypassing the coalescing rules
+    pub(crate) fn push_front(&mut self, input: SM::Input) {
+        self.inputs.push_front(input);
+    }
+
+    /// Take the oldest queued input
+    pub fn pop(&mut self) -> Option<SM::Input> {
+        self.inputs.pop_front()
+    }
+
+    /// Look at the oldest queued input without removing it
+    pub fn peek(&self) -> Option<&SM::Input> {
+        self.inputs.front()
+    }
+
+    /// Get the number of queued inputs
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    /// Check if the queue is empty
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+
+    /// Remove all queued inputs
+    pub fn clear(&mut self) {
+        self.inputs.clear();
+    }
+
+    /// Iterate over the queued inputs, oldest first
+    pub fn iter(&self) -> impl Iterator<Item = &SM::Input> {
+        self.inputs.iter()
+    }
+
+    /// Get the number of inputs dropped or merged by coalescing rules so far
+    pub fn coalesced_count(&self) -> usize {
+        self.coalesced_count
+    }
+}
+
+impl<SM: StateMachine> core::fmt::Debug for InputQueue<SM> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("InputQueue")
+            .field("inputs", &self.inputs)
+            .field("coalesced_count", &self.coalesced_count)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    define_state_machine! {
+        name: Sensor,
+        states: { Normal, Alarm },
+        inputs: { Timer, Emergency, Reading(value: u32), _Log },
+        initial: Normal,
+        transitions: {
+            Normal + Timer => Normal,
+            Normal + Reading => Normal,
+            Normal + Emergency => Alarm,
+            Normal + _Log => Normal,
+        },
+        queue: {
+            coalesce _Log,
+            coalesce Reading,
+            Emergency supersedes Timer,
+            Emergency supersedes _Log,
+        }
+    }
+
+    #[test]
+    fn test_coalescing_rules() {
+        let mut queue = InputQueue::<Sensor>::new();
+
+        assert!(queue.push(Input::_Log));
+        assert!(!queue.push(Input::_Log));
+        assert!(queue.push(Input::Timer));
+        assert!(queue.push(Input::_Log));
+        assert_eq!(queue.len(), 3);
+
+        // Coalescing keeps the newest payload
+        assert!(queue.push(Input::Reading(1)));
+        assert!(!queue.push(Input::Reading(2)));
+        assert_eq!(queue.len(), 4);
+
+        // Emergency drops every queued Timer and _Log
+        assert!(queue.push(Input::Emergency));
+        assert_eq!(
+            queue.iter().cloned().collect::<Vec<_>>(),
+            vec![Input::Reading(2), Input::Emergency]
+        );
+        assert_eq!(queue.coalesced_count(), 5);
+
+        assert_eq!(queue.pop(), Some(Input::Reading(2)));
+        assert_eq!(queue.peek(), Some(&Input::Emergency));
+    }
+}
@@ -0,0 +1,337 @@
+use crate::core::StateMachine;
+use crate::hashing::HashMap;
+use crate::query::StateMachineQuery;
+use alloc::collections::VecDeque;
+use alloc::{format, string::String, vec::Vec};
+
+/// Analysis results for a single state machine
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MachineReport {
+    /// The name the machine was registered under
+    pub name: String,
+    /// Number of states
+    pub state_count: usize,
+    /// Number of input types
+    pub input_count: usize,
+    /// Number of transitions, including self-loops
+    pub transition_count: usize,
+    /// States that cannot be reached from the initial state
+    pub unreachable_states: Vec<String>,
+    /// Lint warnings, one message per finding
+    pub warnings: Vec<String>,
+    /// Longest shortest path between any two states, in transitions
+    pub diameter: usize,
+}
+
+impl MachineReport {
+    /// Analyze a state machine
+    ///
+    /// # Arguments
+    /// - `name`: The name to report the machine under
+    pub fn analyze<SM: StateMachine>(name: impl Into<String>) -> Self {
+        let states = SM::states();
+        let inputs = SM::inputs();
+
+        let unreachable_states: Vec<_> = StateMachineQuery::<SM>::unreachable_states()
+            .iter()
+            .map(|state| SM::state_name(&state))
+            .collect();
+        let transition_count = states
+            .iter()
+            .map(|state| {
+                SM::valid_inputs_iter(state)
+                    .filter(|input| SM::next_state(state, input).is_some())
+                    .count()
+            })
+            .sum();
+
+        let mut warnings: Vec<_> = unreachable_states
+            .iter()
+            .map(|state| format!("state {state} is unreachable from the initial state"))
+            .collect();
+        warnings.extend(
+            StateMachineQuery::<SM>::unused_inputs()
+                .iter()
+                .map(|input| format!("input {} triggers no transition", SM::input_name(input))),
+        );
+
+        let diameter = states
+            .iter()
+            .map(|state| Self::eccentricity::<SM>(state))
+            .max()
+            .unwrap_or(0);
+
+        Self {
+            name: name.into(),
+            state_count: states.len(),
+            input_count: inputs.len(),
+            transition_count,
+            unreachable_states,
+            warnings,
+            diameter,
+        }
+    }
+
+    /// Get the largest shortest-path distance from a state to any state it can reach
+    fn eccentricity<SM: StateMachine>(from: &SM::State) -> usize {
+        let mut distances = HashMap::default();
+        let mut queue = VecDeque::new();
+        distances.insert(from.clone(), 0);
+        queue.push_back(from.clone());
+
+        while let Some(current) = queue.pop_front() {
+            let distance = distances[&current];
+            for input in SM::valid_inputs_iter(&current) {
+                if let Some(next_state) = SM::next_state(&current, &input)
+                    && !distances.contains_key(&next_state)
+                {
+                    distances.insert(next_state.clone(), distance + 1);
+                    queue.push_back(next_state);
+                }
+            }
+        }
+
+        distances.into_values().max().unwrap_or(0)
+    }
+}
+
+/// Cross-machine report produced by [`Registry::analyze_all`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RegistryReport {
+    /// Per-machine results, in registration order
+    pub machines: Vec<MachineReport>,
+}
+
+impl RegistryReport {
+    /// Get the total number of states across all machines
+    pub fn total_states(&self) -> usize {
+        self.machines
+            .iter()
+            .map(|machine| machine.state_count)
+            .sum()
+    }
+
+    /// Get the total number of lint warnings across all machines
+    pub fn total_warnings(&self) -> usize {
+        self.machines
+            .iter()
+            .map(|machine| machine.warnings.len())
+            .sum()
+    }
+
+    /// Get the machines that have unreachable states
+    pub fn machines_with_unreachable_states(&self) -> Vec<&MachineReport> {
+        self.machines
+            .iter()
+            .filter(|machine| !machine.unreachable_states.is_empty())
+            .collect()
+    }
+
+    /// Get the machine with the largest diameter
+    ///
+    /// Ties go to the machine registered first.
+    pub fn largest_diameter(&self) -> Option<&MachineReport> {
+        self.machines
+            .iter()
+            .rev()
+            .max_by_key(|machine| machine.diameter)
+    }
+
+    /// Render the report as Markdown
+    pub fn to_markdown(&self) -> String {
+        let mut markdown = String::from("# State Machine Registry Report\n\n");
+
+        markdown.push_str(&format!(
+            "- **Machines**: {}\n\
+            - **Total States**: {}\n\
+            - **Total Warnings**: {}\n\
+            - **Machines with Unreachable States**: {}\n",
+            self.machines.len(),
+            self.total_states(),
+            self.total_warnings(),
+            self.machines_with_unreachable_states().len()
+        ));
+        if let Some(machine) = self.largest_diameter() {
+            markdown.push_str(&format!(
+                "- **Largest Diameter**: {} ({})\n",
+                machine.diameter, machine.name
+            ));
+        }
+
+        markdown.push_str(
+            "\n| Machine | States | Inputs | Transitions | Diameter | Warnings |\n\
+            |---------|--------|--------|-------------|----------|----------|\n",
+        );
+        for machine in &self.machines {
+            markdown.push_str(&format!(
+                "| {} | {} | {} | {} | {} | {} |\n",
+                machine.name,
+                machine.state_count,
+                machine.input_count,
+                machine.transition_count,
+                machine.diameter,
+                machine.warnings.len()
+            ));
+        }
+
+        for machine in self.machines.iter().filter(|m| !m.warnings.is_empty()) {
+            markdown.push_str(&format!("\n## {}\n\n", machine.name));
+            for warning in &machine.warnings {
+                markdown.push_str(&format!("- {warning}\n"));
+            }
+        }
+
+        markdown
+    }
+
+    /// Render the report as pretty-printed JSON
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("registry report is always serializable")
+    }
+}
+
+/// Analysis entry point of a registered machine, taking the name to report under
+type AnalyzeFn = fn(String) -> MachineReport;
+
+/// Collection of state machine types for bulk analysis
+///
+/// Machines are registered by type; the analysis runs on demand, so registering
+/// is cheap.
+///
+/// # Example
+/// ```ignore
+/// let mut registry = Registry::new();
+/// registry.register::<door::Door>("door");
+/// registry.register::<order::Order>("order");
+/// println!("{}", registry.analyze_all().to_markdown());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Registry {
+    /// Registered machines with their analysis entry points
+    machines: Vec<(String, AnalyzeFn)>,
+}
+
+impl Registry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a state machine type under a name
+    pub fn register<SM: StateMachine>(&mut self, name: impl Into<String>) -> &mut Self {
+        self.machines
+            .push((name.into(), MachineReport::analyze::<SM>));
+        self
+    }
+
+    /// Get the names of the registered machines, in registration order
+    pub fn names(&self) -> Vec<&str> {
+        self.machines
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+
+    /// Get the number of registered machines
+    pub fn len(&self) -> usize {
+        self.machines.len()
+    }
+
+    /// Check if no machine is registered
+    pub fn is_empty(&self) -> bool {
+        self.machines.is_empty()
+    }
+
+    /// Analyze every registered machine
+    pub fn analyze_all(&self) -> RegistryReport {
+        RegistryReport {
+            machines: self
+                .machines
+                .iter()
+                .map(|(name, analyze)| analyze(name.clone()))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    define_state_machine! {
+        name: Door,
+        module: door,
+        states: { Closed, Open, Broken },
+        inputs: { Push, Pull, Kick },
+        initial: Closed,
+        transitions: {
+            Closed + Push => Open,
+            Open + Pull => Closed,
+            Broken + Pull => Broken
+        }
+    }
+
+    define_state_machine! {
+        name: Pipeline,
+        module: pipeline,
+        states: { Queued, Building, Testing, Done },
+        inputs: { Next },
+        initial: Queued,
+        transitions: {
+            Queued + Next => Building,
+            Building + Next => Testing,
+            Testing + Next => Done
+        }
+    }
+
+    #[test]
+    fn test_analyze_all() {
+        let mut registry = Registry::new();
+        registry
+            .register::<door::Door>("door")
+            .register::<pipeline::Pipeline>("pipeline");
+        assert_eq!(registry.names(), vec!["door", "pipeline"]);
+
+        let report = registry.analyze_all();
+        assert_eq!(report.total_states(), 7);
+
+        let door = &report.machines[0];
+        assert_eq!(door.unreachable_states, vec!["Broken"]);
+        assert_eq!(
+            door.warnings,
+            vec![
+                "state Broken is unreachable from the initial state",
+                "input Kick triggers no transition"
+            ]
+        );
+        assert_eq!(door.transition_count, 3);
+        assert_eq!(report.machines_with_unreachable_states(), vec![door]);
+
+        let largest = report.largest_diameter().unwrap();
+        assert_eq!((largest.name.as_str(), largest.diameter), ("pipeline", 3));
+
+        let markdown = report.to_markdown();
+        assert!(markdown.contains("- **Total States**: 7"));
+        assert!(markdown.contains("| pipeline | 4 | 1 | 3 | 3 | 0 |"));
+        assert!(markdown.contains("## door\n\n- state Broken is unreachable"));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_report_json() {
+        let mut registry = Registry::new();
+        registry.register::<pipeline::Pipeline>("pipeline");
+        let report = registry.analyze_all();
+
+        let json = report.to_json();
+        assert!(json.contains("\"diameter\": 3"));
+        assert_eq!(
+            serde_json::from_str::<RegistryReport>(&json).unwrap(),
+            report
+        );
+    }
+}
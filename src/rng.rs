@@ -0,0 +1,94 @@
+#[cfg(feature = "std")]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Seedable xorshift generator behind the randomized helpers of the crate
+///
+/// Not suitable for cryptography. The same seed always yields the same sequence, so
+/// runs seeded explicitly are reproducible.
+#[derive(Debug, Clone)]
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    /// Create a generator, scrambling the seed so that small seeds differ early
+    pub(crate) fn new(seed: u64) -> Self {
+        Self((seed ^ 0x9e37_79b9_7f4a_7c15).max(1))
+    }
+
+    /// Create a generator seeded from the system clock
+    #[cfg(feature = "std")]
+    pub(crate) fn from_time() -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos() as u64);
+        Self::new(seed)
+    }
+
+    /// Draw the next raw value
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Draw a uniformly distributed value in `[0, 1)`
+    pub(crate) fn next_unit(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Draw a uniformly distributed value below `bound`
+    ///
+    /// Raw values from the incomplete last block of `bound` values are redrawn, so
+    /// every result is equally likely.
+    ///
+    /// # Panics
+    /// Panics if `bound` is 0
+    pub(crate) fn below(&mut self, bound: u64) -> u64 {
+        assert!(bound > 0, "cannot draw below 0");
+        // 2^64 mod bound: the number of raw values left over after whole blocks
+        let rejected = bound.wrapping_neg() % bound;
+        loop {
+            let value = self.next_u64();
+            if value >= rejected {
+                return value % bound;
+            }
+        }
+    }
+
+    /// Draw a uniformly distributed index below `len`
+    ///
+    /// # Panics
+    /// Panics if `len` is 0
+    pub(crate) fn index(&mut self, len: usize) -> usize {
+        self.below(len as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seeded_sequences() {
+        let mut a = Rng::new(7);
+        let mut b = Rng::new(7);
+        let mut c = Rng::new(8);
+        let drawn: Vec<_> = (0..4).map(|_| a.next_u64()).collect();
+        assert_eq!(drawn, (0..4).map(|_| b.next_u64()).collect::<Vec<_>>());
+        assert_ne!(drawn, (0..4).map(|_| c.next_u64()).collect::<Vec<_>>());
+        assert_ne!(Rng::new(0).next_u64(), 0);
+    }
+
+    #[test]
+    fn test_ranges() {
+        let mut rng = Rng::new(42);
+        let mut counts = [0usize; 3];
+        for _ in 0..30_000 {
+            counts[rng.index(3)] += 1;
+        }
+        assert!(counts.iter().all(|count| (9_000..11_000).contains(count)));
+        assert_eq!(rng.below(1), 0);
+        assert!(rng.below(u64::MAX) < u64::MAX);
+        assert!((0.0..1.0).contains(&rng.next_unit()));
+    }
+}
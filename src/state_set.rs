@@ -0,0 +1,350 @@
+use crate::core::StateMachine;
+use alloc::vec::Vec;
+use core::ops::{BitAnd, BitOr, Not, Sub};
+
+/// Set of states of a machine, as returned by [`StateMachineQuery`](crate::StateMachineQuery)
+///
+/// Declared states are stored as bits indexed by
+/// [`state_index`](StateMachine::state_index), so membership and the set operations
+/// don't hash or compare states. States that aren't declared, which only hand-written
+/// machines can produce, are kept in a list next to the bits. Iteration yields declared
+/// states in declaration order, followed by undeclared states in insertion order.
+///
+/// # Example
+/// ```ignore
+/// let reachable = StateMachineQuery::<Order>::reachable_states(&State::Created);
+/// let terminal = StateMachineQuery::<Order>::terminal_states();
+/// let in_progress = reachable & !terminal;
+/// assert!(in_progress.contains(&State::Paid));
+/// ```
+pub struct StateSet<SM: StateMachine> {
+    /// Bits of the declared states, by index
+    bits: Vec<u64>,
+    /// Members that aren't declared states
+    extra: Vec<SM::State>,
+}
+
+impl<SM: StateMachine> StateSet<SM> {
+    /// Create an empty set
+    pub fn new() -> Self {
+        Self {
+            bits: Vec::new(),
+            extra: Vec::new(),
+        }
+    }
+
+    /// Create the set of all declared states
+    pub fn all() -> Self {
+        SM::states().into_iter().collect()
+    }
+
+    /// Add a state to the set
+    ///
+    /// # Returns
+    /// Returns true if the state was not in the set yet
+    pub fn insert(&mut self, state: SM::State) -> bool {
+        match SM::state_index(&state) {
+            Some(index) => {
+                let (word, bit) = (index / 64, 1u64 << (index % 64));
+                if self.bits.len() <= word {
+                    self.bits.resize(word + 1, 0);
+                }
+                let added = self.bits[word] & bit == 0;
+                self.bits[word] |= bit;
+                added
+            }
+            None if self.extra.contains(&state) => false,
+            None => {
+                self.extra.push(state);
+                true
+            }
+        }
+    }
+
+    /// Remove a state from the set
+    ///
+    /// # Returns
+    /// Returns true if the state was in the set
+    pub fn remove(&mut self, state: &SM::State) -> bool {
+        match SM::state_index(state) {
+            Some(index) => {
+                let (word, bit) = (index / 64, 1u64 << (index % 64));
+                match self.bits.get_mut(word) {
+                    Some(bits) if *bits & bit != 0 => {
+                        *bits &= !bit;
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            None => {
+                let len = self.extra.len();
+                self.extra.retain(|member| member != state);
+                self.extra.len() != len
+            }
+        }
+    }
+
+    /// Check whether a state is in the set
+    pub fn contains(&self, state: &SM::State) -> bool {
+        match SM::state_index(state) {
+            Some(index) => self
+                .bits
+                .get(index / 64)
+                .is_some_and(|bits| bits & (1u64 << (index % 64)) != 0),
+            None => self.extra.contains(state),
+        }
+    }
+
+    /// Get the number of states in the set
+    pub fn len(&self) -> usize {
+        self.bits
+            .iter()
+            .map(|bits| bits.count_ones() as usize)
+            .sum::<usize>()
+            + self.extra.len()
+    }
+
+    /// Check whether the set is empty
+    pub fn is_empty(&self) -> bool {
+        self.extra.is_empty() && self.bits.iter().all(|bits| *bits == 0)
+    }
+
+    /// Iterate over the states in the set, declared states first in declaration order
+    pub fn iter(&self) -> alloc::vec::IntoIter<SM::State> {
+        self.to_vec().into_iter()
+    }
+
+    /// Collect the states in the set, declared states first in declaration order
+    pub fn to_vec(&self) -> Vec<SM::State> {
+        let mut states: Vec<_> = if self.is_empty() {
+            Vec::new()
+        } else {
+            SM::states()
+                .into_iter()
+                .filter(|state| self.contains(state))
+                .collect()
+        };
+        states.extend(self.extra.iter().cloned());
+        states
+    }
+
+    /// Get the states in either set
+    pub fn union(&self, other: &Self) -> Self {
+        let len = self.bits.len().max(other.bits.len());
+        let bits = (0..len)
+            .map(|word| self.word(word) | other.word(word))
+            .collect();
+        let mut extra = self.extra.clone();
+        extra.extend(
+            other
+                .extra
+                .iter()
+                .filter(|state| !self.extra.contains(state))
+                .cloned(),
+        );
+        Self { bits, extra }
+    }
+
+    /// Get the states in both sets
+    pub fn intersection(&self, other: &Self) -> Self {
+        let len = self.bits.len().min(other.bits.len());
+        let bits = (0..len)
+            .map(|word| self.word(word) & other.word(word))
+            .collect();
+        let extra = self
+            .extra
+            .iter()
+            .filter(|state| other.extra.contains(state))
+            .cloned()
+            .collect();
+        Self { bits, extra }
+    }
+
+    /// Get the states in this set but not in the other
+    pub fn difference(&self, other: &Self) -> Self {
+        let bits = (0..self.bits.len())
+            .map(|word| self.word(word) & !other.word(word))
+            .collect();
+        let extra = self
+            .extra
+            .iter()
+            .filter(|state| !other.extra.contains(state))
+            .cloned()
+            .collect();
+        Self { bits, extra }
+    }
+
+    /// Get the declared states not in the set
+    ///
+    /// Undeclared states are never part of the complement.
+    pub fn complement(&self) -> Self {
+        Self::all().difference(self)
+    }
+
+    /// Check whether every state of this set is in the other
+    pub fn is_subset(&self, other: &Self) -> bool {
+        (0..self.bits.len()).all(|word| self.word(word) & !other.word(word) == 0)
+            && self.extra.iter().all(|state| other.extra.contains(state))
+    }
+
+    /// Get a word of the bits, zero past the end
+    fn word(&self, word: usize) -> u64 {
+        self.bits.get(word).copied().unwrap_or(0)
+    }
+}
+
+impl<SM: StateMachine> Default for StateSet<SM> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<SM: StateMachine> Clone for StateSet<SM> {
+    fn clone(&self) -> Self {
+        Self {
+            bits: self.bits.clone(),
+            extra: self.extra.clone(),
+        }
+    }
+}
+
+impl<SM: StateMachine> PartialEq for StateSet<SM> {
+    fn eq(&self, other: &Self) -> bool {
+        self.is_subset(other) && other.is_subset(self)
+    }
+}
+
+impl<SM: StateMachine> Eq for StateSet<SM> {}
+
+impl<SM: StateMachine> core::fmt::Debug for StateSet<SM> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_set().entries(self.to_vec()).finish()
+    }
+}
+
+impl<SM: StateMachine> FromIterator<SM::State> for StateSet<SM> {
+    fn from_iter<I: IntoIterator<Item = SM::State>>(iter: I) -> Self {
+        let mut set = Self::new();
+        set.extend(iter);
+        set
+    }
+}
+
+impl<SM: StateMachine> Extend<SM::State> for StateSet<SM> {
+    fn extend<I: IntoIterator<Item = SM::State>>(&mut self, iter: I) {
+        for state in iter {
+            self.insert(state);
+        }
+    }
+}
+
+impl<SM: StateMachine> IntoIterator for StateSet<SM> {
+    type Item = SM::State;
+    type IntoIter = alloc::vec::IntoIter<SM::State>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.to_vec().into_iter()
+    }
+}
+
+impl<SM: StateMachine> IntoIterator for &StateSet<SM> {
+    type Item = SM::State;
+    type IntoIter = alloc::vec::IntoIter<SM::State>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<SM: StateMachine> BitOr for StateSet<SM> {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        self.union(&other)
+    }
+}
+
+impl<SM: StateMachine> BitAnd for StateSet<SM> {
+    type Output = Self;
+
+    fn bitand(self, other: Self) -> Self {
+        self.intersection(&other)
+    }
+}
+
+impl<SM: StateMachine> Sub for StateSet<SM> {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        self.difference(&other)
+    }
+}
+
+impl<SM: StateMachine> Not for StateSet<SM> {
+    type Output = Self;
+
+    fn not(self) -> Self {
+        self.complement()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    define_state_machine! {
+        name: Order,
+        states: { Created, Paid, Shipped, Delivered, Cancelled },
+        inputs: { Pay, Ship, Deliver, Cancel },
+        initial: Created,
+        transitions: {
+            Created + Pay => Paid,
+            Created + Cancel => Cancelled,
+            Paid + Ship => Shipped,
+            Shipped + Deliver => Delivered,
+        }
+    }
+
+    #[test]
+    fn test_state_set_operations() {
+        let mut set = StateSet::<Order>::new();
+        assert!(set.is_empty());
+        assert!(set.insert(State::Shipped));
+        assert!(set.insert(State::Created));
+        assert!(!set.insert(State::Created));
+        assert_eq!(set.len(), 2);
+        assert!(set.contains(&State::Created));
+        assert!(!set.contains(&State::Paid));
+        // Iteration follows declaration order, not insertion order
+        assert_eq!(set.to_vec(), vec![State::Created, State::Shipped]);
+        assert_eq!(format!("{set:?}"), "{Created, Shipped}");
+
+        let complement = !set.clone();
+        assert_eq!(
+            complement.to_vec(),
+            vec![State::Paid, State::Delivered, State::Cancelled]
+        );
+        assert_eq!(set.clone() | complement.clone(), StateSet::all());
+        assert!((set.clone() & complement).is_empty());
+
+        let other: StateSet<Order> = [State::Shipped, State::Paid].into_iter().collect();
+        assert_eq!((set.clone() & other.clone()).to_vec(), vec![State::Shipped]);
+        assert_eq!((set.clone() - other.clone()).to_vec(), vec![State::Created]);
+        assert!(!set.is_subset(&other));
+        assert!((set.clone() & other).is_subset(&set));
+
+        assert!(set.remove(&State::Shipped));
+        assert!(!set.remove(&State::Shipped));
+        assert_eq!(set.into_iter().collect::<Vec<_>>(), vec![State::Created]);
+    }
+
+    #[test]
+    fn test_composite_queries() {
+        let reachable = StateMachineQuery::<Order>::reachable_states(&State::Paid);
+        let terminal = StateMachineQuery::<Order>::terminal_states();
+        let in_progress = reachable & !terminal;
+        assert_eq!(in_progress.to_vec(), vec![State::Paid, State::Shipped]);
+    }
+}
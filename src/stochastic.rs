@@ -0,0 +1,310 @@
+//! Monte-Carlo simulation of a machine driven by random inputs
+//!
+//! A [`StochasticInstance`] wraps an instance and feeds it inputs picked at random,
+//! each enabled transition being as likely as its [`weight`](StateMachine::weight).
+//! With weights modelling how users behave, many simulated runs estimate how likely a
+//! state is to be reached and how many steps it takes:
+//!
+//! ```ignore
+//! let mut order = StochasticInstance::new(StateMachineInstance::<Order>::new(), 42);
+//! let report = order.simulate(&State::Delivered, 10_000, 100);
+//! println!("{:.0}% delivered after {:.1} steps", report.reach_probability() * 100.0, report.mean_steps().unwrap());
+//! ```
+
+use crate::core::StateMachine;
+use crate::error::TransitionError;
+use crate::instance::StateMachineInstance;
+use crate::rng::Rng;
+use alloc::vec::Vec;
+
+/// Instance wrapper picking its own inputs at random, by transition weight
+///
+/// Each step considers the valid inputs of the current state that lead to a next
+/// state and whose guard accepts the instance context, and picks one with probability
+/// proportional to its weight. Transitions with weight 0 are never picked. Picks are
+/// reproducible: the same seed on the same instance yields the same inputs.
+pub struct StochasticInstance<SM: StateMachine> {
+    /// The wrapped instance
+    instance: StateMachineInstance<SM>,
+    /// Source of the picks
+    rng: Rng,
+}
+
+impl<SM: StateMachine> StochasticInstance<SM> {
+    /// Wrap an instance
+    ///
+    /// # Arguments
+    /// - `instance`: The instance to drive
+    /// - `seed`: Seed of the random picks
+    pub fn new(instance: StateMachineInstance<SM>, seed: u64) -> Self {
+        Self {
+            instance,
+            rng: Rng::new(seed),
+        }
+    }
+
+    /// Get a read-only reference to the wrapped instance
+    pub fn instance(&self) -> &StateMachineInstance<SM> {
+        &self.instance
+    }
+
+    /// Get a mutable reference to the wrapped instance, e.g. to register callbacks
+    pub fn instance_mut(&mut self) -> &mut StateMachineInstance<SM> {
+        &mut self.instance
+    }
+
+    /// Unwrap the instance
+    pub fn into_inner(self) -> StateMachineInstance<SM> {
+        self.instance
+    }
+
+    /// Get the current state of the wrapped instance
+    pub fn current_state(&self) -> &SM::State {
+        self.instance.current_state()
+    }
+
+    /// Pick an enabled input by weight, without applying it
+    ///
+    /// # Returns
+    /// Returns the picked input, or None if no transition with a positive weight is
+    /// enabled in the current state
+    pub fn pick(&mut self) -> Option<SM::Input> {
+        let state = self.instance.current_state();
+        let candidates: Vec<(SM::Input, u64)> = SM::valid_inputs_iter(state)
+            .filter(|input| {
+                SM::next_state(state, input).is_some()
+                    && SM::check_guard(
+                        state,
+                        input,
+                        self.instance.context(),
+                        self.instance.config(),
+                    )
+            })
+            .map(|input| {
+                let weight = u64::from(SM::weight(state, &input));
+                (input, weight)
+            })
+            .filter(|(_, weight)| *weight > 0)
+            .collect();
+
+        let total: u64 = candidates.iter().map(|(_, weight)| weight).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut roll = self.rng.below(total);
+        for (input, weight) in candidates {
+            if roll < weight {
+                return Some(input);
+            }
+            roll -= weight;
+        }
+        None
+    }
+
+    /// Pick an enabled input by weight and apply it
+    ///
+    /// # Returns
+    /// - `Ok(Some(input))`: The input was picked and the transition succeeded
+    /// - `Ok(None)`: No transition is enabled, the instance is left unchanged
+    /// - `Err(error)`: The transition failed, e.g. vetoed by a callback
+    pub fn step(&mut self) -> Result<Option<SM::Input>, TransitionError<SM>> {
+        let Some(input) = self.pick() else {
+            return Ok(None);
+        };
+        self.instance.transition(input.clone())?;
+        Ok(Some(input))
+    }
+
+    /// Step until a state is reached
+    ///
+    /// # Arguments
+    /// - `target`: The state to reach
+    /// - `max_steps`: The maximum number of steps
+    ///
+    /// # Returns
+    /// Returns the number of steps taken, 0 if the instance is in the target state
+    /// already, or None if the target was not reached within `max_steps`, no
+    /// transition was enabled or a transition failed
+    pub fn run_until(&mut self, target: &SM::State, max_steps: usize) -> Option<usize> {
+        for steps in 0..=max_steps {
+            if self.instance.current_state() == target {
+                return Some(steps);
+            }
+            if steps == max_steps || !matches!(self.step(), Ok(Some(_))) {
+                break;
+            }
+        }
+        None
+    }
+
+    /// Estimate by simulation how likely and how quickly a state is reached
+    ///
+    /// Every run [`reset`](StateMachineInstance::reset)s the instance and then steps
+    /// until the target is reached or the run ends as in [`run_until`](Self::run_until).
+    /// Time is measured in transitions taken.
+    ///
+    /// # Arguments
+    /// - `target`: The state to reach
+    /// - `runs`: The number of runs to simulate
+    /// - `max_steps`: The maximum number of steps of each run
+    ///
+    /// # Returns
+    /// Returns the outcome of the runs
+    pub fn simulate(
+        &mut self,
+        target: &SM::State,
+        runs: usize,
+        max_steps: usize,
+    ) -> SimulationReport {
+        let mut report = SimulationReport {
+            runs,
+            reached: 0,
+            total_steps: 0,
+        };
+        for _ in 0..runs {
+            self.instance.reset();
+            if let Some(steps) = self.run_until(target, max_steps) {
+                report.reached += 1;
+                report.total_steps += steps;
+            }
+        }
+        report
+    }
+}
+
+impl<SM: StateMachine> core::fmt::Debug for StochasticInstance<SM> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("StochasticInstance")
+            .field("instance", &self.instance)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Outcome of [`StochasticInstance::simulate`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SimulationReport {
+    /// Number of runs simulated
+    pub runs: usize,
+    /// Number of runs that reached the target
+    pub reached: usize,
+    /// Steps taken by the runs that reached the target, summed
+    pub total_steps: usize,
+}
+
+impl SimulationReport {
+    /// Get the share of runs that reached the target, 0 if there were no runs
+    pub fn reach_probability(&self) -> f64 {
+        if self.runs == 0 {
+            0.0
+        } else {
+            self.reached as f64 / self.runs as f64
+        }
+    }
+
+    /// Get the mean number of steps of the runs that reached the target
+    ///
+    /// # Returns
+    /// Returns None if no run reached the target
+    pub fn mean_steps(&self) -> Option<f64> {
+        (self.reached > 0).then(|| self.total_steps as f64 / self.reached as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    define_state_machine! {
+        name: Order,
+        states: { Placed, Shipped, Lost, Delivered },
+        inputs: { Ship, Deliver, Lose, Resend, Poke },
+        initial: Placed,
+        context: bool,
+        transitions: {
+            Placed + Ship => Shipped,
+            Shipped + Deliver => Delivered,
+            Shipped + Lose => Lost,
+            Lost + Resend => Shipped,
+            Placed + Poke => internal if |blocked| !*blocked,
+        },
+        weights: {
+            Shipped + Deliver => 3,
+            Shipped + Lose => 1,
+            Placed + Poke => 0,
+        }
+    }
+
+    mod unweighted {
+        use crate::*;
+
+        define_state_machine! {
+            name: Order,
+            states: { Placed, Shipped, Lost, Delivered },
+            inputs: { Ship, Deliver, Lose, Resend, Poke },
+            initial: Placed,
+            context: bool,
+            transitions: {
+                Placed + Ship => Shipped,
+                Shipped + Deliver => Delivered,
+                Shipped + Lose => Lost,
+                Lost + Resend => Shipped,
+                Placed + Poke => internal if |blocked| !*blocked,
+            }
+        }
+    }
+
+    #[test]
+    fn test_weights_change_definition_hash() {
+        assert_ne!(
+            Order::definition_hash(),
+            unweighted::Order::definition_hash()
+        );
+    }
+
+    #[test]
+    fn test_weighted_steps() {
+        assert_eq!(Order::weight(&State::Shipped, &Input::Deliver), 3);
+        assert_eq!(Order::weight(&State::Lost, &Input::Resend), 1);
+
+        let mut order = StochasticInstance::new(StateMachineInstance::<Order>::new(), 7);
+        assert_eq!(order.step(), Ok(Some(Input::Ship)));
+        let mut delivered = 0;
+        for _ in 0..4000 {
+            match order.pick() {
+                Some(Input::Deliver) => delivered += 1,
+                picked => assert_eq!(picked, Some(Input::Lose)),
+            }
+        }
+        // Delivering weighs 3 against 1 for losing the parcel
+        assert!((2800..3200).contains(&delivered), "{delivered}");
+
+        // Internal transitions with weight 0 are never picked
+        order.instance_mut().reset();
+        assert!((0..100).all(|_| order.pick() == Some(Input::Ship)));
+
+        order.instance_mut().reset_to(State::Delivered);
+        assert_eq!(order.step(), Ok(None));
+        assert_eq!(order.current_state(), &State::Delivered);
+    }
+
+    #[test]
+    fn test_simulate() {
+        let mut order = StochasticInstance::new(StateMachineInstance::<Order>::new(), 7);
+        let report = order.simulate(&State::Delivered, 1000, 100);
+        assert_eq!(report.runs, 1000);
+        assert_eq!(report.reach_probability(), 1.0);
+        // Shipping and delivering take 2 steps, every lost parcel 2 more
+        let mean = report.mean_steps().unwrap();
+        assert!((2.4..2.9).contains(&mean), "{mean}");
+
+        // The same seed replays the same runs
+        let mut again = StochasticInstance::new(StateMachineInstance::<Order>::new(), 7);
+        assert_eq!(again.simulate(&State::Delivered, 1000, 100), report);
+
+        let report = order.simulate(&State::Delivered, 2, 1);
+        assert_eq!(report.reached, 0);
+        assert_eq!(report.mean_steps(), None);
+        assert_eq!(order.run_until(&State::Shipped, 5), Some(0));
+    }
+}
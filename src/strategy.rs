@@ -0,0 +1,148 @@
+//! Property-test strategies for state machines, behind the `proptest` feature
+//!
+//! [`Strategies`] generates states, inputs and input sequences of any machine, so
+//! property tests can fuzz it without hand-written generators. Input sequences are
+//! random walks along the declared transitions: every input is valid in the state
+//! the walk has reached, and sequences shrink to shorter walks.
+//!
+//! ```ignore
+//! proptest! {
+//!     #[test]
+//!     fn every_order_can_be_rolled_back(inputs in Strategies::<Order>::input_sequences(20)) {
+//!         let mut order = StateMachineInstance::<Order>::new();
+//!         for input in &inputs {
+//!             prop_assert!(order.transition(input.clone()).is_ok());
+//!         }
+//!         prop_assert_eq!(order.undo_n(inputs.len()), inputs.len());
+//!     }
+//! }
+//! ```
+//!
+//! Like the queries, walks follow the declared transitions and don't evaluate guards,
+//! so a guarded input may still be rejected when the sequence is applied.
+
+use crate::core::StateMachine;
+use proptest::prelude::*;
+use proptest::sample::{Index, Select};
+
+/// Generators of states, inputs and valid input sequences of a machine
+pub struct Strategies<SM: StateMachine> {
+    _phantom: std::marker::PhantomData<SM>,
+}
+
+impl<SM> Strategies<SM>
+where
+    SM: StateMachine,
+    SM::State: 'static,
+    SM::Input: 'static,
+{
+    /// Generate any declared state
+    ///
+    /// Shrinks towards the states declared first.
+    pub fn states() -> Select<SM::State> {
+        proptest::sample::select(SM::states())
+    }
+
+    /// Generate any declared input
+    ///
+    /// Inputs carrying payloads have the values [`StateMachine::inputs`] gives them.
+    /// Shrinks towards the inputs declared first.
+    ///
+    /// # Panics
+    /// Panics if the machine declares no input
+    pub fn inputs() -> Select<SM::Input> {
+        proptest::sample::select(SM::inputs())
+    }
+
+    /// Generate sequences of valid inputs from the initial state
+    ///
+    /// # Arguments
+    /// - `max_len`: The maximum number of inputs
+    ///
+    /// # Returns
+    /// Returns the strategy, see [`input_sequences_from`](Self::input_sequences_from)
+    pub fn input_sequences(max_len: usize) -> impl Strategy<Value = Vec<SM::Input>> {
+        Self::input_sequences_from(SM::initial_state(), max_len)
+    }
+
+    /// Generate sequences of valid inputs from the given state
+    ///
+    /// Each step picks one of the inputs of the current state that lead to a next
+    /// state. A sequence ends early in a state without outgoing transitions.
+    ///
+    /// # Arguments
+    /// - `from`: The starting state
+    /// - `max_len`: The maximum number of inputs
+    ///
+    /// # Returns
+    /// Returns the strategy
+    pub fn input_sequences_from(
+        from: SM::State,
+        max_len: usize,
+    ) -> impl Strategy<Value = Vec<SM::Input>> {
+        proptest::collection::vec(any::<Index>(), 0..=max_len).prop_map(move |choices| {
+            let mut state = from.clone();
+            let mut inputs = Vec::with_capacity(choices.len());
+            for choice in choices {
+                let transitions: Vec<_> = SM::valid_inputs_iter(&state)
+                    .filter_map(|input| SM::next_state(&state, &input).map(|to| (input, to)))
+                    .collect();
+                if transitions.is_empty() {
+                    break;
+                }
+                let (input, to) = choice.get(&transitions).clone();
+                inputs.push(input);
+                state = to;
+            }
+            inputs
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    define_state_machine! {
+        name: Order,
+        states: { Created, Paid, Shipped, Cancelled },
+        inputs: { Pay, Ship, Cancel },
+        initial: Created,
+        final: { Shipped, Cancelled },
+        transitions: {
+            Created + Pay => Paid,
+            Paid + Ship => Shipped,
+            _ + Cancel => Cancelled
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_generated_states_and_inputs(
+            state in Strategies::<Order>::states(),
+            input in Strategies::<Order>::inputs(),
+        ) {
+            prop_assert!(Order::states().contains(&state));
+            prop_assert!(Order::inputs().contains(&input));
+        }
+
+        #[test]
+        fn test_input_sequences_are_valid(inputs in Strategies::<Order>::input_sequences(6)) {
+            // Every walk ends in a final state within two steps
+            prop_assert!(inputs.len() <= 2);
+            let mut order = StateMachineInstance::<Order>::new();
+            for input in inputs {
+                prop_assert!(order.transition(input).is_ok());
+            }
+        }
+
+        #[test]
+        fn test_input_sequences_from(
+            inputs in Strategies::<Order>::input_sequences_from(State::Paid, 6),
+        ) {
+            prop_assert!(inputs.len() <= 1);
+            prop_assert!(inputs.iter().all(|input| *input != Input::Pay));
+        }
+    }
+}
@@ -0,0 +1,539 @@
+use crate::core::StateMachine;
+use crate::instance::StateMachineInstance;
+#[cfg(feature = "prometheus")]
+use crate::metrics::{PrometheusSamples, TransitionMetrics, escape_label, prometheus_labels};
+use crate::query::StateMachineQuery;
+use crate::state_set::StateSet;
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Callback receiving each completed instance before it is evicted
+type ArchiveFn<SM, M> = Box<dyn FnMut(&str, StateMachineInstance<SM>, M) + Send>;
+
+/// When a supervised instance counts as completed
+///
+/// An instance is completed once it sits in a final state and hasn't left it for
+/// the idle duration. By default the final states are those the machine declares as
+/// final, or its terminal states if it declares none.
+pub struct CompletionPolicy<SM: StateMachine> {
+    /// States in which an instance can complete
+    final_states: StateSet<SM>,
+    /// How long an instance must stay in a final state
+    idle_for: Duration,
+}
+
+impl<SM: StateMachine> CompletionPolicy<SM> {
+    /// Create a policy completing instances idle in a final state for `idle_for`
+    ///
+    /// The final states are those declared with [`StateMachine::is_final`]. Machines
+    /// that declare none complete in their terminal states instead.
+    pub fn new(idle_for: Duration) -> Self {
+        let declared: StateSet<SM> = SM::states().into_iter().filter(SM::is_final).collect();
+        let final_states = if declared.is_empty() {
+            StateMachineQuery::<SM>::terminal_states()
+        } else {
+            declared
+        };
+        Self {
+            final_states,
+            idle_for,
+        }
+    }
+
+    /// Replace the final states, e.g. to include states that still accept inputs
+    pub fn final_states(mut self, states: impl IntoIterator<Item = SM::State>) -> Self {
+        self.final_states = states.into_iter().collect();
+        self
+    }
+
+    /// Check if an instance is completed at the given time
+    pub fn is_completed(&self, instance: &StateMachineInstance<SM>, now: SystemTime) -> bool {
+        let now_ms = now
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64);
+        self.final_states.contains(instance.current_state())
+            && now_ms.saturating_sub(instance.state_entered_at())
+                >= self.idle_for.as_millis() as u64
+    }
+}
+
+impl<SM: StateMachine> std::fmt::Debug for CompletionPolicy<SM> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CompletionPolicy")
+            .field("final_states", &self.final_states)
+            .field("idle_for", &self.idle_for)
+            .finish()
+    }
+}
+
+/// An instance managed by a [`Supervisor`] together with its metadata
+struct Supervised<SM: StateMachine, M> {
+    /// The managed instance
+    instance: StateMachineInstance<SM>,
+    /// User-defined metadata, e.g. tenant, priority or tags
+    metadata: M,
+}
+
+/// Fleet of instances of one state machine, keyed by id
+///
+/// Each instance carries typed metadata that is independent of the machine's own
+/// state, so the fleet can be sliced by tenant, priority and the like. Instances are
+/// kept in id order, which makes all listings deterministic.
+///
+/// # Example
+/// ```ignore
+/// struct Meta { tenant: String, priority: u8 }
+///
+/// let mut fleet = Supervisor::<order::Order, Meta>::new();
+/// fleet.spawn("order-1", Meta { tenant: "acme".into(), priority: 1 });
+/// let urgent = fleet.instances_where(|meta| meta.priority > 0);
+/// let by_tenant = fleet.state_counts_by(|meta| meta.tenant.clone());
+/// ```
+pub struct Supervisor<SM: StateMachine, M = ()> {
+    /// Managed instances by id
+    instances: BTreeMap<String, Supervised<SM, M>>,
+    /// Completion detection and the archive callback, if configured
+    completion: Option<(CompletionPolicy<SM>, ArchiveFn<SM, M>)>,
+}
+
+impl<SM: StateMachine, M> Default for Supervisor<SM, M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<SM: StateMachine, M> Supervisor<SM, M> {
+    /// Create an empty supervisor
+    pub fn new() -> Self {
+        Self {
+            instances: BTreeMap::new(),
+            completion: None,
+        }
+    }
+
+    /// Configure automatic archival of completed instances
+    ///
+    /// Completed instances are handed to `archive`, e.g. to serialize them into cold
+    /// storage, and evicted by [`collect_completed`](Self::collect_completed).
+    ///
+    /// # Arguments
+    /// - `policy`: Decides when an instance is completed
+    /// - `archive`: Receives the id, instance and metadata of each completed instance
+    pub fn set_completion_policy<F>(&mut self, policy: CompletionPolicy<SM>, archive: F)
+    where
+        F: FnMut(&str, StateMachineInstance<SM>, M) + Send + 'static,
+    {
+        self.completion = Some((policy, Box::new(archive)));
+    }
+
+    /// Archive and evict all instances completed at the given time
+    ///
+    /// There is no background timer; call this periodically, e.g. from a
+    /// housekeeping task. Does nothing without a completion policy.
+    ///
+    /// # Arguments
+    /// - `now`: The current time
+    ///
+    /// # Returns
+    /// Returns the ids of the evicted instances, in id order
+    pub fn collect_completed(&mut self, now: SystemTime) -> Vec<String> {
+        let Some((policy, archive)) = &mut self.completion else {
+            return Vec::new();
+        };
+
+        let completed: Vec<String> = self
+            .instances
+            .iter()
+            .filter(|(_, supervised)| policy.is_completed(&supervised.instance, now))
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in &completed {
+            if let Some(supervised) = self.instances.remove(id) {
+                archive(id, supervised.instance, supervised.metadata);
+            }
+        }
+        completed
+    }
+
+    /// Add an instance under an id
+    ///
+    /// # Returns
+    /// Returns the instance and metadata previously stored under the id, if any
+    pub fn insert(
+        &mut self,
+        id: impl Into<String>,
+        instance: StateMachineInstance<SM>,
+        metadata: M,
+    ) -> Option<(StateMachineInstance<SM>, M)> {
+        self.instances
+            .insert(id.into(), Supervised { instance, metadata })
+            .map(|supervised| (supervised.instance, supervised.metadata))
+    }
+
+    /// Create a new instance in its initial state under an id
+    ///
+    /// # Returns
+    /// Returns a mutable reference to the new instance
+    pub fn spawn(&mut self, id: impl Into<String>, metadata: M) -> &mut StateMachineInstance<SM>
+    where
+        SM::Context: Default,
+        SM::Config: Default,
+    {
+        let id = id.into();
+        self.insert(id.clone(), StateMachineInstance::new(), metadata);
+        self.get_mut(&id).unwrap()
+    }
+
+    /// Remove an instance
+    ///
+    /// # Returns
+    /// Returns the removed instance and its metadata, if the id was known
+    pub fn remove(&mut self, id: &str) -> Option<(StateMachineInstance<SM>, M)> {
+        self.instances
+            .remove(id)
+            .map(|supervised| (supervised.instance, supervised.metadata))
+    }
+
+    /// Get an instance by id
+    pub fn get(&self, id: &str) -> Option<&StateMachineInstance<SM>> {
+        self.instances
+            .get(id)
+            .map(|supervised| &supervised.instance)
+    }
+
+    /// Get a mutable reference to an instance by id
+    pub fn get_mut(&mut self, id: &str) -> Option<&mut StateMachineInstance<SM>> {
+        self.instances
+            .get_mut(id)
+            .map(|supervised| &mut supervised.instance)
+    }
+
+    /// Get the metadata of an instance
+    pub fn metadata(&self, id: &str) -> Option<&M> {
+        self.instances
+            .get(id)
+            .map(|supervised| &supervised.metadata)
+    }
+
+    /// Get a mutable reference to the metadata of an instance
+    pub fn metadata_mut(&mut self, id: &str) -> Option<&mut M> {
+        self.instances
+            .get_mut(id)
+            .map(|supervised| &mut supervised.metadata)
+    }
+
+    /// Get the ids of all instances, in id order
+    pub fn ids(&self) -> impl Iterator<Item = &str> {
+        self.instances.keys().map(String::as_str)
+    }
+
+    /// Iterate over all instances with their ids and metadata, in id order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &StateMachineInstance<SM>, &M)> {
+        self.instances
+            .iter()
+            .map(|(id, supervised)| (id.as_str(), &supervised.instance, &supervised.metadata))
+    }
+
+    /// Get the instances whose metadata matches a predicate
+    ///
+    /// # Returns
+    /// Returns the matching ids and instances, in id order
+    pub fn instances_where<F>(&self, predicate: F) -> Vec<(&str, &StateMachineInstance<SM>)>
+    where
+        F: Fn(&M) -> bool,
+    {
+        self.iter()
+            .filter(|(_, _, metadata)| predicate(metadata))
+            .map(|(id, instance, _)| (id, instance))
+            .collect()
+    }
+
+    /// Count instances per current state
+    ///
+    /// # Returns
+    /// Returns the number of instances by state name
+    pub fn state_counts(&self) -> BTreeMap<String, usize> {
+        let mut counts = BTreeMap::new();
+        for (_, instance, _) in self.iter() {
+            *counts
+                .entry(SM::state_name(instance.current_state()))
+                .or_insert(0) += 1;
+        }
+        counts
+    }
+
+    /// Count instances per metadata label and current state
+    ///
+    /// Suited for exporting metrics with the label as an extra dimension, e.g. one
+    /// gauge per tenant and state.
+    ///
+    /// # Arguments
+    /// - `label`: Derives the label from an instance's metadata
+    ///
+    /// # Returns
+    /// Returns the number of instances by (label, state name)
+    pub fn state_counts_by<F>(&self, label: F) -> BTreeMap<(String, String), usize>
+    where
+        F: Fn(&M) -> String,
+    {
+        let mut counts = BTreeMap::new();
+        for (_, instance, metadata) in self.iter() {
+            *counts
+                .entry((label(metadata), SM::state_name(instance.current_state())))
+                .or_insert(0) += 1;
+        }
+        counts
+    }
+
+    /// Render the metrics of the fleet in the Prometheus text exposition format
+    ///
+    /// Exposes the gauge `yasm_instances`, the number of instances by metadata label
+    /// and state, and the counters of
+    /// [`TransitionMetrics::to_prometheus`](crate::TransitionMetrics::to_prometheus),
+    /// summed over the instances with the same label. Instances that don't
+    /// [collect metrics](StateMachineInstance::set_collect_metrics) only count towards
+    /// the gauge.
+    ///
+    /// # Arguments
+    /// - `machine`: The value of the `machine` label
+    /// - `label_name`: The name of the metadata label, a valid Prometheus label name
+    /// - `label`: Derives the label value from an instance's metadata
+    ///
+    /// # Example
+    /// ```ignore
+    /// let text = fleet.to_prometheus("Order", "tenant", |meta| meta.tenant.clone());
+    /// ```
+    #[cfg(feature = "prometheus")]
+    pub fn to_prometheus<F>(&self, machine: &str, label_name: &str, label: F) -> String
+    where
+        F: Fn(&M) -> String,
+    {
+        let mut by_label: BTreeMap<String, TransitionMetrics<SM>> = BTreeMap::new();
+        for (_, instance, metadata) in self.iter() {
+            if let Some(metrics) = instance.metrics() {
+                by_label.entry(label(metadata)).or_default().merge(metrics);
+            }
+        }
+
+        let mut samples = PrometheusSamples::default();
+        for ((value, state), count) in self.state_counts_by(&label) {
+            samples.instances.push(format!(
+                "yasm_instances{{{},state=\"{}\"}} {count}\n",
+                prometheus_labels(machine, &[(label_name, &value)]),
+                escape_label(&state)
+            ));
+        }
+        for (value, metrics) in &by_label {
+            samples.add(metrics, &prometheus_labels(machine, &[(label_name, value)]));
+        }
+        samples.render()
+    }
+
+    /// Get the number of instances
+    pub fn len(&self) -> usize {
+        self.instances.len()
+    }
+
+    /// Check if the supervisor manages no instances
+    pub fn is_empty(&self) -> bool {
+        self.instances.is_empty()
+    }
+}
+
+impl<SM: StateMachine, M: std::fmt::Debug> std::fmt::Debug for Supervisor<SM, M> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map()
+            .entries(self.instances.iter().map(|(id, supervised)| {
+                (
+                    id,
+                    (supervised.instance.current_state(), &supervised.metadata),
+                )
+            }))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    define_state_machine! {
+        name: Ticket,
+        states: { Open, Assigned, Closed },
+        inputs: { Assign, Close },
+        initial: Open,
+        transitions: {
+            Open + Assign => Assigned,
+            Open + Close => Closed,
+            Assigned + Close => Closed
+        }
+    }
+
+    mod shipment {
+        use crate::*;
+
+        define_state_machine! {
+            name: Shipment,
+            states: { InTransit, Delivered, Lost },
+            inputs: { Deliver, Lose },
+            initial: InTransit,
+            final: { Delivered },
+            transitions: {
+                InTransit + Deliver => Delivered,
+                InTransit + Lose => Lost
+            }
+        }
+    }
+
+    #[derive(Debug)]
+    struct Meta {
+        tenant: &'static str,
+        priority: u8,
+    }
+
+    #[test]
+    fn test_metadata_queries() {
+        let mut fleet = Supervisor::<Ticket, Meta>::new();
+        fleet.spawn(
+            "t1",
+            Meta {
+                tenant: "acme",
+                priority: 2,
+            },
+        );
+        fleet
+            .spawn(
+                "t2",
+                Meta {
+                    tenant: "acme",
+                    priority: 0,
+                },
+            )
+            .transition(Input::Assign)
+            .unwrap();
+        fleet
+            .spawn(
+                "t3",
+                Meta {
+                    tenant: "globex",
+                    priority: 1,
+                },
+            )
+            .transition(Input::Close)
+            .unwrap();
+        assert_eq!(fleet.len(), 3);
+
+        let urgent: Vec<_> = fleet
+            .instances_where(|meta| meta.priority > 0)
+            .into_iter()
+            .map(|(id, instance)| (id, instance.current_state().clone()))
+            .collect();
+        assert_eq!(urgent, vec![("t1", State::Open), ("t3", State::Closed)]);
+
+        let counts = fleet.state_counts_by(|meta| meta.tenant.to_string());
+        assert_eq!(counts[&("acme".to_string(), "Open".to_string())], 1);
+        assert_eq!(counts[&("acme".to_string(), "Assigned".to_string())], 1);
+        assert_eq!(counts[&("globex".to_string(), "Closed".to_string())], 1);
+        assert_eq!(fleet.state_counts()["Open"], 1);
+
+        fleet.metadata_mut("t2").unwrap().priority = 5;
+        assert_eq!(fleet.instances_where(|meta| meta.priority > 4).len(), 1);
+
+        let (instance, meta) = fleet.remove("t3").unwrap();
+        assert_eq!(*instance.current_state(), State::Closed);
+        assert_eq!(meta.tenant, "globex");
+        assert_eq!(fleet.ids().collect::<Vec<_>>(), vec!["t1", "t2"]);
+    }
+
+    #[test]
+    fn test_collect_completed() {
+        use std::sync::{Arc, Mutex};
+
+        let archived = Arc::new(Mutex::new(Vec::new()));
+        let archived_clone = Arc::clone(&archived);
+        let mut fleet = Supervisor::<Ticket, u8>::new();
+        fleet.set_completion_policy(
+            CompletionPolicy::new(Duration::from_secs(60)),
+            move |id, instance, priority| {
+                archived_clone.lock().unwrap().push((
+                    id.to_string(),
+                    instance.current_state().clone(),
+                    priority,
+                ));
+            },
+        );
+
+        fleet.spawn("t1", 1).transition(Input::Close).unwrap();
+        fleet.spawn("t2", 2).transition(Input::Assign).unwrap();
+        fleet.spawn("t3", 3);
+
+        // Closed for less than the idle duration
+        assert!(fleet.collect_completed(SystemTime::now()).is_empty());
+
+        let later = SystemTime::now() + Duration::from_secs(120);
+        assert_eq!(fleet.collect_completed(later), vec!["t1"]);
+        assert_eq!(
+            *archived.lock().unwrap(),
+            vec![("t1".to_string(), State::Closed, 1)]
+        );
+        assert_eq!(fleet.ids().collect::<Vec<_>>(), vec!["t2", "t3"]);
+
+        // Custom final states cover states that still accept inputs
+        fleet.set_completion_policy(
+            CompletionPolicy::new(Duration::ZERO).final_states([State::Assigned]),
+            |_, _, _| {},
+        );
+        assert_eq!(fleet.collect_completed(later), vec!["t2"]);
+        assert_eq!(fleet.len(), 1);
+    }
+
+    #[cfg(feature = "prometheus")]
+    #[test]
+    fn test_prometheus_labels() {
+        let mut fleet = Supervisor::<Ticket, &str>::new();
+        for (id, tenant) in [("t1", "acme"), ("t2", "acme"), ("t3", "globex")] {
+            let instance = fleet.spawn(id, tenant);
+            instance.set_collect_metrics(true);
+            instance.transition(Input::Assign).unwrap();
+        }
+        fleet.spawn("t4", "globex");
+
+        let text = fleet.to_prometheus("Ticket", "tenant", |tenant| tenant.to_string());
+        assert!(text.starts_with(
+            "# HELP yasm_instances Instances by state\n\
+             # TYPE yasm_instances gauge\n\
+             yasm_instances{machine=\"Ticket\",tenant=\"acme\",state=\"Assigned\"} 2\n\
+             yasm_instances{machine=\"Ticket\",tenant=\"globex\",state=\"Assigned\"} 1\n\
+             yasm_instances{machine=\"Ticket\",tenant=\"globex\",state=\"Open\"} 1\n"
+        ));
+        // Counters are summed per tenant
+        assert!(text.contains(
+            "yasm_transitions_total{machine=\"Ticket\",tenant=\"acme\",from=\"Open\",input=\"Assign\",to=\"Assigned\"} 2\n"
+        ));
+        assert!(text.contains(
+            "yasm_transitions_total{machine=\"Ticket\",tenant=\"globex\",from=\"Open\",input=\"Assign\",to=\"Assigned\"} 1\n"
+        ));
+    }
+
+    #[test]
+    fn test_completion_policy_final_states() {
+        use shipment::{Input as ShipmentInput, Shipment};
+
+        // Declared final states take precedence over terminal states
+        let later = SystemTime::now() + Duration::from_secs(1);
+        let policy = CompletionPolicy::<Shipment>::new(Duration::ZERO);
+        let mut delivered = StateMachineInstance::<Shipment>::new();
+        delivered.transition(ShipmentInput::Deliver).unwrap();
+        let mut lost = StateMachineInstance::<Shipment>::new();
+        lost.transition(ShipmentInput::Lose).unwrap();
+        assert!(policy.is_completed(&delivered, later));
+        assert!(!policy.is_completed(&lost, later));
+
+        // Without declared final states, terminal states complete
+        let policy = CompletionPolicy::<Ticket>::new(Duration::ZERO);
+        let mut closed = StateMachineInstance::<Ticket>::new();
+        closed.transition(Input::Close).unwrap();
+        assert!(policy.is_completed(&closed, later));
+    }
+}
@@ -0,0 +1,266 @@
+use crate::core::StateMachine;
+use crate::error::TransitionError;
+use crate::instance::StateMachineInstance;
+
+/// An applied input: the state it was applied in, the input and its result
+type Step<SM> = (
+    <SM as StateMachine>::State,
+    <SM as StateMachine>::Input,
+    Result<<SM as StateMachine>::State, TransitionError<SM>>,
+);
+
+/// Fluent harness for behavior tests of a machine
+///
+/// A scenario drives an instance step by step: [`given`](Self::given) places it in a
+/// state, [`when`](Self::when) applies an input, and the `expect` methods check the
+/// outcome of the last input. Checks run immediately and panic with a message naming
+/// the failing step, reported at the caller's line, so a scenario reads as a test.
+///
+/// # Example
+/// ```ignore
+/// #[test]
+/// fn paid_orders_ship_once() {
+///     Scenario::<Order>::new()
+///         .given(State::Paid)
+///         .when(Input::Ship)
+///         .expect(State::Shipped)
+///         .when(Input::Ship)
+///         .expect_rejected();
+/// }
+/// ```
+pub struct Scenario<SM: StateMachine> {
+    /// The instance driven by the scenario
+    instance: StateMachineInstance<SM>,
+    /// The last input applied
+    last: Option<Step<SM>>,
+    /// Number of inputs applied so far
+    steps: usize,
+}
+
+impl<SM: StateMachine> Default for Scenario<SM>
+where
+    SM::Context: Default,
+    SM::Config: Default,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<SM: StateMachine> Scenario<SM> {
+    /// Create a scenario on a fresh instance with default context and configuration
+    pub fn new() -> Self
+    where
+        SM::Context: Default,
+        SM::Config: Default,
+    {
+        Self::with_instance(StateMachineInstance::new())
+    }
+
+    /// Create a scenario driving the given instance, e.g. one with a custom context
+    /// or callbacks
+    pub fn with_instance(instance: StateMachineInstance<SM>) -> Self {
+        Self {
+            instance,
+            last: None,
+            steps: 0,
+        }
+    }
+
+    /// Place the instance in a state, bypassing transitions
+    ///
+    /// The history is cleared, see
+    /// [`StateMachineInstance::reset_to`](crate::StateMachineInstance::reset_to).
+    pub fn given(mut self, state: SM::State) -> Self {
+        self.instance.reset_to(state);
+        self.last = None;
+        self
+    }
+
+    /// Apply an input, keeping its result for the next `expect`
+    pub fn when(mut self, input: SM::Input) -> Self {
+        let state = self.instance.current_state().clone();
+        let result = self.instance.transition(input.clone());
+        self.steps += 1;
+        self.last = Some((state, input, result));
+        self
+    }
+
+    /// Apply inputs in order, each of which must be accepted
+    ///
+    /// # Panics
+    /// Panics if an input is rejected
+    #[track_caller]
+    pub fn when_all(mut self, inputs: impl IntoIterator<Item = SM::Input>) -> Self {
+        for input in inputs {
+            self = self.when(input);
+            if let Some((state, input, Err(error))) = &self.last {
+                panic!(
+                    "scenario step {}: input {input:?} in state {state:?} was rejected: {error}",
+                    self.steps
+                );
+            }
+        }
+        self
+    }
+
+    /// Check that the last input was accepted and the instance is now in `state`
+    ///
+    /// Before any input was applied, only the current state is checked.
+    ///
+    /// # Panics
+    /// Panics if the last input was rejected or the instance is in another state
+    #[track_caller]
+    pub fn expect(self, state: SM::State) -> Self {
+        if let Some((from, input, Err(error))) = &self.last {
+            panic!(
+                "scenario step {}: expected input {input:?} in state {from:?} to lead to {state:?}, but it was rejected: {error}",
+                self.steps
+            );
+        }
+        let current = self.instance.current_state();
+        if *current != state {
+            match &self.last {
+                Some((from, input, _)) => panic!(
+                    "scenario step {}: expected input {input:?} in state {from:?} to lead to {state:?}, but it led to {current:?}",
+                    self.steps
+                ),
+                None => {
+                    panic!("scenario: expected state {state:?}, but the instance is in {current:?}")
+                }
+            }
+        }
+        self
+    }
+
+    /// Check that the last input was rejected, with any error
+    ///
+    /// # Panics
+    /// Panics if no input was applied or the last one was accepted
+    #[track_caller]
+    pub fn expect_rejected(self) -> Self {
+        match &self.last {
+            Some((_, _, Err(_))) => self,
+            Some((from, input, Ok(to))) => panic!(
+                "scenario step {}: expected input {input:?} in state {from:?} to be rejected, but it led to {to:?}",
+                self.steps
+            ),
+            None => panic!("scenario: expected a rejection, but no input was applied"),
+        }
+    }
+
+    /// Check that the last input was rejected with the given error
+    ///
+    /// # Panics
+    /// Panics if no input was applied, the last one was accepted or it failed with
+    /// another error
+    #[track_caller]
+    pub fn expect_error(self, expected: TransitionError<SM>) -> Self {
+        match &self.last {
+            Some((_, _, Err(error))) if *error == expected => self,
+            Some((from, input, result)) => panic!(
+                "scenario step {}: expected input {input:?} in state {from:?} to fail with {expected:?}, got {result:?}",
+                self.steps
+            ),
+            None => panic!("scenario: expected {expected:?}, but no input was applied"),
+        }
+    }
+
+    /// Check a condition on the context
+    ///
+    /// # Arguments
+    /// - `description`: What the condition checks, for the panic message
+    /// - `check`: The condition, receiving the context
+    ///
+    /// # Panics
+    /// Panics if the condition doesn't hold
+    #[track_caller]
+    pub fn expect_context<F>(self, description: &str, check: F) -> Self
+    where
+        F: FnOnce(&SM::Context) -> bool,
+    {
+        if !check(self.instance.context()) {
+            panic!(
+                "scenario step {}: expected the context to satisfy \"{description}\" in state {:?}",
+                self.steps,
+                self.instance.current_state()
+            );
+        }
+        self
+    }
+
+    /// Get the instance driven by the scenario
+    pub fn instance(&self) -> &StateMachineInstance<SM> {
+        &self.instance
+    }
+
+    /// Get the instance driven by the scenario, ending the scenario
+    pub fn into_instance(self) -> StateMachineInstance<SM> {
+        self.instance
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    define_state_machine! {
+        name: Order,
+        states: { Created, Paid, Shipped },
+        inputs: { Pay, Ship, Refund },
+        initial: Created,
+        context: u32,
+        transitions: {
+            Created + Pay => Paid,
+            Paid + Ship => Shipped if |stock| *stock > 0,
+            Paid + Refund => Created
+        }
+    }
+
+    #[test]
+    fn test_scenario() {
+        let order = Scenario::<Order>::new()
+            .expect(State::Created)
+            .when(Input::Pay)
+            .expect(State::Paid)
+            .when(Input::Ship)
+            .expect_error(TransitionError::GuardRejected {
+                state: State::Paid,
+                input: Input::Ship,
+            })
+            .expect_context("out of stock", |stock| *stock == 0)
+            .given(State::Created)
+            .when_all([Input::Pay, Input::Refund, Input::Pay])
+            .expect(State::Paid)
+            .given(State::Shipped)
+            .when(Input::Pay)
+            .expect_rejected()
+            .into_instance();
+        assert_eq!(*order.current_state(), State::Shipped);
+
+        let mut stocked = StateMachineInstance::<Order>::with_context(1);
+        stocked.transition(Input::Pay).unwrap();
+        Scenario::with_instance(stocked)
+            .when(Input::Ship)
+            .expect(State::Shipped);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "scenario step 2: expected input Ship in state Paid to lead to Shipped"
+    )]
+    fn test_scenario_reports_failed_step() {
+        Scenario::<Order>::new()
+            .when(Input::Pay)
+            .expect(State::Paid)
+            .when(Input::Ship)
+            .expect(State::Shipped);
+    }
+
+    #[test]
+    #[should_panic(expected = "scenario step 1: input Ship in state Created was rejected")]
+    fn test_scenario_rejected_sequence() {
+        Scenario::<Order>::new().when_all([Input::Ship, Input::Pay]);
+    }
+}
@@ -0,0 +1,174 @@
+//! Timeouts declared with the `timeouts` block of `define_state_machine!`
+//!
+//! A [`TimedInstance`] arms the timeout of each state it enters and fires the timeout
+//! input once the deadline has passed. There is no background thread: the owner drives
+//! it with [`tick`](TimedInstance::tick), e.g. after sleeping until
+//! [`next_deadline`](TimedInstance::next_deadline) on the runtime of their choice:
+//!
+//! ```ignore
+//! let mut conn = TimedInstance::new(StateMachineInstance::<Connection>::new());
+//! conn.transition(Input::Dial)?;
+//! while let Some(deadline) = conn.next_deadline() {
+//!     tokio::time::sleep_until(deadline.into()).await;
+//!     conn.tick(Instant::now());
+//! }
+//! ```
+
+use crate::core::StateMachine;
+use crate::error::TransitionError;
+use crate::instance::StateMachineInstance;
+use std::time::Instant;
+
+/// Instance wrapper that fires the timeout inputs of the machine
+///
+/// Entering a state, including re-entering it through an external self-transition,
+/// restarts its timeout. Ignored and deferred inputs and internal transitions leave
+/// the running timeout alone. Transitions must go through the wrapper to arm timeouts.
+pub struct TimedInstance<SM: StateMachine> {
+    /// The wrapped instance
+    instance: StateMachineInstance<SM>,
+    /// When the timeout of the current state fires, and its input
+    armed: Option<(Instant, SM::Input)>,
+}
+
+impl<SM: StateMachine> TimedInstance<SM> {
+    /// Wrap an instance, arming the timeout of its current state from now
+    pub fn new(instance: StateMachineInstance<SM>) -> Self {
+        let mut timed = Self {
+            instance,
+            armed: None,
+        };
+        timed.arm(Instant::now());
+        timed
+    }
+
+    /// Get a read-only reference to the wrapped instance
+    pub fn instance(&self) -> &StateMachineInstance<SM> {
+        &self.instance
+    }
+
+    /// Unwrap the instance, dropping the armed timeout
+    pub fn into_inner(self) -> StateMachineInstance<SM> {
+        self.instance
+    }
+
+    /// Get when the timeout of the current state fires, if it has one
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.armed.as_ref().map(|(deadline, _)| *deadline)
+    }
+
+    /// Execute a state transition, see [`StateMachineInstance::transition`]
+    pub fn transition(&mut self, input: SM::Input) -> Result<SM::State, TransitionError<SM>> {
+        self.apply(input, Instant::now())
+    }
+
+    /// Fire the timeout of the current state if its deadline has passed
+    ///
+    /// The timeout of the state entered this way is armed from the deadline that
+    /// fired, not from `now`, so that chained timeouts don't drift when ticks are late.
+    ///
+    /// # Arguments
+    /// - `now`: The current time
+    ///
+    /// # Returns
+    /// Returns the result of the timeout transition, or None if no timeout was due
+    pub fn tick(&mut self, now: Instant) -> Option<Result<SM::State, TransitionError<SM>>> {
+        let (deadline, _) = self.armed.as_ref()?;
+        if *deadline > now {
+            return None;
+        }
+        let (deadline, input) = self.armed.take()?;
+        Some(self.apply(input, deadline))
+    }
+
+    /// Apply an input, restarting the timeout if a state is entered
+    fn apply(&mut self, input: SM::Input, now: Instant) -> Result<SM::State, TransitionError<SM>> {
+        let state = self.instance.current_state();
+        let enters = !SM::is_ignored(state, &input)
+            && !SM::is_deferred(state, &input)
+            && !SM::is_internal(state, &input);
+        let result = self.instance.transition(input);
+        // A transition followed by an invariant failure still entered the state
+        if enters
+            && result
+                .as_ref()
+                .map_or_else(TransitionError::took_effect, |_| true)
+        {
+            self.arm(now);
+        }
+        result
+    }
+
+    /// Arm the timeout of the current state, starting at `now`
+    fn arm(&mut self, now: Instant) {
+        self.armed =
+            SM::timeout(self.instance.current_state()).map(|(after, input)| (now + after, input));
+    }
+}
+
+impl<SM: StateMachine> std::fmt::Debug for TimedInstance<SM> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TimedInstance")
+            .field("instance", &self.instance)
+            .field("armed", &self.armed)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+    use std::time::Duration;
+
+    define_state_machine! {
+        name: Connection,
+        states: { Idle, Dialing, Connected, Failed },
+        inputs: { Dial, Answer, Ping, Timeout },
+        initial: Idle,
+        transitions: {
+            Idle + Dial => Dialing,
+            Dialing + Answer => Connected,
+            Dialing + Timeout => Failed,
+            Connected + Ping => internal,
+            Connected + Timeout => Idle,
+            Failed + Dial => Dialing,
+        },
+        timeouts: {
+            Dialing after Duration::from_secs(5) => Timeout,
+            Connected after Duration::from_secs(60) => Timeout,
+        }
+    }
+
+    #[test]
+    fn test_timeouts() {
+        assert_eq!(
+            Connection::timeout(&State::Dialing),
+            Some((Duration::from_secs(5), Input::Timeout))
+        );
+        assert_eq!(Connection::timeout(&State::Idle), None);
+
+        let mut conn = TimedInstance::new(StateMachineInstance::<Connection>::new());
+        assert_eq!(conn.next_deadline(), None);
+        assert!(conn.tick(Instant::now()).is_none());
+
+        conn.transition(Input::Dial).unwrap();
+        let deadline = conn.next_deadline().unwrap();
+        assert!(conn.tick(deadline - Duration::from_millis(1)).is_none());
+        assert_eq!(conn.tick(deadline), Some(Ok(State::Failed)));
+        assert_eq!(conn.next_deadline(), None);
+
+        // Internal transitions keep the running timeout
+        conn.transition(Input::Dial).unwrap();
+        conn.transition(Input::Answer).unwrap();
+        let deadline = conn.next_deadline().unwrap();
+        conn.transition(Input::Ping).unwrap();
+        assert_eq!(conn.next_deadline(), Some(deadline));
+        assert_eq!(
+            conn.tick(deadline + Duration::from_secs(1)),
+            Some(Ok(State::Idle))
+        );
+        assert_eq!(*conn.instance().current_state(), State::Idle);
+        assert_eq!(conn.into_inner().history_len(), 6);
+    }
+}
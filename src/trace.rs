@@ -0,0 +1,92 @@
+use alloc::collections::BTreeMap;
+use alloc::{format, string::String};
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// Counter making correlation IDs unique within the process
+static NEXT_CORRELATION: AtomicU64 = AtomicU64::new(0);
+
+/// Opaque trace context carried along with a transition
+///
+/// Holds the propagation fields of a distributed trace as key/value pairs, e.g. the
+/// W3C `traceparent` header or Jaeger's `uber-trace-id`. The library doesn't
+/// interpret the fields; it hands them to transition event callbacks, stores them
+/// in history and attaches them to [`TransitionEvent`](crate::TransitionEvent)s, so
+/// the spans of a workflow can be stitched together across services.
+///
+/// # Example
+/// ```ignore
+/// let trace = TraceContext::new().with("traceparent", request.header("traceparent"));
+/// order.transition_with_trace(Input::Pay, trace)?;
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TraceContext {
+    /// Propagation fields by name
+    fields: BTreeMap<String, String>,
+}
+
+impl TraceContext {
+    /// Name of the field holding the correlation ID
+    pub const CORRELATION_ID: &'static str = "correlation-id";
+
+    /// Create an empty trace context
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a trace context with a fresh correlation ID
+    ///
+    /// A correlation ID names one business action. Machines that react to each other's
+    /// transitions with [`transition_caused_by`](crate::StateMachineInstance::transition_caused_by)
+    /// pass it on, so the action can be followed through events and history across the
+    /// whole chain of machines. Fresh IDs are unique within the process.
+    pub fn correlated() -> Self {
+        let id = format!(
+            "{:x}-{:x}",
+            crate::instance::unix_millis(),
+            NEXT_CORRELATION.fetch_add(1, Ordering::Relaxed)
+        );
+        Self::new().with_correlation_id(id)
+    }
+
+    /// Set the correlation ID, replacing any previous one
+    pub fn with_correlation_id(self, id: impl Into<String>) -> Self {
+        self.with(Self::CORRELATION_ID, id)
+    }
+
+    /// Get the correlation ID, if one is set
+    pub fn correlation_id(&self) -> Option<&str> {
+        self.get(Self::CORRELATION_ID)
+    }
+
+    /// Add a propagation field, replacing any previous value
+    pub fn with(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.insert(key, value);
+        self
+    }
+
+    /// Set a propagation field
+    ///
+    /// # Returns
+    /// Returns the previous value of the field, if any
+    pub fn insert(&mut self, key: impl Into<String>, value: impl Into<String>) -> Option<String> {
+        self.fields.insert(key.into(), value.into())
+    }
+
+    /// Get a propagation field
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.fields.get(key).map(String::as_str)
+    }
+
+    /// Iterate over all propagation fields, in key order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.fields
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+
+    /// Check if the context has no fields
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+}
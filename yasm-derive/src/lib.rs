@@ -0,0 +1,1037 @@
+//! Procedural version of `yasm::define_state_machine!` with precise diagnostics
+//!
+//! [`state_machine!`](state_machine) accepts exactly the syntax of
+//! `define_state_machine!` and expands to it, but checks the definition first. Mistakes
+//! that make the declarative macro fail with "no rules expected this token" or a type
+//! error deep inside the generated code are reported at the offending identifier:
+//! - states, inputs and outputs used in rules but never declared
+//! - the same state and input used by more than one rule
+//! - rules declared for final states
+//! - fields out of order, unknown or missing
+//!
+//! Declared states and inputs that no rule refers to, and states that can't be reached
+//! from the initial state, are reported as warnings. Each check is a lint that can be
+//! turned off with `allow` or into an error with `deny`, for the whole definition or a
+//! single state or input:
+//!
+//! ```ignore
+//! state_machine! {
+//!     #[deny(unreachable_state)]
+//!     name: Door,
+//!     states: { Closed, Open, #[allow(unreachable_state)] Legacy },
+//!     ...
+//! }
+//! ```
+//!
+//! Use it through the `derive` feature of `yasm`, which re-exports the macro.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Delimiter, Group, Span, TokenStream as TokenStream2, TokenTree};
+use quote::{format_ident, quote};
+use std::collections::{HashMap, HashSet};
+use syn::ext::IdentExt;
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::{Attribute, Expr, Ident, LitStr, Token, Type, braced, parenthesized};
+
+mod kw {
+    syn::custom_keyword!(ignores);
+    syn::custom_keyword!(defers);
+    syn::custom_keyword!(internal);
+    syn::custom_keyword!(compensate);
+    syn::custom_keyword!(emits);
+    syn::custom_keyword!(after);
+    syn::custom_keyword!(coalesce);
+    syn::custom_keyword!(supersedes);
+}
+
+/// Fields of a definition, in the order `define_state_machine!` expects them
+const FIELDS: &[&str] = &[
+    "name",
+    "module",
+    "state_name",
+    "input_name",
+    "output_name",
+    "states",
+    "state_aliases",
+    "inputs",
+    "outputs",
+    "initial",
+    "final",
+    "accepting",
+    "context",
+    "config",
+    "sealed",
+    "transitions",
+    "queue",
+    "timeouts",
+    "weights",
+];
+
+/// Lints reported by the checks, whose level attributes can set
+const LINTS: &[&str] = &["unreachable_state", "unused_state", "unused_input"];
+
+/// Fields every definition must have
+const REQUIRED_FIELDS: &[&str] = &["name", "states", "inputs", "initial", "transitions"];
+
+/// Define a state machine like `yasm::define_state_machine!`, checking the definition
+/// with precise error locations
+///
+/// # Example
+/// ```ignore
+/// use yasm::*;
+///
+/// state_machine! {
+///     name: Door,
+///     states: { Closed, Open },
+///     inputs: { Push },
+///     initial: Closed,
+///     transitions: {
+///         Closed + Push => Opne   // error: unknown state `Opne`, pointing at `Opne`
+///     }
+/// }
+/// ```
+#[proc_macro]
+pub fn state_machine(input: TokenStream) -> TokenStream {
+    expand(input.into())
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Check a definition and expand it to `define_state_machine!`
+fn expand(input: TokenStream2) -> syn::Result<TokenStream2> {
+    let definition: Definition = syn::parse2(input.clone())?;
+    let warnings = definition.check()?;
+    let input = strip_lint_attributes(input, true);
+    Ok(quote! {
+        ::yasm::define_state_machine! { #input }
+        #(#warnings)*
+    })
+}
+
+/// The parts of a definition that refer to states, inputs and outputs
+#[derive(Default)]
+struct Definition {
+    /// Lint levels set by attributes
+    lints: Lints,
+    /// Declared states
+    states: Vec<Ident>,
+    /// Former state names with the state each stands for
+    state_aliases: Vec<(LitStr, Ident)>,
+    /// Declared inputs
+    inputs: Vec<Ident>,
+    /// Declared outputs, if the definition has an `outputs` block
+    outputs: Option<Vec<Ident>>,
+    /// The initial state
+    initial: Option<Ident>,
+    /// Declared final states
+    finals: Vec<Ident>,
+    /// Declared accepting states
+    accepting: Vec<Ident>,
+    /// Rules of the `transitions` block
+    rules: Vec<Rule>,
+    /// Inputs named in the `queue` block
+    queue_inputs: Vec<Ident>,
+    /// State and input of each entry of the `timeouts` block
+    timeouts: Vec<(Ident, Ident)>,
+    /// State and input of each entry of the `weights` block
+    weights: Vec<(Ident, Ident)>,
+}
+
+/// A rule of the `transitions` block
+struct Rule {
+    /// The source state, None for wildcard transitions
+    from: Option<Ident>,
+    /// Location of the source state or wildcard
+    span: Span,
+    /// The input
+    input: Ident,
+    /// The target state, None for internal transitions, ignored and deferred inputs
+    to: Option<Ident>,
+    /// The emitted output
+    output: Option<Ident>,
+    /// Whether the target and output are those of the previous rule, as for all but
+    /// the first input of a multi-input rule
+    shared: bool,
+}
+
+impl Parse for Definition {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut definition = Definition::default();
+        let mut seen: Vec<(String, Span)> = Vec::new();
+        let attributes = input.call(Attribute::parse_outer)?;
+        definition.lints.apply(&attributes, None)?;
+
+        while !input.is_empty() {
+            // `final` is a keyword, so accept keywords as field names
+            let key = input.call(Ident::parse_any)?;
+            let name = key.to_string();
+            let Some(position) = FIELDS.iter().position(|field| *field == name) else {
+                return Err(syn::Error::new(
+                    key.span(),
+                    format!(
+                        "unknown field `{name}`, expected one of {}",
+                        FIELDS.join(", ")
+                    ),
+                ));
+            };
+            if seen.iter().any(|(field, _)| *field == name) {
+                return Err(syn::Error::new(
+                    key.span(),
+                    format!("duplicate field `{name}`"),
+                ));
+            }
+            if let Some((later, _)) = seen.iter().find(|(field, _)| {
+                FIELDS.iter().position(|known| known == field).unwrap_or(0) > position
+            }) {
+                return Err(syn::Error::new(
+                    key.span(),
+                    format!("field `{name}` must come before `{later}`"),
+                ));
+            }
+            seen.push((name.clone(), key.span()));
+            input.parse::<Token![:]>()?;
+
+            match name.as_str() {
+                "name" | "module" | "state_name" | "input_name" | "output_name" => {
+                    input.parse::<Ident>()?;
+                }
+                "states" => definition.states = parse_items(input, &mut definition.lints)?,
+                "state_aliases" => {
+                    let content;
+                    braced!(content in input);
+                    definition.state_aliases = parse_aliases(&content)?;
+                }
+                "inputs" => definition.inputs = parse_inputs(input, &mut definition.lints)?,
+                "outputs" => definition.outputs = Some(parse_idents(input)?),
+                "initial" => definition.initial = Some(input.parse()?),
+                "final" => definition.finals = parse_idents(input)?,
+                "accepting" => definition.accepting = parse_idents(input)?,
+                "context" | "config" => {
+                    input.parse::<Type>()?;
+                }
+                "sealed" => {
+                    input.parse::<Expr>()?;
+                }
+                "transitions" => {
+                    let content;
+                    braced!(content in input);
+                    definition.rules = parse_rules(&content)?;
+                }
+                "queue" => {
+                    let content;
+                    braced!(content in input);
+                    definition.queue_inputs = parse_queue(&content)?;
+                }
+                "timeouts" => {
+                    let content;
+                    braced!(content in input);
+                    definition.timeouts = parse_timeouts(&content)?;
+                }
+                _ => {
+                    let content;
+                    braced!(content in input);
+                    definition.weights = parse_weights(&content)?;
+                }
+            }
+
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+
+        for field in REQUIRED_FIELDS {
+            if !seen.iter().any(|(name, _)| name == field) {
+                return Err(syn::Error::new(
+                    Span::call_site(),
+                    format!("missing field `{field}`"),
+                ));
+            }
+        }
+        Ok(definition)
+    }
+}
+
+/// Parse a braced, comma separated list of identifiers, each with optional doc comments
+fn parse_idents(input: ParseStream) -> syn::Result<Vec<Ident>> {
+    let content;
+    braced!(content in input);
+    let parse_ident = |input: ParseStream| {
+        input.call(Attribute::parse_outer)?;
+        input.parse::<Ident>()
+    };
+    Ok(
+        Punctuated::<Ident, Token![,]>::parse_terminated_with(&content, parse_ident)?
+            .into_iter()
+            .collect(),
+    )
+}
+
+/// Parse the `states` block, applying the lint attributes of each state
+fn parse_items(input: ParseStream, lints: &mut Lints) -> syn::Result<Vec<Ident>> {
+    let content;
+    braced!(content in input);
+    let mut items = Vec::new();
+    while !content.is_empty() {
+        let attributes = content.call(Attribute::parse_outer)?;
+        let item: Ident = content.parse()?;
+        lints.apply(&attributes, Some(&item))?;
+        items.push(item);
+        if !content.is_empty() {
+            content.parse::<Token![,]>()?;
+        }
+    }
+    Ok(items)
+}
+
+/// Parse the `inputs` block, skipping payload fields and applying the lint attributes
+/// of each input
+fn parse_inputs(input: ParseStream, lints: &mut Lints) -> syn::Result<Vec<Ident>> {
+    let content;
+    braced!(content in input);
+    let mut inputs = Vec::new();
+    while !content.is_empty() {
+        let attributes = content.call(Attribute::parse_outer)?;
+        let item: Ident = content.parse()?;
+        lints.apply(&attributes, Some(&item))?;
+        inputs.push(item);
+        if content.peek(syn::token::Paren) {
+            let fields;
+            parenthesized!(fields in content);
+            fields.parse::<TokenStream2>()?;
+        }
+        if !content.is_empty() {
+            content.parse::<Token![,]>()?;
+        }
+    }
+    Ok(inputs)
+}
+
+/// Parse the entries of the `state_aliases` block
+fn parse_aliases(input: ParseStream) -> syn::Result<Vec<(LitStr, Ident)>> {
+    let mut aliases = Vec::new();
+    while !input.is_empty() {
+        let alias: LitStr = input.parse()?;
+        input.parse::<Token![=>]>()?;
+        aliases.push((alias, input.parse()?));
+        if !input.is_empty() {
+            input.parse::<Token![,]>()?;
+        }
+    }
+    Ok(aliases)
+}
+
+/// Parse the rules of the `transitions` block
+fn parse_rules(input: ParseStream) -> syn::Result<Vec<Rule>> {
+    let mut rules = Vec::new();
+    while !input.is_empty() {
+        let (from, span) = if input.peek(Token![_]) {
+            (None, input.parse::<Token![_]>()?.span)
+        } else {
+            let from: Ident = input.parse()?;
+            let span = from.span();
+            (Some(from), span)
+        };
+        if from.is_some() && (input.peek(kw::ignores) || input.peek(kw::defers)) {
+            if input.peek(kw::ignores) {
+                input.parse::<kw::ignores>()?;
+            } else {
+                input.parse::<kw::defers>()?;
+            }
+            rules.push(Rule {
+                from,
+                span,
+                input: input.parse()?,
+                to: None,
+                output: None,
+                shared: false,
+            });
+        } else {
+            input.parse::<Token![+]>()?;
+            let mut inputs = vec![input.parse::<Ident>()?];
+            while input.peek(Token![|]) {
+                input.parse::<Token![|]>()?;
+                inputs.push(input.parse()?);
+            }
+            input.parse::<Token![=>]>()?;
+            let to = if input.peek(kw::internal) {
+                input.parse::<kw::internal>()?;
+                None
+            } else {
+                if input.peek(kw::compensate) {
+                    input.parse::<kw::compensate>()?;
+                }
+                Some(input.parse()?)
+            };
+            let output = if input.peek(kw::emits) {
+                input.parse::<kw::emits>()?;
+                Some(input.parse()?)
+            } else {
+                None
+            };
+            if input.peek(Token![if]) {
+                input.parse::<Token![if]>()?;
+                input.parse::<Expr>()?;
+            }
+            // The target and output are shared, so only the first input checks them
+            rules.extend(inputs.into_iter().enumerate().map(|(index, inp)| Rule {
+                from: from.clone(),
+                span,
+                input: inp,
+                to: to.clone(),
+                output: output.clone(),
+                shared: index > 0,
+            }));
+        }
+        if !input.is_empty() {
+            input.parse::<Token![,]>()?;
+        }
+    }
+    Ok(rules)
+}
+
+/// Parse the rules of the `queue` block, returning the inputs they name
+fn parse_queue(input: ParseStream) -> syn::Result<Vec<Ident>> {
+    let mut inputs = Vec::new();
+    while !input.is_empty() {
+        if input.peek(kw::coalesce) {
+            input.parse::<kw::coalesce>()?;
+            inputs.push(input.parse()?);
+        } else {
+            inputs.push(input.parse()?);
+            input.parse::<kw::supersedes>()?;
+            inputs.push(input.parse()?);
+        }
+        if !input.is_empty() {
+            input.parse::<Token![,]>()?;
+        }
+    }
+    Ok(inputs)
+}
+
+/// Parse the entries of the `timeouts` block
+fn parse_timeouts(input: ParseStream) -> syn::Result<Vec<(Ident, Ident)>> {
+    let mut timeouts = Vec::new();
+    while !input.is_empty() {
+        let state: Ident = input.parse()?;
+        input.parse::<kw::after>()?;
+        input.parse::<Expr>()?;
+        input.parse::<Token![=>]>()?;
+        timeouts.push((state, input.parse()?));
+        if !input.is_empty() {
+            input.parse::<Token![,]>()?;
+        }
+    }
+    Ok(timeouts)
+}
+
+/// Parse the entries of the `weights` block
+fn parse_weights(input: ParseStream) -> syn::Result<Vec<(Ident, Ident)>> {
+    let mut weights = Vec::new();
+    while !input.is_empty() {
+        let state: Ident = input.parse()?;
+        input.parse::<Token![+]>()?;
+        let transition_input: Ident = input.parse()?;
+        input.parse::<Token![=>]>()?;
+        input.parse::<Expr>()?;
+        weights.push((state, transition_input));
+        if !input.is_empty() {
+            input.parse::<Token![,]>()?;
+        }
+    }
+    Ok(weights)
+}
+
+impl Definition {
+    /// Check the definition, returning warnings to emit or all errors found
+    fn check(&self) -> syn::Result<Vec<TokenStream2>> {
+        let mut errors = Errors::default();
+        let states = declared(&self.states, "state", &mut errors);
+        let inputs = declared(&self.inputs, "input", &mut errors);
+        let outputs = self
+            .outputs
+            .as_ref()
+            .map(|outputs| declared(outputs, "output", &mut errors));
+
+        let mut used_states: HashSet<String> = HashSet::new();
+        let mut used_inputs: HashSet<String> = HashSet::new();
+        let mut check_state = |state: &Ident, errors: &mut Errors| {
+            if !states.contains(&state.to_string()) {
+                errors.push(state.span(), format!("unknown state `{state}`"));
+            }
+            used_states.insert(state.to_string());
+        };
+        let mut check_input = |input: &Ident, errors: &mut Errors| {
+            if !inputs.contains(&input.to_string()) {
+                errors.push(input.span(), format!("unknown input `{input}`"));
+            }
+            used_inputs.insert(input.to_string());
+        };
+
+        if let Some(initial) = &self.initial {
+            check_state(initial, &mut errors);
+        }
+        for state in self.finals.iter().chain(&self.accepting) {
+            check_state(state, &mut errors);
+        }
+        let finals: HashSet<String> = self.finals.iter().map(Ident::to_string).collect();
+
+        // Aliases stand for a declared state and don't shadow one
+        let mut aliases = HashSet::new();
+        for (alias, state) in &self.state_aliases {
+            if states.contains(&alias.value()) {
+                errors.push(
+                    alias.span(),
+                    format!("alias `{}` is the name of a state", alias.value()),
+                );
+            } else if !aliases.insert(alias.value()) {
+                errors.push(
+                    alias.span(),
+                    format!("duplicate state alias `{}`", alias.value()),
+                );
+            }
+            if !states.contains(&state.to_string()) {
+                errors.push(state.span(), format!("unknown state `{state}`"));
+            }
+        }
+
+        let mut declared_pairs: HashMap<(String, String), Span> = HashMap::new();
+        for rule in &self.rules {
+            if let Some(from) = &rule.from {
+                check_state(from, &mut errors);
+                if finals.contains(&from.to_string()) {
+                    errors.push(
+                        rule.span,
+                        format!("final state `{from}` cannot have transition rules"),
+                    );
+                }
+            }
+            check_input(&rule.input, &mut errors);
+            if let Some(to) = rule.to.as_ref().filter(|_| !rule.shared) {
+                check_state(to, &mut errors);
+            }
+            if let Some(output) = rule.output.as_ref().filter(|_| !rule.shared) {
+                match &outputs {
+                    Some(outputs) if outputs.contains(&output.to_string()) => {}
+                    Some(_) => errors.push(output.span(), format!("unknown output `{output}`")),
+                    None => errors.push(
+                        output.span(),
+                        "`emits` requires an `outputs` block".to_string(),
+                    ),
+                }
+            }
+
+            let from = rule.from.as_ref().map_or("_".to_string(), Ident::to_string);
+            let pair = (from, rule.input.to_string());
+            if let Some(first) = declared_pairs.get(&pair) {
+                let message = if rule.from.is_some() {
+                    format!(
+                        "conflicting rules for state `{}` and input `{}`",
+                        pair.0, pair.1
+                    )
+                } else {
+                    format!("conflicting wildcard rules for input `{}`", pair.1)
+                };
+                errors.push(rule.span, message);
+                errors.push(*first, "first rule declared here".to_string());
+            } else {
+                declared_pairs.insert(pair, rule.span);
+            }
+        }
+
+        for (state, input) in &self.timeouts {
+            check_state(state, &mut errors);
+            check_input(input, &mut errors);
+        }
+        for input in &self.queue_inputs {
+            check_input(input, &mut errors);
+        }
+        for (state, input) in &self.weights {
+            check_state(state, &mut errors);
+            check_input(input, &mut errors);
+            let pair = (state.to_string(), input.to_string());
+            let wildcard = ("_".to_string(), input.to_string());
+            if !declared_pairs.contains_key(&pair) && !declared_pairs.contains_key(&wildcard) {
+                errors.push(
+                    state.span(),
+                    format!(
+                        "weight given for undeclared transition from `{state}` with input `{input}`"
+                    ),
+                );
+            }
+        }
+        errors.into_result()?;
+
+        let reachable = self.reachable_states();
+        let mut findings: Vec<(&str, &Ident, String)> = Vec::new();
+        for state in &self.states {
+            if !used_states.contains(&state.to_string()) {
+                findings.push((
+                    "unused_state",
+                    state,
+                    format!("state `{state}` is not used by any rule"),
+                ));
+            } else if !reachable.contains(&state.to_string()) {
+                findings.push((
+                    "unreachable_state",
+                    state,
+                    format!("state `{state}` is unreachable from the initial state"),
+                ));
+            }
+        }
+        for input in &self.inputs {
+            if !used_inputs.contains(&input.to_string()) {
+                findings.push((
+                    "unused_input",
+                    input,
+                    format!("input `{input}` is not used by any rule"),
+                ));
+            }
+        }
+
+        let mut warnings = Vec::new();
+        let mut denied = Errors::default();
+        for (lint, ident, message) in findings {
+            match self.lints.level(lint, ident) {
+                Level::Allow => {}
+                Level::Warn => warnings.push(warning(ident.span(), &message, warnings.len())),
+                Level::Deny => denied.push(ident.span(), format!("{message} (denied by `{lint}`)")),
+            }
+        }
+        denied.into_result()?;
+        Ok(warnings)
+    }
+
+    /// Get the names of the states reachable from the initial state
+    ///
+    /// Guards are not evaluated, so every rule counts as a possible transition. A
+    /// wildcard rule leads from every state that is not final and has no rule of its
+    /// own for the input.
+    fn reachable_states(&self) -> HashSet<String> {
+        let finals: HashSet<String> = self.finals.iter().map(Ident::to_string).collect();
+        let own_rules: HashSet<(String, String)> = self
+            .rules
+            .iter()
+            .filter_map(|rule| {
+                let from = rule.from.as_ref()?;
+                Some((from.to_string(), rule.input.to_string()))
+            })
+            .collect();
+
+        let mut reachable = HashSet::new();
+        let mut to_visit: Vec<String> = self.initial.iter().map(Ident::to_string).collect();
+        while let Some(state) = to_visit.pop() {
+            if !reachable.insert(state.clone()) {
+                continue;
+            }
+            for rule in &self.rules {
+                let applies = match &rule.from {
+                    Some(from) => *from == state,
+                    None => {
+                        !finals.contains(&state)
+                            && !own_rules.contains(&(state.clone(), rule.input.to_string()))
+                    }
+                };
+                if let Some(to) = rule.to.as_ref().filter(|_| applies) {
+                    to_visit.push(to.to_string());
+                }
+            }
+        }
+        reachable
+    }
+}
+
+/// How a lint is reported
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Level {
+    /// Not reported
+    Allow,
+    /// Reported as a warning
+    Warn,
+    /// Reported as an error
+    Deny,
+}
+
+/// Lint levels set with `allow` and `deny` attributes, on the whole definition or on
+/// single states and inputs
+#[derive(Default)]
+struct Lints {
+    /// Level of each lint, by lint and the state or input it is restricted to
+    levels: HashMap<(String, Option<String>), Level>,
+}
+
+impl Lints {
+    /// Record the lint levels set by attributes
+    ///
+    /// Doc comments are skipped. Other attributes are rejected, as
+    /// `define_state_machine!` doesn't accept them.
+    ///
+    /// # Arguments
+    /// - `attributes`: The attributes to apply
+    /// - `item`: The state or input they are attached to, None for the whole definition
+    fn apply(&mut self, attributes: &[Attribute], item: Option<&Ident>) -> syn::Result<()> {
+        for attribute in attributes {
+            let level = if attribute.path().is_ident("allow") {
+                Level::Allow
+            } else if attribute.path().is_ident("deny") {
+                Level::Deny
+            } else if attribute.path().is_ident("doc") && item.is_some() {
+                continue;
+            } else {
+                return Err(syn::Error::new_spanned(
+                    attribute,
+                    "only `allow` and `deny` attributes are supported here",
+                ));
+            };
+            let lints =
+                attribute.parse_args_with(Punctuated::<Ident, Token![,]>::parse_terminated)?;
+            for lint in lints {
+                if !LINTS.contains(&lint.to_string().as_str()) {
+                    return Err(syn::Error::new(
+                        lint.span(),
+                        format!(
+                            "unknown lint `{lint}`, expected one of {}",
+                            LINTS.join(", ")
+                        ),
+                    ));
+                }
+                self.levels
+                    .insert((lint.to_string(), item.map(Ident::to_string)), level);
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the level of a lint for a state or input
+    ///
+    /// A level set on the state or input wins over one set on the whole definition,
+    /// and lints are warnings by default.
+    fn level(&self, lint: &str, item: &Ident) -> Level {
+        self.levels
+            .get(&(lint.to_string(), Some(item.to_string())))
+            .or_else(|| self.levels.get(&(lint.to_string(), None)))
+            .copied()
+            .unwrap_or(Level::Warn)
+    }
+}
+
+/// Remove `allow` and `deny` attributes, which `define_state_machine!` doesn't accept
+///
+/// Attributes are looked for before the fields and in the `states` and `inputs`
+/// blocks, where [`Lints::apply`] reads them.
+fn strip_lint_attributes(tokens: TokenStream2, top_level: bool) -> TokenStream2 {
+    let mut output = Vec::new();
+    let mut field = None;
+    let mut tokens = tokens.into_iter().peekable();
+    while let Some(token) = tokens.next() {
+        match &token {
+            TokenTree::Punct(punct) if punct.as_char() == '#' => {
+                if let Some(TokenTree::Group(group)) = tokens.peek()
+                    && group.delimiter() == Delimiter::Bracket
+                    && matches!(
+                        group.stream().into_iter().next(),
+                        Some(TokenTree::Ident(name)) if name == "allow" || name == "deny"
+                    )
+                {
+                    tokens.next();
+                    continue;
+                }
+            }
+            TokenTree::Ident(ident) if top_level => field = Some(ident.to_string()),
+            TokenTree::Group(group)
+                if top_level
+                    && group.delimiter() == Delimiter::Brace
+                    && matches!(field.as_deref(), Some("states" | "inputs")) =>
+            {
+                let mut stripped = Group::new(
+                    Delimiter::Brace,
+                    strip_lint_attributes(group.stream(), false),
+                );
+                stripped.set_span(group.span());
+                output.push(TokenTree::Group(stripped));
+                continue;
+            }
+            _ => {}
+        }
+        output.push(token);
+    }
+    output.into_iter().collect()
+}
+
+/// Collect the names of declared identifiers, reporting duplicates
+fn declared(idents: &[Ident], kind: &str, errors: &mut Errors) -> HashSet<String> {
+    let mut names = HashSet::new();
+    for ident in idents {
+        if !names.insert(ident.to_string()) {
+            errors.push(ident.span(), format!("duplicate {kind} `{ident}`"));
+        }
+    }
+    names
+}
+
+/// Errors collected while checking a definition
+#[derive(Default)]
+struct Errors(Option<syn::Error>);
+
+impl Errors {
+    /// Add an error at a location
+    fn push(&mut self, span: Span, message: String) {
+        let error = syn::Error::new(span, message);
+        match &mut self.0 {
+            Some(errors) => errors.combine(error),
+            None => self.0 = Some(error),
+        }
+    }
+
+    /// Turn the collected errors into a result
+    fn into_result(self) -> syn::Result<()> {
+        self.0.map_or(Ok(()), Err)
+    }
+}
+
+/// Emit a compiler warning at a location
+///
+/// Stable procedural macros can't emit warnings directly, so this refers to a
+/// deprecated constant whose note carries the message.
+fn warning(span: Span, message: &str, index: usize) -> TokenStream2 {
+    let name = format_ident!("__yasm_warning_{}", index);
+    let usage = Ident::new(&name.to_string(), span);
+    quote! {
+        const _: () = {
+            #[deprecated(note = #message)]
+            #[allow(non_upper_case_globals)]
+            const #name: () = ();
+            #usage
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Expand a definition, returning the error messages
+    fn errors(input: TokenStream2) -> Vec<String> {
+        match expand(input) {
+            Ok(_) => Vec::new(),
+            Err(error) => error.into_iter().map(|error| error.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_valid_definition() {
+        let expanded = expand(quote! {
+            name: Order,
+            states: {
+                /// Waiting for payment
+                Created,
+                Paid,
+                Shipped
+            },
+            inputs: { Pay(amount: u32), /// Hand over to the carrier
+                Ship, Cancel },
+            outputs: { Receipt },
+            initial: Created,
+            context: u32,
+            transitions: {
+                Created + Pay => Paid emits Receipt if |stock| *stock > 0,
+                Paid + Ship => compensate Shipped,
+                Paid + Cancel => internal,
+                _ + Cancel | Ship => Created,
+                Created ignores Ship,
+                Shipped defers Pay,
+            },
+            queue: { coalesce Ship, Cancel supersedes Ship },
+            timeouts: { Paid after std::time::Duration::from_secs(5) => Cancel },
+            weights: { Paid + Ship => 3, Shipped + Cancel => 1 }
+        })
+        .unwrap();
+        let expanded = expanded.to_string();
+        assert!(expanded.starts_with(":: yasm :: define_state_machine !"));
+        assert!(!expanded.contains("__yasm_warning"));
+    }
+
+    #[test]
+    fn test_unknown_names() {
+        assert_eq!(
+            errors(quote! {
+                name: Door,
+                states: { Closed, Open },
+                inputs: { Push },
+                initial: Close,
+                transitions: { Closed + Push | Pull => Opne emits Creak }
+            }),
+            vec![
+                "unknown state `Close`",
+                "unknown state `Opne`",
+                "`emits` requires an `outputs` block",
+                "unknown input `Pull`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_conflicting_rules() {
+        assert_eq!(
+            errors(quote! {
+                name: Door,
+                states: { Closed, Open, Open },
+                inputs: { Push },
+                initial: Closed,
+                transitions: {
+                    Closed + Push => Open,
+                    Closed ignores Push,
+                    _ + Push => Closed,
+                    _ + Push => internal,
+                }
+            }),
+            vec![
+                "duplicate state `Open`",
+                "conflicting rules for state `Closed` and input `Push`",
+                "first rule declared here",
+                "conflicting wildcard rules for input `Push`",
+                "first rule declared here",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_final_states_and_weights() {
+        assert_eq!(
+            errors(quote! {
+                name: Door,
+                states: { Closed, Open, Gone },
+                inputs: { Push },
+                initial: Closed,
+                final: { Gone, Lost },
+                accepting: { Open, Ajar },
+                transitions: {
+                    Closed + Push => Open,
+                    Open + Push => Gone,
+                    Gone ignores Push,
+                },
+                weights: { Closed + Push => 2, Open + Pull => 1 }
+            }),
+            vec![
+                "unknown state `Lost`",
+                "unknown state `Ajar`",
+                "final state `Gone` cannot have transition rules",
+                "unknown input `Pull`",
+                "weight given for undeclared transition from `Open` with input `Pull`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_state_aliases() {
+        assert!(
+            errors(quote! {
+                name: Door,
+                states: { Closed, Open },
+                state_aliases: { "Shut" => Closed, "Opened" => Open },
+                inputs: { Push },
+                initial: Closed,
+                transitions: { Closed + Push => Open }
+            })
+            .is_empty()
+        );
+        assert_eq!(
+            errors(quote! {
+                name: Door,
+                states: { Closed, Open },
+                state_aliases: { "Open" => Closed, "Shut" => Closed, "Shut" => Open, "Ajar" => Half },
+                inputs: { Push },
+                initial: Closed,
+                transitions: { Closed + Push => Open }
+            }),
+            vec![
+                "alias `Open` is the name of a state",
+                "duplicate state alias `Shut`",
+                "unknown state `Half`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_field_errors() {
+        assert_eq!(
+            errors(quote! {
+                name: Door,
+                inputs: { Push },
+                states: { Closed },
+            }),
+            vec!["field `states` must come before `inputs`"]
+        );
+        assert_eq!(
+            errors(quote! {
+                name: Door,
+                states: { Closed },
+                inputs: { Push },
+                initial: Closed,
+            }),
+            vec!["missing field `transitions`"]
+        );
+        assert_eq!(errors(quote! { name: Door, colour: Red }).len(), 1);
+    }
+
+    #[test]
+    fn test_unused_warnings() {
+        let expanded = expand(quote! {
+            name: Door,
+            states: { Closed, Open, Broken },
+            inputs: { Push, Kick },
+            initial: Closed,
+            transitions: { Closed + Push => Open }
+        })
+        .unwrap()
+        .to_string();
+        assert!(expanded.contains("state `Broken` is not used by any rule"));
+        assert!(expanded.contains("input `Kick` is not used by any rule"));
+        assert!(!expanded.contains("state `Open`"));
+    }
+
+    #[test]
+    fn test_reachability_lints() {
+        let definition = |attributes: TokenStream2| {
+            quote! {
+                #attributes
+                name: Door,
+                states: { Closed, Open, Jammed, #[allow(unreachable_state)] Legacy },
+                inputs: { Push, #[allow(unused_input)] Kick },
+                initial: Closed,
+                final: { Open },
+                transitions: {
+                    Closed + Push => Open,
+                    Jammed + Push => Closed,
+                    Legacy + Push => Jammed,
+                    _ + Push => Jammed,
+                }
+            }
+        };
+
+        let expanded = expand(definition(quote! {})).unwrap().to_string();
+        assert!(expanded.contains("state `Jammed` is unreachable from the initial state"));
+        assert!(!expanded.contains("Legacy` is unreachable"));
+        assert!(!expanded.contains("input `Kick`"));
+        assert!(!expanded.contains("unreachable_state") && !expanded.contains("unused_input"));
+
+        let allowed = expand(definition(quote! { #[allow(unreachable_state)] })).unwrap();
+        assert!(!allowed.to_string().contains("__yasm_warning"));
+        assert_eq!(
+            errors(definition(quote! { #[deny(unreachable_state)] })),
+            vec![
+                "state `Jammed` is unreachable from the initial state (denied by `unreachable_state`)"
+            ]
+        );
+        assert_eq!(
+            errors(definition(quote! { #[allow(dead_state)] })),
+            vec![
+                "unknown lint `dead_state`, expected one of unreachable_state, unused_state, unused_input"
+            ]
+        );
+        assert_eq!(
+            errors(definition(quote! { #[derive(Debug)] })),
+            vec!["only `allow` and `deny` attributes are supported here"]
+        );
+    }
+}